@@ -40,6 +40,7 @@ pub enum RpcError {
     // State-related errors,
     NotSynced = -32120,
     UnknownSlot = -32121,
+    UnknownTransaction = -32122,
 
     // Parsing errors
     ParseError = -32190,
@@ -66,6 +67,7 @@ fn to_tuple(e: RpcError) -> (i64, String) {
         // State-related errors
         RpcError::NotSynced => "Blockchain is not synced",
         RpcError::UnknownSlot => "Did not find slot",
+        RpcError::UnknownTransaction => "Did not find transaction",
         // Parsing errors
         RpcError::ParseError => "Parse error",
         // Contract-related errors
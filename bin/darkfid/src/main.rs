@@ -50,6 +50,7 @@ use darkfi::{
     },
     util::path::expand_path,
     wallet::{walletdb::init_wallet, WalletPtr},
+    zk::ParamsCache,
     Error, Result,
 };
 
@@ -87,6 +88,10 @@ struct Args {
     /// Path to blockchain database
     database: String,
 
+    #[structopt(long, default_value = "~/.config/darkfi/darkfid_zkcache")]
+    /// Path to the ZK params cache
+    zk_cache: String,
+
     #[structopt(long, default_value = "tcp://127.0.0.1:8340")]
     /// JSON-RPC listen URL
     rpc_listen: Url,
@@ -123,6 +128,14 @@ struct Args {
     /// Prefered transports of outbound connections for the consensus protocol (repeatable flag)
     consensus_p2p_transports: Vec<String>,
 
+    #[structopt(long, default_value = "~/.config/darkfi/darkfid_consensus_hosts.tsv")]
+    /// Path to the host list of the consensus protocol
+    consensus_p2p_hostlist: String,
+
+    #[structopt(long, default_value = "~/.config/darkfi/darkfid_consensus_anchors.txt")]
+    /// Path to the anchor peers of the consensus protocol
+    consensus_p2p_anchorlist: String,
+
     #[structopt(long)]
     /// P2P accept addresses for the syncing protocol (repeatable flag)
     sync_p2p_accept: Vec<Url>,
@@ -147,6 +160,14 @@ struct Args {
     /// Prefered transports of outbound connections for the syncing protocol (repeatable flag)
     sync_p2p_transports: Vec<String>,
 
+    #[structopt(long, default_value = "~/.config/darkfi/darkfid_sync_hosts.tsv")]
+    /// Path to the host list of the syncing protocol
+    sync_p2p_hostlist: String,
+
+    #[structopt(long, default_value = "~/.config/darkfi/darkfid_sync_anchors.txt")]
+    /// Path to the anchor peers of the syncing protocol
+    sync_p2p_anchorlist: String,
+
     #[structopt(long)]
     /// Enable localnet hosts
     localnet: bool,
@@ -206,11 +227,21 @@ impl RequestHandler for Darkfid {
             Some("clock") => return self.misc_clock(req.id, params).await,
             Some("get_info") => return self.misc_get_info(req.id, params).await,
             Some("get_consensus_info") => return self.misc_get_consensus_info(req.id, params).await,
+            Some("get_bans") => return self.misc_get_bans(req.id, params).await,
+            Some("ban") => return self.misc_ban(req.id, params).await,
+            Some("unban") => return self.misc_unban(req.id, params).await,
 
             // ==================
             // Blockchain methods
             // ==================
             Some("blockchain.get_slot") => return self.blockchain_get_slot(req.id, params).await,
+            Some("blockchain.get_tx") => return self.blockchain_get_tx(req.id, params).await,
+            Some("blockchain.get_tx_location") => {
+                return self.blockchain_get_tx_location(req.id, params).await
+            }
+            Some("blockchain.nullifier_spent_by") => {
+                return self.blockchain_nullifier_spent_by(req.id, params).await
+            }
             Some("blockchain.last_known_slot") => {
                 return self.blockchain_last_known_slot(req.id, params).await
             }
@@ -298,6 +329,9 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'_>>) -> Result<()> {
     let db_path = format!("{}/{}", expand_path(&args.database)?.to_str().unwrap(), args.chain);
     let sled_db = sled::open(&db_path)?;
 
+    // Open the ZK params cache
+    let params_cache = ParamsCache::new(&expand_path(&args.zk_cache)?)?;
+
     // Initialize validator state
     let (bootstrap_ts, genesis_ts, genesis_data, initial_distribution) = match args.chain.as_str() {
         "mainnet" => (
@@ -340,6 +374,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'_>>) -> Result<()> {
         wallet.clone(),
         faucet_pubkeys,
         args.consensus,
+        params_cache,
     )
     .await?;
 
@@ -354,6 +389,8 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'_>>) -> Result<()> {
             outbound_transports: net::settings::get_outbound_transports(args.sync_p2p_transports),
             localnet: args.localnet,
             channel_log: args.channel_log,
+            hostlist: Some(args.sync_p2p_hostlist),
+            anchorlist: Some(args.sync_p2p_anchorlist),
             ..Default::default()
         };
 
@@ -400,6 +437,8 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'_>>) -> Result<()> {
                 ),
                 localnet: args.localnet,
                 channel_log: args.channel_log,
+                hostlist: Some(args.consensus_p2p_hostlist),
+                anchorlist: Some(args.consensus_p2p_anchorlist),
                 ..Default::default()
             };
             let p2p = net::P2p::new(consensus_network_settings).await;
@@ -472,7 +511,13 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'_>>) -> Result<()> {
 
         info!("Starting consensus protocol task");
         let _ex = ex.clone();
-        ex.spawn(proposal_task(consensus_p2p.unwrap(), sync_p2p.unwrap(), state, _ex)).detach();
+        ex.spawn(proposal_task(
+            consensus_p2p.clone().unwrap(),
+            sync_p2p.clone().unwrap(),
+            state,
+            _ex,
+        ))
+        .detach();
     } else {
         info!("Not starting consensus P2P network");
     }
@@ -482,6 +527,12 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'_>>) -> Result<()> {
     print!("\r");
     info!("Caught termination signal, cleaning up and exiting...");
 
+    info!("Stopping P2P networks...");
+    sync_p2p.unwrap().stop().await;
+    if let Some(consensus_p2p) = consensus_p2p {
+        consensus_p2p.stop().await;
+    }
+
     info!("Flushing sled database...");
     let flushed_bytes = sled_db.flush_async().await?;
     info!("Flushed {} bytes", flushed_bytes);
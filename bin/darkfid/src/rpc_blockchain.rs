@@ -16,8 +16,10 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::str::FromStr;
+
 use darkfi_sdk::{
-    crypto::{ContractId, MerkleNode},
+    crypto::{ContractId, MerkleNode, Nullifier},
     db::SMART_CONTRACT_ZKAS_DB_NAME,
 };
 use darkfi_serial::{deserialize, serialize};
@@ -65,6 +67,110 @@ impl Darkfid {
         JsonResponse::new(json!(serialize(&blocks[0])), id).into()
     }
 
+    // RPCAPI:
+    // Queries the blockchain database for a transaction with the given hash.
+    // Returns the serialized transaction upon success.
+    //
+    // --> {"jsonrpc": "2.0", "method": "blockchain.get_tx", "params": ["TxHash"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": [...], "id": 1}
+    pub async fn blockchain_get_tx(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 1 || !params[0].is_string() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let Ok(tx_hash) = blake3::Hash::from_hex(params[0].as_str().unwrap()) else {
+            return JsonError::new(InvalidParams, None, id).into()
+        };
+
+        let blockchain = { self.validator_state.read().await.blockchain.clone() };
+
+        let tx = match blockchain.transactions.get(&[tx_hash], false) {
+            Ok(v) => v[0].clone(),
+            Err(e) => {
+                error!("[RPC] blockchain.get_tx: Failed fetching tx from txstore: {}", e);
+                return JsonError::new(InternalError, None, id).into()
+            }
+        };
+
+        let Some(tx) = tx else { return server_error(RpcError::UnknownTransaction, id, None) };
+
+        JsonResponse::new(json!(serialize(&tx)), id).into()
+    }
+
+    // RPCAPI:
+    // Queries the blockchain database for the location of the transaction with
+    // the given hash. Returns the slot and the hash of the block it landed in,
+    // along with its position in the block.
+    //
+    // --> {"jsonrpc": "2.0", "method": "blockchain.get_tx_location", "params": ["TxHash"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"slot": 42, "block": "BlockHash", "position": 0}, "id": 1}
+    pub async fn blockchain_get_tx_location(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 1 || !params[0].is_string() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let Ok(tx_hash) = blake3::Hash::from_hex(params[0].as_str().unwrap()) else {
+            return JsonError::new(InvalidParams, None, id).into()
+        };
+
+        let blockchain = { self.validator_state.read().await.blockchain.clone() };
+
+        let location = match blockchain.tx_locations.get(&[tx_hash], false) {
+            Ok(v) => v[0],
+            Err(e) => {
+                error!("[RPC] blockchain.get_tx_location: Failed fetching tx location: {}", e);
+                return JsonError::new(InternalError, None, id).into()
+            }
+        };
+
+        let Some(location) = location else {
+            return server_error(RpcError::UnknownTransaction, id, None)
+        };
+
+        let ret = json!({
+            "slot": location.slot,
+            "block": location.blockhash.to_hex().as_str(),
+            "position": location.position,
+        });
+
+        JsonResponse::new(ret, id).into()
+    }
+
+    // RPCAPI:
+    // Queries the blockchain database for the transaction that revealed the
+    // given nullifier. Returns the transaction hash, or `null` if the nullifier
+    // was not spent.
+    //
+    // --> {"jsonrpc": "2.0", "method": "blockchain.nullifier_spent_by", "params": ["Nullifier"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": "TxHash", "id": 1}
+    pub async fn blockchain_nullifier_spent_by(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 1 || !params[0].is_string() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let nullifier = match Nullifier::from_str(params[0].as_str().unwrap()) {
+            Ok(v) => v,
+            Err(e) => {
+                error!(
+                    "[RPC] blockchain.nullifier_spent_by: Error decoding string to Nullifier: {}",
+                    e
+                );
+                return JsonError::new(InvalidParams, None, id).into()
+            }
+        };
+
+        let blockchain = { self.validator_state.read().await.blockchain.clone() };
+
+        match blockchain.nullifiers.spent_by(&nullifier) {
+            Ok(Some(tx_hash)) => JsonResponse::new(json!(tx_hash.to_hex().as_str()), id).into(),
+            Ok(None) => JsonResponse::new(json!(null), id).into(),
+            Err(e) => {
+                error!("[RPC] blockchain.nullifier_spent_by: Failed fetching nullifier: {}", e);
+                JsonError::new(InternalError, None, id).into()
+            }
+        }
+    }
+
     // RPCAPI:
     // Queries the blockchain database to find the last known slot
     //
@@ -19,7 +19,7 @@
 use serde_json::{json, Value};
 
 use darkfi::{
-    rpc::jsonrpc::{JsonResponse, JsonResult},
+    rpc::jsonrpc::{ErrorCode::InvalidParams, JsonError, JsonResponse, JsonResult},
     util::time::Timestamp,
 };
 
@@ -69,4 +69,71 @@ impl Darkfid {
         };
         JsonResponse::new(resp, id).into()
     }
+
+    // RPCAPI:
+    // Retrieves the hosts currently banned on the sync and consensus P2P
+    // networks, along with the UNIX timestamp their ban expires at.
+    //
+    // --> {"jsonrpc": "2.0", "method": "get_bans", "params": [], "id": 42}
+    // <-- {"jsonrpc": "2.0", "result": {"sync": [{"host": "1.2.3.4", "until": 1680000000}], "consensus": []}, "id": 42}
+    pub async fn misc_get_bans(&self, id: Value, _params: &[Value]) -> JsonResult {
+        let sync = match &self.sync_p2p {
+            Some(p2p) => p2p.get_bans().await,
+            None => json!([]),
+        };
+        let consensus = match &self.consensus_p2p {
+            Some(p2p) => p2p.get_bans().await,
+            None => json!([]),
+        };
+        JsonResponse::new(json!({"sync": sync, "consensus": consensus}), id).into()
+    }
+
+    // RPCAPI:
+    // Bans a host on the sync and consensus P2P networks, disconnecting any
+    // channels to it. The ban duration in seconds is optional and defaults
+    // to the configured one.
+    //
+    // --> {"jsonrpc": "2.0", "method": "ban", "params": ["1.2.3.4", 3600], "id": 42}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 42}
+    pub async fn misc_ban(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.is_empty() || params.len() > 2 || !params[0].is_string() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let duration = match params.get(1) {
+            Some(d) => match d.as_u64() {
+                Some(d) => Some(d),
+                None => return JsonError::new(InvalidParams, None, id).into(),
+            },
+            None => None,
+        };
+
+        let host = params[0].as_str().unwrap();
+        for p2p in [&self.sync_p2p, &self.consensus_p2p].into_iter().flatten() {
+            let duration = duration.unwrap_or(p2p.settings().ban_duration_seconds);
+            p2p.ban(host, duration).await;
+        }
+
+        JsonResponse::new(json!(true), id).into()
+    }
+
+    // RPCAPI:
+    // Lifts the ban of a host on the sync and consensus P2P networks.
+    // Returns false if the host wasn't banned on either of them.
+    //
+    // --> {"jsonrpc": "2.0", "method": "unban", "params": ["1.2.3.4"], "id": 42}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 42}
+    pub async fn misc_unban(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 1 || !params[0].is_string() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let host = params[0].as_str().unwrap();
+        let mut unbanned = false;
+        for p2p in [&self.sync_p2p, &self.consensus_p2p].into_iter().flatten() {
+            unbanned |= p2p.bans().unban(host).await;
+        }
+
+        JsonResponse::new(json!(unbanned), id).into()
+    }
 }
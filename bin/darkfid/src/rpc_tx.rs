@@ -31,11 +31,12 @@ use crate::{server_error, RpcError};
 impl Darkfid {
     // RPCAPI:
     // Simulate a network state transition with the given transaction.
-    // Returns `true` if the transaction is valid, otherwise, a corresponding
-    // error.
+    // Returns the gas used by the transaction if it is valid, otherwise, a
+    // corresponding error. Clients can use it to pick the transaction's gas
+    // limit. Note that older nodes return `true` instead.
     //
     // --> {"jsonrpc": "2.0", "method": "tx.simulate", "params": ["base58encodedTX"], "id": 1}
-    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": 1234567, "id": 1}
     pub async fn tx_simulate(&self, id: Value, params: &[Value]) -> JsonResult {
         if params.len() != 1 || !params[0].is_string() {
             return JsonError::new(InvalidParams, None, id).into()
@@ -64,12 +65,16 @@ impl Darkfid {
         };
 
         // Simulate state transition
-        if let Err(e) = self.validator_state.read().await.verify_transactions(&[tx], false).await {
-            error!("[RPC] tx.broadcast: Failed to validate state transition: {}", e);
-            return server_error(RpcError::TxSimulationFail, id, None)
-        };
+        let gas_used =
+            match self.validator_state.read().await.verify_transactions(&[tx], false).await {
+                Ok(v) => v[0],
+                Err(e) => {
+                    error!("[RPC] tx.simulate: Failed to validate state transition: {}", e);
+                    return server_error(RpcError::TxSimulationFail, id, None)
+                }
+            };
 
-        JsonResponse::new(json!(true), id).into()
+        JsonResponse::new(json!(gas_used), id).into()
     }
 
     // RPCAPI:
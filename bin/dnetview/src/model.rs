@@ -66,6 +66,7 @@ pub struct NodeInfo {
     pub children: Vec<SessionInfo>,
     pub external_addr: Option<String>,
     pub is_offline: bool,
+    pub bans: Vec<String>,
 }
 
 impl NodeInfo {
@@ -76,8 +77,9 @@ impl NodeInfo {
         children: Vec<SessionInfo>,
         external_addr: Option<String>,
         is_offline: bool,
+        bans: Vec<String>,
     ) -> Self {
-        Self { id, name, state, children, external_addr, is_offline }
+        Self { id, name, state, children, external_addr, is_offline, bans }
     }
 }
 
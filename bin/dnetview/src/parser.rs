@@ -24,7 +24,10 @@ use serde_json::Value;
 use smol::Executor;
 use url::Url;
 
-use darkfi::util::{async_util, time::NanoTimestamp};
+use darkfi::util::{
+    async_util,
+    time::{timestamp_to_date, DateFormat, NanoTimestamp},
+};
 
 use crate::{
     config::{DnvConfig, Node, NodeType},
@@ -153,7 +156,8 @@ impl DataParser {
             SessionInfo::new(session_id, name, is_empty, parent, connects, accept_addr, None);
         sessions.push(session_info);
 
-        let node = NodeInfo::new(node_id, node_name, state, sessions.clone(), None, true);
+        let node =
+            NodeInfo::new(node_id, node_name, state, sessions.clone(), None, true, Vec::new());
 
         self.update_selectables(sessions, node).await?;
         Ok(())
@@ -169,12 +173,14 @@ impl DataParser {
         let _manual = &reply["session_manual"];
         let outbound = &reply["session_outbound"];
         let state = &reply["state"];
+        let bans = &reply.get("bans");
 
         let mut sessions: Vec<SessionInfo> = Vec::new();
 
         let node_id = make_node_id(&node_name)?;
 
         let ext_addr = self.parse_external_addr(addr).await?;
+        let bans = self.parse_bans(bans).await?;
         let in_session = self.parse_inbound(inbound, &node_id).await?;
         let out_session = self.parse_outbound(outbound, &node_id).await?;
         //let man_session = self.parse_manual(manual, &node_id).await?;
@@ -190,6 +196,7 @@ impl DataParser {
             sessions.clone(),
             ext_addr,
             false,
+            bans,
         );
 
         self.update_selectables(sessions.clone(), node).await?;
@@ -305,6 +312,23 @@ impl DataParser {
         }
     }
 
+    async fn parse_bans(&self, bans: &Option<&Value>) -> DnetViewResult<Vec<String>> {
+        let mut ban_vec = Vec::new();
+        // Nodes running older versions don't report bans
+        if let Some(Value::Array(bans)) = bans {
+            for ban in bans {
+                let host = ban["host"].as_str().unwrap_or("Null");
+                let until = ban["until"].as_i64().unwrap_or(0);
+                ban_vec.push(format!(
+                    "{} (until {})",
+                    host,
+                    timestamp_to_date(until, DateFormat::DateTime)
+                ));
+            }
+        }
+        Ok(ban_vec)
+    }
+
     async fn parse_inbound(
         &self,
         inbound: &Value,
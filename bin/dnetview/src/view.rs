@@ -331,6 +331,13 @@ impl<'a> View {
                         format!("P2P state: {}", node.state),
                         style,
                     )));
+                    if !node.bans.is_empty() {
+                        lines.push(Spans::from(Span::styled("Bans:".to_string(), style)));
+                        for ban in &node.bans {
+                            let ban = Span::styled(format!("      {}", ban), style);
+                            lines.push(Spans::from(ban));
+                        }
+                    }
                 }
                 Some(SelectableObject::Session(session)) => {
                     //debug!(target: "dnetview", "render_info()::SelectableObject::Session");
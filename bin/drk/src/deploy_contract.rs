@@ -17,48 +17,62 @@
  */
 
 use std::{
-    env::set_current_dir,
     fs::{read, read_dir, read_to_string, File},
     io::{ErrorKind, Write},
-    path::{Path, PathBuf},
+    path::Path,
     str::FromStr,
 };
 
-use rand::{rngs::OsRng, RngCore};
-
+use anyhow::{anyhow, Result};
 use darkfi::{
-    crypto::keypair::SecretKey,
-    node::{MemoryState, State},
-    runtime::vm_runtime::Runtime,
+    tx::{ContractDeploy, Transaction, DEFAULT_GAS_LIMIT},
     util::cli::{fg_green, fg_red},
     zkas::ZkBinary,
-    Error, Result,
 };
+use darkfi_sdk::{
+    crypto::{ContractId, PublicKey, SecretKey, DEPLOY_CONTRACT_ID},
+    tx::ContractCall,
+};
+use darkfi_serial::serialize;
+use rand::rngs::OsRng;
 
 const CIRCUIT_DIR_NAME: &str = "proof";
 const CONTRACT_FILE_NAME: &str = "contract.wasm";
 const DEPLOY_KEY_NAME: &str = "deploy.key";
 
+/// Magic bytes every wasm module starts with
+const WASM_MAGIC: &[u8] = b"\0asm";
+
 /// Creates a new deploy key used for deploying private smart contracts.
 /// This key allows to update the wasm code and the zk circuits on chain
 /// by creating a signature. When deployed, the contract can be accessed
 /// by requesting the public counterpart of this secret key.
-pub fn create_deploy_key(mut rng: impl RngCore, path: &Path) -> Result<SecretKey> {
-    let secret = SecretKey::random(&mut rng);
+pub fn create_deploy_key(path: &Path) -> Result<SecretKey> {
+    let secret = SecretKey::random(&mut OsRng);
     let mut file = File::create(path)?;
-    file.write_all(bs58::encode(&secret.to_bytes()).into_string().as_bytes())?;
+    file.write_all(secret.to_string().as_bytes())?;
     Ok(secret)
 }
 
 /// Reads a deploy key from a file on the filesystem and returns it.
-fn read_deploy_key(s: &Path) -> core::result::Result<SecretKey, std::io::Error> {
-    eprintln!("Trying to read deploy key from file: {:?}", s);
-    let contents = read_to_string(s)?;
-    let secret = SecretKey::from_str(&contents).unwrap();
-    Ok(secret)
+/// If the file doesn't exist, a new deploy key is created in it.
+fn read_deploy_key(path: &Path) -> Result<SecretKey> {
+    eprintln!("Trying to read deploy key from file: {:?}", path);
+    match read_to_string(path) {
+        Ok(contents) => Ok(SecretKey::from_str(contents.trim())?),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            // We didn't find a deploy key, generate a new one.
+            eprintln!("Did not find an existing key, creating a new one.");
+            let secret = create_deploy_key(path)?;
+            eprintln!("Created new deploy key in {:?}.", path);
+            Ok(secret)
+        }
+        Err(e) => Err(anyhow!("Failed to read deploy key: {}", e)),
+    }
 }
 
-/// Creates necessary data to deploy a given smart contract on the network.
+/// Creates a transaction deploying the given smart contract on the network,
+/// or upgrading it in case it was deployed before with the same deploy key.
 /// For consistency, we point this function to a directory where our smart
 /// contract and the compiled circuits are contained. This is going to give
 /// us a uniform approach to scm and gives a generic layout of the source:
@@ -68,114 +82,74 @@ fn read_deploy_key(s: &Path) -> core::result::Result<SecretKey, std::io::Error>
 /// ├── deploy.key
 /// ├── Makefile
 /// ├── proof
-/// │   ├── circuit0.zk
-/// │   ├── circuit0.zk.bin
-/// │   ├── circuit1.zk
-/// │   └── circuit1.zk.bin
+/// │   ├── circuit0.zk
+/// │   ├── circuit0.zk.bin
+/// │   ├── circuit1.zk
+/// │   └── circuit1.zk.bin
 /// ├── contract.wasm
 /// ├── src
-/// │   └── lib.rs
+/// │   └── lib.rs
 /// └── tests
 /// ```
-//pub fn create_deploy_data(path: &Path) -> Result<ContractDeploy> {
-pub fn create_deploy_data(path: &Path) -> Result<()> {
-    // Try to chdir into the contract directory
-    if let Err(e) = set_current_dir(path) {
-        eprintln!("Failed to chdir into {:?}", path);
-        return Err(e.into())
-    }
-
-    let deploy_key: SecretKey;
-
-    let deploy_key = match read_deploy_key(&PathBuf::from(DEPLOY_KEY_NAME)) {
-        Ok(v) => deploy_key = v,
-        Err(e) => {
-            if e.kind() == ErrorKind::NotFound {
-                // We didn't find a deploy key, generate a new one.
-                eprintln!("Did not find an existing key, creating a new one.");
-                match create_deploy_key(&mut OsRng, &PathBuf::from(DEPLOY_KEY_NAME)) {
-                    Ok(v) => {
-                        eprintln!("Created new deploy key in \"{}\".", DEPLOY_KEY_NAME);
-                        deploy_key = v;
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to create new deploy key");
-                        return Err(e)
-                    }
-                }
-            }
-            eprintln!("Failed to read deploy key");
-            return Err(e.into())
-        }
-    };
-
-    // Search for ZK circuits in the directory. If none are found, we'll bail.
-    // The logic searches for `.zk.bin` files created by zkas.
-    eprintln!("Searching for compiled ZK circuits in \"{}\" ...", CIRCUIT_DIR_NAME);
+pub fn create_deploy_tx(path: &Path) -> Result<Transaction> {
+    let deploy_key = read_deploy_key(&path.join(DEPLOY_KEY_NAME))?;
+    let contract_id = ContractId::derive(deploy_key);
+    eprintln!("Contract ID: {}", contract_id);
+
+    // Search for ZK circuits in the directory. The logic searches for
+    // `.zk.bin` files created by zkas.
+    let circuit_dir = path.join(CIRCUIT_DIR_NAME);
+    eprintln!("Searching for compiled ZK circuits in {:?} ...", circuit_dir);
     let mut circuits = vec![];
-    for i in read_dir(CIRCUIT_DIR_NAME)? {
-        if let Err(e) = i {
-            eprintln!("Error iterating over \"{}\" directory", CIRCUIT_DIR_NAME);
-            return Err(e.into())
-        }
-
-        let f = i.unwrap();
-        let fname = f.file_name();
-        let fname = fname.to_str().unwrap();
+    if circuit_dir.exists() {
+        for entry in read_dir(&circuit_dir)? {
+            let path = entry?.path();
+            if !path.to_string_lossy().ends_with(".zk.bin") {
+                continue
+            }
 
-        if fname.ends_with(".zk.bin") {
             // Validate that the files can be properly decoded
-            eprintln!("{} {}", fg_green("Found:"), f.path().display());
-            let buf = read(f.path())?;
+            eprintln!("{} {}", fg_green("Found:"), path.display());
+            let buf = read(&path)?;
             if let Err(e) = ZkBinary::decode(&buf) {
-                eprintln!("{} Failed to decode zkas bincode in {:?}", fg_red("Error:"), f.path());
-                return Err(e)
+                eprintln!("{} Failed to decode zkas bincode in {:?}", fg_red("Error:"), path);
+                return Err(e.into())
             }
 
-            circuits.push(buf.clone());
+            circuits.push(buf);
         }
     }
 
     if circuits.is_empty() {
-        return Err(Error::Custom("Found no valid ZK circuits".to_string()))
+        eprintln!("Found no ZK circuits, deploying the contract without any");
     }
 
-    /* FIXME
-    // Validate wasm binary. We inspect the bincode and try to load it into
-    // the wasm runtime. If loaded, we then look for the `ENTRYPOINT` function
-    // which we hardcode into our sdk and runtime and is the canonical way to
-    // run wasm binaries on chain.
-    eprintln!("Inspecting wasm binary in \"{}\"", CONTRACT_FILE_NAME);
-    let wasm_bytes = read(CONTRACT_FILE_NAME)?;
-    eprintln!("Initializing moch wasm runtime to check validity");
-    let runtime = match Runtime::new(&wasm_bytes, MemoryState::new(State::dummy()?)) {
-        Ok(v) => {
-            eprintln!("Found {} wasm binary", fg_green("valid"));
-            v
-        }
-        Err(e) => {
-            eprintln!("Failed to initialize wasm runtime");
-            return Err(e)
-        }
-    };
-
-    eprintln!("Looking for entrypoint function inside the wasm");
-    let cs = ContractSection::Exec;
-    if let Err(e) = runtime.instance.exports.get_function(cs.name()) {
-        eprintln!("{} Could not find entrypoint function", fg_red("Error:"));
-        return Err(e.into())
+    // The node compiles the wasm and looks for the contract functions in it,
+    // so here we only make sure we're looking at a wasm binary.
+    let wasm_path = path.join(CONTRACT_FILE_NAME);
+    eprintln!("Inspecting wasm binary in {:?}", wasm_path);
+    let wasm_bincode = read(&wasm_path)?;
+    if !wasm_bincode.starts_with(WASM_MAGIC) {
+        return Err(anyhow!("{:?} is not a wasm binary", wasm_path))
     }
 
-    // TODO: Create a ZK proof enforcing the deploy key relations with their public
-    // counterparts (public key and contract address)
-    let mut total_bytes = 0;
-    total_bytes += wasm_bytes.len();
-    for circuit in circuits {
-        total_bytes += circuit.len();
-    }
-    */
+    let deploy = ContractDeploy {
+        deploy_key: PublicKey::from_secret(deploy_key),
+        wasm_bincode,
+        circuits,
+        // The contract's `__initialize` function gets an empty payload
+        payload: vec![],
+    };
+
+    let calls = vec![ContractCall { contract_id: *DEPLOY_CONTRACT_ID, data: serialize(&deploy) }];
+    let mut tx = Transaction {
+        calls,
+        gas_limit: DEFAULT_GAS_LIMIT,
+        proofs: vec![vec![]],
+        signatures: vec![],
+    };
+    let sigs = tx.create_sigs(&mut OsRng, &[deploy_key])?;
+    tx.signatures = vec![sigs];
 
-    // TODO: Return the data back to the main function, and work further in creating
-    // a transaction and broadcasting it.
-    Ok(())
+    Ok(tx)
 }
@@ -44,7 +44,9 @@ use darkfi::{
     util::{
         cli::{get_log_config, get_log_level},
         parse::encode_base10,
+        path::expand_path,
     },
+    zk::{ParamsCache, ParamsCachePtr},
 };
 
 /// Airdrop methods
@@ -74,6 +76,9 @@ use wallet_dao::DaoParams;
 /// Wallet functionality related to Money
 mod wallet_money;
 
+/// Smart contract deployment
+mod deploy_contract;
+
 #[derive(Parser)]
 #[command(about = cli_desc!())]
 struct Args {
@@ -85,6 +90,10 @@ struct Args {
     /// darkfid JSON-RPC endpoint
     endpoint: Url,
 
+    #[arg(long, default_value = "~/.config/darkfi/drk_zkcache")]
+    /// Path to the ZK params cache
+    zk_cache: String,
+
     #[command(subcommand)]
     command: Subcmd,
 }
@@ -173,6 +182,17 @@ enum Subcmd {
     #[command(subcommand, about = cli_desc!())]
     Otc(OtcSubcmd),
 
+    /// Create a transaction deploying or upgrading a smart contract
+    ///
+    /// The contract is read from the given directory, which holds its
+    /// `contract.wasm`, its compiled circuits under `proof/`, and the
+    /// `deploy.key` it's deployed with. A new deploy key is created if
+    /// there isn't one.
+    Deploy {
+        /// Path to the contract directory
+        path: String,
+    },
+
     /// Inspect a transaction from stdin
     Inspect,
 
@@ -322,6 +342,7 @@ enum DaoSubcmd {
 
 pub struct Drk {
     pub rpc_client: RpcClient,
+    pub params_cache: ParamsCachePtr,
 }
 
 impl Drk {
@@ -346,13 +367,15 @@ async fn main() -> Result<()> {
         TermLogger::init(log_level, log_config, TerminalMode::Mixed, ColorChoice::Auto)?;
     }
 
+    let params_cache = ParamsCache::new(&expand_path(&args.zk_cache)?)?;
+
     match args.command {
         Subcmd::Ping => {
             let rpc_client = RpcClient::new(args.endpoint)
                 .await
                 .with_context(|| "Could not connect to darkfid RPC endpoint")?;
 
-            let drk = Drk { rpc_client };
+            let drk = Drk { rpc_client, params_cache };
             drk.ping().await.with_context(|| "Failed to ping darkfid RPC endpoint")?;
             Ok(())
         }
@@ -385,7 +408,7 @@ async fn main() -> Result<()> {
                 .await
                 .with_context(|| "Could not connect to darkfid RPC endpoint")?;
 
-            let drk = Drk { rpc_client };
+            let drk = Drk { rpc_client, params_cache };
 
             if initialize {
                 drk.initialize_money().await?;
@@ -530,7 +553,7 @@ async fn main() -> Result<()> {
                 .await
                 .with_context(|| "Could not connect to darkfid RPC endpoint")?;
 
-            let drk = Drk { rpc_client };
+            let drk = Drk { rpc_client, params_cache };
             drk.unspend_coin(&coin).await.with_context(|| "Failed to mark coin as unspent")?;
 
             Ok(())
@@ -544,7 +567,7 @@ async fn main() -> Result<()> {
                 .await
                 .with_context(|| "Could not connect to darkfid RPC endpoint")?;
 
-            let drk = Drk { rpc_client };
+            let drk = Drk { rpc_client, params_cache };
 
             let address = match address {
                 Some(v) => PublicKey::from_str(v.as_str()).with_context(|| "Invalid address")?,
@@ -571,7 +594,7 @@ async fn main() -> Result<()> {
                 .await
                 .with_context(|| "Could not connect to darkfid RPC endpoint")?;
 
-            let drk = Drk { rpc_client };
+            let drk = Drk { rpc_client, params_cache };
 
             let tx = drk
                 .transfer(&amount, token_id, rcpt, dao, dao_bulla)
@@ -588,7 +611,7 @@ async fn main() -> Result<()> {
                 .await
                 .with_context(|| "Could not connect to darkfid RPC endpoint")?;
 
-            let drk = Drk { rpc_client };
+            let drk = Drk { rpc_client, params_cache };
 
             match cmd {
                 OtcSubcmd::Init { value_pair, token_pair } => {
@@ -644,6 +667,16 @@ async fn main() -> Result<()> {
             }
         }
 
+        Subcmd::Deploy { path } => {
+            let path = expand_path(&path)?;
+            let tx = deploy_contract::create_deploy_tx(&path)
+                .with_context(|| "Failed to create deployment transaction")?;
+
+            println!("{}", bs58::encode(&serialize(&tx)).into_string());
+
+            Ok(())
+        }
+
         Subcmd::Inspect => {
             let mut buf = String::new();
             stdin().read_to_string(&mut buf)?;
@@ -664,7 +697,7 @@ async fn main() -> Result<()> {
                 .await
                 .with_context(|| "Could not connect to darkfid RPC endpoint")?;
 
-            let drk = Drk { rpc_client };
+            let drk = Drk { rpc_client, params_cache };
 
             let txid =
                 drk.broadcast_tx(&tx).await.with_context(|| "Failed to broadcast transaction")?;
@@ -679,7 +712,7 @@ async fn main() -> Result<()> {
                 .await
                 .with_context(|| "Could not connect to darkfid RPC endpoint")?;
 
-            let drk = Drk { rpc_client };
+            let drk = Drk { rpc_client, params_cache };
 
             drk.subscribe_blocks(args.endpoint)
                 .await
@@ -693,7 +726,7 @@ async fn main() -> Result<()> {
                 .await
                 .with_context(|| "Could not connect to darkfid RPC endpoint")?;
 
-            let drk = Drk { rpc_client };
+            let drk = Drk { rpc_client, params_cache };
 
             if reset {
                 eprintln!("Reset requested.");
@@ -781,7 +814,7 @@ async fn main() -> Result<()> {
                     .await
                     .with_context(|| "Could not connect to darkfid RPC endpoint")?;
 
-                let drk = Drk { rpc_client };
+                let drk = Drk { rpc_client, params_cache };
 
                 drk.import_dao(dao_name, dao_params)
                     .await
@@ -795,7 +828,7 @@ async fn main() -> Result<()> {
                     .await
                     .with_context(|| "Could not connect to darkfid RPC endpoint")?;
 
-                let drk = Drk { rpc_client };
+                let drk = Drk { rpc_client, params_cache };
 
                 drk.dao_list(dao_id).await.with_context(|| "Failed to list DAO")?;
 
@@ -807,7 +840,7 @@ async fn main() -> Result<()> {
                     .await
                     .with_context(|| "Could not connect to darkfid RPC endpoint")?;
 
-                let drk = Drk { rpc_client };
+                let drk = Drk { rpc_client, params_cache };
 
                 let tx = drk.dao_mint(dao_id).await.with_context(|| "Failed to mint DAO")?;
                 println!("{}", bs58::encode(&serialize(&tx)).into_string());
@@ -826,7 +859,7 @@ async fn main() -> Result<()> {
                     .await
                     .with_context(|| "Could not connect to darkfid RPC endpoint")?;
 
-                let drk = Drk { rpc_client };
+                let drk = Drk { rpc_client, params_cache };
 
                 let tx = drk
                     .dao_propose(dao_id, rcpt, amount, token_id)
@@ -842,7 +875,7 @@ async fn main() -> Result<()> {
                     .await
                     .with_context(|| "Could not connect to darkfid RPC endpoint")?;
 
-                let drk = Drk { rpc_client };
+                let drk = Drk { rpc_client, params_cache };
 
                 let proposals = drk.get_dao_proposals(dao_id).await?;
                 for proposal in proposals {
@@ -857,7 +890,7 @@ async fn main() -> Result<()> {
                     .await
                     .with_context(|| "Could not connect to darkfid RPC endpoint")?;
 
-                let drk = Drk { rpc_client };
+                let drk = Drk { rpc_client, params_cache };
 
                 let proposals = drk.get_dao_proposals(dao_id).await?;
                 let Some(proposal) = proposals.iter().find(|x| x.id == proposal_id) else {
@@ -889,7 +922,7 @@ async fn main() -> Result<()> {
                     .await
                     .with_context(|| "Could not connect to darkfid RPC endpoint")?;
 
-                let drk = Drk { rpc_client };
+                let drk = Drk { rpc_client, params_cache };
 
                 let _ = f64::from_str(&vote_weight).with_context(|| "Invalid vote weight")?;
                 let weight = decode_base10(&vote_weight, 8, true)?;
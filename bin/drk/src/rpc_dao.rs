@@ -18,8 +18,8 @@
 
 use anyhow::{anyhow, Result};
 use darkfi::{
-    tx::Transaction,
-    zk::{empty_witnesses, halo2::Field, ProvingKey, ZkCircuit},
+    tx::{Transaction, DEFAULT_GAS_LIMIT},
+    zk::halo2::Field,
     zkas::ZkBinary,
 };
 use darkfi_dao_contract::{
@@ -65,12 +65,10 @@ impl Drk {
             return Err(anyhow!("DAO Mint circuit not found"));
         };
 
-        let dao_mint_zkbin = ZkBinary::decode(&dao_mint_zkbin.1)?;
-        let k = 13;
-        let dao_mint_circuit =
-            ZkCircuit::new(empty_witnesses(&dao_mint_zkbin), dao_mint_zkbin.clone());
+        let dao_mint_bincode = &dao_mint_zkbin.1;
+        let dao_mint_zkbin = ZkBinary::decode(dao_mint_bincode)?;
         eprintln!("Creating DAO Mint proving key");
-        let dao_mint_pk = ProvingKey::build(k, &dao_mint_circuit);
+        let dao_mint_pk = self.params_cache.proving_key(dao_mint_bincode)?;
 
         let (params, proofs) =
             dao_client::make_mint_call(&dao_info, &dao.secret_key, &dao_mint_zkbin, &dao_mint_pk)?;
@@ -79,7 +77,8 @@ impl Drk {
         params.encode(&mut data)?;
         let calls = vec![ContractCall { contract_id: *DAO_CONTRACT_ID, data }];
         let proofs = vec![proofs];
-        let mut tx = Transaction { calls, proofs, signatures: vec![] };
+        let mut tx =
+            Transaction { calls, gas_limit: DEFAULT_GAS_LIMIT, proofs, signatures: vec![] };
         let sigs = tx.create_sigs(&mut OsRng, &[dao.secret_key])?;
         tx.signatures = vec![sigs];
 
@@ -152,19 +151,15 @@ impl Drk {
             return Err(anyhow!("Propose Main circuit not found"))
         };
 
-        let propose_burn_zkbin = ZkBinary::decode(&propose_burn_zkbin.1)?;
-        let propose_main_zkbin = ZkBinary::decode(&propose_main_zkbin.1)?;
-
-        let k = 13;
-        let propose_burn_circuit =
-            ZkCircuit::new(empty_witnesses(&propose_burn_zkbin), propose_burn_zkbin.clone());
-        let propose_main_circuit =
-            ZkCircuit::new(empty_witnesses(&propose_main_zkbin), propose_main_zkbin.clone());
+        let propose_burn_bincode = &propose_burn_zkbin.1;
+        let propose_burn_zkbin = ZkBinary::decode(propose_burn_bincode)?;
+        let propose_main_bincode = &propose_main_zkbin.1;
+        let propose_main_zkbin = ZkBinary::decode(propose_main_bincode)?;
 
         eprintln!("Creating Propose Burn circuit proving key");
-        let propose_burn_pk = ProvingKey::build(k, &propose_burn_circuit);
+        let propose_burn_pk = self.params_cache.proving_key(propose_burn_bincode)?;
         eprintln!("Creating Propose Main circuit proving key");
-        let propose_main_pk = ProvingKey::build(k, &propose_main_circuit);
+        let propose_main_pk = self.params_cache.proving_key(propose_main_bincode)?;
 
         // Now create the parameters for the proposal tx
         let signature_secret = SecretKey::random(&mut OsRng);
@@ -233,7 +228,8 @@ impl Drk {
         params.encode(&mut data)?;
         let calls = vec![ContractCall { contract_id: *DAO_CONTRACT_ID, data }];
         let proofs = vec![proofs];
-        let mut tx = Transaction { calls, proofs, signatures: vec![] };
+        let mut tx =
+            Transaction { calls, gas_limit: DEFAULT_GAS_LIMIT, proofs, signatures: vec![] };
         let sigs = tx.create_sigs(&mut OsRng, &vec![signature_secret])?;
         tx.signatures = vec![sigs];
 
@@ -341,19 +337,15 @@ impl Drk {
             return Err(anyhow!("DAO Vote Main circuit not found"))
         };
 
-        let dao_vote_burn_zkbin = ZkBinary::decode(&dao_vote_burn_zkbin.1)?;
-        let dao_vote_main_zkbin = ZkBinary::decode(&dao_vote_main_zkbin.1)?;
-
-        let k = 13;
-        let dao_vote_burn_circuit =
-            ZkCircuit::new(empty_witnesses(&dao_vote_burn_zkbin), dao_vote_burn_zkbin.clone());
-        let dao_vote_main_circuit =
-            ZkCircuit::new(empty_witnesses(&dao_vote_main_zkbin), dao_vote_main_zkbin.clone());
+        let dao_vote_burn_bincode = &dao_vote_burn_zkbin.1;
+        let dao_vote_burn_zkbin = ZkBinary::decode(dao_vote_burn_bincode)?;
+        let dao_vote_main_bincode = &dao_vote_main_zkbin.1;
+        let dao_vote_main_zkbin = ZkBinary::decode(dao_vote_main_bincode)?;
 
         eprintln!("Creating DAO Vote Burn proving key");
-        let dao_vote_burn_pk = ProvingKey::build(k, &dao_vote_burn_circuit);
+        let dao_vote_burn_pk = self.params_cache.proving_key(dao_vote_burn_bincode)?;
         eprintln!("Creating DAO Vote Main proving key");
-        let dao_vote_main_pk = ProvingKey::build(k, &dao_vote_main_circuit);
+        let dao_vote_main_pk = self.params_cache.proving_key(dao_vote_main_bincode)?;
 
         let (params, proofs) = call.make(
             &dao_vote_burn_zkbin,
@@ -366,7 +358,8 @@ impl Drk {
         params.encode(&mut data)?;
         let calls = vec![ContractCall { contract_id: *DAO_CONTRACT_ID, data }];
         let proofs = vec![proofs];
-        let mut tx = Transaction { calls, proofs, signatures: vec![] };
+        let mut tx =
+            Transaction { calls, gas_limit: DEFAULT_GAS_LIMIT, proofs, signatures: vec![] };
         let sigs = tx.create_sigs(&mut OsRng, &input_secrets)?;
         tx.signatures = vec![sigs];
 
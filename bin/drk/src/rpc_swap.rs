@@ -18,9 +18,9 @@
 
 use anyhow::{anyhow, Result};
 use darkfi::{
-    tx::Transaction,
+    tx::{Transaction, DEFAULT_GAS_LIMIT},
     util::parse::encode_base10,
-    zk::{proof::ProvingKey, vm::ZkCircuit, vm_stack::empty_witnesses, Proof},
+    zk::Proof,
     zkas::ZkBinary,
 };
 use darkfi_money_contract::{
@@ -104,17 +104,15 @@ impl Drk {
             return Err(anyhow!("Burn circuit not found"))
         };
 
-        let mint_zkbin = ZkBinary::decode(&mint_zkbin.1)?;
-        let burn_zkbin = ZkBinary::decode(&burn_zkbin.1)?;
-
-        let k = 13;
-        let mint_circuit = ZkCircuit::new(empty_witnesses(&mint_zkbin), mint_zkbin.clone());
-        let burn_circuit = ZkCircuit::new(empty_witnesses(&burn_zkbin), burn_zkbin.clone());
+        let mint_bincode = &mint_zkbin.1;
+        let mint_zkbin = ZkBinary::decode(mint_bincode)?;
+        let burn_bincode = &burn_zkbin.1;
+        let burn_zkbin = ZkBinary::decode(burn_bincode)?;
 
         eprintln!("Creating Mint circuit proving key");
-        let mint_pk = ProvingKey::build(k, &mint_circuit);
+        let mint_pk = self.params_cache.proving_key(mint_bincode)?;
         eprintln!("Creating Burn circuit proving key");
-        let burn_pk = ProvingKey::build(k, &burn_circuit);
+        let burn_pk = self.params_cache.proving_key(burn_bincode)?;
 
         // Now we should have everything we need to build the swap half
         eprintln!("Building first half of the swap transaction");
@@ -190,17 +188,15 @@ impl Drk {
             return Err(anyhow!("Burn circuit not found"))
         };
 
-        let mint_zkbin = ZkBinary::decode(&mint_zkbin.1)?;
-        let burn_zkbin = ZkBinary::decode(&burn_zkbin.1)?;
-
-        let k = 13;
-        let mint_circuit = ZkCircuit::new(empty_witnesses(&mint_zkbin), mint_zkbin.clone());
-        let burn_circuit = ZkCircuit::new(empty_witnesses(&burn_zkbin), burn_zkbin.clone());
+        let mint_bincode = &mint_zkbin.1;
+        let mint_zkbin = ZkBinary::decode(mint_bincode)?;
+        let burn_bincode = &burn_zkbin.1;
+        let burn_zkbin = ZkBinary::decode(burn_bincode)?;
 
         eprintln!("Creating Mint circuit proving key");
-        let mint_pk = ProvingKey::build(k, &mint_circuit);
+        let mint_pk = self.params_cache.proving_key(mint_bincode)?;
         eprintln!("Creating Burn circuit proving key");
-        let burn_pk = ProvingKey::build(k, &burn_circuit);
+        let burn_pk = self.params_cache.proving_key(burn_bincode)?;
 
         // TODO: Maybe some kind of verification at this point
 
@@ -240,6 +236,7 @@ impl Drk {
         full_params.encode(&mut data)?;
         let mut tx = Transaction {
             calls: vec![ContractCall { contract_id, data }],
+            gas_limit: DEFAULT_GAS_LIMIT,
             proofs: vec![full_proofs],
             signatures: vec![],
         };
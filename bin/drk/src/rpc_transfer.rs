@@ -18,9 +18,9 @@
 
 use anyhow::{anyhow, Result};
 use darkfi::{
-    tx::Transaction,
+    tx::{Transaction, DEFAULT_GAS_LIMIT},
     util::parse::{decode_base10, encode_base10},
-    zk::{halo2::Field, proof::ProvingKey, vm::ZkCircuit, vm_stack::empty_witnesses},
+    zk::halo2::Field,
     zkas::ZkBinary,
 };
 use darkfi_dao_contract::dao_model::DaoBulla;
@@ -116,17 +116,15 @@ impl Drk {
             return Err(anyhow!("Burn circuit not found"))
         };
 
-        let mint_zkbin = ZkBinary::decode(&mint_zkbin.1)?;
-        let burn_zkbin = ZkBinary::decode(&burn_zkbin.1)?;
-
-        let k = 13;
-        let mint_circuit = ZkCircuit::new(empty_witnesses(&mint_zkbin), mint_zkbin.clone());
-        let burn_circuit = ZkCircuit::new(empty_witnesses(&burn_zkbin), burn_zkbin.clone());
+        let mint_bincode = &mint_zkbin.1;
+        let mint_zkbin = ZkBinary::decode(mint_bincode)?;
+        let burn_bincode = &burn_zkbin.1;
+        let burn_zkbin = ZkBinary::decode(burn_bincode)?;
 
         eprintln!("Creating Mint circuit proving key");
-        let mint_pk = ProvingKey::build(k, &mint_circuit);
+        let mint_pk = self.params_cache.proving_key(mint_bincode)?;
         eprintln!("Creating Burn circuit proving key");
-        let burn_pk = ProvingKey::build(k, &burn_circuit);
+        let burn_pk = self.params_cache.proving_key(burn_bincode)?;
 
         // Now we should have everything we need to build the transaction
         let (params, proofs, secrets, spent_coins) = build_transfer_tx(
@@ -151,7 +149,8 @@ impl Drk {
         params.encode(&mut data)?;
         let calls = vec![ContractCall { contract_id, data }];
         let proofs = vec![proofs];
-        let mut tx = Transaction { calls, proofs, signatures: vec![] };
+        let mut tx =
+            Transaction { calls, gas_limit: DEFAULT_GAS_LIMIT, proofs, signatures: vec![] };
         let sigs = tx.create_sigs(&mut OsRng, &secrets)?;
         tx.signatures = vec![sigs];
 
@@ -22,8 +22,8 @@ use async_std::sync::{Arc, Mutex, RwLock};
 use async_trait::async_trait;
 use chrono::Utc;
 use darkfi::{
-    tx::Transaction,
-    zk::{halo2::Field, proof::ProvingKey, vm::ZkCircuit, vm_stack::empty_witnesses},
+    tx::{Transaction, DEFAULT_GAS_LIMIT},
+    zk::{halo2::Field, proof::ProvingKey, ParamsCache},
     zkas::ZkBinary,
 };
 use darkfi_money_contract::{
@@ -107,6 +107,10 @@ struct Args {
     /// Path to blockchain database
     database: String,
 
+    #[structopt(long, default_value = "~/.config/darkfi/faucetd_zkcache")]
+    /// Path to the ZK params cache
+    zk_cache: String,
+
     #[structopt(long, default_value = "tcp://127.0.0.1:9340")]
     /// JSON-RPC listen URL
     rpc_listen: Url,
@@ -228,17 +232,15 @@ impl Faucetd {
             return Err(Error::ZkasBincodeNotFound);
         };
 
-        let mint_zkbin = ZkBinary::decode(&mint_zkbin)?;
-        let burn_zkbin = ZkBinary::decode(&burn_zkbin)?;
-
-        let k = 13;
-        let mint_circuit = ZkCircuit::new(empty_witnesses(&mint_zkbin), mint_zkbin.clone());
-        let burn_circuit = ZkCircuit::new(empty_witnesses(&burn_zkbin), burn_zkbin.clone());
+        let params_cache = { validator_state.read().await.params_cache.clone() };
 
         info!("Creating mint circuit proving key");
-        let mint_provingkey = ProvingKey::build(k, &mint_circuit);
+        let mint_provingkey = params_cache.proving_key(&mint_zkbin)?;
         info!("Creating burn circuit proving key");
-        let burn_provingkey = ProvingKey::build(k, &burn_circuit);
+        let burn_provingkey = params_cache.proving_key(&burn_zkbin)?;
+
+        let mint_zkbin = ZkBinary::decode(&mint_zkbin)?;
+        let burn_zkbin = ZkBinary::decode(&burn_zkbin)?;
 
         {
             let provingkeys = vec![
@@ -459,7 +461,8 @@ impl Faucetd {
         params.encode(&mut data).unwrap();
         let calls = vec![ContractCall { contract_id: cid, data }];
         let proofs = vec![proofs];
-        let mut tx = Transaction { calls, proofs, signatures: vec![] };
+        let mut tx =
+            Transaction { calls, gas_limit: DEFAULT_GAS_LIMIT, proofs, signatures: vec![] };
         let sigs = tx.create_sigs(&mut OsRng, &secret_keys).unwrap();
         tx.signatures = vec![sigs];
 
@@ -531,6 +534,9 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'_>>) -> Result<()> {
     let db_path = format!("{}/{}", expand_path(&args.database)?.to_str().unwrap(), args.chain);
     let sled_db = sled::open(&db_path)?;
 
+    // Open the ZK params cache
+    let params_cache = ParamsCache::new(&expand_path(&args.zk_cache)?)?;
+
     // Initialize validator state
     let (bootstrap_ts, genesis_ts, genesis_data, initial_distribution) = match args.chain.as_str() {
         "mainnet" => (
@@ -574,6 +580,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'_>>) -> Result<()> {
         wallet.clone(),
         faucet_pubkeys,
         false,
+        params_cache,
     )
     .await?;
 
@@ -46,6 +46,9 @@ impl RequestHandler for JsonRpcInterface {
         match req.method.as_str() {
             Some("ping") => self.pong(req.id, req.params).await,
             Some("get_info") => self.get_info(req.id, req.params).await,
+            Some("get_bans") => self.get_bans(req.id, req.params).await,
+            Some("ban") => self.ban(req.id, req.params).await,
+            Some("unban") => self.unban(req.id, req.params).await,
             Some(_) | None => JsonError::new(ErrorCode::MethodNotFound, None, req.id).into(),
         }
     }
@@ -68,4 +71,50 @@ impl JsonRpcInterface {
         let resp = self.p2p.get_info().await;
         JsonResponse::new(resp, id).into()
     }
+    // RPCAPI:
+    // Retrieves the currently banned hosts, along with the UNIX timestamp
+    // their ban expires at.
+    // --> {"jsonrpc": "2.0", "method": "get_bans", "params": [], "id": 42}
+    // <-- {"jsonrpc": "2.0", "result": [{"host": "1.2.3.4", "until": 1680000000}], "id": 42}
+    async fn get_bans(&self, id: Value, _params: Value) -> JsonResult {
+        let resp = self.p2p.get_bans().await;
+        JsonResponse::new(resp, id).into()
+    }
+
+    // RPCAPI:
+    // Bans a host, disconnecting any channels to it. The ban duration in
+    // seconds is optional and defaults to the configured one.
+    // --> {"jsonrpc": "2.0", "method": "ban", "params": ["1.2.3.4", 3600], "id": 42}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 42}
+    async fn ban(&self, id: Value, params: Value) -> JsonResult {
+        let params = params.as_array().unwrap();
+        if params.is_empty() || params.len() > 2 || !params[0].is_string() {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        }
+
+        let duration = match params.get(1) {
+            Some(d) => match d.as_u64() {
+                Some(d) => d,
+                None => return JsonError::new(ErrorCode::InvalidParams, None, id).into(),
+            },
+            None => self.p2p.settings().ban_duration_seconds,
+        };
+
+        self.p2p.ban(params[0].as_str().unwrap(), duration).await;
+        JsonResponse::new(json!(true), id).into()
+    }
+
+    // RPCAPI:
+    // Lifts the ban of a host. Returns false if the host wasn't banned.
+    // --> {"jsonrpc": "2.0", "method": "unban", "params": ["1.2.3.4"], "id": 42}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 42}
+    async fn unban(&self, id: Value, params: Value) -> JsonResult {
+        let params = params.as_array().unwrap();
+        if params.len() != 1 || !params[0].is_string() {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        }
+
+        let unbanned = self.p2p.bans().unban(params[0].as_str().unwrap()).await;
+        JsonResponse::new(json!(unbanned), id).into()
+    }
 }
@@ -212,7 +212,7 @@ async fn spawn_network(
             for url in hosts {
                 vec.push(url.clone());
             }
-            p2p.hosts().store(vec).await;
+            p2p.hosts().store(None, vec).await;
         }
         None => info!("No saved hosts found for {}", name),
     }
@@ -26,6 +26,7 @@ use clap::Parser as ClapParser;
 
 use darkfi::{
     cli_desc,
+    zk::minimum_k,
     zkas::{Analyzer, Compiler, Lexer, Parser, ZkBinary},
 };
 
@@ -112,9 +113,21 @@ fn main() {
         !args.strip,
     );
 
-    let bincode = compiler.compile();
+    let mut bincode = compiler.compile();
     // ANCHOR_END: zkas
 
+    // Lay out the circuit to find the smallest `k` it fits in, and
+    // record it in the binary so the users of the circuit don't have
+    // to guess it.
+    let k = match ZkBinary::decode(&bincode).and_then(|zkbin| minimum_k(&zkbin)) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Error: Failed computing the circuit's k. {}", e);
+            exit(1);
+        }
+    };
+    ZkBinary::set_k(&mut bincode, k).unwrap();
+
     let output = match args.output {
         Some(o) => o,
         None => format!("{}.bin", args.input),
@@ -133,7 +146,7 @@ fn main() {
         exit(1);
     };
 
-    println!("Wrote output to {}", &output);
+    println!("Wrote output to {} (k={})", &output, k);
 
     if args.examine {
         let zkbin = ZkBinary::decode(&bincode).unwrap();
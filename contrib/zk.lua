@@ -20,7 +20,7 @@ local number = token(l.NUMBER, l.integer)
 
 -- Keywords.
 local keyword = token(l.KEYWORD, word_match{
-  'constant', 'contract', 'circuit',
+  'constant', 'contract', 'circuit', 'for', 'in',
 })
 
 -- Constants.
@@ -43,6 +43,7 @@ local instruction = token('instruction', word_match{
   'base_add', 'base_mul', 'base_sub',
   'poseidon_hash', 'merkle_root',
   'range_check', 'less_than_strict', 'less_than_loose', 'bool_check',
+  'cond_select', 'zero_cond', 'and', 'or', 'not',
   'witness_base',
   'constrain_equal_base', 'constrain_equal_point',
   'constrain_instance',
@@ -11,6 +11,7 @@ syn keyword zkasKeyword
     \ constant
     \ contract
     \ circuit
+    \ for in
 
 syn keyword zkasType 
     \ EcPoint EcFixedPoint EcFixedPointBase EcFixedPointShort EcNiPoint
@@ -23,6 +24,7 @@ syn keyword zkasInstruction
     \ base_add base_mul base_sub
     \ poseidon_hash merkle_root
     \ range_check less_than_strict less_than_loose  bool_check
+    \ cond_select zero_cond and or not
     \ witness_base
     \ constrain_equal_base constrain_equal_point
     \ constrain_instance
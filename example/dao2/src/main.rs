@@ -17,7 +17,7 @@
  */
 
 use darkfi::{
-    blockchain::Blockchain,
+    blockchain::{Blockchain, DbOverlay},
     consensus::{TESTNET_GENESIS_HASH_BYTES, TESTNET_GENESIS_TIMESTAMP},
     crypto::{
         coin::Coin,
@@ -172,6 +172,7 @@ fn validate(
 
     // Now we finished verification stage, just apply all changes
     assert_eq!(tx.calls.len(), updates.len());
+    let overlay = DbOverlay::default();
     for (call, update) in tx.calls.iter().zip(updates.iter()) {
         // Lookup the wasm bytes
         let (_, contract_name, wasm_bytes) =
@@ -179,9 +180,11 @@ fn validate(
         debug!(target: "demo", "{}::apply() contract called", contract_name);
 
         let mut runtime = Runtime::new(wasm_bytes, blockchain.clone(), call.contract_id)?;
+        runtime.set_db_overlay(overlay.clone());
 
         runtime.apply(&update)?;
     }
+    overlay.commit()?;
 
     Ok(())
 }
@@ -309,7 +312,9 @@ async fn main() -> BoxResult<()> {
         // Deploy function to initialize the smart contract state.
         // Here we pass an empty payload, but it's possible to feed in arbitrary data.
         dao_runtime.deploy(&[])?;
+        dao_runtime.db_overlay().commit()?;
         money_runtime.deploy(&[])?;
+        money_runtime.db_overlay().commit()?;
         debug!(target: "demo", "Deployed DAO and money contracts");
     }
 
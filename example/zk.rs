@@ -22,6 +22,7 @@ use darkfi::{
     zk::{
         proof::{Proof, ProvingKey, VerifyingKey},
         vm::{Witness, ZkCircuit},
+        vm_rows::circuit_k,
         vm_stack::empty_witnesses,
     },
     zkas::decoder::ZkBinary,
@@ -46,8 +47,8 @@ fn main() -> Result<()> {
     // Prover
     // ======
     // Bigger k = more rows, but slower circuit
-    // Number of rows is 2^k
-    let k = 13;
+    // Number of rows is 2^k, and it's taken from the bincode
+    let k = circuit_k(&zkbin)?;
 
     // Witness values
     let value = 42;
@@ -34,6 +34,7 @@ use darkfi::{
     tx::Transaction,
     util::{path::expand_path, time::Timestamp},
     wallet::walletdb::init_wallet,
+    zk::ParamsCache,
     Result,
 };
 use darkfi_sdk::crypto::MerkleNode;
@@ -328,6 +329,7 @@ async fn generate(localnet: &str, name: &str) -> Result<()> {
         wallet,
         vec![],
         false,
+        ParamsCache::in_memory(),
     )
     .await?;
     let info = StateInfo::new(&*state.read().await);
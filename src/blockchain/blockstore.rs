@@ -18,6 +18,7 @@
 
 use darkfi_serial::{deserialize, serialize};
 
+use super::{DbBatch, DbOverlay};
 use crate::{
     consensus::{Block, Header},
     util::time::Timestamp,
@@ -68,6 +69,36 @@ impl HeaderStore {
         Ok(ret)
     }
 
+    /// Stage a slice of [`Header`] in the given [`DbOverlay`], to be written
+    /// along with the rest of its updates once the overlay is committed.
+    /// On success, the function returns the header hashes in the same order.
+    pub fn stage(&self, overlay: &DbOverlay, headers: &[Header]) -> Vec<blake3::Hash> {
+        let mut ret = Vec::with_capacity(headers.len());
+        let mut batch = DbBatch::default();
+
+        for header in headers {
+            let serialized = serialize(header);
+            let headerhash = blake3::hash(&serialized);
+            batch.insert(headerhash.as_bytes(), &serialized);
+            ret.push(headerhash);
+        }
+
+        overlay.stage(&self.0, batch);
+        ret
+    }
+
+    /// Remove a slice of headerhashes from the headerstore.
+    pub fn remove(&self, headerhashes: &[blake3::Hash]) -> Result<()> {
+        let mut batch = sled::Batch::default();
+
+        for hash in headerhashes {
+            batch.remove(hash.as_bytes());
+        }
+
+        self.0.apply_batch(batch)?;
+        Ok(())
+    }
+
     /// Check if the headerstore contains a given headerhash.
     pub fn contains(&self, headerhash: &blake3::Hash) -> Result<bool> {
         Ok(self.0.contains_key(headerhash.as_bytes())?)
@@ -153,6 +184,36 @@ impl BlockStore {
         Ok(ret)
     }
 
+    /// Stage a slice of [`Block`] in the given [`DbOverlay`], to be written
+    /// along with the rest of its updates once the overlay is committed.
+    /// On success, the function returns the block hashes in the same order.
+    pub fn stage(&self, overlay: &DbOverlay, blocks: &[Block]) -> Vec<blake3::Hash> {
+        let mut ret = Vec::with_capacity(blocks.len());
+        let mut batch = DbBatch::default();
+
+        for block in blocks {
+            let serialized = serialize(block);
+            let blockhash = blake3::hash(&serialized);
+            batch.insert(blockhash.as_bytes(), &serialized);
+            ret.push(blockhash);
+        }
+
+        overlay.stage(&self.0, batch);
+        ret
+    }
+
+    /// Remove a slice of blockhashes from the blockstore.
+    pub fn remove(&self, blockhashes: &[blake3::Hash]) -> Result<()> {
+        let mut batch = sled::Batch::default();
+
+        for hash in blockhashes {
+            batch.remove(hash.as_bytes());
+        }
+
+        self.0.apply_batch(batch)?;
+        Ok(())
+    }
+
     /// Check if the blockstore contains a given blockhash.
     pub fn contains(&self, blockhash: &blake3::Hash) -> Result<bool> {
         Ok(self.0.contains_key(blockhash.as_bytes())?)
@@ -239,6 +300,32 @@ impl BlockOrderStore {
         Ok(())
     }
 
+    /// Stage a slice of slots and blockhashes in the given [`DbOverlay`], to
+    /// be written along with the rest of its updates once the overlay is
+    /// committed.
+    pub fn stage(&self, overlay: &DbOverlay, slots: &[u64], hashes: &[blake3::Hash]) {
+        assert_eq!(slots.len(), hashes.len());
+        let mut batch = DbBatch::default();
+
+        for (i, sl) in slots.iter().enumerate() {
+            batch.insert(&sl.to_be_bytes(), hashes[i].as_bytes());
+        }
+
+        overlay.stage(&self.0, batch);
+    }
+
+    /// Remove a slice of slots from the blockorderstore.
+    pub fn remove(&self, slots: &[u64]) -> Result<()> {
+        let mut batch = sled::Batch::default();
+
+        for slot in slots {
+            batch.remove(&slot.to_be_bytes());
+        }
+
+        self.0.apply_batch(batch)?;
+        Ok(())
+    }
+
     /// Check if the blockorderstore contains a given slot.
     pub fn contains(&self, slot: u64) -> Result<bool> {
         Ok(self.0.contains_key(slot.to_be_bytes())?)
@@ -286,6 +373,21 @@ impl BlockOrderStore {
         Ok(slots)
     }
 
+    /// Retrieve all slots after the given one from the blockorderstore,
+    /// in the form of a tuple (`slot`, `blockhash`).
+    pub fn get_all_after(&self, slot: u64) -> Result<Vec<(u64, blake3::Hash)>> {
+        let mut slots = vec![];
+
+        for found in self.0.range((slot + 1).to_be_bytes()..) {
+            let (key, value) = found?;
+            let slot_bytes: [u8; 8] = key.as_ref().try_into().unwrap();
+            let hash_bytes: [u8; 32] = value.as_ref().try_into().unwrap();
+            slots.push((u64::from_be_bytes(slot_bytes), blake3::Hash::from(hash_bytes)));
+        }
+
+        Ok(slots)
+    }
+
     /// Fetch n hashes after given slot. In the iteration, if a slot is not
     /// found, the iteration stops and the function returns what it has found
     /// so far in the `BlockOrderStore`.
@@ -20,6 +20,7 @@ use darkfi_sdk::crypto::ContractId;
 use darkfi_serial::{deserialize, serialize};
 use log::{debug, error};
 
+use super::{DbBatch, DbOverlay};
 use crate::{Error, Result};
 
 const SLED_CONTRACTS_TREE: &[u8] = b"_contracts";
@@ -52,6 +53,15 @@ impl WasmStore {
         Err(Error::WasmBincodeNotFound)
     }
 
+    /// Same as `get()`, but if bincode for the ContractId was staged in the
+    /// given [`DbOverlay`], that one is returned instead.
+    pub fn get_staged(&self, overlay: &DbOverlay, contract_id: ContractId) -> Result<Vec<u8>> {
+        match overlay.get(&self.0, &serialize(&contract_id)) {
+            Some(bincode) => bincode.ok_or(Error::WasmBincodeNotFound),
+            None => self.get(contract_id),
+        }
+    }
+
     /// Inserts or replaces the bincode for a given ContractId
     pub fn insert(&self, contract_id: ContractId, bincode: &[u8]) -> Result<()> {
         if let Err(e) = self.0.insert(&serialize(&contract_id), bincode) {
@@ -61,6 +71,14 @@ impl WasmStore {
 
         Ok(())
     }
+
+    /// Stage the bincode for a given ContractId in the given [`DbOverlay`],
+    /// to be inserted or replaced once the overlay is committed.
+    pub fn stage(&self, overlay: &DbOverlay, contract_id: ContractId, bincode: &[u8]) {
+        let mut batch = DbBatch::default();
+        batch.insert(&serialize(&contract_id), bincode);
+        overlay.stage(&self.0, batch);
+    }
 }
 
 /// The `ContractStateStore` is a `sled` tree that stores pointers to contracts'
@@ -77,7 +95,7 @@ impl ContractStateStore {
     ///   key: ContractId
     /// value: Vec<blake3(ContractId || tree_name)>
     /// ```
-    /// These values get mutated with `init()` and `remove()`.
+    /// These values get mutated with `init()`, `stage()` and `remove()`.
     pub fn new(db: &sled::Db) -> Result<Self> {
         let tree = db.open_tree(SLED_CONTRACTS_TREE)?;
         Ok(Self(tree))
@@ -135,6 +153,44 @@ impl ContractStateStore {
         Ok(tree)
     }
 
+    /// Same as `init()`, but the state is marked as initialized in the given
+    /// [`DbOverlay`], so it only reaches the main `ContractStateStore` tree
+    /// once the overlay is committed, along with the rest of the contract's
+    /// state updates. States initialized in the overlay are taken into account.
+    /// Note that the returned tree gets created right away, empty.
+    pub fn stage(
+        &self,
+        db: &sled::Db,
+        overlay: &DbOverlay,
+        contract_id: &ContractId,
+        tree_name: &str,
+    ) -> Result<sled::Tree> {
+        debug!(target: "blockchain::contractstore", "Staging state tree for {}:{}", contract_id, tree_name);
+
+        let contract_id_bytes = serialize(contract_id);
+        let ptr = contract_id.hash_state_id(tree_name);
+
+        let mut state_pointers =
+            self.state_pointers(Some(overlay), &contract_id_bytes)?.unwrap_or_default();
+
+        // If the db was never initialized, it should not be in here.
+        if state_pointers.contains(&ptr) {
+            return Err(Error::ContractAlreadyInitialized)
+        }
+
+        // Now we add it so it's marked as initialized once the overlay is
+        // committed. The tree is opened first, so nothing gets staged in
+        // case of not being able to open it.
+        state_pointers.push(ptr);
+        let tree = db.open_tree(ptr)?;
+
+        let mut batch = DbBatch::default();
+        batch.insert(&contract_id_bytes, &serialize(&state_pointers));
+        overlay.stage(&self.0, batch);
+
+        Ok(tree)
+    }
+
     /// Do a lookup of an existing contract state. In order to succeed, the
     /// state must have been previously initialized with `init()`. If the
     /// state has been found, a handle to it will be returned. Otherwise, we
@@ -144,6 +200,28 @@ impl ContractStateStore {
         db: &sled::Db,
         contract_id: &ContractId,
         tree_name: &str,
+    ) -> Result<sled::Tree> {
+        self.lookup_in(db, None, contract_id, tree_name)
+    }
+
+    /// Same as `lookup()`, but also finds the states initialized in the
+    /// given [`DbOverlay`] with `stage()`.
+    pub fn lookup_staged(
+        &self,
+        db: &sled::Db,
+        overlay: &DbOverlay,
+        contract_id: &ContractId,
+        tree_name: &str,
+    ) -> Result<sled::Tree> {
+        self.lookup_in(db, Some(overlay), contract_id, tree_name)
+    }
+
+    fn lookup_in(
+        &self,
+        db: &sled::Db,
+        overlay: Option<&DbOverlay>,
+        contract_id: &ContractId,
+        tree_name: &str,
     ) -> Result<sled::Tree> {
         debug!(target: "blockchain::contractstore", "Looking up state tree for {}:{}", contract_id, tree_name);
 
@@ -151,12 +229,9 @@ impl ContractStateStore {
         let ptr = contract_id.hash_state_id(tree_name);
 
         // A guard to make sure we went through init()
-        if !self.0.contains_key(&contract_id_bytes)? {
+        let Some(state_pointers) = self.state_pointers(overlay, &contract_id_bytes)? else {
             return Err(Error::ContractNotFound(contract_id.to_string()))
-        }
-
-        let state_pointers = self.0.get(&contract_id_bytes)?.unwrap();
-        let state_pointers: Vec<[u8; 32]> = deserialize(&state_pointers)?;
+        };
 
         // We assume the tree has been created already, so it should be listed
         // in this array. If not, that's an error.
@@ -203,4 +278,23 @@ impl ContractStateStore {
 
         Ok(())
     }
+
+    /// Fetch the state pointers of the given serialized ContractId, as they
+    /// are staged in the given [`DbOverlay`] if they were touched there.
+    /// Returns `None` if the contract has no states.
+    fn state_pointers(
+        &self,
+        overlay: Option<&DbOverlay>,
+        contract_id_bytes: &[u8],
+    ) -> Result<Option<Vec<[u8; 32]>>> {
+        let bytes = match overlay.and_then(|overlay| overlay.get(&self.0, contract_id_bytes)) {
+            Some(v) => v,
+            None => self.0.get(contract_id_bytes)?.map(|v| v.to_vec()),
+        };
+
+        match bytes {
+            Some(bytes) => Ok(Some(deserialize(&bytes)?)),
+            None => Ok(None),
+        }
+    }
 }
@@ -16,8 +16,10 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::collections::HashSet;
+
 use darkfi_serial::serialize;
-use log::debug;
+use log::{debug, info, warn};
 
 use crate::{
     consensus::{Block, BlockInfo, SlotCheckpoint},
@@ -38,11 +40,17 @@ pub mod rootstore;
 pub use rootstore::RootStore;
 
 pub mod txstore;
-pub use txstore::TxStore;
+pub use txstore::{TxLocation, TxLocationStore, TxStore};
 
 pub mod contractstore;
 pub use contractstore::{ContractStateStore, WasmStore};
 
+pub mod overlay;
+pub use overlay::{DbBatch, DbOverlay, UndoLog};
+
+pub mod undostore;
+pub use undostore::UndoStore;
+
 /// Structure holding all sled trees that define the concept of Blockchain.
 #[derive(Clone)]
 pub struct Blockchain {
@@ -58,6 +66,8 @@ pub struct Blockchain {
     pub slot_checkpoints: SlotCheckpointStore,
     /// Transactions sled tree
     pub transactions: TxStore,
+    /// Transaction locations sled tree
+    pub tx_locations: TxLocationStore,
     /// Nullifiers sled tree
     pub nullifiers: NullifierStore,
     /// Merkle roots sled tree
@@ -66,6 +76,8 @@ pub struct Blockchain {
     pub contracts: ContractStateStore,
     /// Wasm bincodes
     pub wasm_bincode: WasmStore,
+    /// Undo logs of the blocks
+    pub undo: UndoStore,
 }
 
 impl Blockchain {
@@ -76,51 +88,238 @@ impl Blockchain {
         let order = BlockOrderStore::new(db, genesis_ts, genesis_data)?;
         let slot_checkpoints = SlotCheckpointStore::new(db)?;
         let transactions = TxStore::new(db)?;
+        let tx_locations = TxLocationStore::new(db)?;
         let nullifiers = NullifierStore::new(db)?;
         let merkle_roots = RootStore::new(db)?;
         let contracts = ContractStateStore::new(db)?;
         let wasm_bincode = WasmStore::new(db)?;
+        let undo = UndoStore::new(db)?;
 
-        Ok(Self {
+        let blockchain = Self {
             sled_db: db.clone(),
             headers,
             blocks,
             order,
             slot_checkpoints,
             transactions,
+            tx_locations,
             nullifiers,
             merkle_roots,
             contracts,
             wasm_bincode,
-        })
+            undo,
+        };
+
+        // Repair the tip a crash could have left behind
+        let removed = blockchain.repair_tip()?;
+        if !removed.is_empty() {
+            info!(target: "blockchain", "Removed {} partially written block(s)", removed.len());
+        }
+
+        Ok(blockchain)
     }
 
     /// Insert a given slice of [`BlockInfo`] into the blockchain database.
     /// This functions wraps all the logic of separating the block into specific
     /// data that can be fed into the different trees of the database.
+    /// Each block is written in a single transaction, so either the whole block
+    /// ends up in the database, or none of it does.
     /// Upon success, the functions returns a vector of the block hashes that
     /// were given and appended to the ledger.
     pub fn add(&self, blocks: &[BlockInfo]) -> Result<Vec<blake3::Hash>> {
         let mut ret = Vec::with_capacity(blocks.len());
 
-        // TODO: Make db writes here completely atomic
         for block in blocks {
-            // Store transactions
-            self.transactions.insert(&block.txs)?;
+            let overlay = DbOverlay::default();
+            ret.extend(self.stage(&overlay, &[block.clone()]));
+            self.commit(&overlay, block.header.slot)?;
+        }
+
+        Ok(ret)
+    }
+
+    /// Write the updates staged in the given [`DbOverlay`] for the block in the
+    /// given slot, keeping track of what they overwrite so the block can be
+    /// reverted with [`Blockchain::revert_to`].
+    pub fn commit(&self, overlay: &DbOverlay, slot: u64) -> Result<()> {
+        self.undo.commit(overlay, slot)
+    }
+
+    /// Write the updates staged in the given revert [`DbOverlay`], along with the
+    /// ones staged for each of the given blocks, in a single transaction. The
+    /// block overlays are layered on top of the revert one, in order, so they're
+    /// written in that order, each one keeping track of what it overwrites so its
+    /// block can be reverted with [`Blockchain::revert_to`].
+    pub fn commit_layers(&self, revert: &DbOverlay, blocks: &[(DbOverlay, u64)]) -> Result<()> {
+        let mut layers = vec![(revert, None)];
+        layers.extend(blocks.iter().map(|(overlay, slot)| (overlay, Some(*slot))));
+        self.undo.commit_layers(&layers)
+    }
+
+    /// Revert the blockchain to the block in the given slot, removing all the
+    /// blocks after it, along with their transactions and all the state updates
+    /// that were committed with them. The whole revert is done atomically.
+    /// Upon success, the function returns the reverted blocks, in order.
+    pub fn revert_to(&self, slot: u64) -> Result<Vec<BlockInfo>> {
+        let overlay = DbOverlay::default();
+        let blocks = self.stage_revert_to(&overlay, slot)?;
+        overlay.commit()?;
+        Ok(blocks)
+    }
+
+    /// Same as [`Blockchain::revert_to`], but the revert is staged in the given
+    /// [`DbOverlay`], so new blocks can be verified and staged on top of it,
+    /// and everything written at once.
+    pub fn stage_revert_to(&self, overlay: &DbOverlay, slot: u64) -> Result<Vec<BlockInfo>> {
+        if !self.order.contains(slot)? {
+            return Err(Error::SlotNotFound(slot))
+        }
+
+        let reverted = self.order.get_all_after(slot)?;
+        if reverted.is_empty() {
+            return Ok(vec![])
+        }
+
+        let (slots, hashes): (Vec<u64>, Vec<blake3::Hash>) = reverted.into_iter().unzip();
+        let blocks = self.get_blocks_by_hash(&hashes)?;
+
+        info!(target: "blockchain", "Reverting {} block(s) after slot {}", slots.len(), slot);
+        self.undo.stage_revert(&self.sled_db, overlay, &slots)?;
+
+        Ok(blocks)
+    }
+
+    /// Retrieve the slot of the block with the given hash, if it's part of the
+    /// canonical chain.
+    pub fn canonical_slot(&self, blockhash: &blake3::Hash) -> Result<Option<u64>> {
+        let Some(block) = self.blocks.get(&[*blockhash], false)?[0].clone() else {
+            return Ok(None)
+        };
+
+        let Some(header) = self.headers.get(&[block.header], false)?[0].clone() else {
+            return Ok(None)
+        };
+
+        match self.order.get(&[header.slot], false)?[0] {
+            Some(v) if v == *blockhash => Ok(Some(header.slot)),
+            _ => Ok(None),
+        }
+    }
 
-            // Store header
-            self.headers.insert(&[block.header.clone()])?;
+    /// Stage a given slice of [`BlockInfo`] in the given [`DbOverlay`], so
+    /// the blocks are written together with the rest of its updates, like the
+    /// contracts' state transitions, when the overlay gets committed.
+    /// Returns a vector of the block hashes that were given.
+    pub fn stage(&self, overlay: &DbOverlay, blocks: &[BlockInfo]) -> Vec<blake3::Hash> {
+        let mut ret = Vec::with_capacity(blocks.len());
+
+        for block in blocks {
+            // Stage header
+            self.headers.stage(overlay, &[block.header.clone()]);
 
-            // Store block
+            // Stage block
             let blk: Block = Block::from(block.clone());
-            let blockhash = self.blocks.insert(&[blk])?;
+            let blockhash = self.blocks.stage(overlay, &[blk]);
             ret.push(blockhash[0]);
 
-            // Store block order
-            self.order.insert(&[block.header.slot], &[blockhash[0]])?;
+            // Stage transactions, along with their location in the block
+            let txids = self.transactions.stage(overlay, &block.txs);
+            let locations: Vec<TxLocation> = (0..txids.len())
+                .map(|position| TxLocation {
+                    slot: block.header.slot,
+                    blockhash: blockhash[0],
+                    position: position as u32,
+                })
+                .collect();
+            self.tx_locations.stage(overlay, &txids, &locations);
+
+            // Stage block order
+            self.order.stage(overlay, &[block.header.slot], &[blockhash[0]]);
+
+            // Stage the block's Merkle root
+            self.merkle_roots.stage(overlay, &[block.header.root]);
         }
 
-        Ok(ret)
+        ret
+    }
+
+    /// Remove the blocks at the tip of the block order that were only partially
+    /// written to the database. Blocks used to be written one tree after another,
+    /// so a crash could only leave the last ones incomplete. This is cheap enough
+    /// to run on every startup, unlike [`Blockchain::check_consistency`].
+    /// Returns the slots that were removed from the block order.
+    pub fn repair_tip(&self) -> Result<Vec<u64>> {
+        let mut removed = vec![];
+
+        loop {
+            let (slot, blockhash) = self.order.get_last()?;
+            if slot == 0 || self.is_block_complete(&blockhash)? {
+                break
+            }
+
+            warn!(target: "blockchain", "Removing partially written block {} at slot {}", blockhash, slot);
+            self.order.remove(&[slot])?;
+            removed.push(slot);
+        }
+
+        Ok(removed)
+    }
+
+    /// Look for blocks that were only partially written to the database,
+    /// and remove what was left of them. Blocks used to be written one tree
+    /// after another, so a crash in the middle could leave a slot pointing
+    /// to a missing block, or a block missing its header or transactions.
+    /// This goes through the whole chain, so it has to be asked for explicitly.
+    /// Returns the slots that were removed from the block order.
+    pub fn check_consistency(&self) -> Result<Vec<u64>> {
+        let mut removed = vec![];
+
+        let mut ordered = HashSet::new();
+        for (slot, blockhash) in self.order.get_all()? {
+            if self.is_block_complete(&blockhash)? {
+                ordered.insert(blockhash);
+                continue
+            }
+
+            warn!(target: "blockchain", "Removing partially written block {} at slot {}", blockhash, slot);
+            self.order.remove(&[slot])?;
+            removed.push(slot);
+        }
+
+        // Blocks that aren't part of the block order never made it to the
+        // ledger, so we remove them along with their headers.
+        for (blockhash, block) in self.blocks.get_all()? {
+            if ordered.contains(&blockhash) {
+                continue
+            }
+
+            warn!(target: "blockchain", "Removing orphan block {}", blockhash);
+            self.blocks.remove(&[blockhash])?;
+            self.headers.remove(&[block.header])?;
+        }
+
+        Ok(removed)
+    }
+
+    /// Check if the block with the given hash, its header and all of its
+    /// transactions are in the database.
+    fn is_block_complete(&self, blockhash: &blake3::Hash) -> Result<bool> {
+        let block = match &self.blocks.get(&[*blockhash], false)?[0] {
+            Some(v) => v.clone(),
+            None => return Ok(false),
+        };
+
+        if !self.headers.contains(&block.header)? {
+            return Ok(false)
+        }
+
+        for tx in &block.txs {
+            if !self.transactions.contains(tx)? {
+                return Ok(false)
+            }
+        }
+
+        Ok(true)
     }
 
     /// Check if the given [`BlockInfo`] is in the database and all trees.
@@ -130,10 +329,8 @@ impl Blockchain {
             Err(_) => return Ok(false),
         };
 
-        // TODO: Check if we have all transactions
-
-        // Check provided info produces the same hash
-        Ok(blockhash == block.blockhash())
+        // Check provided info produces the same hash, and we have all of it
+        Ok(blockhash == block.blockhash() && self.is_block_complete(&blockhash)?)
     }
 
     /// Retrieve [`BlockInfo`]s by given hashes. Fails if any of them are not found.
@@ -18,15 +18,16 @@
 
 use darkfi_sdk::crypto::Nullifier;
 use darkfi_serial::{deserialize, serialize};
+use log::debug;
 
+use super::{DbBatch, DbOverlay};
 use crate::Result;
 
 const SLED_NULLIFIER_TREE: &[u8] = b"_nullifiers";
 
 /// The `NullifierStore` is a `sled` tree storing all the nullifiers seen
 /// in existing blocks. The key is the nullifier itself, while the value
-/// is an empty vector that's not used. As a sidenote, perhaps we could
-/// hold the transaction hash where the nullifier was seen in the value.
+/// is the hash of the transaction that revealed it.
 #[derive(Clone)]
 pub struct NullifierStore(sled::Tree);
 
@@ -37,25 +38,50 @@ impl NullifierStore {
         Ok(Self(tree))
     }
 
-    /// Insert a slice of [`Nullifier`] into the store. With sled, the
-    /// operation is done as a batch. The nullifier is used as a key,
-    /// while the value is an empty vector.
-    pub fn insert(&self, nfs: &[Nullifier]) -> Result<()> {
+    /// Insert a slice of [`Nullifier`] revealed by the given transaction into
+    /// the store. With sled, the operation is done as a batch. The nullifier
+    /// is used as a key, while the value is the transaction hash.
+    pub fn insert(&self, nfs: &[Nullifier], txid: &blake3::Hash) -> Result<()> {
         let mut batch = sled::Batch::default();
 
         for nf in nfs {
-            batch.insert(serialize(nf), vec![] as Vec<u8>);
+            batch.insert(serialize(nf), txid.as_bytes());
         }
 
         self.0.apply_batch(batch)?;
         Ok(())
     }
 
+    /// Stage a slice of [`Nullifier`] revealed by the given transaction in
+    /// the given [`DbOverlay`], to be written along with the rest of its
+    /// updates once the overlay is committed.
+    pub fn stage(&self, overlay: &DbOverlay, nfs: &[Nullifier], txid: &blake3::Hash) {
+        let mut batch = DbBatch::default();
+
+        for nf in nfs {
+            batch.insert(&serialize(nf), txid.as_bytes());
+        }
+
+        overlay.stage(&self.0, batch);
+    }
+
     /// Check if the nullifierstore contains a given nullifier.
     pub fn contains(&self, nullifier: &Nullifier) -> Result<bool> {
         Ok(self.0.contains_key(serialize(nullifier))?)
     }
 
+    /// Fetch the hash of the transaction that revealed the given nullifier.
+    /// Returns `None` if the nullifier was not found, or if it was stored
+    /// before the transactions got recorded, in which case its value is empty.
+    pub fn spent_by(&self, nullifier: &Nullifier) -> Result<Option<blake3::Hash>> {
+        let Some(found) = self.0.get(serialize(nullifier))? else { return Ok(None) };
+        let Ok(hash_bytes) = <[u8; 32]>::try_from(found.as_ref()) else {
+            debug!(target: "blockchain::nfstore", "No transaction recorded for nullifier {:?}", nullifier);
+            return Ok(None)
+        };
+        Ok(Some(hash_bytes.into()))
+    }
+
     /// Retrieve all nullifiers from the store.
     /// Be careful as this will try to load everything in memory.
     pub fn get_all(&self) -> Result<Vec<Nullifier>> {
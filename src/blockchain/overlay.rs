@@ -0,0 +1,237 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::{btree_map, BTreeMap, HashMap},
+    ops::Bound,
+    sync::{Arc, Mutex},
+};
+
+use darkfi_serial::serialize;
+use sled::transaction::{TransactionError, TransactionResult, Transactional};
+
+use crate::Result;
+
+/// Writes made to a sled tree. Unlike a `sled::Batch`, they can be read
+/// back before they get written to the database.
+/// A `None` value marks a removed key.
+#[derive(Clone, Debug, Default)]
+pub struct DbBatch(BTreeMap<Vec<u8>, Option<Vec<u8>>>);
+
+impl DbBatch {
+    pub fn insert(&mut self, key: &[u8], value: &[u8]) {
+        self.0.insert(key.to_vec(), Some(value.to_vec()));
+    }
+
+    pub fn remove(&mut self, key: &[u8]) {
+        self.0.insert(key.to_vec(), None);
+    }
+
+    /// Apply the writes of the given batch on top of this one's.
+    pub fn extend(&mut self, other: DbBatch) {
+        self.0.extend(other.0)
+    }
+
+    /// Retrieve the writes made to keys within the given bounds.
+    pub fn range(&self, bounds: (Bound<Vec<u8>>, Bound<Vec<u8>>)) -> DbBatch {
+        DbBatch(self.0.range(bounds).map(|(k, v)| (k.clone(), v.clone())).collect())
+    }
+}
+
+impl IntoIterator for DbBatch {
+    type Item = (Vec<u8>, Option<Vec<u8>>);
+    type IntoIter = btree_map::IntoIter<Vec<u8>, Option<Vec<u8>>>;
+
+    /// Iterate over the writes in ascending key order.
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+/// Values a committed [`DbOverlay`] overwrote, in the form of
+/// (`tree name`, `key`, `previous value`). A `None` previous value
+/// means the key didn't exist before.
+pub type UndoLog = Vec<(Vec<u8>, Vec<u8>, Option<Vec<u8>>)>;
+
+/// State updates that were not written to the database yet, such as the
+/// ones of contract calls or of blocks being appended to the chain. The
+/// wasm runtimes sharing an overlay see the updates staged by each other,
+/// and [`DbOverlay::commit`] writes all of them at once, in a single sled
+/// transaction over all the trees involved.
+/// An overlay can be layered on top of another with [`DbOverlay::layer`],
+/// in which case it also sees the updates staged in the ones under it.
+#[derive(Clone, Default)]
+pub struct DbOverlay {
+    /// Writes staged in this overlay, per tree
+    staged: Arc<Mutex<HashMap<Vec<u8>, (sled::Tree, DbBatch)>>>,
+    /// Overlay this one is layered on top of
+    parent: Option<Box<DbOverlay>>,
+}
+
+impl DbOverlay {
+    /// Create an overlay on top of this one. It sees the updates staged in
+    /// this one, but keeps its own apart, so they can be written with their
+    /// own undo log by [`DbOverlay::commit_layers`].
+    pub fn layer(&self) -> Self {
+        Self { staged: Arc::new(Mutex::new(HashMap::new())), parent: Some(Box::new(self.clone())) }
+    }
+
+    /// Stage writes made to the given tree, on top of the ones already staged.
+    pub fn stage(&self, tree: &sled::Tree, batch: DbBatch) {
+        let mut trees = self.staged.lock().unwrap();
+        let (_, staged) =
+            trees.entry(tree.name().to_vec()).or_insert_with(|| (tree.clone(), DbBatch::default()));
+        staged.0.extend(batch.0);
+    }
+
+    /// Look up a key staged for the given tree. Returns `None` if the key was
+    /// not touched, and `Some(None)` if it was removed.
+    pub fn get(&self, tree: &sled::Tree, key: &[u8]) -> Option<Option<Vec<u8>>> {
+        let found = {
+            let trees = self.staged.lock().unwrap();
+            trees.get(&tree.name().to_vec()).and_then(|(_, batch)| batch.0.get(key).cloned())
+        };
+
+        match (found, &self.parent) {
+            (Some(v), _) => Some(v),
+            (None, Some(parent)) => parent.get(tree, key),
+            (None, None) => None,
+        }
+    }
+
+    /// Retrieve the writes staged for the given tree, made to keys within
+    /// the given bounds.
+    pub fn staged_range(
+        &self,
+        tree: &sled::Tree,
+        bounds: (Bound<Vec<u8>>, Bound<Vec<u8>>),
+    ) -> DbBatch {
+        let mut staged = match &self.parent {
+            Some(parent) => parent.staged_range(tree, bounds.clone()),
+            None => DbBatch::default(),
+        };
+
+        let trees = self.staged.lock().unwrap();
+        if let Some((_, batch)) = trees.get(&tree.name().to_vec()) {
+            staged.extend(batch.range(bounds));
+        }
+
+        staged
+    }
+
+    /// Retrieve the keys inserted in the given tree that are staged in this
+    /// overlay, leaving out the ones it is layered on top of.
+    pub fn inserted_keys(&self, tree: &sled::Tree) -> Vec<Vec<u8>> {
+        let trees = self.staged.lock().unwrap();
+        let Some((_, batch)) = trees.get(&tree.name().to_vec()) else { return vec![] };
+        batch.0.iter().filter(|(_, v)| v.is_some()).map(|(k, _)| k.clone()).collect()
+    }
+
+    /// Write all the updates staged in this overlay to the database in a single
+    /// transaction, so either all of them or none of them end up in the trees.
+    /// The ones of the overlays it is layered on top of are left out.
+    pub fn commit(&self) -> Result<()> {
+        Self::write(&[(self, None)], None)
+    }
+
+    /// Same as [`DbOverlay::commit`], but the values the updates overwrite are
+    /// recorded as an [`UndoLog`] under `undo_key` in the `undo` tree, within
+    /// the same transaction, so the updates can be reverted later on.
+    pub(crate) fn commit_with_undo(&self, undo: &sled::Tree, undo_key: &[u8]) -> Result<()> {
+        Self::write(&[(self, Some(undo_key))], Some(undo))
+    }
+
+    /// Write the updates staged in the given overlays, in order, in a single
+    /// transaction. The values overwritten by the ones given with an undo key
+    /// are recorded as an [`UndoLog`] under it in the `undo` tree.
+    pub(crate) fn commit_layers(
+        layers: &[(&DbOverlay, Option<&[u8]>)],
+        undo: &sled::Tree,
+    ) -> Result<()> {
+        Self::write(layers, Some(undo))
+    }
+
+    fn write(layers: &[(&DbOverlay, Option<&[u8]>)], undo: Option<&sled::Tree>) -> Result<()> {
+        // Trees touched by the layers, along with their index in `handles`
+        let mut indexes = HashMap::new();
+        let mut handles = vec![];
+        let mut batches = Vec::with_capacity(layers.len());
+
+        for (overlay, _) in layers {
+            let mut layer = vec![];
+            for (name, (tree, batch)) in overlay.staged.lock().unwrap().drain() {
+                let index = *indexes.entry(name).or_insert_with(|| {
+                    handles.push(tree);
+                    handles.len() - 1
+                });
+                layer.push((index, batch));
+            }
+            batches.push(layer);
+        }
+
+        let undo_index = undo.map(|undo_tree| {
+            *indexes.entry(undo_tree.name().to_vec()).or_insert_with(|| {
+                handles.push(undo_tree.clone());
+                handles.len() - 1
+            })
+        });
+
+        let res: TransactionResult<()> = handles.as_slice().transaction(|views| {
+            for (layer, (_, undo_key)) in batches.iter().zip(layers.iter()) {
+                let mut undo_log: UndoLog = vec![];
+
+                for (index, batch) in layer.iter() {
+                    for (key, value) in batch.0.iter() {
+                        let previous = match value {
+                            Some(value) => {
+                                views[*index].insert(key.as_slice(), value.as_slice())?
+                            }
+                            None => views[*index].remove(key.as_slice())?,
+                        };
+
+                        if undo_key.is_some() {
+                            undo_log.push((
+                                handles[*index].name().to_vec(),
+                                key.clone(),
+                                previous.map(|v| v.to_vec()),
+                            ));
+                        }
+                    }
+                }
+
+                if let (Some(undo_key), Some(undo_index)) = (undo_key, undo_index) {
+                    views[undo_index].insert(*undo_key, serialize(&undo_log))?;
+                }
+            }
+
+            Ok(())
+        });
+
+        match res {
+            Ok(()) => {}
+            Err(TransactionError::Storage(e)) => return Err(e.into()),
+            Err(TransactionError::Abort(())) => unreachable!(),
+        }
+
+        for tree in handles {
+            tree.flush()?;
+        }
+
+        Ok(())
+    }
+}
@@ -19,6 +19,7 @@
 use darkfi_sdk::crypto::MerkleNode;
 use darkfi_serial::{deserialize, serialize};
 
+use super::{DbBatch, DbOverlay};
 use crate::Result;
 
 const SLED_ROOTS_TREE: &[u8] = b"_merkleroots";
@@ -50,6 +51,19 @@ impl RootStore {
         Ok(())
     }
 
+    /// Stage a slice of [`MerkleNode`] in the given [`DbOverlay`], to be
+    /// written along with the rest of its updates once the overlay is
+    /// committed.
+    pub fn stage(&self, overlay: &DbOverlay, roots: &[MerkleNode]) {
+        let mut batch = DbBatch::default();
+
+        for root in roots {
+            batch.insert(&serialize(root), &[]);
+        }
+
+        overlay.stage(&self.0, batch);
+    }
+
     /// Check if the rootstore contains a given Merkle root.
     pub fn contains(&self, root: &MerkleNode) -> Result<bool> {
         Ok(self.0.contains_key(serialize(root))?)
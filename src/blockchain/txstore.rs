@@ -16,11 +16,13 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use darkfi_serial::{deserialize, serialize};
+use darkfi_serial::{deserialize, serialize, SerialDecodable, SerialEncodable};
 
+use super::{DbBatch, DbOverlay};
 use crate::{tx::Transaction, Error, Result};
 
 const SLED_TX_TREE: &[u8] = b"_transactions";
+const SLED_TX_LOCATION_TREE: &[u8] = b"_transaction_locations";
 
 /// The `TxStore` is a `sled` tree storing all the blockchain's
 /// transactions where the key is the transaction hash, and the value is
@@ -56,6 +58,25 @@ impl TxStore {
         Ok(ret)
     }
 
+    /// Stage a slice of [`Transaction`] in the given [`DbOverlay`], to be
+    /// written along with the rest of its updates once the overlay is
+    /// committed. On success, the function returns the transaction hashes
+    /// in the same order as the input transactions.
+    pub fn stage(&self, overlay: &DbOverlay, transactions: &[Transaction]) -> Vec<blake3::Hash> {
+        let mut ret = Vec::with_capacity(transactions.len());
+        let mut batch = DbBatch::default();
+
+        for tx in transactions {
+            let serialized = serialize(tx);
+            let txhash = blake3::hash(&serialized);
+            batch.insert(txhash.as_bytes(), &serialized);
+            ret.push(txhash);
+        }
+
+        overlay.stage(&self.0, batch);
+        ret
+    }
+
     /// Check if the txstore contains a given transaction hash.
     pub fn contains(&self, txid: &blake3::Hash) -> Result<bool> {
         Ok(self.0.contains_key(txid.as_bytes())?)
@@ -101,3 +122,80 @@ impl TxStore {
         Ok(txs)
     }
 }
+
+/// The location of a transaction in the blockchain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, SerialEncodable, SerialDecodable)]
+pub struct TxLocation {
+    /// Slot of the block holding the transaction
+    pub slot: u64,
+    /// Hash of the block holding the transaction
+    pub blockhash: blake3::Hash,
+    /// Index of the transaction in the block
+    pub position: u32,
+}
+
+/// The `TxLocationStore` is a `sled` tree indexing the blockchain's
+/// transactions, where the key is the transaction hash, and the value is
+/// the serialized [`TxLocation`] of the block the transaction landed in.
+#[derive(Clone)]
+pub struct TxLocationStore(sled::Tree);
+
+impl TxLocationStore {
+    /// Opens a new or existing `TxLocationStore` on the given sled database.
+    pub fn new(db: &sled::Db) -> Result<Self> {
+        let tree = db.open_tree(SLED_TX_LOCATION_TREE)?;
+        Ok(Self(tree))
+    }
+
+    /// Insert a slice of transaction hashes and their [`TxLocation`] into
+    /// the store. With sled, the operation is done as a batch.
+    pub fn insert(&self, txids: &[blake3::Hash], locations: &[TxLocation]) -> Result<()> {
+        assert_eq!(txids.len(), locations.len());
+        let mut batch = sled::Batch::default();
+
+        for (txid, location) in txids.iter().zip(locations.iter()) {
+            batch.insert(txid.as_bytes(), serialize(location));
+        }
+
+        self.0.apply_batch(batch)?;
+        Ok(())
+    }
+
+    /// Stage a slice of transaction hashes and their [`TxLocation`] in the
+    /// given [`DbOverlay`], to be written along with the rest of its updates
+    /// once the overlay is committed.
+    pub fn stage(&self, overlay: &DbOverlay, txids: &[blake3::Hash], locations: &[TxLocation]) {
+        assert_eq!(txids.len(), locations.len());
+        let mut batch = DbBatch::default();
+
+        for (txid, location) in txids.iter().zip(locations.iter()) {
+            batch.insert(txid.as_bytes(), &serialize(location));
+        }
+
+        overlay.stage(&self.0, batch);
+    }
+
+    /// Fetch the locations of given tx hashes from the store.
+    /// The resulting vector contains `Option`, which is `Some` if the tx
+    /// was found in the store, and otherwise it is `None`, if it has not.
+    /// The second parameter is a boolean which tells the function to fail in
+    /// case at least one tx was not found.
+    pub fn get(&self, txids: &[blake3::Hash], strict: bool) -> Result<Vec<Option<TxLocation>>> {
+        let mut ret = Vec::with_capacity(txids.len());
+
+        for txid in txids {
+            if let Some(found) = self.0.get(txid.as_bytes())? {
+                let location = deserialize(&found)?;
+                ret.push(Some(location));
+            } else {
+                if strict {
+                    let s = txid.to_hex().as_str().to_string();
+                    return Err(Error::TransactionNotFound(s))
+                }
+                ret.push(None);
+            }
+        }
+
+        Ok(ret)
+    }
+}
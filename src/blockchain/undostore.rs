@@ -0,0 +1,124 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashMap;
+
+use darkfi_serial::deserialize;
+
+use super::{overlay::UndoLog, DbBatch, DbOverlay};
+use crate::{Error, Result};
+
+const SLED_UNDO_TREE: &[u8] = b"_undo";
+
+/// The `UndoStore` is a `sled` tree storing what's needed to revert the
+/// blocks written to the database. The key is the block slot, while the
+/// value is the serialized [`UndoLog`] of the values the block's updates
+/// overwrote, across all trees, including the contracts' states.
+#[derive(Clone)]
+pub struct UndoStore(sled::Tree);
+
+impl UndoStore {
+    /// Opens a new or existing `UndoStore` on the given sled database.
+    pub fn new(db: &sled::Db) -> Result<Self> {
+        let tree = db.open_tree(SLED_UNDO_TREE)?;
+        Ok(Self(tree))
+    }
+
+    /// Write the updates staged in the given [`DbOverlay`] for the block in
+    /// the given slot, recording the values they overwrite in the same
+    /// transaction.
+    pub fn commit(&self, overlay: &DbOverlay, slot: u64) -> Result<()> {
+        overlay.commit_with_undo(&self.0, &slot.to_be_bytes())
+    }
+
+    /// Check if the undostore contains the undo log of a given slot.
+    pub fn contains(&self, slot: u64) -> Result<bool> {
+        Ok(self.0.contains_key(slot.to_be_bytes())?)
+    }
+
+    /// Write the updates staged in the given [`DbOverlay`]s, in order, in a
+    /// single transaction. The ones given with a slot are the updates of the
+    /// block in that slot, and get their undo log recorded along with them.
+    pub fn commit_layers(&self, layers: &[(&DbOverlay, Option<u64>)]) -> Result<()> {
+        let keys: Vec<Option<[u8; 8]>> =
+            layers.iter().map(|(_, slot)| slot.map(|s| s.to_be_bytes())).collect();
+        let layers: Vec<(&DbOverlay, Option<&[u8]>)> = layers
+            .iter()
+            .zip(keys.iter())
+            .map(|((overlay, _), key)| (*overlay, key.as_ref().map(|k| &k[..])))
+            .collect();
+
+        DbOverlay::commit_layers(&layers, &self.0)
+    }
+
+    /// Stage the revert of the updates of the blocks in the given slots in the
+    /// given [`DbOverlay`], by writing back the values recorded in their undo
+    /// logs, latest slot first. The undo logs get removed along with it.
+    /// Fails if the undo log of any of the slots is not found.
+    pub fn stage_revert(&self, db: &sled::Db, overlay: &DbOverlay, slots: &[u64]) -> Result<()> {
+        let mut slots = slots.to_vec();
+        slots.sort_unstable();
+
+        // Trees touched by the undo logs
+        let mut trees = HashMap::new();
+        let mut removed = DbBatch::default();
+
+        for slot in slots.iter().rev() {
+            let Some(found) = self.0.get(slot.to_be_bytes())? else {
+                return Err(Error::UndoLogNotFound(*slot))
+            };
+
+            let log: UndoLog = deserialize(&found)?;
+            let mut batches: HashMap<Vec<u8>, DbBatch> = HashMap::new();
+            for (tree_name, key, previous) in log {
+                if !trees.contains_key(&tree_name) {
+                    trees.insert(tree_name.clone(), db.open_tree(&tree_name)?);
+                }
+
+                let batch = batches.entry(tree_name).or_default();
+                match previous {
+                    Some(value) => batch.insert(&key, &value),
+                    None => batch.remove(&key),
+                }
+            }
+
+            // Older blocks are staged last, so the values they
+            // overwrote are the ones that end up written.
+            for (tree_name, batch) in batches {
+                overlay.stage(&trees[&tree_name], batch);
+            }
+
+            removed.remove(&slot.to_be_bytes());
+        }
+
+        overlay.stage(&self.0, removed);
+        Ok(())
+    }
+
+    /// Remove the undo logs of all but the latest `keep` blocks, as the
+    /// blocks before them can't be reverted anymore.
+    pub fn prune(&self, keep: usize) -> Result<()> {
+        let mut batch = sled::Batch::default();
+        for key in self.0.iter().keys().rev().skip(keep) {
+            batch.remove(key?);
+        }
+
+        self.0.apply_batch(batch)?;
+        Ok(())
+    }
+}
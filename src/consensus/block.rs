@@ -215,6 +215,15 @@ impl net::Message for BlockResponse {
     fn name() -> &'static str {
         "blockresponse"
     }
+
+    fn priority() -> net::MessagePriority {
+        net::MessagePriority::Low
+    }
+
+    fn max_payload_size() -> usize {
+        // Bulk sync data, only bounded by the global cap
+        usize::MAX
+    }
 }
 
 /// This struct represents a block proposal, used for consensus.
@@ -265,6 +274,10 @@ impl net::Message for BlockProposal {
     fn name() -> &'static str {
         "proposal"
     }
+
+    fn priority() -> net::MessagePriority {
+        net::MessagePriority::High
+    }
 }
 
 impl From<BlockProposal> for BlockInfo {
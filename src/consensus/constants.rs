@@ -105,8 +105,13 @@ pub const TXS_CAP: usize = 50;
 /// Block leader reward
 pub const REWARD: u64 = 1;
 
-/// Leader proofs k for zk proof rows (rows=2^k)
-pub const LEADER_PROOF_K: u32 = 13;
+/// Maximum number of finalized blocks a fork can revert. The undo logs
+/// of the blocks before them are pruned.
+pub const MAX_REORG_DEPTH: usize = 10;
+
+/// Maximum `k` of the circuits a contract deployment can bring, bounding
+/// the cost of creating their verifying keys.
+pub const MAX_DEPLOY_CIRCUIT_K: u32 = 16;
 
 // TODO: Describe these constants
 pub const RADIX_BITS: usize = 76;
@@ -29,12 +29,18 @@ use log::info;
 use rand::{thread_rng, Rng};
 
 use super::{
-    constants,
+    constants::{self, MAX_REORG_DEPTH},
     leadcoin::{LeadCoin, LeadCoinSecrets},
     utils::fbig2base,
-    Block, BlockProposal, Float10,
+    Block, BlockInfo, BlockProposal, Float10,
+};
+use crate::{
+    blockchain::{Blockchain, DbOverlay},
+    net,
+    tx::Transaction,
+    util::time::Timestamp,
+    Error, Result,
 };
-use crate::{blockchain::Blockchain, net, tx::Transaction, util::time::Timestamp, Error, Result};
 
 use std::{
     fs::File,
@@ -133,6 +139,12 @@ impl ConsensusState {
         self.genesis_ts.elapsed() / constants::SLOT_TIME
     }
 
+    /// Calculates the starting timestamp of the provided slot.
+    /// Slot duration is configured using the `SLOT_TIME` constant.
+    pub fn slot_timestamp(&self, slot: u64) -> Timestamp {
+        Timestamp(self.genesis_ts.0 + (slot * constants::SLOT_TIME) as i64)
+    }
+
     /// Calculates the relative number of the provided slot.
     pub fn relative_slot(&self, slot: u64) -> u64 {
         slot % constants::EPOCH_LENGTH as u64
@@ -532,14 +544,20 @@ impl ConsensusState {
             }
         }
 
-        // If no fork was found, we check with canonical
+        // If no fork was found, we check with canonical. The proposal can also
+        // extend an older canonical block, in which case the blocks after it
+        // get reverted if its fork gets finalized.
         if chain_index == -1 {
-            let (last_slot, last_block) = self.blockchain.last()?;
-            if proposal.block.header.previous != last_block ||
-                proposal.block.header.slot <= last_slot
-            {
+            let (last_slot, _) = self.blockchain.last()?;
+            let slot = self.blockchain.canonical_slot(&proposal.block.header.previous)?;
+            let Some(slot) = slot.filter(|_| proposal.block.header.slot > last_slot) else {
                 info!(target: "consensus::state", "find_extended_chain_index(): Proposal doesn't extend any known chain");
                 return Ok(-2)
+            };
+
+            if self.reorg_too_deep(slot)? {
+                info!(target: "consensus::state", "find_extended_chain_index(): Proposal reverts too many finalized blocks");
+                return Ok(-2)
             }
 
             // Proposal extends canonical chain
@@ -561,6 +579,35 @@ impl ConsensusState {
         Ok(self.forks.len() as i64 - 1)
     }
 
+    /// Stage in the given overlay the revert of the canonical blockchain to the
+    /// block the given fork extends, in case blocks were finalized after it.
+    /// Reverting more than [`MAX_REORG_DEPTH`] blocks is refused.
+    /// Returns the reverted blocks, in order.
+    pub fn stage_revert_to_fork(&self, fork: &Fork, overlay: &DbOverlay) -> Result<Vec<BlockInfo>> {
+        let previous = fork.sequence[0].proposal.block.header.previous;
+        let (_, last_block) = self.blockchain.last()?;
+        if previous == last_block {
+            return Ok(vec![])
+        }
+
+        let Some(slot) = self.blockchain.canonical_slot(&previous)? else {
+            return Err(Error::BlockNotFound(previous.to_hex().as_str().to_string()))
+        };
+
+        if self.reorg_too_deep(slot)? {
+            return Err(Error::ReorgTooDeep(MAX_REORG_DEPTH))
+        }
+
+        info!(target: "consensus::state", "stage_revert_to_fork(): Reverting canonical chain to slot {}", slot);
+        self.blockchain.stage_revert_to(overlay, slot)
+    }
+
+    /// Check if extending the canonical block in the given slot would revert
+    /// more than [`MAX_REORG_DEPTH`] blocks.
+    fn reorg_too_deep(&self, slot: u64) -> Result<bool> {
+        Ok(self.blockchain.order.get_after(slot, MAX_REORG_DEPTH as u64)?.len() > MAX_REORG_DEPTH)
+    }
+
     /// Search the chains we're holding for the given proposal.
     pub fn proposal_exists(&self, input_proposal: &blake3::Hash) -> bool {
         for chain in self.forks.iter() {
@@ -691,6 +738,15 @@ impl net::Message for ConsensusResponse {
     fn name() -> &'static str {
         "consensusresponse"
     }
+
+    fn priority() -> net::MessagePriority {
+        net::MessagePriority::Low
+    }
+
+    fn max_payload_size() -> usize {
+        // Bulk sync data, only bounded by the global cap
+        usize::MAX
+    }
 }
 
 /// Auxiliary structure used for consensus syncing.
@@ -716,6 +772,15 @@ impl net::Message for ConsensusSlotCheckpointsResponse {
     fn name() -> &'static str {
         "consensusslotcheckpointsresponse"
     }
+
+    fn priority() -> net::MessagePriority {
+        net::MessagePriority::Low
+    }
+
+    fn max_payload_size() -> usize {
+        // Bulk sync data, only bounded by the global cap
+        usize::MAX
+    }
 }
 
 /// Auxiliary structure used to keep track of slot validation parameters.
@@ -776,6 +841,15 @@ impl net::Message for SlotCheckpointResponse {
     fn name() -> &'static str {
         "slotcheckpointresponse"
     }
+
+    fn priority() -> net::MessagePriority {
+        net::MessagePriority::Low
+    }
+
+    fn max_payload_size() -> usize {
+        // Bulk sync data, only bounded by the global cap
+        usize::MAX
+    }
 }
 
 /// Auxiliary structure used to keep track of consensus state checkpoints.
@@ -16,19 +16,28 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::{collections::HashMap, io::Cursor};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::Cursor,
+    ops::Bound::Unbounded,
+    thread,
+};
 
 use async_std::sync::{Arc, RwLock};
 use darkfi_sdk::{
     crypto::{
         constants::MERKLE_DEPTH,
-        contract_id::{DAO_CONTRACT_ID, MONEY_CONTRACT_ID},
+        contract_id::{
+            DAO_CONTRACT_ID, DEPLOY_CONTRACT_ID, MONEY_CONTRACT_ID,
+            MONEY_CONTRACT_LEAD_NULLIFIERS_TREE, MONEY_CONTRACT_NULLIFIERS_TREE,
+        },
         schnorr::{SchnorrPublic, SchnorrSecret},
-        MerkleNode, PublicKey, SecretKey,
+        ContractId, MerkleNode, Nullifier, PublicKey, SecretKey,
     },
     db::SMART_CONTRACT_ZKAS_DB_NAME,
     incrementalmerkletree::{bridgetree::BridgeTree, Tree},
     pasta::{group::ff::PrimeField, pallas},
+    tx::ContractCall,
 };
 use darkfi_serial::{deserialize, serialize, Decodable, Encodable, WriteExt};
 use log::{debug, error, info, warn};
@@ -43,17 +52,20 @@ use super::{
 };
 
 use crate::{
-    blockchain::Blockchain,
+    blockchain::{Blockchain, DbBatch, DbOverlay},
     rpc::jsonrpc::JsonNotification,
-    runtime::vm_runtime::Runtime,
+    runtime::{
+        gas::{GAS_DEPLOY_PER_BYTE, GAS_DEPLOY_PER_CIRCUIT_ROW},
+        vm_runtime::{ContractSection, Runtime, TxContext},
+    },
     system::{Subscriber, SubscriberPtr},
-    tx::Transaction,
+    tx::{ContractDeploy, Transaction, MAX_GAS_LIMIT},
     util::time::Timestamp,
     wallet::WalletPtr,
     zk::{
+        circuit_k,
         proof::{ProvingKey, VerifyingKey},
-        vm::ZkCircuit,
-        vm_stack::empty_witnesses,
+        ParamsCachePtr,
     },
     zkas::ZkBinary,
     Error, Result,
@@ -62,8 +74,6 @@ use crate::{
 /// Atomic pointer to validator state.
 pub type ValidatorStatePtr = Arc<RwLock<ValidatorState>>;
 
-type VerifyingKeyMap = Arc<RwLock<HashMap<[u8; 32], Vec<(String, VerifyingKey)>>>>;
-
 /// This struct represents the state of a validator node.
 pub struct ValidatorState {
     /// Leader proof proving key
@@ -81,10 +91,10 @@ pub struct ValidatorState {
     ///       and then we don't have to deal with json in this module but only
     //        externally.
     pub subscribers: HashMap<&'static str, SubscriberPtr<JsonNotification>>,
-    /// ZK proof verifying keys for smart contract calls
-    pub verifying_keys: VerifyingKeyMap,
     /// Wallet interface
     pub wallet: WalletPtr,
+    /// Cache for ZK proving and verifying keys
+    pub params_cache: ParamsCachePtr,
 }
 
 impl ValidatorState {
@@ -97,6 +107,7 @@ impl ValidatorState {
         wallet: WalletPtr,
         faucet_pubkeys: Vec<PublicKey>,
         enable_participation: bool,
+        params_cache: ParamsCachePtr,
     ) -> Result<ValidatorStatePtr> {
         debug!(target: "consensus::validator", "Initializing ValidatorState");
 
@@ -108,19 +119,12 @@ impl ValidatorState {
         //wallet.exec_sql(consensus_tree_init_query).await?;
         //wallet.exec_sql(consensus_keys_init_query).await?;
 
-        debug!(target: "consensus::validator", "Generating leader proof keys with k: {}", constants::LEADER_PROOF_K);
+        debug!(target: "consensus::validator", "Generating leader proof keys");
         let bincode = include_bytes!("../../proof/lead.zk.bin");
-        let zkbin = ZkBinary::decode(bincode)?;
-        let witnesses = empty_witnesses(&zkbin);
-        let circuit = ZkCircuit::new(witnesses, zkbin);
-
-        let lead_verifying_key = VerifyingKey::build(constants::LEADER_PROOF_K, &circuit);
+        let lead_verifying_key = params_cache.verifying_key(bincode)?;
         // We only need this proving key if we're going to participate in the consensus.
-        let lead_proving_key = if enable_participation {
-            Some(ProvingKey::build(constants::LEADER_PROOF_K, &circuit))
-        } else {
-            None
-        };
+        let lead_proving_key =
+            if enable_participation { Some(params_cache.proving_key(bincode)?) } else { None };
 
         let blockchain = Blockchain::new(db, genesis_ts, genesis_data)?;
         let consensus = ConsensusState::new(
@@ -151,10 +155,6 @@ impl ValidatorState {
         let money_contract_deploy_payload = serialize(&faucet_pubkeys);
         let dao_contract_deploy_payload = vec![];
 
-        // In this hashmap, we keep references to ZK proof verifying keys needed
-        // for the circuits our native contracts provide.
-        let mut verifying_keys = HashMap::new();
-
         let native_contracts = vec![
             (
                 "Money Contract",
@@ -174,35 +174,18 @@ impl ValidatorState {
         for nc in native_contracts {
             info!(target: "consensus::validator", "Deploying {} with ContractID {}", nc.0, nc.1);
             let mut runtime = Runtime::new(&nc.2[..], blockchain.clone(), nc.1)?;
+            // Native contracts are deployed in the genesis slot.
+            runtime.set_tx_context(TxContext { timestamp: genesis_ts.0, ..Default::default() });
             runtime.deploy(&nc.3)?;
+            runtime.db_overlay().commit()?;
             info!(target: "consensus::validator", "Successfully deployed {}", nc.0);
 
             // When deployed, we can do a lookup for the zkas circuits and
-            // initialize verifying keys for them.
+            // create verifying keys for them, so they're cached by the time
+            // the contract gets called.
             info!(target: "consensus::validator", "Creating ZK verifying keys for {} zkas circuits", nc.0);
-            info!(target: "consensus::validator", "Looking up zkas db for {} (ContractID: {})", nc.0, nc.1);
-            let zkas_db = blockchain.contracts.lookup(
-                &blockchain.sled_db,
-                &nc.1,
-                SMART_CONTRACT_ZKAS_DB_NAME,
-            )?;
-
-            let mut vks = vec![];
-            for i in zkas_db.iter() {
-                info!(target: "consensus::validator", "Iterating over zkas db");
-                let (zkas_ns, zkas_bincode) = i?;
-                info!(target: "consensus::validator", "Deserializing namespace");
-                let zkas_ns: String = deserialize(&zkas_ns)?;
-                info!(target: "consensus::validator", "Creating VerifyingKey for zkas circuit with namespace {}", zkas_ns);
-                let zkbin = ZkBinary::decode(&zkas_bincode)?;
-                let circuit = ZkCircuit::new(empty_witnesses(&zkbin), zkbin);
-                // FIXME: This k=13 man...
-                let vk = VerifyingKey::build(13, &circuit);
-                vks.push((zkas_ns, vk));
-            }
-
+            Self::contract_verifying_keys(&blockchain, &params_cache, None, &nc.1)?;
             info!(target: "consensus::validator", "Finished creating VerifyingKey objects for {} (ContractID: {})", nc.0, nc.1);
-            verifying_keys.insert(nc.1.to_bytes(), vks);
         }
         info!(target: "consensus::validator", "Finished deployment of native wasm contracts");
         // -----NATIVE WASM CONTRACTS-----
@@ -219,8 +202,8 @@ impl ValidatorState {
             blockchain,
             unconfirmed_txs,
             subscribers,
-            verifying_keys: Arc::new(RwLock::new(verifying_keys)),
             wallet,
+            params_cache,
         }));
 
         Ok(state)
@@ -546,7 +529,10 @@ impl ValidatorState {
         // Validate state transition against canonical state
         // TODO: This should be validated against fork state
         info!(target: "consensus::validator", "receive_proposal(): Starting state transition validation");
-        if let Err(e) = self.verify_transactions(&proposal.block.txs, false).await {
+        if let Err(e) = self
+            .verify_transactions_staged(&proposal.block.txs, None, proposal.block.header.slot)
+            .await
+        {
             error!(target: "consensus::validator", "receive_proposal(): Transaction verifications failed: {}", e);
             return Err(e)
         };
@@ -575,6 +561,16 @@ impl ValidatorState {
         Ok(true)
     }
 
+    /// Append provided transactions vector to unconfirmed_txs, skipping the ones it
+    /// already contains.
+    pub fn append_txs(&mut self, transactions: &Vec<Transaction>) {
+        for tx in transactions {
+            if !self.unconfirmed_txs.contains(tx) {
+                self.unconfirmed_txs.push(tx.clone());
+            }
+        }
+    }
+
     /// Remove provided transactions vector from unconfirmed_txs if they exist.
     pub fn remove_txs(&mut self, transactions: &Vec<Transaction>) -> Result<()> {
         for tx in transactions {
@@ -655,30 +651,61 @@ impl ValidatorState {
             finalized.push(state_checkpoint.proposal.clone().into());
         }
 
-        // Adding finalized proposals to canonical
-        info!(target: "consensus::validator", "consensus: Adding {} finalized block to canonical chain.", finalized.len());
-        match self.blockchain.add(&finalized) {
+        // If the fork doesn't extend our last canonical block, the blocks it
+        // replaces have to be reverted first. The revert is only staged, so the
+        // fork's blocks are verified on top of it, and nothing gets written
+        // unless all of them are valid.
+        let revert = DbOverlay::default();
+        let reverted = match self.consensus.stage_revert_to_fork(&fork, &revert) {
             Ok(v) => v,
             Err(e) => {
-                error!(target: "consensus::validator", "consensus: Failed appending finalized blocks to canonical chain: {}", e);
+                error!(target: "consensus::validator", "consensus: Failed reverting canonical chain: {}", e);
                 return Err(e)
             }
         };
 
-        let blocks_subscriber = self.subscribers.get("blocks").unwrap().clone();
-
-        // Validating state transitions
+        // Validating state transitions. Each proposal is staged along with its
+        // state transitions on top of the ones before it.
+        info!(target: "consensus::validator", "consensus: Adding {} finalized block to canonical chain.", finalized.len());
+        let mut staged: Vec<(DbOverlay, u64)> = vec![];
         for proposal in &finalized {
             // TODO: Is this the right place? We're already doing this in protocol_sync.
             // TODO: These state transitions have already been checked. (I wrote this, but where?)
-            // TODO: FIXME: The state transitions have already been written, they have to be in memory
-            //              until this point.
             info!(target: "consensus::validator", "Applying state transition for finalized block");
-            if let Err(e) = self.verify_transactions(&proposal.txs, true).await {
+            let overlay = match staged.last() {
+                Some((previous, _)) => previous.layer(),
+                None => revert.layer(),
+            };
+            if let Err(e) = self
+                .verify_transactions_staged(&proposal.txs, Some(&overlay), proposal.header.slot)
+                .await
+            {
                 error!(target: "consensus::validator", "Finalized block transaction verifications failed: {}", e);
                 return Err(e)
             }
 
+            self.blockchain.stage(&overlay, &[proposal.clone()]);
+            staged.push((overlay, proposal.header.slot));
+        }
+
+        // The revert and the finalized blocks are written at once
+        if let Err(e) = self.blockchain.commit_layers(&revert, &staged) {
+            error!(target: "consensus::validator", "consensus: Failed appending finalized blocks to canonical chain: {}", e);
+            return Err(e)
+        }
+
+        // Blocks this deep can't be reverted anymore
+        self.blockchain.undo.prune(constants::MAX_REORG_DEPTH)?;
+
+        // The transactions of the reverted blocks go back to the memory pool,
+        // unless the fork includes them.
+        for block in reverted {
+            self.append_txs(&block.txs);
+        }
+
+        let blocks_subscriber = self.subscribers.get("blocks").unwrap().clone();
+
+        for proposal in &finalized {
             // Remove proposal transactions from memory pool
             if let Err(e) = self.remove_txs(&proposal.txs) {
                 error!(target: "consensus::validator", "Removing finalized block transactions failed: {}", e);
@@ -745,18 +772,27 @@ impl ValidatorState {
     /// Validate and append to canonical state received blocks.
     pub async fn receive_blocks(&mut self, blocks: &[BlockInfo]) -> Result<()> {
         // Verify state transitions for all blocks and their respective transactions.
+        // Each block is written along with its state transitions in one go, so a
+        // failure or a crash halfway doesn't leave any of them behind.
         info!(target: "consensus::validator", "receive_blocks(): Starting state transition validations");
         for block in blocks {
-            if let Err(e) = self.verify_transactions(&block.txs, true).await {
+            let overlay = DbOverlay::default();
+            if let Err(e) =
+                self.verify_transactions_staged(&block.txs, Some(&overlay), block.header.slot).await
+            {
                 error!(target: "consensus::validator", "receive_blocks(): Transaction verifications failed: {}", e);
                 return Err(e)
             }
+
+            info!(target: "consensus::validator", "receive_blocks(): Appending block to ledger");
+            self.blockchain.stage(&overlay, &[block.clone()]);
+            self.blockchain.commit(&overlay, block.header.slot)?;
         }
 
-        info!(target: "consensus::validator", "receive_blocks(): All state transitions passed");
-        info!(target: "consensus::validator", "receive_blocks(): Appending blocks to ledger");
-        self.blockchain.add(blocks)?;
+        // Blocks this deep can't be reverted anymore
+        self.blockchain.undo.prune(constants::MAX_REORG_DEPTH)?;
 
+        info!(target: "consensus::validator", "receive_blocks(): All state transitions passed");
         Ok(())
     }
 
@@ -834,103 +870,129 @@ impl ValidatorState {
 
     /// Validate signatures, wasm execution, and zk proofs for given transactions.
     /// If all of those succeed, try to execute a state update for the contract calls.
-    /// Currently the verifications are sequential, and the function will fail if any
-    /// of the verifications fail.
+    /// The "metadata" calls don't touch the contracts' state, so they're executed for
+    /// all the transactions in parallel, along with the signature verification. The
+    /// zk proofs of all the transactions are then verified in batches, one for each
+    /// verifying key. Finally the transactions are executed sequentially, and the
+    /// function will fail if any of them fails.
     /// The function takes a boolean called `write` which tells it to actually write
-    /// the state transitions to the database.
-    // TODO: This should be paralellized as if even one tx in the batch fails to verify,
-    //       we can drop everything.
-    pub async fn verify_transactions(&self, txs: &[Transaction], write: bool) -> Result<()> {
+    /// the state transitions to the database. The state updates of all the given
+    /// transactions are written atomically, so a failing call leaves nothing of
+    /// them behind.
+    /// Each transaction can spend up to its declared gas limit over all of its
+    /// calls and their phases. On success, returns the gas used by each of them.
+    /// Contracts see the transactions as executed in the current slot.
+    pub async fn verify_transactions(&self, txs: &[Transaction], write: bool) -> Result<Vec<u64>> {
+        let slot = self.consensus.current_slot();
+        if !write {
+            return self.verify_transactions_staged(txs, None, slot).await
+        }
+
+        let overlay = DbOverlay::default();
+        let gas_used = self.verify_transactions_staged(txs, Some(&overlay), slot).await?;
+
+        if let Err(e) = overlay.commit() {
+            error!(target: "consensus::validator", "Failed to write state updates: {}", e);
+            return Err(e)
+        }
+        info!(target: "consensus::validator", "State updates written successfully");
+
+        Ok(gas_used)
+    }
+
+    /// Same as [`ValidatorState::verify_transactions`], but if an overlay is given,
+    /// the state updates are staged in it instead of being written to the database.
+    /// The transactions are executed on top of what's already staged in the overlay,
+    /// so the caller can commit it along with other updates, like the blocks holding
+    /// the transactions. Contracts see the transactions as executed in the given slot.
+    async fn verify_transactions_staged(
+        &self,
+        txs: &[Transaction],
+        overlay: Option<&DbOverlay>,
+        slot: u64,
+    ) -> Result<Vec<u64>> {
         info!(target: "consensus::validator", "Verifying {} transaction(s)", txs.len());
-        for tx in txs {
+
+        // Chain context the contracts are executed in
+        let slot_context = TxContext {
+            slot,
+            epoch: self.consensus.slot_epoch(slot),
+            timestamp: self.consensus.slot_timestamp(slot).0,
+            ..Default::default()
+        };
+
+        // Without an overlay, the transactions don't see each other's state
+        // updates, so their metadata calls can all be run upfront, in parallel.
+        // With one, each transaction's metadata has to see the updates staged
+        // by the ones before it, so it's run right before the transaction is
+        // executed.
+        let mut metadata = match overlay {
+            Some(_) => vec![],
+            None => self.verify_metadata_batch(txs, slot_context).await?,
+        }
+        .into_iter();
+
+        // Tables of public inputs used for ZK proof verification, along with
+        // the gas used by each transaction
+        let mut zkp_tables = Vec::with_capacity(txs.len());
+        let mut txs_gas = Vec::with_capacity(txs.len());
+
+        // Verifying keys for the circuits the transactions since the first one
+        // whose ZK proofs weren't verified yet see
+        let mut verifying_keys = HashMap::new();
+        let mut unverified = 0;
+
+        for (i, tx) in txs.iter().enumerate() {
             let tx_hash = blake3::hash(&serialize(tx));
-            info!(target: "consensus::validator", "Verifying transaction {}", tx_hash);
+            let (zkp_table, mut gas_used) = match metadata.next() {
+                Some(v) => v,
+                None => Self::verify_metadata(
+                    &self.blockchain,
+                    &self.params_cache,
+                    tx,
+                    slot_context,
+                    overlay,
+                )?,
+            };
+            zkp_tables.push(zkp_table);
+            self.load_verifying_keys(tx, overlay, &mut verifying_keys).await;
 
-            // Table of public inputs used for ZK proof verification
-            let mut zkp_table = vec![];
-            // Table of public keys used for signature verification
-            let mut sig_table = vec![];
-            // State updates produced by contract execcution
+            info!(target: "consensus::validator", "Executing transaction {}", tx_hash);
+            let tx_context = TxContext { tx_hash: *tx.data_hash()?.as_bytes(), ..slot_context };
+
+            // State updates produced by contract execcution, along with the
+            // ones of the contracts each call called itself
             let mut updates = vec![];
+            let mut nested_updates = vec![];
 
-            // Iterate over all calls to get the metadata
             for (idx, call) in tx.calls.iter().enumerate() {
+                // Deployments were checked along with the metadata, and only
+                // do something once applied.
+                if call.contract_id == *DEPLOY_CONTRACT_ID {
+                    updates.push(vec![]);
+                    nested_updates.push(vec![]);
+                    continue
+                }
+
                 info!(target: "consensus::validator", "Executing contract call {}", idx);
-                let wasm = match self.blockchain.wasm_bincode.get(call.contract_id) {
-                    Ok(v) => {
-                        info!(target: "consensus::validator", "Found wasm bincode for {}", call.contract_id);
-                        v
-                    }
-                    Err(e) => {
-                        error!(
-                            target: "consensus::validator",
-                            "Could not find wasm bincode for contract {}: {}",
-                            call.contract_id, e
-                        );
-                        return Err(Error::ContractNotFound(call.contract_id.to_string()))
-                    }
-                };
+                let mut runtime =
+                    Self::contract_runtime(&self.blockchain, overlay, call.contract_id)?;
+                runtime.set_gas_limit(tx.gas_limit - gas_used);
+                runtime.set_tx_context(TxContext { call_idx: idx as u32, ..tx_context });
+                if let Some(overlay) = overlay {
+                    runtime.set_db_overlay(overlay.clone());
+                }
 
-                // Write the actual payload data
                 let mut payload = vec![];
                 payload.write_u32(idx as u32)?; // Call index
                 tx.calls.encode(&mut payload)?; // Actual call data
 
-                // Instantiate the wasm runtime
-                let mut runtime =
-                    match Runtime::new(&wasm, self.blockchain.clone(), call.contract_id) {
-                        Ok(v) => v,
-                        Err(e) => {
-                            error!(
-                                target: "consensus::validator",
-                                "Failed to instantiate WASM runtime for contract {}",
-                                call.contract_id
-                            );
-                            return Err(e)
-                        }
-                    };
-
-                info!(target: "consensus::validator", "Executing \"metadata\" call");
-                let metadata = match runtime.metadata(&payload) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        error!(target: "consensus::validator", "Failed to execute \"metadata\" call: {}", e);
-                        return Err(e)
-                    }
-                };
-
-                // Decode the metadata retrieved from the execution
-                let mut decoder = Cursor::new(&metadata);
-                let zkp_pub: Vec<(String, Vec<pallas::Base>)> = match Decodable::decode(
-                    &mut decoder,
-                ) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        error!(target: "consensus::validator", "Failed to decode ZK public inputs from metadata: {}", e);
-                        return Err(e.into())
-                    }
-                };
-
-                let sig_pub: Vec<PublicKey> = match Decodable::decode(&mut decoder) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        error!(target: "consensus::validator", "Failed to decode signature pubkeys from metadata: {}", e);
-                        return Err(e.into())
-                    }
-                };
-
-                // TODO: Make sure we've read all the bytes above.
-                info!(target: "consensus::validator", "Successfully executed \"metadata\" call");
-                zkp_table.push(zkp_pub);
-                sig_table.push(sig_pub);
-
-                // After getting the metadata, we run the "exec" function with the same
-                // runtime and the same payload.
                 info!(target: "consensus::validator", "Executing \"exec\" call");
                 match runtime.exec(&payload) {
                     Ok(v) => {
                         info!(target: "consensus::validator", "Successfully executed \"exec\" call");
                         updates.push(v);
+                        nested_updates.push(runtime.nested_updates());
                     }
                     Err(e) => {
                         error!(
@@ -941,101 +1003,602 @@ impl ValidatorState {
                         return Err(e)
                     }
                 };
+                gas_used += runtime.gas_used();
                 // At this point we're done with the call and move on to the next one.
             }
 
-            // When we're done looping and executing over the tx's contract calls, we
-            // move on with verification. First we verify the signatures as that's
-            // cheaper, and then finally we verify the ZK proofs.
-            info!(target: "consensus::validator", "Verifying signatures for transaction {}", tx_hash);
-            if sig_table.len() != tx.signatures.len() {
-                error!(target: "consensus::validator", "Incorrect number of signatures in tx {}", tx_hash);
-                return Err(Error::InvalidSignature)
-            }
-
-            match tx.verify_sigs(sig_table) {
-                Ok(()) => {
-                    info!(target: "consensus::validator", "Signatures verification for tx {} successful", tx_hash)
-                }
-                Err(e) => {
-                    error!(target: "consensus::validator", "Signature verification for tx {} failed: {}", tx_hash, e);
-                    return Err(e)
-                }
-            };
-
-            // NOTE: When it comes to the ZK proofs, we first do a lookup of the
-            // verifying keys, but if we do not find them, we'll generate them
-            // inside of this function. This can be kinda expensive, so open to
-            // alternatives.
-            info!(target: "consensus::validator", "Verifying ZK proofs for transaction {}", tx_hash);
-            match tx.verify_zkps(self.verifying_keys.clone(), zkp_table).await {
-                Ok(()) => {
-                    info!(target: "consensus::validator", "ZK proof verification for tx {} successful", tx_hash)
-                }
-                Err(e) => {
-                    error!(target: "consensus::validator", "ZK proof verification for tx {} failed: {}", tx_hash, e);
-                    return Err(e)
-                }
-            };
-
-            // After the verifications stage passes, if we're told to write, we
+            // After the execution stage passes, if we're told to write, we
             // apply the state updates.
             assert!(tx.calls.len() == updates.len());
-            if write {
+            if let Some(overlay) = overlay {
                 info!(target: "consensus::validator", "Performing state updates");
-                for (call, update) in tx.calls.iter().zip(updates.iter()) {
+                let nullifiers_before = self.staged_nullifiers(overlay)?;
+
+                // The updates of all calls are staged in the same overlay, so each
+                // call sees the ones before it, and they're written at once when
+                // the overlay gets committed.
+                for (idx, ((call, update), nested)) in
+                    tx.calls.iter().zip(updates.iter()).zip(nested_updates.iter()).enumerate()
+                {
+                    let call_context = TxContext { call_idx: idx as u32, ..tx_context };
+
+                    if call.contract_id == *DEPLOY_CONTRACT_ID {
+                        let gas_limit = tx.gas_limit - gas_used;
+                        gas_used += self.apply_deploy(call, overlay, gas_limit, call_context)?;
+                        continue
+                    }
+
                     // For this we instantiate the runtimes again.
                     // TODO: Optimize this
-                    // TODO: Sum up the gas costs of previous calls during execution
-                    //       and verification and these.
-                    let wasm = match self.blockchain.wasm_bincode.get(call.contract_id) {
-                        Ok(v) => {
-                            info!(target: "consensus::validator", "Found wasm bincode for {}", call.contract_id);
-                            v
-                        }
-                        Err(e) => {
-                            error!(
-                                target: "consensus::validator",
-                                "Could not find wasm bincode for contract {}: {}",
-                                call.contract_id, e
-                            );
-                            return Err(Error::ContractNotFound(call.contract_id.to_string()))
-                        }
-                    };
-
                     let mut runtime =
-                        match Runtime::new(&wasm, self.blockchain.clone(), call.contract_id) {
-                            Ok(v) => v,
-                            Err(e) => {
-                                error!(
-                                    target: "consensus::validator",
-                                    "Failed to instantiate WASM runtime for contract {}",
-                                    call.contract_id
-                                );
-                                return Err(e)
-                            }
-                        };
+                        Self::contract_runtime(&self.blockchain, Some(overlay), call.contract_id)?;
+                    runtime.set_gas_limit(tx.gas_limit - gas_used);
+                    runtime.set_db_overlay(overlay.clone());
+                    runtime.set_tx_context(call_context);
 
                     info!(target: "consensus::validator", "Executing \"apply\" call");
                     match runtime.apply(update) {
-                        // TODO: FIXME: This should be done in an atomic tx/batch
                         Ok(()) => {
-                            info!(target: "consensus::validator", "State update applied successfully")
+                            info!(target: "consensus::validator", "State update staged successfully")
                         }
                         Err(e) => {
                             error!(target: "consensus::validator", "Failed to apply state update: {}", e);
                             return Err(e)
                         }
                     };
+                    gas_used += runtime.gas_used();
+
+                    // Then the updates of the contracts it called with `call_contract`
+                    for (contract_id, update) in nested {
+                        let mut runtime =
+                            Self::contract_runtime(&self.blockchain, Some(overlay), *contract_id)?;
+                        runtime.set_gas_limit(tx.gas_limit - gas_used);
+                        runtime.set_db_overlay(overlay.clone());
+                        runtime.set_tx_context(call_context);
+
+                        info!(target: "consensus::validator", "Executing nested \"apply\" call for contract id {}", contract_id);
+                        if let Err(e) = runtime.apply(update) {
+                            error!(target: "consensus::validator", "Failed to apply nested state update: {}", e);
+                            return Err(e)
+                        }
+                        gas_used += runtime.gas_used();
+                    }
                 }
+
+                // Index the nullifiers the transaction revealed, so we can tell
+                // which transaction spent them.
+                let nullifiers: Vec<Nullifier> = self
+                    .staged_nullifiers(overlay)?
+                    .difference(&nullifiers_before)
+                    .map(|nf| deserialize(nf))
+                    .collect::<std::result::Result<_, _>>()?;
+                self.blockchain.nullifiers.stage(overlay, &nullifiers, &tx_hash);
             } else {
                 info!(target: "consensus::validator", "Skipping apply of state updates because write=false");
             }
 
-            info!(target: "consensus::validator", "Transaction {} verified successfully", tx_hash);
+            info!(
+                target: "consensus::validator",
+                "Transaction {} executed successfully, gas used: {}/{}",
+                tx_hash, gas_used, tx.gas_limit
+            );
+            txs_gas.push(gas_used);
+
+            // The transactions after this one see the circuits of the contracts
+            // it deployed, so the proofs made for the circuits they replace are
+            // verified right away.
+            let redeployed = tx
+                .calls
+                .iter()
+                .filter(|call| call.contract_id == *DEPLOY_CONTRACT_ID)
+                .filter_map(|call| deserialize::<ContractDeploy>(&call.data).ok())
+                .any(|deploy| verifying_keys.contains_key(&deploy.contract_id().to_bytes()));
+            if redeployed {
+                Self::verify_zkps(
+                    &txs[unverified..=i],
+                    std::mem::take(&mut verifying_keys),
+                    std::mem::take(&mut zkp_tables),
+                )
+                .await?;
+                unverified = i + 1;
+            }
         }
 
-        Ok(())
+        // Nothing was written to the database so far, so the rest of the ZK
+        // proofs are verified last, all at once.
+        Self::verify_zkps(&txs[unverified..], verifying_keys, zkp_tables).await?;
+
+        Ok(txs_gas)
+    }
+
+    /// Verify the ZK proofs of the given transactions with the given verifying
+    /// keys, batched by circuit.
+    async fn verify_zkps(
+        txs: &[Transaction],
+        verifying_keys: HashMap<[u8; 32], Vec<(String, VerifyingKey)>>,
+        zkp_tables: Vec<Vec<Vec<(String, Vec<pallas::Base>)>>>,
+    ) -> Result<()> {
+        info!(target: "consensus::validator", "Verifying ZK proofs for {} transaction(s)", txs.len());
+        let verifying_keys = Arc::new(RwLock::new(verifying_keys));
+        match Transaction::verify_zkps_batch(txs, verifying_keys, zkp_tables).await {
+            Ok(()) => {
+                info!(target: "consensus::validator", "ZK proof verification successful");
+                Ok(())
+            }
+            Err(e) => {
+                error!(target: "consensus::validator", "ZK proof verification failed: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Run [`ValidatorState::verify_metadata`] for each of the given transactions
+    /// against the database. The metadata calls are CPU bound, so they're run off
+    /// the executor, split between the available threads.
+    async fn verify_metadata_batch(
+        &self,
+        txs: &[Transaction],
+        slot_context: TxContext,
+    ) -> Result<Vec<(Vec<Vec<(String, Vec<pallas::Base>)>>, u64)>> {
+        let n_threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        let chunk_size = ((txs.len() + n_threads - 1) / n_threads).max(1);
+
+        let blockchain = self.blockchain.clone();
+        let params_cache = self.params_cache.clone();
+        let owned_txs = txs.to_vec();
+        let results: Vec<Result<_>> = smol::unblock(move || {
+            thread::scope(|s| {
+                let handles: Vec<_> = owned_txs
+                    .chunks(chunk_size)
+                    .map(|chunk| {
+                        let blockchain = &blockchain;
+                        let params_cache = &params_cache;
+                        s.spawn(move || {
+                            chunk
+                                .iter()
+                                .map(|tx| {
+                                    Self::verify_metadata(
+                                        blockchain,
+                                        params_cache,
+                                        tx,
+                                        slot_context,
+                                        None,
+                                    )
+                                })
+                                .collect::<Vec<_>>()
+                        })
+                    })
+                    .collect();
+
+                let mut results = vec![];
+                for handle in handles {
+                    match handle.join() {
+                        Ok(r) => results.extend(r),
+                        Err(_) => {
+                            error!(target: "consensus::validator", "Metadata verification thread panicked");
+                            return Err(Error::ThreadPanicked)
+                        }
+                    }
+                }
+                Ok(results)
+            })
+        })
+        .await?;
+
+        results.into_iter().collect()
+    }
+
+    /// Execute the "metadata" calls of a transaction, and verify its signatures.
+    /// Returns the table of public inputs used for its ZK proof verification,
+    /// along with the gas spent on the calls.
+    /// If an overlay is given, the calls see the state updates staged in it,
+    /// without being able to stage any of their own.
+    fn verify_metadata(
+        blockchain: &Blockchain,
+        params_cache: &ParamsCachePtr,
+        tx: &Transaction,
+        slot_context: TxContext,
+        overlay: Option<&DbOverlay>,
+    ) -> Result<(Vec<Vec<(String, Vec<pallas::Base>)>>, u64)> {
+        let tx_hash = blake3::hash(&serialize(tx));
+        info!(target: "consensus::validator", "Verifying transaction {}", tx_hash);
+        let tx_context = TxContext { tx_hash: *tx.data_hash()?.as_bytes(), ..slot_context };
+
+        if tx.gas_limit > MAX_GAS_LIMIT {
+            error!(
+                target: "consensus::validator",
+                "Transaction {} gas limit {} is above the maximum of {}",
+                tx_hash, tx.gas_limit, MAX_GAS_LIMIT
+            );
+            return Err(Error::GasLimitTooHigh(tx.gas_limit, MAX_GAS_LIMIT))
+        }
+
+        // Gas spent so far by the transaction
+        let mut gas_used = 0;
+
+        // Table of public inputs used for ZK proof verification
+        let mut zkp_table = vec![];
+        // Table of public keys used for signature verification
+        let mut sig_table = vec![];
+
+        // Iterate over all calls to get the metadata
+        for (idx, call) in tx.calls.iter().enumerate() {
+            // Deployments are handled natively, and have to be signed with the
+            // deploy key of the contract.
+            if call.contract_id == *DEPLOY_CONTRACT_ID {
+                info!(target: "consensus::validator", "Verifying contract deployment call {}", idx);
+                let (deploy, deploy_gas) =
+                    Self::verify_deploy(blockchain, params_cache, call, tx.gas_limit - gas_used)?;
+                gas_used += deploy_gas;
+                zkp_table.push(vec![]);
+                sig_table.push(vec![deploy.deploy_key]);
+                continue
+            }
+
+            info!(target: "consensus::validator", "Executing contract call {}", idx);
+            let mut runtime = Self::contract_runtime(blockchain, overlay, call.contract_id)?;
+            runtime.set_gas_limit(tx.gas_limit - gas_used);
+            runtime.set_tx_context(TxContext { call_idx: idx as u32, ..tx_context });
+            if let Some(overlay) = overlay {
+                // A layer of its own keeps anything the call does out of
+                // the overlay.
+                runtime.set_db_overlay(overlay.layer());
+            }
+
+            // Write the actual payload data
+            let mut payload = vec![];
+            payload.write_u32(idx as u32)?; // Call index
+            tx.calls.encode(&mut payload)?; // Actual call data
+
+            info!(target: "consensus::validator", "Executing \"metadata\" call");
+            let metadata = match runtime.metadata(&payload) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!(target: "consensus::validator", "Failed to execute \"metadata\" call: {}", e);
+                    return Err(e)
+                }
+            };
+            gas_used += runtime.gas_used();
+
+            // Decode the metadata retrieved from the execution
+            let mut decoder = Cursor::new(&metadata);
+            let zkp_pub: Vec<(String, Vec<pallas::Base>)> = match Decodable::decode(&mut decoder) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!(target: "consensus::validator", "Failed to decode ZK public inputs from metadata: {}", e);
+                    return Err(e.into())
+                }
+            };
+
+            let sig_pub: Vec<PublicKey> = match Decodable::decode(&mut decoder) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!(target: "consensus::validator", "Failed to decode signature pubkeys from metadata: {}", e);
+                    return Err(e.into())
+                }
+            };
+
+            // TODO: Make sure we've read all the bytes above.
+            info!(target: "consensus::validator", "Successfully executed \"metadata\" call");
+            zkp_table.push(zkp_pub);
+            sig_table.push(sig_pub);
+        }
+
+        // When we're done looping over the tx's contract calls, we verify the
+        // signatures as that's cheap. The ZK proofs are verified afterwards,
+        // together with the ones of the other transactions.
+        info!(target: "consensus::validator", "Verifying signatures for transaction {}", tx_hash);
+        if sig_table.len() != tx.signatures.len() {
+            error!(target: "consensus::validator", "Incorrect number of signatures in tx {}", tx_hash);
+            return Err(Error::InvalidSignature)
+        }
+
+        match tx.verify_sigs(sig_table) {
+            Ok(()) => {
+                info!(target: "consensus::validator", "Signatures verification for tx {} successful", tx_hash)
+            }
+            Err(e) => {
+                error!(target: "consensus::validator", "Signature verification for tx {} failed: {}", tx_hash, e);
+                return Err(e)
+            }
+        };
+
+        Ok((zkp_table, gas_used))
+    }
+
+    /// Retrieve the nullifiers staged in the given overlay for the money
+    /// contract's nullifier trees.
+    fn staged_nullifiers(&self, overlay: &DbOverlay) -> Result<HashSet<Vec<u8>>> {
+        let mut nullifiers = HashSet::new();
+
+        for tree_name in [MONEY_CONTRACT_NULLIFIERS_TREE, MONEY_CONTRACT_LEAD_NULLIFIERS_TREE] {
+            let tree = self.blockchain.contracts.lookup(
+                &self.blockchain.sled_db,
+                &MONEY_CONTRACT_ID,
+                tree_name,
+            )?;
+            nullifiers.extend(overlay.inserted_keys(&tree));
+        }
+
+        Ok(nullifiers)
+    }
+
+    /// Decode the contract deployment in the given call, and check that its
+    /// circuits and wasm bincode are valid. The verifying keys of the circuits
+    /// are created here, so they're cached by the time the contract is called.
+    /// Returns the deployment along with the gas it costs, failing if that's
+    /// above `gas_limit`.
+    fn verify_deploy(
+        blockchain: &Blockchain,
+        params_cache: &ParamsCachePtr,
+        call: &ContractCall,
+        gas_limit: u64,
+    ) -> Result<(ContractDeploy, u64)> {
+        let deploy: ContractDeploy = match deserialize(&call.data) {
+            Ok(v) => v,
+            Err(e) => {
+                error!(target: "consensus::validator", "Failed to decode contract deployment: {}", e);
+                return Err(e.into())
+            }
+        };
+        let contract_id = deploy.contract_id();
+        let mut gas_used = call.data.len() as u64 * GAS_DEPLOY_PER_BYTE;
+        if gas_used > gas_limit {
+            error!(target: "consensus::validator", "Contract {} deployment ran out of gas", contract_id);
+            return Err(Error::OutOfGas(gas_limit))
+        }
+
+        let mut namespaces = HashSet::new();
+        for bincode in &deploy.circuits {
+            let zkbin = match ZkBinary::decode(bincode) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!(target: "consensus::validator", "Invalid zkas bincode for contract {}: {}", contract_id, e);
+                    return Err(e)
+                }
+            };
+
+            if !namespaces.insert(zkbin.namespace.clone()) {
+                error!(target: "consensus::validator", "Duplicate zkas circuit {} for contract {}", zkbin.namespace, contract_id);
+                return Err(Error::ContractDeployFailed(format!(
+                    "Duplicate zkas circuit {}",
+                    zkbin.namespace
+                )))
+            }
+
+            // The circuit's k is given by the deployer, so it has to be bounded
+            // before anything gets built with it.
+            let k = circuit_k(&zkbin)?;
+            if k > constants::MAX_DEPLOY_CIRCUIT_K {
+                error!(target: "consensus::validator", "zkas circuit {} for contract {} has k={} above the maximum of {}", zkbin.namespace, contract_id, k, constants::MAX_DEPLOY_CIRCUIT_K);
+                return Err(Error::ContractDeployFailed(format!(
+                    "zkas circuit {} has k={} above the maximum of {}",
+                    zkbin.namespace,
+                    k,
+                    constants::MAX_DEPLOY_CIRCUIT_K
+                )))
+            }
+
+            gas_used += (1u64 << k) * GAS_DEPLOY_PER_CIRCUIT_ROW;
+            // Charged before creating the verifying key, which is the costly part
+            if gas_used > gas_limit {
+                error!(target: "consensus::validator", "Contract {} deployment ran out of gas", contract_id);
+                return Err(Error::OutOfGas(gas_limit))
+            }
+
+            if let Err(e) = params_cache.verifying_key(bincode) {
+                error!(target: "consensus::validator", "Failed creating verifying key for zkas circuit {} of contract {}: {}", zkbin.namespace, contract_id, e);
+                return Err(e)
+            }
+        }
+
+        // Make sure the wasm compiles and provides all the contract functions
+        let runtime = Runtime::new(&deploy.wasm_bincode, blockchain.clone(), contract_id)?;
+        for section in [
+            ContractSection::Deploy,
+            ContractSection::Exec,
+            ContractSection::Update,
+            ContractSection::Metadata,
+        ] {
+            if runtime.instance.exports.get_function(section.name()).is_err() {
+                error!(target: "consensus::validator", "Contract {} is missing {} function", contract_id, section.name());
+                return Err(Error::ContractDeployFailed(format!(
+                    "Missing {} function",
+                    section.name()
+                )))
+            }
+        }
+
+        Ok((deploy, gas_used))
+    }
+
+    /// Stage the deployment of the contract in the given call: its circuits
+    /// replace any previous ones, its `__initialize` function is executed, and
+    /// its wasm bincode is stored. Returns the gas spent on `__initialize`.
+    fn apply_deploy(
+        &self,
+        call: &ContractCall,
+        overlay: &DbOverlay,
+        gas_limit: u64,
+        tx_context: TxContext,
+    ) -> Result<u64> {
+        let deploy: ContractDeploy = deserialize(&call.data)?;
+        let contract_id = deploy.contract_id();
+        info!(target: "consensus::validator", "Deploying contract {}", contract_id);
+
+        let db = &self.blockchain.sled_db;
+        let contracts = &self.blockchain.contracts;
+        let zkas_tree =
+            match contracts.lookup_staged(db, overlay, &contract_id, SMART_CONTRACT_ZKAS_DB_NAME) {
+                Ok(v) => v,
+                Err(_) => {
+                    contracts.stage(db, overlay, &contract_id, SMART_CONTRACT_ZKAS_DB_NAME)?
+                }
+            };
+
+        let mut batch = DbBatch::default();
+        for key in zkas_tree.iter().keys() {
+            batch.remove(&key?);
+        }
+        for (key, value) in overlay.staged_range(&zkas_tree, (Unbounded, Unbounded)) {
+            if value.is_some() {
+                batch.remove(&key);
+            }
+        }
+        for bincode in &deploy.circuits {
+            let zkbin = ZkBinary::decode(bincode)?;
+            batch.insert(&serialize(&zkbin.namespace), bincode);
+        }
+        overlay.stage(&zkas_tree, batch);
+
+        let mut runtime = Runtime::new(&deploy.wasm_bincode, self.blockchain.clone(), contract_id)?;
+        runtime.set_gas_limit(gas_limit);
+        runtime.set_db_overlay(overlay.clone());
+        runtime.set_tx_context(tx_context);
+        if let Err(e) = runtime.deploy(&deploy.payload) {
+            error!(target: "consensus::validator", "Failed to deploy contract {}: {}", contract_id, e);
+            return Err(e)
+        }
+
+        info!(target: "consensus::validator", "Contract {} deployment staged successfully", contract_id);
+        Ok(runtime.gas_used())
+    }
+
+    /// Create the ZK verifying keys for the circuits of the given contract.
+    /// If an overlay is given, the circuits staged in it replace the ones in
+    /// the database.
+    fn contract_verifying_keys(
+        blockchain: &Blockchain,
+        params_cache: &ParamsCachePtr,
+        overlay: Option<&DbOverlay>,
+        contract_id: &ContractId,
+    ) -> Result<Vec<(String, VerifyingKey)>> {
+        info!(target: "consensus::validator", "Looking up zkas db for ContractID {}", contract_id);
+        let db = &blockchain.sled_db;
+        let zkas_db = match overlay {
+            Some(overlay) => blockchain.contracts.lookup_staged(
+                db,
+                overlay,
+                contract_id,
+                SMART_CONTRACT_ZKAS_DB_NAME,
+            )?,
+            None => blockchain.contracts.lookup(db, contract_id, SMART_CONTRACT_ZKAS_DB_NAME)?,
+        };
+
+        let mut circuits = BTreeMap::new();
+        for i in zkas_db.iter() {
+            info!(target: "consensus::validator", "Iterating over zkas db");
+            let (zkas_ns, zkas_bincode) = i?;
+            circuits.insert(zkas_ns.to_vec(), zkas_bincode.to_vec());
+        }
+        if let Some(overlay) = overlay {
+            for (zkas_ns, zkas_bincode) in overlay.staged_range(&zkas_db, (Unbounded, Unbounded)) {
+                match zkas_bincode {
+                    Some(zkas_bincode) => circuits.insert(zkas_ns, zkas_bincode),
+                    None => circuits.remove(&zkas_ns),
+                };
+            }
+        }
+
+        let mut vks = vec![];
+        for (zkas_ns, zkas_bincode) in circuits {
+            info!(target: "consensus::validator", "Deserializing namespace");
+            let zkas_ns: String = deserialize(&zkas_ns)?;
+            info!(target: "consensus::validator", "Creating VerifyingKey for zkas circuit with namespace {}", zkas_ns);
+            // The cache is keyed by the circuit itself, so an upgraded
+            // contract gets the keys of its new circuits.
+            let vk = params_cache.verifying_key(&zkas_bincode)?;
+            vks.push((zkas_ns, vk));
+        }
+
+        Ok(vks)
+    }
+
+    /// Add the ZK verifying keys of the contracts called by the given
+    /// transaction to `verifying_keys`, in case they're not there yet. They're
+    /// created for the circuits the contracts have in the given overlay, or
+    /// in the database if none is given. The keys are fetched off the
+    /// executor, since they may have to be created, which can be expensive.
+    async fn load_verifying_keys(
+        &self,
+        tx: &Transaction,
+        overlay: Option<&DbOverlay>,
+        verifying_keys: &mut HashMap<[u8; 32], Vec<(String, VerifyingKey)>>,
+    ) {
+        let mut missing = HashMap::new();
+        for call in &tx.calls {
+            if call.contract_id != *DEPLOY_CONTRACT_ID &&
+                !verifying_keys.contains_key(&call.contract_id.to_bytes())
+            {
+                missing.insert(call.contract_id.to_bytes(), call.contract_id);
+            }
+        }
+
+        if missing.is_empty() {
+            return
+        }
+
+        let blockchain = self.blockchain.clone();
+        let params_cache = self.params_cache.clone();
+        let overlay = overlay.cloned();
+        let loaded: Vec<_> = smol::unblock(move || {
+            missing
+                .into_values()
+                .map(|contract_id| {
+                    // A contract without circuits, or a missing one, gets an
+                    // empty set, so its proofs fail to verify.
+                    let vks = Self::contract_verifying_keys(
+                        &blockchain,
+                        &params_cache,
+                        overlay.as_ref(),
+                        &contract_id,
+                    )
+                    .unwrap_or_default();
+                    (contract_id.to_bytes(), vks)
+                })
+                .collect()
+        })
+        .await;
+
+        verifying_keys.extend(loaded);
+    }
+
+    /// Instantiate the wasm runtime of the given contract. If an overlay is
+    /// given, the wasm bincode staged in it is used, if any.
+    fn contract_runtime(
+        blockchain: &Blockchain,
+        overlay: Option<&DbOverlay>,
+        contract_id: ContractId,
+    ) -> Result<Runtime> {
+        let wasm = match overlay {
+            Some(overlay) => blockchain.wasm_bincode.get_staged(overlay, contract_id),
+            None => blockchain.wasm_bincode.get(contract_id),
+        };
+        let wasm = match wasm {
+            Ok(v) => {
+                info!(target: "consensus::validator", "Found wasm bincode for {}", contract_id);
+                v
+            }
+            Err(e) => {
+                error!(
+                    target: "consensus::validator",
+                    "Could not find wasm bincode for contract {}: {}",
+                    contract_id, e
+                );
+                return Err(Error::ContractNotFound(contract_id.to_string()))
+            }
+        };
+
+        match Runtime::new(&wasm, blockchain.clone(), contract_id) {
+            Ok(v) => Ok(v),
+            Err(e) => {
+                error!(
+                    target: "consensus::validator",
+                    "Failed to instantiate WASM runtime for contract {}", contract_id
+                );
+                Err(e)
+            }
+        }
     }
 
     /// Append to canonical state received finalized slot checkpoints from block sync task.
@@ -16,7 +16,10 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use darkfi::{tx::Transaction, Result};
+use darkfi::{
+    tx::{Transaction, DEFAULT_GAS_LIMIT},
+    Result,
+};
 use darkfi_sdk::{
     crypto::{
         merkle_prelude::*, pallas, pasta_prelude::*, pedersen_commitment_u64, poseidon_hash, Coin,
@@ -94,7 +97,7 @@ async fn integration_test() -> Result<()> {
     params.encode(&mut data)?;
     let calls = vec![ContractCall { contract_id: dao_th.dao_contract_id, data }];
     let proofs = vec![proofs];
-    let mut tx = Transaction { calls, proofs, signatures: vec![] };
+    let mut tx = Transaction { calls, gas_limit: DEFAULT_GAS_LIMIT, proofs, signatures: vec![] };
     let sigs = tx.create_sigs(&mut OsRng, &[dao_th.dao_kp.secret])?;
     tx.signatures = vec![sigs];
 
@@ -165,7 +168,7 @@ async fn integration_test() -> Result<()> {
     params.encode(&mut data)?;
     let calls = vec![ContractCall { contract_id, data }];
     let proofs = vec![proofs];
-    let mut tx = Transaction { calls, proofs, signatures: vec![] };
+    let mut tx = Transaction { calls, gas_limit: DEFAULT_GAS_LIMIT, proofs, signatures: vec![] };
     let sigs = tx.create_sigs(&mut OsRng, &vec![dao_th.faucet_kp.secret])?;
     tx.signatures = vec![sigs];
 
@@ -286,7 +289,7 @@ async fn integration_test() -> Result<()> {
     params.encode(&mut data)?;
     let calls = vec![ContractCall { contract_id, data }];
     let proofs = vec![proofs];
-    let mut tx = Transaction { calls, proofs, signatures: vec![] };
+    let mut tx = Transaction { calls, gas_limit: DEFAULT_GAS_LIMIT, proofs, signatures: vec![] };
     let sigs = tx.create_sigs(&mut OsRng, &vec![dao_th.faucet_kp.secret])?;
     tx.signatures = vec![sigs];
 
@@ -422,7 +425,7 @@ async fn integration_test() -> Result<()> {
     params.encode(&mut data)?;
     let calls = vec![ContractCall { contract_id, data }];
     let proofs = vec![proofs];
-    let mut tx = Transaction { calls, proofs, signatures: vec![] };
+    let mut tx = Transaction { calls, gas_limit: DEFAULT_GAS_LIMIT, proofs, signatures: vec![] };
     let sigs = tx.create_sigs(&mut OsRng, &vec![signature_secret])?;
     tx.signatures = vec![sigs];
 
@@ -527,7 +530,7 @@ async fn integration_test() -> Result<()> {
     params.encode(&mut data)?;
     let calls = vec![ContractCall { contract_id, data }];
     let proofs = vec![proofs];
-    let mut tx = Transaction { calls, proofs, signatures: vec![] };
+    let mut tx = Transaction { calls, gas_limit: DEFAULT_GAS_LIMIT, proofs, signatures: vec![] };
     let sigs = tx.create_sigs(&mut OsRng, &vec![signature_secret])?;
     tx.signatures = vec![sigs];
 
@@ -595,7 +598,7 @@ async fn integration_test() -> Result<()> {
     params.encode(&mut data)?;
     let calls = vec![ContractCall { contract_id, data }];
     let proofs = vec![proofs];
-    let mut tx = Transaction { calls, proofs, signatures: vec![] };
+    let mut tx = Transaction { calls, gas_limit: DEFAULT_GAS_LIMIT, proofs, signatures: vec![] };
     let sigs = tx.create_sigs(&mut OsRng, &vec![signature_secret])?;
     tx.signatures = vec![sigs];
 
@@ -660,7 +663,7 @@ async fn integration_test() -> Result<()> {
     params.encode(&mut data)?;
     let calls = vec![ContractCall { contract_id, data }];
     let proofs = vec![proofs];
-    let mut tx = Transaction { calls, proofs, signatures: vec![] };
+    let mut tx = Transaction { calls, gas_limit: DEFAULT_GAS_LIMIT, proofs, signatures: vec![] };
     let sigs = tx.create_sigs(&mut OsRng, &vec![signature_secret])?;
     tx.signatures = vec![sigs];
 
@@ -848,6 +851,7 @@ async fn integration_test() -> Result<()> {
 
     let mut tx = Transaction {
         calls: vec![xfer_call, exec_call],
+        gas_limit: DEFAULT_GAS_LIMIT,
         proofs: vec![xfer_proofs, exec_proofs],
         signatures: vec![],
     };
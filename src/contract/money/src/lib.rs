@@ -81,12 +81,12 @@ darkfi_sdk::define_contract!(
 
 // These are the different sled trees that will be created
 pub const MONEY_CONTRACT_COIN_ROOTS_TREE: &str = "coin_roots";
-pub const MONEY_CONTRACT_NULLIFIERS_TREE: &str = "nullifiers";
+pub use darkfi_sdk::crypto::contract_id::MONEY_CONTRACT_NULLIFIERS_TREE;
 pub const MONEY_CONTRACT_FIXED_SUPPLY_TREE: &str = "fixed_supply_tokens";
 pub const MONEY_CONTRACT_INFO_TREE: &str = "info";
 // lead coin, nullifier sled trees.
 pub const MONEY_CONTRACT_LEAD_COIN_ROOTS_TREE: &str = "lead_coin_roots";
-pub const MONEY_CONTRACT_LEAD_NULLIFIERS_TREE: &str = "lead_nullifiers";
+pub use darkfi_sdk::crypto::contract_id::MONEY_CONTRACT_LEAD_NULLIFIERS_TREE;
 pub const MONEY_CONTRACT_LEAD_INFO_TREE: &str = "lead_info";
 
 // This is a key inside the info tree
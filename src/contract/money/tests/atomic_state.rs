@@ -0,0 +1,129 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Integration test for the atomicity of state updates.
+//!
+//! A batch of transactions is verified where the last one double spends
+//! a coin the ones before it created and spent. The batch must be rejected
+//! as a whole, without any of the state updates of the valid transactions
+//! being written.
+
+use darkfi::{
+    tx::{Transaction, DEFAULT_GAS_LIMIT},
+    Result,
+};
+use darkfi_sdk::{
+    crypto::{
+        merkle_prelude::*, pallas, pasta_prelude::*, poseidon_hash, MerkleNode, Nullifier, TokenId,
+    },
+    ContractCall,
+};
+use darkfi_serial::{serialize, Encodable};
+use log::info;
+use rand::rngs::OsRng;
+
+use darkfi_money_contract::{
+    client::{build_transfer_tx, Coin, EncryptedNote, OwnCoin},
+    MoneyFunction, MONEY_CONTRACT_COIN_ROOTS_TREE,
+};
+
+mod harness;
+use harness::{init_logger, MoneyTestHarness};
+
+#[async_std::test]
+async fn money_contract_atomic_state() -> Result<()> {
+    init_logger()?;
+
+    let mut th = MoneyTestHarness::new().await?;
+    let token_id = TokenId::from(pallas::Base::random(&mut OsRng));
+
+    info!(target: "money", "[Faucet] Building Alice's airdrop tx");
+    let (alicedrop_tx, alicedrop_params) = th.airdrop(100, token_id, &th.alice_kp.public)?;
+    th.alice_merkle_tree.append(&MerkleNode::from(alicedrop_params.outputs[0].coin));
+    let leaf_position = th.alice_merkle_tree.witness().unwrap();
+
+    let ciphertext = alicedrop_params.outputs[0].ciphertext.clone();
+    let ephem_public = alicedrop_params.outputs[0].ephem_public;
+    let note = EncryptedNote { ciphertext, ephem_public }.decrypt(&th.alice_kp.secret)?;
+    let alice_oc = OwnCoin {
+        coin: Coin::from(alicedrop_params.outputs[0].coin),
+        note: note.clone(),
+        secret: th.alice_kp.secret,
+        nullifier: Nullifier::from(poseidon_hash([th.alice_kp.secret.inner(), note.serial])),
+        leaf_position,
+    };
+
+    info!(target: "money", "[Alice] Building payment tx to Bob");
+    let (params, proofs, secret_keys, _spent_coins) = build_transfer_tx(
+        &th.alice_kp,
+        &th.bob_kp.public,
+        100,
+        token_id,
+        pallas::Base::zero(),
+        pallas::Base::zero(),
+        pallas::Base::random(&mut OsRng),
+        &[alice_oc.clone()],
+        &th.alice_merkle_tree,
+        &th.mint_zkbin,
+        &th.mint_pk,
+        &th.burn_zkbin,
+        &th.burn_pk,
+        false,
+    )?;
+
+    let mut data = vec![MoneyFunction::Transfer as u8];
+    params.encode(&mut data)?;
+    let calls = vec![ContractCall { contract_id: th.money_contract_id, data }];
+    let mut alice2bob_tx = Transaction {
+        calls,
+        gas_limit: DEFAULT_GAS_LIMIT,
+        proofs: vec![proofs],
+        signatures: vec![],
+    };
+    let sigs = alice2bob_tx.create_sigs(&mut OsRng, &secret_keys)?;
+    alice2bob_tx.signatures = vec![sigs];
+
+    let coin_roots = {
+        let state = th.bob_state.read().await;
+        state.blockchain.contracts.lookup(
+            &state.blockchain.sled_db,
+            &th.money_contract_id,
+            MONEY_CONTRACT_COIN_ROOTS_TREE,
+        )?
+    };
+
+    info!(target: "money", "[Bob] Executing a batch double spending Alice's coin");
+    let txs = [alicedrop_tx.clone(), alice2bob_tx.clone(), alice2bob_tx.clone()];
+    assert!(th.bob_state.read().await.verify_transactions(&txs, true).await.is_err());
+
+    // Nothing of the first two transactions should have been written
+    assert!(coin_roots.is_empty());
+    assert!(!th.bob_state.read().await.blockchain.nullifiers.contains(&alice_oc.nullifier)?);
+
+    info!(target: "money", "[Bob] Executing the batch without the double spend");
+    let txs = [alicedrop_tx, alice2bob_tx];
+    th.bob_state.read().await.verify_transactions(&txs, true).await?;
+
+    assert!(!coin_roots.is_empty());
+
+    // The validator indexed the nullifier by the transaction spending it
+    let spent_by = th.bob_state.read().await.blockchain.nullifiers.spent_by(&alice_oc.nullifier)?;
+    assert_eq!(spent_by, Some(blake3::hash(&serialize(&alice2bob_tx))));
+
+    Ok(())
+}
@@ -27,7 +27,10 @@
 //!
 //! TODO: Malicious cases
 
-use darkfi::{tx::Transaction, Result};
+use darkfi::{
+    tx::{Transaction, DEFAULT_GAS_LIMIT},
+    Result,
+};
 use darkfi_sdk::{
     crypto::{
         merkle_prelude::*, pallas, pasta_prelude::*, poseidon_hash, MerkleNode, Nullifier, TokenId,
@@ -123,7 +126,8 @@ async fn money_contract_transfer() -> Result<()> {
     alice_params.encode(&mut data)?;
     let calls = vec![ContractCall { contract_id: th.money_contract_id, data }];
     let proofs = vec![alice_proofs];
-    let mut alicedrop_tx = Transaction { calls, proofs, signatures: vec![] };
+    let mut alicedrop_tx =
+        Transaction { calls, gas_limit: DEFAULT_GAS_LIMIT, proofs, signatures: vec![] };
     let sigs = alicedrop_tx.create_sigs(&mut OsRng, &alicedrop_secret_keys)?;
     alicedrop_tx.signatures = vec![sigs];
 
@@ -134,7 +138,8 @@ async fn money_contract_transfer() -> Result<()> {
     bob_params.encode(&mut data)?;
     let calls = vec![ContractCall { contract_id: th.money_contract_id, data }];
     let proofs = vec![bob_proofs];
-    let mut bobdrop_tx = Transaction { calls, proofs, signatures: vec![] };
+    let mut bobdrop_tx =
+        Transaction { calls, gas_limit: DEFAULT_GAS_LIMIT, proofs, signatures: vec![] };
     let sigs = bobdrop_tx.create_sigs(&mut OsRng, &bobdrop_secret_keys)?;
     bobdrop_tx.signatures = vec![sigs];
 
@@ -243,7 +248,8 @@ async fn money_contract_transfer() -> Result<()> {
     alice2bob_params.encode(&mut data)?;
     let calls = vec![ContractCall { contract_id: th.money_contract_id, data }];
     let proofs = vec![alice2bob_proofs];
-    let mut alice2bob_tx = Transaction { calls, proofs, signatures: vec![] };
+    let mut alice2bob_tx =
+        Transaction { calls, gas_limit: DEFAULT_GAS_LIMIT, proofs, signatures: vec![] };
     let sigs = alice2bob_tx.create_sigs(&mut OsRng, &alice2bob_secret_keys)?;
     alice2bob_tx.signatures = vec![sigs];
 
@@ -341,7 +347,8 @@ async fn money_contract_transfer() -> Result<()> {
     bob2alice_params.encode(&mut data)?;
     let calls = vec![ContractCall { contract_id: th.money_contract_id, data }];
     let proofs = vec![bob2alice_proofs];
-    let mut bob2alice_tx = Transaction { calls, proofs, signatures: vec![] };
+    let mut bob2alice_tx =
+        Transaction { calls, gas_limit: DEFAULT_GAS_LIMIT, proofs, signatures: vec![] };
     let sigs = bob2alice_tx.create_sigs(&mut OsRng, &bob2alice_secret_keys)?;
     bob2alice_tx.signatures = vec![sigs];
 
@@ -493,6 +500,7 @@ async fn money_contract_transfer() -> Result<()> {
     swap_full_params.encode(&mut data)?;
     let mut alicebob_swap_tx = Transaction {
         calls: vec![ContractCall { contract_id: th.money_contract_id, data }],
+        gas_limit: DEFAULT_GAS_LIMIT,
         proofs: vec![swap_full_proofs],
         signatures: vec![],
     };
@@ -601,7 +609,8 @@ async fn money_contract_transfer() -> Result<()> {
     alice2alice_params.encode(&mut data)?;
     let calls = vec![ContractCall { contract_id: th.money_contract_id, data }];
     let proofs = vec![alice2alice_proofs];
-    let mut alice2alice_tx = Transaction { calls, proofs, signatures: vec![] };
+    let mut alice2alice_tx =
+        Transaction { calls, gas_limit: DEFAULT_GAS_LIMIT, proofs, signatures: vec![] };
     let sigs = alice2alice_tx.create_sigs(&mut OsRng, &alice2alice_secret_keys)?;
     alice2alice_tx.signatures = vec![sigs];
 
@@ -681,7 +690,8 @@ async fn money_contract_transfer() -> Result<()> {
     bob2bob_params.encode(&mut data)?;
     let calls = vec![ContractCall { contract_id: th.money_contract_id, data }];
     let proofs = vec![bob2bob_proofs];
-    let mut bob2bob_tx = Transaction { calls, proofs, signatures: vec![] };
+    let mut bob2bob_tx =
+        Transaction { calls, gas_limit: DEFAULT_GAS_LIMIT, proofs, signatures: vec![] };
     let sigs = bob2bob_tx.create_sigs(&mut OsRng, &bob2bob_secret_keys)?;
     bob2bob_tx.signatures = vec![sigs];
 
@@ -804,6 +814,7 @@ async fn money_contract_transfer() -> Result<()> {
     swap_full_params.encode(&mut data)?;
     let mut alicebob_swap_tx = Transaction {
         calls: vec![ContractCall { contract_id: th.money_contract_id, data }],
+        gas_limit: DEFAULT_GAS_LIMIT,
         proofs: vec![swap_full_proofs],
         signatures: vec![],
     };
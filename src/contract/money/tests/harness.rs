@@ -22,9 +22,9 @@ use darkfi::{
         ValidatorState, ValidatorStatePtr, TESTNET_BOOTSTRAP_TIMESTAMP, TESTNET_GENESIS_HASH_BYTES,
         TESTNET_GENESIS_TIMESTAMP, TESTNET_INITIAL_DISTRIBUTION,
     },
-    tx::Transaction,
+    tx::{Transaction, DEFAULT_GAS_LIMIT},
     wallet::WalletDb,
-    zk::{empty_witnesses, ProvingKey, ZkCircuit},
+    zk::{empty_witnesses, ParamsCache, ProvingKey, ZkCircuit},
     zkas::ZkBinary,
     Result,
 };
@@ -102,6 +102,10 @@ impl MoneyTestHarness {
         let bob_sled_db = sled::Config::new().temporary(true).open()?;
         let charlie_sled_db = sled::Config::new().temporary(true).open()?;
 
+        // The validators share a params cache, so the verifying keys
+        // only get built once.
+        let params_cache = ParamsCache::in_memory();
+
         let faucet_state = ValidatorState::new(
             &faucet_sled_db,
             *TESTNET_BOOTSTRAP_TIMESTAMP,
@@ -111,6 +115,7 @@ impl MoneyTestHarness {
             faucet_wallet,
             faucet_pubkeys.clone(),
             false,
+            params_cache.clone(),
         )
         .await?;
 
@@ -123,6 +128,7 @@ impl MoneyTestHarness {
             alice_wallet,
             faucet_pubkeys.clone(),
             false,
+            params_cache.clone(),
         )
         .await?;
 
@@ -135,6 +141,7 @@ impl MoneyTestHarness {
             bob_wallet,
             faucet_pubkeys.clone(),
             false,
+            params_cache.clone(),
         )
         .await?;
 
@@ -147,6 +154,7 @@ impl MoneyTestHarness {
             charlie_wallet,
             faucet_pubkeys.clone(),
             false,
+            params_cache,
         )
         .await?;
 
@@ -170,10 +178,9 @@ impl MoneyTestHarness {
         let burn_circuit = ZkCircuit::new(burn_witnesses, burn_zkbin.clone());
 
         info!(target: "money_harness", "Creating zk proving keys");
-        let k = 13;
         let mut proving_keys = HashMap::<[u8; 32], Vec<(&str, ProvingKey)>>::new();
-        let mint_pk = ProvingKey::build(k, &mint_circuit);
-        let burn_pk = ProvingKey::build(k, &burn_circuit);
+        let mint_pk = ProvingKey::build(mint_zkbin.k, &mint_circuit);
+        let burn_pk = ProvingKey::build(burn_zkbin.k, &burn_circuit);
         let pks = vec![
             (MONEY_CONTRACT_ZKAS_MINT_NS_V1, mint_pk.clone()),
             (MONEY_CONTRACT_ZKAS_BURN_NS_V1, burn_pk.clone()),
@@ -237,7 +244,8 @@ impl MoneyTestHarness {
         params.encode(&mut data)?;
         let calls = vec![ContractCall { contract_id, data }];
         let proofs = vec![proofs];
-        let mut tx = Transaction { calls, proofs, signatures: vec![] };
+        let mut tx =
+            Transaction { calls, gas_limit: DEFAULT_GAS_LIMIT, proofs, signatures: vec![] };
         let sigs = tx.create_sigs(&mut OsRng, &secret_keys)?;
         tx.signatures = vec![sigs];
 
@@ -18,7 +18,10 @@
 
 use std::{env, str::FromStr};
 
-use darkfi::{tx::Transaction, Result};
+use darkfi::{
+    tx::{Transaction, DEFAULT_GAS_LIMIT},
+    Result,
+};
 use darkfi_sdk::{
     crypto::{
         merkle_prelude::*, pallas, pasta_prelude::*, poseidon_hash, MerkleNode, Nullifier, TokenId,
@@ -116,7 +119,8 @@ async fn alice2alice_random_amounts() -> Result<()> {
         params.encode(&mut data)?;
         let calls = vec![ContractCall { contract_id, data }];
         let proofs = vec![proofs];
-        let mut tx = Transaction { calls, proofs, signatures: vec![] };
+        let mut tx =
+            Transaction { calls, gas_limit: DEFAULT_GAS_LIMIT, proofs, signatures: vec![] };
         let sigs = tx.create_sigs(&mut OsRng, &secret_keys)?;
         tx.signatures = vec![sigs];
 
@@ -258,7 +262,8 @@ async fn alice2alice_random_amounts_multiplecoins() -> Result<()> {
             params.encode(&mut data)?;
             let calls = vec![ContractCall { contract_id, data }];
             let proofs = vec![proofs];
-            let mut tx = Transaction { calls, proofs, signatures: vec![] };
+            let mut tx =
+                Transaction { calls, gas_limit: DEFAULT_GAS_LIMIT, proofs, signatures: vec![] };
             let sigs = tx.create_sigs(&mut OsRng, &secret_keys)?;
             tx.signatures = vec![sigs];
 
@@ -160,4 +160,9 @@ impl net::Message for LookupMapResponse {
     fn name() -> &'static str {
         "lookupmapresponse"
     }
+
+    fn max_payload_size() -> usize {
+        // Bulk sync data, only bounded by the global cap
+        usize::MAX
+    }
 }
@@ -142,6 +142,18 @@ pub enum Error {
     #[error("Malformed packet")]
     MalformedPacket,
 
+    #[error("Packet for command `{0}` has an oversized payload of {1} bytes")]
+    OversizedPacket(String, u64),
+
+    #[error("Peer is banned: {0}")]
+    BannedPeer(String),
+
+    #[error("Peer is incompatible: {0}")]
+    IncompatiblePeer(String),
+
+    #[error("Peer exceeded the rate limit of {0} messages")]
+    RateLimitExceeded(String),
+
     #[error("Socks proxy error: {0}")]
     SocksError(String),
 
@@ -298,6 +310,12 @@ pub enum Error {
     #[error("Slot checkpoint {0} not found in database")]
     SlotCheckpointNotFound(u64),
 
+    #[error("Undo log of block in slot {0} not found in database")]
+    UndoLogNotFound(u64),
+
+    #[error("Reorg reverting more than {0} finalized blocks")]
+    ReorgTooDeep(usize),
+
     #[error("Contract {0} not found in database")]
     ContractNotFound(String),
 
@@ -349,6 +367,14 @@ pub enum Error {
     #[error("wasm runtime out of memory")]
     WasmerOomError(String),
 
+    #[cfg(feature = "wasm-runtime")]
+    #[error("Contract ran out of gas (limit: {0})")]
+    OutOfGas(u64),
+
+    #[cfg(feature = "wasm-runtime")]
+    #[error("Transaction gas limit {0} is above the maximum of {1}")]
+    GasLimitTooHigh(u64, u64),
+
     // TODO: FIXME: The strings are wrong
     #[cfg(feature = "darkfi-sdk")]
     #[error("contract initialize error")]
@@ -366,6 +392,10 @@ pub enum Error {
     #[error("contract execution error")]
     ContractExecError(u64),
 
+    #[cfg(feature = "wasm-runtime")]
+    #[error("Contract deployment failed: {0}")]
+    ContractDeployFailed(String),
+
     // ====================
     // Miscellaneous errors
     // ====================
@@ -375,6 +405,9 @@ pub enum Error {
     #[error("Infallible error: {0}")]
     InfallibleError(String),
 
+    #[error("Worker thread panicked")]
+    ThreadPanicked,
+
     #[cfg(feature = "smol")]
     #[error("async_channel sender error: {0}")]
     AsyncChannelSendError(String),
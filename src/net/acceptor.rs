@@ -19,13 +19,16 @@
 use std::{env, fs};
 
 use async_std::sync::{Arc, Mutex};
-use log::{error, info};
+use log::{debug, error, info};
 use smol::Executor;
 use url::Url;
 
 use super::{
-    transport::{TcpTransport, TorTransport, Transport, TransportListener, TransportName},
-    Channel, ChannelPtr, SessionWeakPtr,
+    transport::{
+        MemoryNetwork, MemoryTransport, TcpTransport, TorTransport, Transport, TransportListener,
+        TransportName,
+    },
+    Channel, ChannelPtr, Session, SessionWeakPtr,
 };
 use crate::{
     system::{StoppableTask, StoppableTaskPtr, Subscriber, SubscriberPtr, Subscription},
@@ -132,6 +135,21 @@ impl Acceptor {
 
                 accept!(listener, transport, upgrade);
             }
+            TransportName::Memory => {
+                // Listen on the memory network of our p2p instance
+                let session = self.session.lock().await.clone();
+                let network = match session.and_then(|s| s.upgrade()) {
+                    Some(s) => s.p2p().settings().memory_network.clone(),
+                    None => MemoryNetwork::global(),
+                };
+                let transport = MemoryTransport::new(network, Some(accept_url.clone()));
+                let listener = transport.clone().listen_on(accept_url.clone());
+                accept!(listener, transport, None);
+            }
+            TransportName::Socks5(_) => {
+                error!(target: "net::acceptor", "SOCKS5 proxies only support outbound connections: {}", accept_url);
+                return Err(Error::UnsupportedTransport(accept_url.scheme().to_string()))
+            }
             _ => unimplemented!(),
         }
         Ok(())
@@ -165,8 +183,17 @@ impl Acceptor {
         loop {
             match listener.next().await {
                 Ok((stream, url)) => {
-                    let channel =
-                        Channel::new(stream, url, self.session.lock().await.clone().unwrap()).await;
+                    let session = self.session.lock().await.clone().unwrap();
+
+                    // Refuse banned peers, dropping the stream closes the connection
+                    if let Some(s) = session.upgrade() {
+                        if s.p2p().bans().is_banned(&url).await {
+                            debug!(target: "net::acceptor", "Refusing banned peer {}", url);
+                            continue
+                        }
+                    }
+
+                    let channel = Channel::new(stream, url, session).await;
                     self.channel_subscriber.notify(Ok(channel)).await;
                 }
                 Err(e) => {
@@ -0,0 +1,216 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{collections::HashMap, net::IpAddr};
+
+use async_std::sync::{Arc, Mutex};
+use log::{debug, info};
+use url::{Host, Url};
+
+use super::constants::BAN_SCORE_DECAY_SECONDS;
+use crate::util::time::Timestamp;
+
+/// Pointer to the ban list.
+pub type BanListPtr = Arc<BanList>;
+
+/// Keeps track of peer misbehavior and of the hosts we refuse to talk to.
+/// Peers are identified by the host part of their address, since inbound
+/// connections come from ephemeral ports. Loopback peers are the exception,
+/// as all the ones coming in through a local proxy such as Tor share the
+/// same host, so they are identified by their full address instead.
+/// Scores decay by one point every [`BAN_SCORE_DECAY_SECONDS`].
+pub struct BanList {
+    /// Misbehavior score of each peer, along with the UNIX timestamp
+    /// it was last updated at
+    scores: Mutex<HashMap<String, (u32, u64)>>,
+    /// Banned hosts, along with the UNIX timestamp their ban expires at
+    bans: Mutex<HashMap<String, u64>>,
+    /// Score at which a host gets banned
+    threshold: u32,
+    /// How long a host stays banned for, in seconds
+    duration: u64,
+}
+
+impl BanList {
+    /// Create a new ban list.
+    pub fn new(threshold: u32, duration: u64) -> Arc<Self> {
+        Arc::new(Self {
+            scores: Mutex::new(HashMap::new()),
+            bans: Mutex::new(HashMap::new()),
+            threshold,
+            duration,
+        })
+    }
+
+    /// Increase the misbehavior score of the given address by `weight`.
+    /// Once the score reaches the threshold the host is banned for the
+    /// configured duration. Returns true if the host got banned.
+    pub async fn misbehaving(&self, addr: &Url, weight: u32) -> bool {
+        let Some(key) = ban_key(addr) else { return false };
+
+        let now = now();
+        let mut scores = self.scores.lock().await;
+        let (score, updated) = scores.entry(key.clone()).or_insert((0, now));
+        *score = decay(*score, now.saturating_sub(*updated)).saturating_add(weight);
+        *updated = now;
+        debug!(target: "net::bans::misbehaving()", "bans::misbehaving() [{} score is now {}]", key, score);
+        if *score < self.threshold {
+            return false
+        }
+
+        scores.remove(&key);
+        drop(scores);
+        self.ban(&key, self.duration).await;
+        true
+    }
+
+    /// Current misbehavior score of the peer behind the given address.
+    pub async fn score(&self, addr: &Url) -> u32 {
+        let Some(key) = ban_key(addr) else { return 0 };
+        match self.scores.lock().await.get(&key) {
+            Some((score, updated)) => decay(*score, now().saturating_sub(*updated)),
+            None => 0,
+        }
+    }
+
+    /// Ban a host for `duration` seconds.
+    pub async fn ban(&self, host: &str, duration: u64) {
+        info!(target: "net::bans::ban()", "Banning {} for {} seconds", host, duration);
+        let until = now().saturating_add(duration);
+        self.bans.lock().await.insert(host.to_string(), until);
+    }
+
+    /// Lift the ban of a host. Returns false if it wasn't banned.
+    pub async fn unban(&self, host: &str) -> bool {
+        self.scores.lock().await.remove(host);
+        self.bans.lock().await.remove(host).is_some()
+    }
+
+    /// Check if the peer behind the given address is banned.
+    /// Expired bans are dropped on lookup.
+    pub async fn is_banned(&self, addr: &Url) -> bool {
+        let Some(key) = ban_key(addr) else { return false };
+
+        let mut bans = self.bans.lock().await;
+        match bans.get(&key) {
+            Some(until) if *until > now() => true,
+            Some(_) => {
+                bans.remove(&key);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Return the currently banned hosts, along with the UNIX timestamp
+    /// their ban expires at.
+    pub async fn load_all(&self) -> Vec<(String, u64)> {
+        let now = now();
+        let mut bans = self.bans.lock().await;
+        bans.retain(|_, until| *until > now);
+        bans.iter().map(|(host, until)| (host.clone(), *until)).collect()
+    }
+}
+
+/// Key the score and ban of the peer behind the given address are kept
+/// under: its host, or its full address if it is on loopback.
+pub(super) fn ban_key(addr: &Url) -> Option<String> {
+    let loopback = match addr.host()? {
+        Host::Ipv4(ip) => IpAddr::V4(ip).is_loopback(),
+        Host::Ipv6(ip) => IpAddr::V6(ip).is_loopback(),
+        Host::Domain(domain) => domain == "localhost",
+    };
+
+    match loopback {
+        true => Some(addr.to_string()),
+        false => addr.host_str().map(|host| host.to_string()),
+    }
+}
+
+/// Score left after `elapsed` seconds of decay.
+fn decay(score: u32, elapsed: u64) -> u32 {
+    let decayed = (elapsed / BAN_SCORE_DECAY_SECONDS).min(u32::MAX as u64) as u32;
+    score.saturating_sub(decayed)
+}
+
+/// Current UNIX timestamp in seconds.
+fn now() -> u64 {
+    Timestamp::current_time().0 as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::{ban_key, decay, BanList, BAN_SCORE_DECAY_SECONDS};
+
+    #[async_std::test]
+    async fn test_ban_scoring() {
+        let bans = BanList::new(100, 3600);
+        let peer = Url::parse("tcp://10.0.0.1:41234").unwrap();
+        let same_host = Url::parse("tcp://10.0.0.1:51515").unwrap();
+        let other = Url::parse("tcp://10.0.0.2:41234").unwrap();
+
+        // Scores accumulate until the threshold is crossed
+        assert!(!bans.misbehaving(&peer, 60).await);
+        assert!(!bans.is_banned(&peer).await);
+        assert!(bans.misbehaving(&same_host, 60).await);
+
+        // Bans apply to the host, regardless of port
+        assert!(bans.is_banned(&peer).await);
+        assert!(bans.is_banned(&same_host).await);
+        assert!(!bans.is_banned(&other).await);
+        assert_eq!(bans.load_all().await.len(), 1);
+
+        assert!(bans.unban("10.0.0.1").await);
+        assert!(!bans.unban("10.0.0.1").await);
+        assert!(!bans.is_banned(&peer).await);
+
+        // Expired bans are dropped
+        bans.ban("10.0.0.2", 0).await;
+        assert!(!bans.is_banned(&other).await);
+        assert!(bans.load_all().await.is_empty());
+    }
+
+    #[async_std::test]
+    async fn test_ban_loopback() {
+        let bans = BanList::new(100, 3600);
+        let tor_peer = Url::parse("tcp://127.0.0.1:41234").unwrap();
+        let other_tor_peer = Url::parse("tcp://127.0.0.1:51515").unwrap();
+
+        // Loopback peers don't share their score or ban
+        assert!(!bans.misbehaving(&tor_peer, 60).await);
+        assert_eq!(bans.score(&other_tor_peer).await, 0);
+        assert!(bans.misbehaving(&tor_peer, 60).await);
+        assert!(bans.is_banned(&tor_peer).await);
+        assert!(!bans.is_banned(&other_tor_peer).await);
+
+        assert_eq!(ban_key(&tor_peer).unwrap(), "tcp://127.0.0.1:41234");
+        assert_eq!(ban_key(&Url::parse("tcp://[::1]:8080").unwrap()).unwrap(), "tcp://[::1]:8080");
+        assert_eq!(ban_key(&Url::parse("tcp://10.0.0.1:8080").unwrap()).unwrap(), "10.0.0.1");
+    }
+
+    #[test]
+    fn test_score_decay() {
+        assert_eq!(decay(50, 0), 50);
+        assert_eq!(decay(50, BAN_SCORE_DECAY_SECONDS - 1), 50);
+        assert_eq!(decay(50, BAN_SCORE_DECAY_SECONDS * 20), 30);
+        assert_eq!(decay(50, BAN_SCORE_DECAY_SECONDS * 100), 0);
+        assert_eq!(decay(50, u64::MAX), 0);
+    }
+}
@@ -16,6 +16,11 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
 use async_std::sync::{Arc, Mutex};
 use futures::{
     io::{ReadHalf, WriteHalf},
@@ -28,8 +33,12 @@ use smol::Executor;
 use url::Url;
 
 use super::{
+    constants::{BAN_SCORE_PROTOCOL_VIOLATION, PING_HISTORY_SIZE},
     message,
     message_subscriber::{MessageSubscription, MessageSubsystem},
+    metrics::{ChannelMetrics, TrafficStats},
+    rate_limit::RateLimiter,
+    send_queue::SendQueue,
     transport::TransportStream,
     Session, SessionBitflag, SessionWeakPtr,
 };
@@ -45,8 +54,15 @@ pub type ChannelPtr = Arc<Channel>;
 struct ChannelInfo {
     random_id: u32,
     remote_node_id: String,
+    remote_version: u32,
+    remote_services: u64,
+    remote_features: Vec<String>,
     last_msg: String,
     last_status: String,
+    traffic: TrafficStats,
+    connected_at: Instant,
+    handshake_ms: Option<u64>,
+    ping_rtts_ms: VecDeque<u64>,
     // Message log which is cleared on querying get_info
     log: Option<Mutex<Vec<(NanoTimestamp, String, String)>>>,
 }
@@ -61,8 +77,15 @@ impl ChannelInfo {
         Self {
             random_id: rand::thread_rng().gen(),
             remote_node_id: String::new(),
+            remote_version: 0,
+            remote_services: 0,
+            remote_features: Vec::new(),
             last_msg: String::new(),
             last_status: String::new(),
+            traffic: TrafficStats::default(),
+            connected_at: Instant::now(),
+            handshake_ms: None,
+            ping_rtts_ms: VecDeque::new(),
             log,
         }
     }
@@ -82,8 +105,15 @@ impl ChannelInfo {
         json!({
             "random_id": self.random_id,
             "remote_node_id": self.remote_node_id,
+            "remote_version": self.remote_version,
+            "remote_services": self.remote_services,
+            "remote_features": self.remote_features,
             "last_msg": self.last_msg,
             "last_status": self.last_status,
+            "traffic": self.traffic.to_json(),
+            "handshake_ms": self.handshake_ms,
+            "ping_rtts_ms": self.ping_rtts_ms,
+            "uptime_seconds": self.connected_at.elapsed().as_secs(),
             "log": log,
         })
     }
@@ -98,9 +128,14 @@ pub struct Channel {
     message_subsystem: MessageSubsystem,
     stop_subscriber: SubscriberPtr<Error>,
     receive_task: StoppableTaskPtr,
+    send_task: StoppableTaskPtr,
+    send_queue: SendQueue,
+    send_limiter: RateLimiter,
+    recv_limiter: RateLimiter,
     stopped: Mutex<bool>,
     info: Mutex<ChannelInfo>,
     session: SessionWeakPtr,
+    max_payload_size: usize,
 }
 
 impl Channel {
@@ -119,7 +154,9 @@ impl Channel {
         let message_subsystem = MessageSubsystem::new();
         Self::setup_dispatchers(&message_subsystem).await;
 
-        let channel_log = session.upgrade().unwrap().p2p().settings().channel_log;
+        let settings = session.upgrade().unwrap().p2p().settings();
+        let channel_log = settings.channel_log;
+        let max_payload_size = settings.max_payload_size;
 
         Arc::new(Self {
             reader,
@@ -128,9 +165,14 @@ impl Channel {
             message_subsystem,
             stop_subscriber: Subscriber::new(),
             receive_task: StoppableTask::new(),
+            send_task: StoppableTask::new(),
+            send_queue: SendQueue::new(),
+            send_limiter: RateLimiter::new(settings.send_rate_limits.clone()),
+            recv_limiter: RateLimiter::new(settings.recv_rate_limits.clone()),
             stopped: Mutex::new(false),
             info: Mutex::new(ChannelInfo::new(channel_log)),
             session,
+            max_payload_size,
         })
     }
 
@@ -139,7 +181,8 @@ impl Channel {
     }
 
     /// Starts the channel. Runs a receive loop to start receiving messages or
-    /// handles a network failure.
+    /// handles a network failure, and a send loop writing out the queued
+    /// messages.
     pub fn start(self: Arc<Self>, executor: Arc<Executor<'_>>) {
         debug!(target: "net::channel::start()", "START, address={}", self.address());
         let self2 = self.clone();
@@ -147,6 +190,13 @@ impl Channel {
             self.clone().main_receive_loop(),
             |result| self2.handle_stop(result),
             Error::NetworkServiceStopped,
+            executor.clone(),
+        );
+        self.send_task.clone().start(
+            self.clone().main_send_loop(),
+            // Send errors get reported to the senders, who stop the channel
+            |_| async {},
+            Error::NetworkServiceStopped,
             executor,
         );
         debug!(target: "net::channel::start()", "END, address={}", self.address());
@@ -162,6 +212,8 @@ impl Channel {
 
             self.stop_subscriber.notify(Error::ChannelStopped).await;
             self.receive_task.stop().await;
+            self.send_task.stop().await;
+            self.send_queue.close().await;
             self.message_subsystem.trigger_error(Error::ChannelStopped).await;
             debug!(target: "net::channel::stop()", "END, address={}", self.address());
         }
@@ -203,6 +255,8 @@ impl Channel {
             }
         }
 
+        self.send_limiter.acquire(M::name()).await;
+
         // Catch failure and stop channel, return a net error
         let result = match self.send_message(message).await {
             Ok(()) => Ok(()),
@@ -230,8 +284,8 @@ impl Channel {
 
     /// Implements send message functionality. Creates a new payload and encodes
     /// it. Then creates a message packet- the base type of the network- and
-    /// copies the payload into it. Then we send the packet over the TCP
-    /// stream.
+    /// copies the payload into it. Then we queue the packet with the message's
+    /// priority and wait for the send loop to write it to the stream.
     async fn send_message<M: message::Message>(&self, message: M) -> Result<()> {
         let mut payload = Vec::new();
         message.encode(&mut payload)?;
@@ -241,13 +295,30 @@ impl Channel {
 
         {
             let info = &mut *self.info.lock().await;
+            info.traffic.record_sent(&packet.command, packet.size());
             if let Some(l) = &info.log {
                 l.lock().await.push((time, "send".to_string(), packet.command.clone()));
             };
         }
 
-        let stream = &mut *self.writer.lock().await;
-        message::send_packet(stream, packet).await
+        self.send_queue.send(M::priority(), packet).await
+    }
+
+    /// Run the send loop. Write out the queued packets in priority order,
+    /// until writing fails.
+    async fn main_send_loop(self: Arc<Self>) -> Result<()> {
+        let writer = &mut *self.writer.lock().await;
+
+        loop {
+            let (packet, reply) = self.send_queue.next().await?;
+            let result = message::send_packet(writer, packet).await;
+            let failed = result.is_err();
+            // The sender might have given up waiting
+            let _ = reply.send(result).await;
+            if failed {
+                return Err(Error::ChannelStopped)
+            }
+        }
     }
 
     /// Subscribe to a messages on the message subsystem.
@@ -280,6 +351,57 @@ impl Channel {
         self.info.lock().await.remote_node_id = remote_node_id;
     }
 
+    /// Services bitfield the peer advertised in the version handshake.
+    pub async fn remote_services(&self) -> u64 {
+        self.info.lock().await.remote_services
+    }
+
+    /// Names of the optional protocols the peer advertised in the version
+    /// handshake.
+    pub async fn remote_features(&self) -> Vec<String> {
+        self.info.lock().await.remote_features.clone()
+    }
+
+    /// Traffic counters of this channel.
+    pub async fn traffic(&self) -> TrafficStats {
+        self.info.lock().await.traffic.clone()
+    }
+
+    /// Snapshot of the metrics of this channel.
+    pub async fn metrics(&self) -> ChannelMetrics {
+        let info = self.info.lock().await;
+        ChannelMetrics {
+            address: self.address(),
+            traffic: info.traffic.clone(),
+            handshake_ms: info.handshake_ms,
+            ping_rtts_ms: info.ping_rtts_ms.iter().copied().collect(),
+            uptime_seconds: info.connected_at.elapsed().as_secs(),
+        }
+    }
+
+    /// Record how long the version handshake took.
+    pub async fn set_handshake_latency(&self, latency: Duration) {
+        self.info.lock().await.handshake_ms = Some(latency.as_millis() as u64);
+    }
+
+    /// Record a ping round trip time, keeping the last [`PING_HISTORY_SIZE`].
+    pub async fn record_ping_rtt(&self, rtt: Duration) {
+        let rtts = &mut self.info.lock().await.ping_rtts_ms;
+        if rtts.len() == PING_HISTORY_SIZE {
+            rtts.pop_front();
+        }
+        rtts.push_back(rtt.as_millis() as u64);
+    }
+
+    /// Record what the peer told us about itself in the version handshake.
+    pub async fn set_remote_version(&self, version: &message::VersionMessage) {
+        let info = &mut *self.info.lock().await;
+        info.remote_node_id = version.node_id.clone();
+        info.remote_version = version.version;
+        info.remote_services = version.services;
+        info.remote_features = version.features.clone();
+    }
+
     /// End of file error. Triggered when unexpected end of file occurs.
     fn is_eof_error(err: Error) -> bool {
         match err {
@@ -288,6 +410,15 @@ impl Channel {
         }
     }
 
+    /// Returns true if the error was caused by the peer not following the
+    /// protocol, like sending malformed or oversized packets.
+    pub fn is_misbehaviour_error(err: &Error) -> bool {
+        matches!(
+            err,
+            Error::MalformedPacket | Error::OversizedPacket(..) | Error::RateLimitExceeded(_)
+        )
+    }
+
     /// Perform network handshake for message subsystem dispatchers.
     async fn setup_dispatchers(message_subsystem: &MessageSubsystem) {
         message_subsystem.add_dispatch::<message::VersionMessage>().await;
@@ -312,7 +443,7 @@ impl Channel {
         let reader = &mut *self.reader.lock().await;
 
         loop {
-            let packet = match message::read_packet(reader).await {
+            let packet = match self.read_packet(reader).await {
                 Ok(packet) => packet,
                 Err(err) => {
                     if Self::is_eof_error(err.clone()) {
@@ -321,6 +452,10 @@ impl Channel {
                             "Inbound connection {} disconnected",
                             self.address()
                         );
+                    } else if Self::is_misbehaviour_error(&err) {
+                        let p2p = self.session().p2p();
+                        p2p.misbehaving(&self, BAN_SCORE_PROTOCOL_VIOLATION, &err.to_string())
+                            .await;
                     } else {
                         error!(
                             target: "net::channel::main_receive_loop()",
@@ -342,6 +477,7 @@ impl Channel {
                 let info = &mut *self.info.lock().await;
                 info.last_msg = packet.command.clone();
                 info.last_status = "recv".to_string();
+                info.traffic.record_recv(&packet.command, packet.size());
                 let time = NanoTimestamp::current_time();
                 //let time = time::unix_timestamp()?;
                 if let Some(l) = &info.log {
@@ -354,6 +490,20 @@ impl Channel {
         }
     }
 
+    /// Read the next packet from the stream, enforcing the inbound rate
+    /// limits.
+    async fn read_packet(
+        &self,
+        reader: &mut ReadHalf<Box<dyn TransportStream>>,
+    ) -> Result<message::Packet> {
+        let packet =
+            message::read_packet(reader, &self.message_subsystem, self.max_payload_size).await?;
+        if !self.recv_limiter.try_acquire(&packet.command).await {
+            return Err(Error::RateLimitExceeded(packet.command))
+        }
+        Ok(packet)
+    }
+
     /// Handle network errors. Panic if error passes silently, otherwise
     /// broadcast the error.
     async fn handle_stop(self: Arc<Self>, result: Result<()>) {
@@ -23,8 +23,10 @@ use log::error;
 use url::Url;
 
 use super::{
-    transport::{TcpTransport, TorTransport, Transport, TransportName},
-    Channel, ChannelPtr, SessionWeakPtr, SettingsPtr,
+    transport::{
+        MemoryTransport, Socks5Transport, TcpTransport, TorTransport, Transport, TransportName,
+    },
+    Channel, ChannelPtr, Session, SessionWeakPtr, SettingsPtr,
 };
 use crate::{Error, Result};
 
@@ -41,7 +43,14 @@ impl Connector {
     }
 
     /// Establish an outbound connection.
+    /// Refuses to connect to banned peers.
     pub async fn connect(&self, connect_url: Url) -> Result<ChannelPtr> {
+        if let Some(session) = self.session.upgrade() {
+            if session.p2p().bans().is_banned(&connect_url).await {
+                return Err(Error::BannedPeer(connect_url.to_string()))
+            }
+        }
+
         let transport_name = TransportName::try_from(connect_url.clone())?;
         self.connect_channel(
             connect_url,
@@ -106,6 +115,24 @@ impl Connector {
 
                 connect!(stream, transport, upgrade)
             }
+            TransportName::Socks5(upgrade) => {
+                let Some(proxy_url) = self.settings.socks5_proxy.clone() else {
+                    error!(target: "net::connector", "No SOCKS5 proxy configured to reach {}", connect_url);
+                    return Err(Error::ConnectFailed)
+                };
+
+                let transport = Socks5Transport::new(proxy_url)?;
+                let stream = transport.clone().dial(connect_url.clone(), Some(timeout));
+                connect!(stream, transport, upgrade)
+            }
+            TransportName::Memory => {
+                // Dial from our own memory address, if we listen on one
+                let local = self.settings.inbound.iter().find(|u| u.scheme() == "memory");
+                let transport =
+                    MemoryTransport::new(self.settings.memory_network.clone(), local.cloned());
+                let stream = transport.clone().dial(connect_url.clone(), Some(timeout));
+                connect!(stream, transport, None)
+            }
             _ => unimplemented!(),
         }
     }
@@ -16,6 +16,68 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+/// Default maximum payload size of the packets we accept from peers
+pub const MAX_PAYLOAD_SIZE: usize = 32 * 1024 * 1024;
+
+/// Maximum payload size of a message, unless it sets its own
+pub const MAX_MESSAGE_PAYLOAD_SIZE: usize = 4 * 1024 * 1024;
+
+/// Consecutive failed connection attempts after which a host is evicted
+pub const HOST_MAX_FAILURES: u32 = 5;
+
+/// Maximum number of hosts kept in the host list
+pub const MAX_HOSTS: usize = 4096;
+
+/// Interval at which the host list gets saved to disk, in seconds
+pub const HOSTLIST_SAVE_INTERVAL: u64 = 5 * 60;
+
+/// Default number of anchor connections kept across restarts
+pub const ANCHOR_CONNECTIONS: u32 = 2;
+
+/// Default misbehavior score at which a peer gets banned
+pub const BAN_THRESHOLD: u32 = 100;
+
+/// Default duration of a ban, in seconds
+pub const BAN_DURATION_SECONDS: u64 = 24 * 60 * 60;
+
+/// Seconds it takes for a misbehavior score to decrease by one point
+pub const BAN_SCORE_DECAY_SECONDS: u64 = 60;
+
+/// Misbehavior score for sending malformed or oversized packets
+pub const BAN_SCORE_PROTOCOL_VIOLATION: u32 = 50;
+
+/// Misbehavior score for replying to a ping with the wrong nonce
+pub const BAN_SCORE_WRONG_PONG: u32 = 20;
+
+/// Version of the p2p wire protocol we speak, sent in the version handshake
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Protocol version of the peers from before the version handshake carried
+/// one, which only send their node ID
+pub const LEGACY_PROTOCOL_VERSION: u32 = 0;
+
+/// Oldest peer protocol version we are still able to talk to
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Clock difference to a peer above which we warn about its timestamp
+pub const MAX_CLOCK_SKEW_SECONDS: u64 = 2 * 60 * 60;
+
+/// Default maximum number of inbound connections
+pub const MAX_INBOUND_CONNECTIONS: usize = 64;
+
+/// Default maximum number of inbound connections from a single IP
+pub const MAX_INBOUND_CONNECTIONS_PER_IP: usize = 4;
+
+/// Default maximum number of inbound connections from a single /24 (IPv4)
+/// or /48 (IPv6) subnet
+pub const MAX_INBOUND_CONNECTIONS_PER_SUBNET: usize = 16;
+
+/// Default maximum number of inbound handshakes started per second
+pub const MAX_INBOUND_HANDSHAKES_PER_SECOND: u32 = 16;
+
+/// Number of ping round trip times kept per channel
+pub const PING_HISTORY_SIZE: usize = 32;
+
 /// Localnet addresses
 pub const LOCALNET: [&str; 5] = ["localhost", "0.0.0.0", "[::]", "127.0.0.1", "[::1]"];
 
@@ -16,26 +16,81 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::{
-    collections::{HashMap, HashSet},
-    net::IpAddr,
-};
+use std::{collections::HashMap, fs, net::IpAddr, path::Path};
 
 use async_std::sync::{Arc, Mutex};
 use ipnet::{Ipv4Net, Ipv6Net};
 use iprange::IpRange;
 use log::{debug, error, warn};
+use rand::seq::SliceRandom;
 use url::Url;
 
-use super::constants::{IP4_PRIV_RANGES, IP6_PRIV_RANGES, LOCALNET};
-use crate::util::encoding::base32;
+use super::constants::{HOST_MAX_FAILURES, IP4_PRIV_RANGES, IP6_PRIV_RANGES, LOCALNET, MAX_HOSTS};
+use crate::{
+    util::{
+        encoding::base32,
+        file::{load_file, save_file},
+        time::Timestamp,
+    },
+    Result,
+};
 
 /// Pointer to hosts class.
 pub type HostsPtr = Arc<Hosts>;
 
+/// What we know about a host address.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HostInfo {
+    /// Last time the address was advertised to us or we connected to it
+    pub last_seen: u64,
+    /// Last time we successfully connected to the address, 0 if never
+    pub last_connected: u64,
+    /// Consecutive failed connection attempts
+    pub failures: u32,
+    /// Peer that advertised the address to us, if any
+    pub source: Option<Url>,
+}
+
+impl HostInfo {
+    fn new(source: Option<Url>) -> Self {
+        Self { last_seen: now(), source, ..Default::default() }
+    }
+
+    /// Serialize the entry into a hostlist line.
+    fn to_line(&self, addr: &Url) -> String {
+        let source = match &self.source {
+            Some(s) => s.to_string(),
+            None => "-".to_string(),
+        };
+        format!(
+            "{}\t{}\t{}\t{}\t{}",
+            addr, self.last_seen, self.last_connected, self.failures, source
+        )
+    }
+
+    /// Parse a hostlist line.
+    fn from_line(line: &str) -> Option<(Url, Self)> {
+        let parts: Vec<&str> = line.split('\t').collect();
+        if parts.len() != 5 {
+            return None
+        }
+
+        let addr = Url::parse(parts[0]).ok()?;
+        let last_seen = parts[1].parse().ok()?;
+        let last_connected = parts[2].parse().ok()?;
+        let failures = parts[3].parse().ok()?;
+        let source = match parts[4] {
+            "-" => None,
+            s => Some(Url::parse(s).ok()?),
+        };
+
+        Some((addr, Self { last_seen, last_connected, failures, source }))
+    }
+}
+
 /// Manages a store of network addresses.
 pub struct Hosts {
-    addrs: Mutex<HashSet<Url>>,
+    addrs: Mutex<HashMap<Url, HostInfo>>,
     localnet: bool,
     ipv4_range: IpRange<Ipv4Net>,
     ipv6_range: IpRange<Ipv6Net>,
@@ -54,11 +109,12 @@ impl Hosts {
         ipv4_range.simplify();
         ipv6_range.simplify();
 
-        Arc::new(Self { addrs: Mutex::new(HashSet::new()), localnet, ipv4_range, ipv6_range })
+        Arc::new(Self { addrs: Mutex::new(HashMap::new()), localnet, ipv4_range, ipv6_range })
     }
 
-    /// Add a new host to the host list, after filtering.
-    pub async fn store(&self, input_addrs: Vec<Url>) {
+    /// Add new hosts to the host list, after filtering. `source` is the
+    /// peer that advertised them, if any.
+    pub async fn store(&self, source: Option<&Url>, input_addrs: Vec<Url>) {
         debug!(target: "net::hosts::store()", "hosts::store() [Start]");
         let addrs = if !self.localnet {
             let filtered = filter_localnet(input_addrs);
@@ -68,10 +124,7 @@ impl Hosts {
             debug!(target: "net::hosts::store()", "hosts::store() [Localnet mode, skipping filterring.]");
             input_addrs
         };
-        let mut addrs_map = self.addrs.lock().await;
-        for addr in addrs {
-            addrs_map.insert(addr);
-        }
+        self.insert(source, addrs).await;
         debug!(target: "net::hosts::store()", "hosts::store() [End]");
     }
 
@@ -82,32 +135,165 @@ impl Hosts {
         let addrs = if !self.localnet {
             let filtered = filter_localnet(input_addrs);
             let filtered = filter_invalid(&self.ipv4_range, &self.ipv6_range, filtered);
-            filter_non_resolving(connection_addr, filtered)
+            filter_non_resolving(connection_addr.clone(), filtered)
         } else {
             debug!(target: "net::hosts::store_ext()", "hosts::store_ext() [Localnet mode, skipping filterring.]");
             input_addrs
         };
+        self.insert(Some(&connection_addr), addrs).await;
+        debug!(target: "net::hosts::store_ext()", "hosts::store_ext() [End]");
+    }
+
+    /// Insert already filtered addresses, refreshing the last seen
+    /// time of the ones we already know.
+    async fn insert(&self, source: Option<&Url>, addrs: Vec<Url>) {
         let mut addrs_map = self.addrs.lock().await;
         for addr in addrs {
-            addrs_map.insert(addr);
+            match addrs_map.get_mut(&addr) {
+                Some(info) => info.last_seen = now(),
+                None => {
+                    make_room(&mut addrs_map);
+                    addrs_map.insert(addr, HostInfo::new(source.cloned()));
+                }
+            }
         }
-        debug!(target: "net::hosts::store_ext()", "hosts::store_ext() [End]");
     }
 
     /// Return the list of hosts.
     pub async fn load_all(&self) -> Vec<Url> {
-        self.addrs.lock().await.iter().cloned().collect()
+        self.addrs.lock().await.keys().cloned().collect()
+    }
+
+    /// Return the list of hosts, ordered by preference for outbound
+    /// connections. Hosts we have connected to before come first, then
+    /// hosts with fewer consecutive failures. Equally ranked hosts are
+    /// shuffled.
+    pub async fn load_ordered(&self) -> Vec<Url> {
+        let mut hosts: Vec<(Url, HostInfo)> =
+            self.addrs.lock().await.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        hosts.shuffle(&mut rand::thread_rng());
+        hosts.sort_by_key(|(_, info)| (info.last_connected == 0, info.failures));
+        hosts.into_iter().map(|(addr, _)| addr).collect()
+    }
+
+    /// Return the stored information of a host.
+    pub async fn get(&self, url: &Url) -> Option<HostInfo> {
+        self.addrs.lock().await.get(url).cloned()
+    }
+
+    /// Record a successful connection to a host, resetting its failures.
+    pub async fn mark_connected(&self, url: &Url) {
+        let now = now();
+        let mut addrs_map = self.addrs.lock().await;
+        if !addrs_map.contains_key(url) {
+            make_room(&mut addrs_map);
+        }
+        let info = addrs_map.entry(url.clone()).or_insert_with(|| HostInfo::new(None));
+        info.last_seen = now;
+        info.last_connected = now;
+        info.failures = 0;
+    }
+
+    /// Record a failed connection attempt to a host. Once the host reaches
+    /// [`HOST_MAX_FAILURES`] consecutive failures it gets evicted.
+    /// Returns true if the host was evicted.
+    pub async fn mark_failed(&self, url: &Url) -> bool {
+        let mut addrs_map = self.addrs.lock().await;
+        let Some(info) = addrs_map.get_mut(url) else { return false };
+
+        info.failures += 1;
+        if info.failures < HOST_MAX_FAILURES {
+            return false
+        }
+
+        debug!(target: "net::hosts::mark_failed()", "hosts::mark_failed() [Evicting {} after {} failures]", url, info.failures);
+        addrs_map.remove(url);
+        true
     }
 
     /// Remove an Url from the list
     pub async fn remove(&self, url: &Url) -> bool {
-        self.addrs.lock().await.remove(url)
+        self.addrs.lock().await.remove(url).is_some()
     }
 
     /// Check if the host list is empty.
     pub async fn is_empty(&self) -> bool {
         self.addrs.lock().await.is_empty()
     }
+
+    /// Load hosts from a hostlist file, if it exists. Malformed lines are skipped.
+    pub async fn load_hostlist(&self, path: &Path) -> Result<()> {
+        if !path.exists() {
+            return Ok(())
+        }
+
+        let contents = load_file(path)?;
+        let mut addrs_map = self.addrs.lock().await;
+        for line in contents.lines() {
+            match HostInfo::from_line(line) {
+                Some((addr, info)) => {
+                    if !addrs_map.contains_key(&addr) {
+                        make_room(&mut addrs_map);
+                    }
+                    addrs_map.insert(addr, info);
+                }
+                None => {
+                    warn!(target: "net::hosts::load_hostlist()", "hosts::load_hostlist() [Skipping malformed line: {}]", line)
+                }
+            }
+        }
+
+        debug!(target: "net::hosts::load_hostlist()", "hosts::load_hostlist() [Loaded {} hosts]", addrs_map.len());
+        Ok(())
+    }
+
+    /// Write the hosts to a hostlist file. The file is written next to the
+    /// target and then renamed over it, so an interrupted save leaves the
+    /// previous hostlist intact.
+    pub async fn save_hostlist(&self, path: &Path) -> Result<()> {
+        let mut lines: Vec<String> = {
+            let addrs_map = self.addrs.lock().await;
+            addrs_map.iter().map(|(addr, info)| info.to_line(addr)).collect()
+        };
+        lines.sort();
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let tmp_path = path.with_extension("tmp");
+        save_file(&tmp_path, &(lines.join("\n") + "\n"))?;
+        fs::rename(tmp_path, path)?;
+
+        debug!(target: "net::hosts::save_hostlist()", "hosts::save_hostlist() [Saved {} hosts]", lines.len());
+        Ok(())
+    }
+}
+
+/// Current UNIX timestamp in seconds.
+/// Evict the least useful host if the host list is full: preferably one
+/// we never connected to, then the one with the most failures, then the
+/// one seen the longest ago.
+fn make_room(addrs_map: &mut HashMap<Url, HostInfo>) {
+    if addrs_map.len() < MAX_HOSTS {
+        return
+    }
+
+    let worst = addrs_map
+        .iter()
+        .max_by_key(|(_, info)| {
+            (info.last_connected == 0, info.failures, std::cmp::Reverse(info.last_seen))
+        })
+        .map(|(addr, _)| addr.clone());
+
+    if let Some(addr) = worst {
+        debug!(target: "net::hosts::make_room()", "hosts::make_room() [Evicting {}, host list is full]", addr);
+        addrs_map.remove(&addr);
+    }
+}
+
+fn now() -> u64 {
+    Timestamp::current_time().0 as u64
 }
 
 /// Auxiliary function to filter localnet hosts.
@@ -308,8 +494,8 @@ mod tests {
     use url::Url;
 
     use crate::net::{
-        constants::{IP4_PRIV_RANGES, IP6_PRIV_RANGES},
-        hosts::{filter_invalid, filter_localnet, filter_non_resolving, is_valid_onion},
+        constants::{HOST_MAX_FAILURES, IP4_PRIV_RANGES, IP6_PRIV_RANGES, MAX_HOSTS},
+        hosts::{filter_invalid, filter_localnet, filter_non_resolving, is_valid_onion, Hosts},
     };
 
     #[test]
@@ -482,4 +668,76 @@ mod tests {
         // Invalid onion
         assert!(!is_valid_onion("facebook.com"));
     }
+
+    #[async_std::test]
+    async fn test_host_scoring() {
+        let hosts = Hosts::new(true);
+        let source = Url::parse("tcp://127.0.0.1:9000").unwrap();
+        let good = Url::parse("tcp://127.0.0.1:9001").unwrap();
+        let flaky = Url::parse("tcp://127.0.0.1:9002").unwrap();
+        let fresh = Url::parse("tcp://127.0.0.1:9003").unwrap();
+        hosts.store(Some(&source), vec![good.clone(), flaky.clone(), fresh.clone()]).await;
+        assert_eq!(hosts.get(&fresh).await.unwrap().source, Some(source));
+
+        // Hosts that worked before come first, failing ones last
+        hosts.mark_connected(&good).await;
+        hosts.mark_connected(&flaky).await;
+        assert!(!hosts.mark_failed(&flaky).await);
+        assert_eq!(hosts.load_ordered().await, vec![good.clone(), flaky.clone(), fresh]);
+
+        // A successful connection resets the failures
+        hosts.mark_connected(&flaky).await;
+        assert_eq!(hosts.get(&flaky).await.unwrap().failures, 0);
+
+        // Hosts that keep failing get evicted
+        for _ in 1..HOST_MAX_FAILURES {
+            assert!(!hosts.mark_failed(&good).await);
+        }
+        assert!(hosts.mark_failed(&good).await);
+        assert!(hosts.get(&good).await.is_none());
+    }
+
+    #[async_std::test]
+    async fn test_hostlist_persistence() {
+        let dir = std::env::temp_dir().join(format!(
+            "darkfi_test_hostlist_{}_{}",
+            std::process::id(),
+            rand::random::<u64>()
+        ));
+        let path = dir.join("hostlist.tsv");
+
+        let hosts = Hosts::new(true);
+        let source = Url::parse("tcp://127.0.0.1:9000").unwrap();
+        let addr = Url::parse("tcp://127.0.0.1:9001").unwrap();
+        hosts.store(Some(&source), vec![addr.clone()]).await;
+        hosts.store(None, vec![source.clone()]).await;
+        hosts.mark_connected(&addr).await;
+        hosts.mark_failed(&source).await;
+        hosts.save_hostlist(&path).await.unwrap();
+
+        // Restore into a fresh host list, as on restart
+        let restored = Hosts::new(true);
+        restored.load_hostlist(&path).await.unwrap();
+        assert_eq!(restored.get(&addr).await, hosts.get(&addr).await);
+        assert_eq!(restored.get(&source).await, hosts.get(&source).await);
+        assert_eq!(restored.load_all().await.len(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[async_std::test]
+    async fn test_hosts_cap() {
+        let hosts = Hosts::new(true);
+        let known = Url::parse("tcp://127.0.0.1:1").unwrap();
+        hosts.mark_connected(&known).await;
+
+        let addrs: Vec<Url> = (2..MAX_HOSTS as u32 + 100)
+            .map(|port| Url::parse(&format!("tcp://127.0.0.1:{}", port)).unwrap())
+            .collect();
+        hosts.store(None, addrs).await;
+
+        // The list stays capped, keeping the host we connected to
+        assert_eq!(hosts.load_all().await.len(), MAX_HOSTS);
+        assert!(hosts.get(&known).await.is_some());
+    }
 }
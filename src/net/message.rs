@@ -17,17 +17,54 @@
  */
 
 use darkfi_serial::{Decodable, Encodable, SerialDecodable, SerialEncodable, VarInt};
-use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use futures::{io, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use log::debug;
 use url::Url;
 
+use super::{
+    constants::{LEGACY_PROTOCOL_VERSION, MAX_MESSAGE_PAYLOAD_SIZE},
+    message_subscriber::MessageSubsystem,
+};
 use crate::{Error, Result};
 
 const MAGIC_BYTES: [u8; 4] = [0xd9, 0xef, 0xb6, 0x7d];
 
+/// Maximum length of a packet's command
+const MAX_COMMAND_LENGTH: usize = 255;
+
+/// Priority of a message in the outbound queue of a channel. Higher
+/// priority messages are written out first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessagePriority {
+    /// Small and urgent messages, like keep-alives and consensus proposals
+    High = 0,
+    Normal = 1,
+    /// Large bulk transfers, like sync responses
+    Low = 2,
+}
+
+impl MessagePriority {
+    /// Number of priority levels
+    pub const COUNT: usize = 3;
+}
+
 /// Generic message template.
 pub trait Message: 'static + Encodable + Decodable + Send + Sync {
     fn name() -> &'static str;
+
+    /// Priority of the message in the outbound queue of a channel.
+    fn priority() -> MessagePriority {
+        MessagePriority::Normal
+    }
+
+    /// Maximum size of the message's serialized payload we accept from the
+    /// network. Packets exceeding it are rejected before their payload is read.
+    /// The global cap in [`Settings`](super::Settings) applies on top of it.
+    /// Messages carrying bulk data, like sync responses, can raise it up to
+    /// that cap.
+    fn max_payload_size() -> usize {
+        MAX_MESSAGE_PAYLOAD_SIZE
+    }
 }
 
 /// Outbound keep-alive message.
@@ -60,9 +97,21 @@ pub struct ExtAddrsMessage {
 }
 
 /// Requests version information of outbound connection.
-#[derive(SerialEncodable, SerialDecodable)]
+/// Peers from before the handshake carried a protocol version only send
+/// their node ID. Their messages still decode, as [`LEGACY_PROTOCOL_VERSION`]
+/// without any services or features, so they can be told apart and
+/// rejected instead of having their handshake time out.
+#[derive(SerialEncodable)]
 pub struct VersionMessage {
     pub node_id: String,
+    /// p2p protocol version of the sender
+    pub version: u32,
+    /// Services bitfield advertised by the sender
+    pub services: u64,
+    /// UNIX timestamp of the sender at the time of sending
+    pub timestamp: u64,
+    /// Names of the optional protocols the sender can run on this channel
+    pub features: Vec<String>,
 }
 
 /// Sends version information to inbound connection. Response to VersionMessage.
@@ -72,46 +121,118 @@ pub struct VerackMessage {
     pub app: String,
 }
 
+impl Decodable for VersionMessage {
+    fn decode<D: std::io::Read>(mut d: D) -> std::io::Result<Self> {
+        let node_id = String::decode(&mut d)?;
+
+        let version = match u32::decode(&mut d) {
+            Ok(version) => version,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                return Ok(Self {
+                    node_id,
+                    version: LEGACY_PROTOCOL_VERSION,
+                    services: 0,
+                    timestamp: 0,
+                    features: vec![],
+                })
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(Self {
+            node_id,
+            version,
+            services: Decodable::decode(&mut d)?,
+            timestamp: Decodable::decode(&mut d)?,
+            features: Decodable::decode(&mut d)?,
+        })
+    }
+}
+
 impl Message for PingMessage {
     fn name() -> &'static str {
         "ping"
     }
+
+    fn max_payload_size() -> usize {
+        4
+    }
+
+    fn priority() -> MessagePriority {
+        MessagePriority::High
+    }
 }
 
 impl Message for PongMessage {
     fn name() -> &'static str {
         "pong"
     }
+
+    fn max_payload_size() -> usize {
+        4
+    }
+
+    fn priority() -> MessagePriority {
+        MessagePriority::High
+    }
 }
 
 impl Message for GetAddrsMessage {
     fn name() -> &'static str {
         "getaddr"
     }
+
+    fn max_payload_size() -> usize {
+        0
+    }
 }
 
 impl Message for AddrsMessage {
     fn name() -> &'static str {
         "addr"
     }
+
+    fn max_payload_size() -> usize {
+        256 * 1024
+    }
 }
 
 impl Message for ExtAddrsMessage {
     fn name() -> &'static str {
         "extaddr"
     }
+
+    fn max_payload_size() -> usize {
+        256 * 1024
+    }
 }
 
 impl Message for VersionMessage {
     fn name() -> &'static str {
         "version"
     }
+
+    fn max_payload_size() -> usize {
+        16 * 1024
+    }
+
+    fn priority() -> MessagePriority {
+        MessagePriority::High
+    }
 }
 
 impl Message for VerackMessage {
     fn name() -> &'static str {
         "verack"
     }
+
+    fn max_payload_size() -> usize {
+        1024
+    }
+
+    fn priority() -> MessagePriority {
+        MessagePriority::High
+    }
 }
 
 /// Packets are the base type read from the network. Converted to messages and
@@ -121,39 +242,75 @@ pub struct Packet {
     pub payload: Vec<u8>,
 }
 
-/// Reads and decodes an inbound payload.
-pub async fn read_packet<R: AsyncRead + Unpin + Sized>(stream: &mut R) -> Result<Packet> {
-    // Packets have a 4 byte header of magic digits
-    // This is used for network debugging
-    let mut magic = [0u8; 4];
-    debug!(target: "net::message", "reading magic...");
+impl Packet {
+    /// Number of bytes the packet takes on the wire, framing included.
+    pub fn size(&self) -> usize {
+        MAGIC_BYTES.len() +
+            VarInt(self.command.len() as u64).length() +
+            self.command.len() +
+            VarInt(self.payload.len() as u64).length() +
+            self.payload.len()
+    }
+}
 
-    stream.read_exact(&mut magic).await?;
+/// Reads and decodes an inbound payload. Packets with a payload larger than
+/// the command's maximum size or `max_payload_size` are rejected before their
+/// payload gets read. Packets with a command that has no dispatcher in the
+/// given [`MessageSubsystem`] are skipped, as long as they fit within
+/// `max_payload_size`, so peers can send messages we don't know about.
+pub async fn read_packet<R: AsyncRead + Unpin + Sized>(
+    stream: &mut R,
+    message_subsystem: &MessageSubsystem,
+    max_payload_size: usize,
+) -> Result<Packet> {
+    loop {
+        // Packets have a 4 byte header of magic digits
+        // This is used for network debugging
+        let mut magic = [0u8; 4];
+        debug!(target: "net::message", "reading magic...");
 
-    debug!(target: "net::message", "read magic {:?}", magic);
-    if magic != MAGIC_BYTES {
-        return Err(Error::MalformedPacket)
-    }
+        stream.read_exact(&mut magic).await?;
 
-    // The type of the message
-    let command_len = VarInt::decode_async(stream).await?.0 as usize;
-    let mut cmd = vec![0u8; command_len];
-    if command_len > 0 {
-        stream.read_exact(&mut cmd).await?;
-    }
-    let cmd = String::from_utf8(cmd)?;
-    debug!(target: "net::message", "read command: {}", cmd);
+        debug!(target: "net::message", "read magic {:?}", magic);
+        if magic != MAGIC_BYTES {
+            return Err(Error::MalformedPacket)
+        }
 
-    let payload_len = VarInt::decode_async(stream).await?.0 as usize;
+        // The type of the message
+        let command_len = VarInt::decode_async(stream).await?.0;
+        if command_len > MAX_COMMAND_LENGTH as u64 {
+            return Err(Error::MalformedPacket)
+        }
+        let mut cmd = vec![0u8; command_len as usize];
+        if command_len > 0 {
+            stream.read_exact(&mut cmd).await?;
+        }
+        let cmd = String::from_utf8(cmd)?;
+        debug!(target: "net::message", "read command: {}", cmd);
 
-    // The message-dependent data (see message types)
-    let mut payload = vec![0u8; payload_len];
-    if payload_len > 0 {
-        stream.read_exact(&mut payload).await?;
-    }
-    debug!(target: "net::message", "read payload {} bytes", payload_len);
+        let command_max_size = message_subsystem.max_payload_size(&cmd).await;
+
+        let payload_len = VarInt::decode_async(stream).await?.0;
+        if payload_len > command_max_size.unwrap_or(usize::MAX).min(max_payload_size) as u64 {
+            return Err(Error::OversizedPacket(cmd, payload_len))
+        }
+
+        if command_max_size.is_none() {
+            debug!(target: "net::message", "Skipping packet with unknown command: {}", cmd);
+            io::copy(&mut (&mut *stream).take(payload_len), &mut io::sink()).await?;
+            continue
+        }
+        let payload_len = payload_len as usize;
 
-    Ok(Packet { command: cmd, payload })
+        // The message-dependent data (see message types)
+        let mut payload = vec![0u8; payload_len];
+        if payload_len > 0 {
+            stream.read_exact(&mut payload).await?;
+        }
+        debug!(target: "net::message", "read payload {} bytes", payload_len);
+
+        return Ok(Packet { command: cmd, payload })
+    }
 }
 
 /// Sends an outbound packet by writing data to TCP stream.
@@ -180,3 +337,90 @@ pub async fn send_packet<W: AsyncWrite + Unpin + Sized>(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use darkfi_serial::{deserialize, serialize};
+    use futures::io::Cursor;
+
+    use super::*;
+
+    async fn packet(command: &str, payload: Vec<u8>) -> Vec<u8> {
+        let mut buf = vec![];
+        send_packet(&mut buf, Packet { command: command.to_string(), payload }).await.unwrap();
+        buf
+    }
+
+    #[test]
+    fn version_message_compat() {
+        let version = VersionMessage {
+            node_id: "node".to_string(),
+            version: 1,
+            services: 0b11,
+            timestamp: 1680000000,
+            features: vec!["seed".to_string()],
+        };
+        let decoded: VersionMessage = deserialize(&serialize(&version)).unwrap();
+        assert_eq!(decoded.version, 1);
+        assert_eq!(decoded.services, 0b11);
+        assert_eq!(decoded.timestamp, 1680000000);
+        assert_eq!(decoded.features, vec!["seed".to_string()]);
+
+        // Legacy peers only send their node ID
+        let decoded: VersionMessage = deserialize(&serialize(&"node".to_string())).unwrap();
+        assert_eq!(decoded.node_id, "node");
+        assert_eq!(decoded.version, LEGACY_PROTOCOL_VERSION);
+        assert!(decoded.features.is_empty());
+
+        // Truncated fields past the version are still malformed
+        let mut truncated = serialize(&"node".to_string());
+        truncated.extend_from_slice(&1u32.to_le_bytes());
+        assert!(deserialize::<VersionMessage>(&truncated).is_err());
+    }
+
+    #[async_std::test]
+    async fn read_packet_limits() {
+        let subsystem = MessageSubsystem::new();
+        subsystem.add_dispatch::<PingMessage>().await;
+        subsystem.add_dispatch::<AddrsMessage>().await;
+
+        // Valid packet
+        let mut stream = Cursor::new(packet("ping", vec![0u8; 4]).await);
+        let p = read_packet(&mut stream, &subsystem, 1024).await.unwrap();
+        assert_eq!(p.command, "ping");
+        assert_eq!(p.payload.len(), 4);
+        assert_eq!(p.size(), packet("ping", vec![0u8; 4]).await.len());
+
+        // Payload larger than the message's maximum size
+        let mut stream = Cursor::new(packet("ping", vec![0u8; 5]).await);
+        let err = read_packet(&mut stream, &subsystem, 1024).await.err().unwrap();
+        assert!(matches!(err, Error::OversizedPacket(_, 5)));
+
+        // Payload larger than the global cap
+        let mut stream = Cursor::new(packet("addr", vec![0u8; 2048]).await);
+        let err = read_packet(&mut stream, &subsystem, 1024).await.err().unwrap();
+        assert!(matches!(err, Error::OversizedPacket(_, 2048)));
+
+        // Commands without a dispatcher are skipped
+        let mut buf = packet("pong", vec![0u8; 4]).await;
+        buf.extend(packet("ping", vec![1u8; 4]).await);
+        let mut stream = Cursor::new(buf);
+        let p = read_packet(&mut stream, &subsystem, 1024).await.unwrap();
+        assert_eq!(p.command, "ping");
+        assert_eq!(p.payload, vec![1u8; 4]);
+
+        // ...unless they're larger than the global cap
+        let mut stream = Cursor::new(packet("pong", vec![0u8; 2048]).await);
+        let err = read_packet(&mut stream, &subsystem, 1024).await.err().unwrap();
+        assert!(matches!(err, Error::OversizedPacket(_, 2048)));
+
+        // A huge announced payload is rejected without being allocated
+        let mut buf = MAGIC_BYTES.to_vec();
+        VarInt(4).encode(&mut buf).unwrap();
+        buf.extend_from_slice(b"ping");
+        VarInt(u64::MAX).encode(&mut buf).unwrap();
+        let mut stream = Cursor::new(buf);
+        let err = read_packet(&mut stream, &subsystem, usize::MAX).await.err().unwrap();
+        assert!(matches!(err, Error::OversizedPacket(_, u64::MAX)));
+    }
+}
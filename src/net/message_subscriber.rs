@@ -63,6 +63,8 @@ trait MessageDispatcherInterface: Send + Sync {
 
     async fn trigger_error(&self, err: Error);
 
+    fn max_payload_size(&self) -> usize;
+
     fn as_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync>;
 }
 
@@ -171,6 +173,11 @@ impl<M: Message> MessageDispatcherInterface for MessageDispatcher<M> {
         self._trigger_all(Err(err)).await;
     }
 
+    /// Maximum payload size of the message type.
+    fn max_payload_size(&self) -> usize {
+        M::max_payload_size()
+    }
+
     /// Converts to Any trait. Enables the dynamic modification of static types.
     fn as_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
         self
@@ -237,6 +244,12 @@ impl MessageSubsystem {
         }
     }
 
+    /// Returns the maximum payload size of the message with the given command,
+    /// or `None` if there's no dispatcher for it.
+    pub async fn max_payload_size(&self, command: &str) -> Option<usize> {
+        self.dispatchers.lock().await.get(command).map(|d| d.max_payload_size())
+    }
+
     /// Transmits an error message across dispatchers.
     pub async fn trigger_error(&self, err: Error) {
         // TODO: this could be parallelized
@@ -0,0 +1,303 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use async_std::{
+    net::{TcpListener, TcpStream},
+    sync::Arc,
+};
+use futures::{AsyncReadExt, AsyncWriteExt};
+use log::{debug, error};
+use serde_json::json;
+use smol::Executor;
+use url::Url;
+
+use crate::Result;
+
+use super::P2pPtr;
+
+/// Traffic counters of a single message command.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CommandStats {
+    pub msgs_sent: u64,
+    pub msgs_recv: u64,
+    pub bytes_sent: u64,
+    pub bytes_recv: u64,
+}
+
+impl CommandStats {
+    fn add(&mut self, other: &CommandStats) {
+        self.msgs_sent += other.msgs_sent;
+        self.msgs_recv += other.msgs_recv;
+        self.bytes_sent += other.bytes_sent;
+        self.bytes_recv += other.bytes_recv;
+    }
+
+    fn to_json(self) -> serde_json::Value {
+        json!({
+            "msgs_sent": self.msgs_sent,
+            "msgs_recv": self.msgs_recv,
+            "bytes_sent": self.bytes_sent,
+            "bytes_recv": self.bytes_recv,
+        })
+    }
+}
+
+/// Per-command traffic counters of a channel, or of many channels
+/// aggregated together.
+#[derive(Clone, Debug, Default)]
+pub struct TrafficStats {
+    commands: BTreeMap<String, CommandStats>,
+}
+
+impl TrafficStats {
+    /// Account for a packet we sent, `bytes` being its size on the wire.
+    pub fn record_sent(&mut self, command: &str, bytes: usize) {
+        let stats = self.commands.entry(command.to_string()).or_default();
+        stats.msgs_sent += 1;
+        stats.bytes_sent += bytes as u64;
+    }
+
+    /// Account for a packet we received, `bytes` being its size on the wire.
+    pub fn record_recv(&mut self, command: &str, bytes: usize) {
+        let stats = self.commands.entry(command.to_string()).or_default();
+        stats.msgs_recv += 1;
+        stats.bytes_recv += bytes as u64;
+    }
+
+    /// Counters of each command, sorted by command name.
+    pub fn commands(&self) -> &BTreeMap<String, CommandStats> {
+        &self.commands
+    }
+
+    /// Counters summed over all commands.
+    pub fn total(&self) -> CommandStats {
+        let mut total = CommandStats::default();
+        for stats in self.commands.values() {
+            total.add(stats);
+        }
+        total
+    }
+
+    /// Add the counters of `other` to ours.
+    pub fn merge(&mut self, other: &TrafficStats) {
+        for (command, stats) in &other.commands {
+            self.commands.entry(command.clone()).or_default().add(stats);
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let commands: serde_json::Map<String, serde_json::Value> = self
+            .commands
+            .iter()
+            .map(|(command, stats)| (command.clone(), stats.to_json()))
+            .collect();
+
+        json!({
+            "total": self.total().to_json(),
+            "commands": commands,
+        })
+    }
+}
+
+/// Snapshot of the metrics of a single channel.
+#[derive(Clone, Debug)]
+pub struct ChannelMetrics {
+    pub address: Url,
+    pub traffic: TrafficStats,
+    /// Time the version handshake took, once it finished
+    pub handshake_ms: Option<u64>,
+    /// Most recent ping round trip times, oldest first
+    pub ping_rtts_ms: Vec<u64>,
+    pub uptime_seconds: u64,
+}
+
+impl ChannelMetrics {
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "traffic": self.traffic.to_json(),
+            "handshake_ms": self.handshake_ms,
+            "ping_rtts_ms": self.ping_rtts_ms,
+            "uptime_seconds": self.uptime_seconds,
+        })
+    }
+}
+
+/// Render the metrics of the p2p network in the Prometheus text exposition
+/// format.
+pub async fn prometheus_text(p2p: &P2pPtr) -> String {
+    let mut channels = vec![];
+    for channel in p2p.channels().lock().await.values() {
+        channels.push(channel.metrics().await);
+    }
+    let traffic = p2p.traffic().await;
+
+    let mut out = String::new();
+
+    write_header(&mut out, "darkfi_p2p_channels", "gauge", "Number of connected channels");
+    let _ = writeln!(out, "darkfi_p2p_channels {}", channels.len());
+
+    let counters: [(&str, &str, fn(&CommandStats) -> u64); 4] = [
+        ("messages_sent", "Messages sent", |s| s.msgs_sent),
+        ("messages_received", "Messages received", |s| s.msgs_recv),
+        ("bytes_sent", "Bytes sent, packet framing included", |s| s.bytes_sent),
+        ("bytes_received", "Bytes received, packet framing included", |s| s.bytes_recv),
+    ];
+
+    for (name, help, value) in &counters {
+        let metric = format!("darkfi_p2p_{}_total", name);
+        write_header(&mut out, &metric, "counter", &format!("{} per command", help));
+        for (command, stats) in traffic.commands() {
+            let _ = writeln!(out, "{}{{command=\"{}\"}} {}", metric, escape(command), value(stats));
+        }
+    }
+
+    for (name, help, value) in &counters {
+        let metric = format!("darkfi_p2p_channel_{}_total", name);
+        write_header(&mut out, &metric, "counter", &format!("{} per channel", help));
+        for channel in &channels {
+            let total = channel.traffic.total();
+            let _ = writeln!(out, "{}{{peer=\"{}\"}} {}", metric, peer(channel), value(&total));
+        }
+    }
+
+    write_header(
+        &mut out,
+        "darkfi_p2p_channel_handshake_ms",
+        "gauge",
+        "Duration of the version handshake",
+    );
+    for channel in &channels {
+        if let Some(handshake_ms) = channel.handshake_ms {
+            let _ = writeln!(
+                out,
+                "darkfi_p2p_channel_handshake_ms{{peer=\"{}\"}} {}",
+                peer(channel),
+                handshake_ms
+            );
+        }
+    }
+
+    write_header(&mut out, "darkfi_p2p_channel_ping_rtt_ms", "gauge", "Last ping round trip time");
+    for channel in &channels {
+        if let Some(rtt) = channel.ping_rtts_ms.last() {
+            let _ = writeln!(
+                out,
+                "darkfi_p2p_channel_ping_rtt_ms{{peer=\"{}\"}} {}",
+                peer(channel),
+                rtt
+            );
+        }
+    }
+
+    write_header(&mut out, "darkfi_p2p_channel_uptime_seconds", "gauge", "Channel uptime");
+    for channel in &channels {
+        let _ = writeln!(
+            out,
+            "darkfi_p2p_channel_uptime_seconds{{peer=\"{}\"}} {}",
+            peer(channel),
+            channel.uptime_seconds
+        );
+    }
+
+    out
+}
+
+fn write_header(out: &mut String, metric: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", metric, help);
+    let _ = writeln!(out, "# TYPE {} {}", metric, kind);
+}
+
+fn peer(channel: &ChannelMetrics) -> String {
+    escape(channel.address.as_str())
+}
+
+/// Escape a label value as required by the text exposition format.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Serve the Prometheus metrics over plain HTTP on `addr`, answering every
+/// request with the current metrics.
+pub async fn serve_prometheus(p2p: P2pPtr, addr: Url, executor: Arc<Executor<'_>>) -> Result<()> {
+    let socket_addr = addr.socket_addrs(|| None)?[0];
+    let listener = TcpListener::bind(socket_addr).await?;
+    debug!(target: "net::metrics::serve_prometheus()", "Serving metrics on {}", addr);
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let p2p = p2p.clone();
+        executor
+            .spawn(async move {
+                if let Err(e) = respond(p2p, stream).await {
+                    error!(target: "net::metrics::serve_prometheus()", "Metrics request failed: {}", e);
+                }
+            })
+            .detach();
+    }
+}
+
+async fn respond(p2p: P2pPtr, mut stream: TcpStream) -> Result<()> {
+    // We serve the same document whatever was asked for, so just drain
+    // the request head.
+    let mut buf = [0u8; 1024];
+    let _ = stream.read(&mut buf).await?;
+
+    let body = prometheus_text(&p2p).await;
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_traffic_stats() {
+        let mut a = TrafficStats::default();
+        a.record_sent("ping", 10);
+        a.record_sent("ping", 10);
+        a.record_recv("pong", 12);
+
+        let mut b = TrafficStats::default();
+        b.record_recv("ping", 10);
+        b.record_sent("addr", 100);
+
+        a.merge(&b);
+        assert_eq!(
+            a.commands()["ping"],
+            CommandStats { msgs_sent: 2, msgs_recv: 1, bytes_sent: 20, bytes_recv: 10 }
+        );
+        assert_eq!(
+            a.total(),
+            CommandStats { msgs_sent: 3, msgs_recv: 2, bytes_sent: 120, bytes_recv: 22 }
+        );
+        assert_eq!(a.to_json()["commands"]["addr"]["bytes_sent"], 100);
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("tcp://a\"b\\c\n"), "tcp://a\\\"b\\\\c\\n");
+    }
+}
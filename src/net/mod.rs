@@ -38,6 +38,25 @@ pub mod connector;
 /// the host store until it finds ones to connect to.
 pub mod hosts;
 
+/// Misbehavior scoring of peers. Protocols report misbehaving peers with a
+/// weight, and peers whose score crosses a threshold get disconnected and
+/// banned for a while. Banned hosts are refused by both the acceptor and
+/// the connector.
+pub mod bans;
+
+/// Token bucket rate limits of the messages channels send and receive,
+/// configured per command.
+pub mod rate_limit;
+
+/// Outbound queue of a channel, writing out messages in order of their
+/// priority while keeping low priority ones from starving.
+pub mod send_queue;
+
+/// Traffic metrics of channels and of the p2p network as a whole: message
+/// and byte counters per command, handshake latency, ping round trip times
+/// and uptime. Besides `get_info`, they can be served to Prometheus.
+pub mod metrics;
+
 /// Generic publish/subscribe class that can dispatch any kind of message to a
 /// subscribed list of dispatchers. Dispatchers subscribe to a single
 /// message format of any type. This is a generalized version of the simple
@@ -110,11 +129,13 @@ pub mod transport;
 pub mod constants;
 
 pub use acceptor::{Acceptor, AcceptorPtr};
+pub use bans::{BanList, BanListPtr};
 pub use channel::{Channel, ChannelPtr};
 pub use connector::Connector;
-pub use hosts::{Hosts, HostsPtr};
-pub use message::Message;
+pub use hosts::{HostInfo, Hosts, HostsPtr};
+pub use message::{Message, MessagePriority};
 pub use message_subscriber::MessageSubscription;
+pub use metrics::{ChannelMetrics, TrafficStats};
 pub use p2p::{P2p, P2pPtr};
 pub use protocol::{ProtocolBase, ProtocolBasePtr, ProtocolJobsManager, ProtocolJobsManagerPtr};
 pub use session::{
@@ -30,16 +30,22 @@ use smol::Executor;
 use url::Url;
 
 use crate::{
-    system::{Subscriber, SubscriberPtr, Subscription},
-    util::async_util::sleep,
-    Result,
+    system::{StoppableTask, Subscriber, SubscriberPtr, Subscription},
+    util::{async_util::sleep, path::expand_path},
+    Error, Result,
 };
 
 use super::{
+    bans::ban_key,
+    constants::HOSTLIST_SAVE_INTERVAL,
     message::Message,
+    metrics::serve_prometheus,
     protocol::{register_default_protocols, ProtocolRegistry},
-    session::{InboundSession, ManualSession, OutboundSession, SeedSyncSession, Session},
-    Channel, ChannelPtr, Hosts, HostsPtr, Settings, SettingsPtr,
+    session::{
+        anchor_session::save_anchors, AnchorSession, InboundSession, ManualSession,
+        OutboundSession, SeedSyncSession, Session, SESSION_OUTBOUND,
+    },
+    BanList, BanListPtr, Channel, ChannelPtr, Hosts, HostsPtr, Settings, SettingsPtr, TrafficStats,
 };
 
 /// List of channels that are awaiting connection.
@@ -83,10 +89,14 @@ pub struct P2p {
     // Used both internally and externally
     stop_subscriber: SubscriberPtr<()>,
     hosts: HostsPtr,
+    bans: BanListPtr,
     protocol_registry: ProtocolRegistry,
+    // Traffic of the channels that have been closed already
+    closed_traffic: Mutex<TrafficStats>,
 
     // We keep a reference to the sessions used for get info
     session_manual: Mutex<Option<Arc<ManualSession>>>,
+    session_anchor: Mutex<Option<Arc<AnchorSession>>>,
     session_inbound: Mutex<Option<Arc<InboundSession>>>,
     session_outbound: Mutex<Option<Arc<OutboundSession>>>,
 
@@ -115,8 +125,11 @@ impl P2p {
             channel_subscriber: Subscriber::new(),
             stop_subscriber: Subscriber::new(),
             hosts: Hosts::new(settings.localnet),
+            bans: BanList::new(settings.ban_threshold, settings.ban_duration_seconds),
             protocol_registry: ProtocolRegistry::new(),
+            closed_traffic: Mutex::new(TrafficStats::default()),
             session_manual: Mutex::new(None),
+            session_anchor: Mutex::new(None),
             session_inbound: Mutex::new(None),
             session_outbound: Mutex::new(None),
             state: Mutex::new(P2pState::Open),
@@ -127,11 +140,23 @@ impl P2p {
         let parent = Arc::downgrade(&self_);
 
         *self_.session_manual.lock().await = Some(ManualSession::new(parent.clone()));
+        *self_.session_anchor.lock().await = Some(AnchorSession::new(parent.clone()));
         *self_.session_inbound.lock().await = Some(InboundSession::new(parent.clone()).await);
         *self_.session_outbound.lock().await = Some(OutboundSession::new(parent));
 
         register_default_protocols(self_.clone()).await;
 
+        // Restore the hosts we knew about on our previous run
+        if let Some(hostlist) = &self_.settings.hostlist {
+            let loaded = match expand_path(hostlist) {
+                Ok(path) => self_.hosts.load_hostlist(&path).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = loaded {
+                error!(target: "net::p2p::new()", "Failed loading hostlist {}: {}", hostlist, e);
+            }
+        }
+
         self_
     }
 
@@ -146,9 +171,12 @@ impl P2p {
         json!({
             "external_addr": format!("{:?}", ext_addr_vec),
             "session_manual": self.session_manual().await.get_info().await,
+            "session_anchor": self.session_anchor().await.get_info().await,
             "session_inbound": self.session_inbound().await.get_info().await,
             "session_outbound": self.session_outbound().await.get_info().await,
             "state": self.state.lock().await.to_string(),
+            "bans": self.get_bans().await,
+            "traffic": self.traffic().await.to_json(),
         })
     }
     // ANCHOR_END: get_info
@@ -160,6 +188,10 @@ impl P2p {
 
         *self.state.lock().await = P2pState::Start;
 
+        // Reconnect to the anchors of our previous run before seeding.
+        // This will block until every anchor connected or failed.
+        self.session_anchor().await.start(executor.clone()).await?;
+
         // Start seed session
         let seed = SeedSyncSession::new(Arc::downgrade(&self));
         // This will block until all seed queries have finished
@@ -175,6 +207,9 @@ impl P2p {
     pub async fn session_manual(&self) -> Arc<ManualSession> {
         self.session_manual.lock().await.as_ref().unwrap().clone()
     }
+    pub async fn session_anchor(&self) -> Arc<AnchorSession> {
+        self.session_anchor.lock().await.as_ref().unwrap().clone()
+    }
     pub async fn session_inbound(&self) -> Arc<InboundSession> {
         self.session_inbound.lock().await.as_ref().unwrap().clone()
     }
@@ -201,14 +236,46 @@ impl P2p {
         let outbound = self.session_outbound().await;
         outbound.clone().start(executor.clone()).await?;
 
+        let metrics_task = StoppableTask::new();
+        if let Some(metrics_addr) = &self.settings.metrics_addr {
+            let addr = metrics_addr.clone();
+            metrics_task.clone().start(
+                serve_prometheus(self.clone(), metrics_addr.clone(), executor.clone()),
+                move |result| async move {
+                    match result {
+                        Ok(()) | Err(Error::NetworkServiceStopped) => {}
+                        Err(e) => {
+                            error!(target: "net::p2p::run()", "Metrics server on {} failed: {}", addr, e)
+                        }
+                    }
+                },
+                Error::NetworkServiceStopped,
+                executor.clone(),
+            );
+        }
+
+        // Save the hosts every now and then, so they survive a crash
+        let hostlist_task = StoppableTask::new();
+        if self.settings.hostlist.is_some() {
+            hostlist_task.clone().start(
+                self.clone().hostlist_save_loop(),
+                |_| async {},
+                Error::NetworkServiceStopped,
+                executor.clone(),
+            );
+        }
+
         let stop_sub = self.subscribe_stop().await;
         // Wait for stop signal
         stop_sub.receive().await;
 
         // Stop the sessions
         manual.stop().await;
+        self.session_anchor().await.stop().await;
         inbound.stop().await;
         outbound.stop().await;
+        metrics_task.stop().await;
+        hostlist_task.stop().await;
 
         debug!(target: "net::p2p::run()", "P2p::run() [END]");
         Ok(())
@@ -332,10 +399,69 @@ impl P2p {
 
     // ANCHOR: stop
     pub async fn stop(&self) {
-        self.stop_subscriber.notify(()).await
+        // Remember our best outbound peers while we are still connected.
+        // Without any, the anchors of our previous run are kept instead.
+        if let Some(anchorlist) = &self.settings.anchorlist {
+            let anchors = self.select_anchors().await;
+            if anchors.is_empty() {
+                debug!(target: "net::p2p::stop()", "No anchors to save, keeping {}", anchorlist);
+            } else if let Err(e) =
+                expand_path(anchorlist).and_then(|path| save_anchors(&path, &anchors))
+            {
+                error!(target: "net::p2p::stop()", "Failed saving anchors {}: {}", anchorlist, e);
+            }
+        }
+
+        self.stop_subscriber.notify(()).await;
+
+        // Persist the hosts so the next run doesn't have to bootstrap from seeds
+        self.save_hostlist().await;
     }
     // ANCHOR_END: stop
 
+    /// Write the hosts to the configured hostlist file, if any.
+    async fn save_hostlist(&self) {
+        let Some(hostlist) = &self.settings.hostlist else { return };
+
+        let saved = match expand_path(hostlist) {
+            Ok(path) => self.hosts.save_hostlist(&path).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = saved {
+            error!(target: "net::p2p::save_hostlist()", "Failed saving hostlist {}: {}", hostlist, e);
+        }
+    }
+
+    /// Periodically save the hosts to the configured hostlist file.
+    async fn hostlist_save_loop(self: Arc<Self>) -> Result<()> {
+        loop {
+            sleep(HOSTLIST_SAVE_INTERVAL).await;
+            self.save_hostlist().await;
+        }
+    }
+
+    /// Pick the outbound peers that have been connected the longest without
+    /// misbehaving, to reconnect to first on our next start.
+    async fn select_anchors(&self) -> Vec<Url> {
+        let mut candidates = vec![];
+        for channel in self.channels.lock().await.values() {
+            if channel.session_type_id() != SESSION_OUTBOUND {
+                continue
+            }
+            if self.bans.score(&channel.address()).await > 0 {
+                continue
+            }
+            candidates.push((channel.metrics().await.uptime_seconds, channel.address()));
+        }
+
+        candidates.sort_by(|a, b| b.0.cmp(&a.0));
+        candidates
+            .into_iter()
+            .take(self.settings.anchor_connections as usize)
+            .map(|(_, addr)| addr)
+            .collect()
+    }
+
     /// Broadcasts a message concurrently across all channels.
     // ANCHOR: broadcast
     pub async fn broadcast<M: Message + Clone>(&self, message: M) -> Result<()> {
@@ -413,7 +539,19 @@ impl P2p {
 
     /// Remove a channel from the list of connected channels.
     pub async fn remove(&self, channel: ChannelPtr) {
-        self.channels.lock().await.remove(&channel.address());
+        if self.channels.lock().await.remove(&channel.address()).is_some() {
+            self.closed_traffic.lock().await.merge(&channel.traffic().await);
+        }
+    }
+
+    /// Traffic counters aggregated over all channels, including the ones
+    /// that have been closed since we started.
+    pub async fn traffic(&self) -> TrafficStats {
+        let mut traffic = self.closed_traffic.lock().await.clone();
+        for channel in self.channels.lock().await.values() {
+            traffic.merge(&channel.traffic().await);
+        }
+        traffic
     }
 
     /// Check whether a channel is stored in the list of connected channels.
@@ -460,6 +598,53 @@ impl P2p {
         self.hosts.clone()
     }
 
+    /// Return an atomic pointer to the ban list.
+    pub fn bans(&self) -> BanListPtr {
+        self.bans.clone()
+    }
+
+    /// Report misbehavior of the peer behind a channel. If its score crosses
+    /// the ban threshold, the peer is banned, disconnected and removed from
+    /// our hosts. Returns true if the peer got banned.
+    pub async fn misbehaving(&self, channel: &Channel, weight: u32, reason: &str) -> bool {
+        let addr = channel.address();
+        warn!(target: "net::p2p::misbehaving()", "Peer {} misbehaved: {}", addr, reason);
+
+        if !self.bans.misbehaving(&addr, weight).await {
+            return false
+        }
+
+        self.hosts.remove(&addr).await;
+        channel.stop().await;
+        true
+    }
+
+    /// Ban a host for `duration` seconds, disconnecting any channels we have to it.
+    /// Loopback peers are banned by their full address instead.
+    pub async fn ban(&self, host: &str, duration: u64) {
+        self.bans.ban(host, duration).await;
+
+        let channels: Vec<ChannelPtr> = self.channels.lock().await.values().cloned().collect();
+        for channel in channels {
+            if ban_key(&channel.address()).as_deref() == Some(host) {
+                self.hosts.remove(&channel.address()).await;
+                channel.stop().await;
+            }
+        }
+    }
+
+    /// Return the currently banned hosts as a JSON array.
+    pub async fn get_bans(&self) -> serde_json::Value {
+        let bans: Vec<serde_json::Value> = self
+            .bans
+            .load_all()
+            .await
+            .into_iter()
+            .map(|(host, until)| json!({"host": host, "until": until}))
+            .collect();
+        serde_json::Value::Array(bans)
+    }
+
     pub fn protocol_registry(&self) -> &ProtocolRegistry {
         &self.protocol_registry
     }
@@ -74,7 +74,7 @@ pub use protocol_seed::ProtocolSeed;
 pub use protocol_version::ProtocolVersion;
 
 pub use protocol_base::{ProtocolBase, ProtocolBasePtr};
-pub use protocol_registry::ProtocolRegistry;
+pub use protocol_registry::{AttachedProtocols, ProtocolRegistry};
 
 use super::{
     session::{SESSION_ALL, SESSION_SEED},
@@ -83,7 +83,7 @@ use super::{
 
 pub async fn register_default_protocols(p2p: P2pPtr) {
     let registry = p2p.protocol_registry();
-    registry.register(SESSION_ALL, ProtocolPing::init).await;
-    registry.register(!SESSION_SEED, ProtocolAddress::init).await;
-    registry.register(SESSION_SEED, ProtocolSeed::init).await;
+    registry.register_core(SESSION_ALL, ProtocolPing::init).await;
+    registry.register_core(!SESSION_SEED, ProtocolAddress::init).await;
+    registry.register_core(SESSION_SEED, ProtocolSeed::init).await;
 }
@@ -19,14 +19,17 @@
 use std::{sync::Arc, time::Instant};
 
 use async_trait::async_trait;
-use log::{debug, error};
+use log::debug;
 use rand::Rng;
 use smol::Executor;
 
 use crate::{util::async_util::sleep, Error, Result};
 
 use super::{
-    super::{message, message_subscriber::MessageSubscription, ChannelPtr, P2pPtr, SettingsPtr},
+    super::{
+        constants::BAN_SCORE_WRONG_PONG, message, message_subscriber::MessageSubscription,
+        ChannelPtr, P2pPtr, SettingsPtr,
+    },
     ProtocolBase, ProtocolBasePtr, ProtocolJobsManager, ProtocolJobsManagerPtr,
 };
 
@@ -36,6 +39,7 @@ pub struct ProtocolPing {
     ping_sub: MessageSubscription<message::PingMessage>,
     pong_sub: MessageSubscription<message::PongMessage>,
     settings: SettingsPtr,
+    p2p: P2pPtr,
     jobsman: ProtocolJobsManagerPtr,
 }
 
@@ -63,6 +67,7 @@ impl ProtocolPing {
             ping_sub,
             pong_sub,
             settings,
+            p2p,
             jobsman: ProtocolJobsManager::new("ProtocolPing", channel),
         })
     }
@@ -90,19 +95,19 @@ impl ProtocolPing {
             // Wait for pong, check nonce matches.
             let pong_msg = self.pong_sub.receive().await?;
             if pong_msg.nonce != nonce {
-                // TODO: this is too extreme
-                error!(
-                    target: "net::protocol_ping::run_ping_pong()",
-                    "Wrong nonce for ping reply. Disconnecting from channel."
-                );
-                self.channel.stop().await;
-                return Err(Error::ChannelStopped)
+                // Penalize the peer, it gets disconnected once it's banned
+                let reason = "Wrong nonce for ping reply";
+                if self.p2p.misbehaving(&self.channel, BAN_SCORE_WRONG_PONG, reason).await {
+                    return Err(Error::ChannelStopped)
+                }
+                continue
             }
-            let duration = start.elapsed().as_millis();
+            let duration = start.elapsed();
+            self.channel.record_ping_rtt(duration).await;
             debug!(
                 target: "net::protocol_ping::run_ping_pong()",
                 "Received Pong message {}ms from [{:?}]",
-                duration,
+                duration.as_millis(),
                 self.channel.address()
             );
         }
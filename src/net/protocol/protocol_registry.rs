@@ -30,8 +30,39 @@ use super::{
 type Constructor =
     Box<dyn Fn(ChannelPtr, P2pPtr) -> BoxFuture<'static, ProtocolBasePtr> + Send + Sync>;
 
+/// Protocols attached to a channel, split into the core protocols that
+/// always run and the optional ones that only start once the peer
+/// advertised them during the version handshake.
+pub struct AttachedProtocols {
+    pub core: Vec<ProtocolBasePtr>,
+    pub optional: Vec<ProtocolBasePtr>,
+}
+
+impl AttachedProtocols {
+    /// Names of the optional protocols, which we advertise as our features.
+    pub fn features(&self) -> Vec<String> {
+        self.optional.iter().map(|protocol| protocol.name().to_string()).collect()
+    }
+
+    /// Protocols to start on a channel whose peer advertised the given
+    /// features: all the core ones, and the optional ones both sides
+    /// support. The rest get dropped.
+    pub fn negotiate(self, remote_features: &[String]) -> Vec<ProtocolBasePtr> {
+        let mut protocols = self.core;
+        for protocol in self.optional {
+            if !remote_features.iter().any(|feature| feature == protocol.name()) {
+                debug!(target: "net::protocol_registry", "Peer does not support {}", protocol.name());
+                continue
+            }
+            protocols.push(protocol);
+        }
+        protocols
+    }
+}
+
 pub struct ProtocolRegistry {
-    protocol_constructors: Mutex<Vec<(SessionBitflag, Constructor)>>,
+    // (session flags, is core protocol, constructor)
+    protocol_constructors: Mutex<Vec<(SessionBitflag, bool, Constructor)>>,
 }
 
 impl Default for ProtocolRegistry {
@@ -46,7 +77,28 @@ impl ProtocolRegistry {
     }
 
     // add_protocol()?
+    /// Register a protocol for the given sessions. It is advertised by its
+    /// name in the version handshake and only started on channels where
+    /// the peer advertises it as well.
     pub async fn register<C, F>(&self, session_flags: SessionBitflag, constructor: C)
+    where
+        C: 'static + Fn(ChannelPtr, P2pPtr) -> F + Send + Sync,
+        F: 'static + Future<Output = ProtocolBasePtr> + Send,
+    {
+        self.add(session_flags, false, constructor).await
+    }
+
+    /// Register a protocol that is part of the base p2p protocol version
+    /// and always runs, without being negotiated.
+    pub(crate) async fn register_core<C, F>(&self, session_flags: SessionBitflag, constructor: C)
+    where
+        C: 'static + Fn(ChannelPtr, P2pPtr) -> F + Send + Sync,
+        F: 'static + Future<Output = ProtocolBasePtr> + Send,
+    {
+        self.add(session_flags, true, constructor).await
+    }
+
+    async fn add<C, F>(&self, session_flags: SessionBitflag, core: bool, constructor: C)
     where
         C: 'static + Fn(ChannelPtr, P2pPtr) -> F + Send + Sync,
         F: 'static + Future<Output = ProtocolBasePtr> + Send,
@@ -54,7 +106,7 @@ impl ProtocolRegistry {
         let constructor = move |channel, p2p| {
             Box::pin(constructor(channel, p2p)) as BoxFuture<'static, ProtocolBasePtr>
         };
-        self.protocol_constructors.lock().await.push((session_flags, Box::new(constructor)));
+        self.protocol_constructors.lock().await.push((session_flags, core, Box::new(constructor)));
     }
 
     pub async fn attach(
@@ -62,9 +114,9 @@ impl ProtocolRegistry {
         selector_id: SessionBitflag,
         channel: ChannelPtr,
         p2p: P2pPtr,
-    ) -> Vec<ProtocolBasePtr> {
-        let mut protocols: Vec<ProtocolBasePtr> = Vec::new();
-        for (session_flags, construct) in self.protocol_constructors.lock().await.iter() {
+    ) -> AttachedProtocols {
+        let mut protocols = AttachedProtocols { core: Vec::new(), optional: Vec::new() };
+        for (session_flags, core, construct) in self.protocol_constructors.lock().await.iter() {
             // Skip protocols that are not registered for this session
             if selector_id & session_flags == 0 {
                 debug!(target: "net::protocol_registry", "Skipping {selector_id:#b}, {session_flags:#b}");
@@ -74,8 +126,62 @@ impl ProtocolRegistry {
             let protocol: ProtocolBasePtr = construct(channel.clone(), p2p.clone()).await;
             debug!(target: "net::protocol_registry", "Attached {}", protocol.name());
 
-            protocols.push(protocol)
+            if *core {
+                protocols.core.push(protocol)
+            } else {
+                protocols.optional.push(protocol)
+            }
         }
         protocols
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use smol::Executor;
+
+    use super::{super::ProtocolBase, *};
+    use crate::Result;
+
+    struct DummyProtocol(&'static str);
+
+    #[async_trait]
+    impl ProtocolBase for DummyProtocol {
+        async fn start(self: Arc<Self>, _executor: Arc<Executor<'_>>) -> Result<()> {
+            Ok(())
+        }
+
+        fn name(&self) -> &'static str {
+            self.0
+        }
+    }
+
+    fn attached() -> AttachedProtocols {
+        AttachedProtocols {
+            core: vec![Arc::new(DummyProtocol("ping")), Arc::new(DummyProtocol("address"))],
+            optional: vec![Arc::new(DummyProtocol("tx")), Arc::new(DummyProtocol("block"))],
+        }
+    }
+
+    fn names(protocols: &[ProtocolBasePtr]) -> Vec<&'static str> {
+        protocols.iter().map(|protocol| protocol.name()).collect()
+    }
+
+    #[test]
+    fn test_negotiate_protocols() {
+        assert_eq!(attached().features(), vec!["tx", "block"]);
+
+        // Core protocols always run, optional ones only if the peer has them
+        let started = attached().negotiate(&["block".to_string(), "unknown".to_string()]);
+        assert_eq!(names(&started), vec!["ping", "address", "block"]);
+
+        let started = attached().negotiate(&[]);
+        assert_eq!(names(&started), vec!["ping", "address"]);
+
+        let started = attached().negotiate(&["tx".to_string(), "block".to_string()]);
+        assert_eq!(names(&started), vec!["ping", "address", "tx", "block"]);
+    }
+}
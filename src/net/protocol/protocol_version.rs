@@ -22,10 +22,13 @@ use std::{sync::Arc, time::Duration};
 use log::*;
 use smol::Executor;
 
-use crate::{Error, Result};
+use crate::{util::time::Timestamp, Error, Result};
 
 use super::super::{
-    message, message_subscriber::MessageSubscription, ChannelPtr, HostsPtr, SettingsPtr,
+    constants::{MAX_CLOCK_SKEW_SECONDS, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION},
+    message,
+    message_subscriber::MessageSubscription,
+    ChannelPtr, HostsPtr, SettingsPtr,
 };
 
 /// Implements the protocol version handshake sent out by nodes at the beginning
@@ -36,13 +39,20 @@ pub struct ProtocolVersion {
     verack_sub: MessageSubscription<message::VerackMessage>,
     settings: SettingsPtr,
     hosts: HostsPtr,
+    features: Vec<String>,
 }
 
 impl ProtocolVersion {
     /// Create a new version protocol. Makes a version and version
     /// acknowledgement subscription, then adds them to a version protocol
-    /// instance.
-    pub async fn new(channel: ChannelPtr, settings: SettingsPtr, hosts: HostsPtr) -> Arc<Self> {
+    /// instance. `features` are the names of the optional protocols we
+    /// advertise to the peer.
+    pub async fn new(
+        channel: ChannelPtr,
+        settings: SettingsPtr,
+        hosts: HostsPtr,
+        features: Vec<String>,
+    ) -> Arc<Self> {
         // Creates a version subscription.
         let version_sub = channel
             .clone()
@@ -57,7 +67,7 @@ impl ProtocolVersion {
             .await
             .expect("Missing verack dispatcher!");
 
-        Arc::new(Self { channel, version_sub, verack_sub, settings, hosts })
+        Arc::new(Self { channel, version_sub, verack_sub, settings, hosts, features })
     }
 
     /// Start version information exchange. Start the timer. Send version info
@@ -75,8 +85,9 @@ impl ProtocolVersion {
         )
         .await;
 
-        if let Err(_e) = result {
-            return Err(Error::ChannelTimeout)
+        match result {
+            Ok(result) => result?,
+            Err(_e) => return Err(Error::ChannelTimeout),
         }
 
         debug!(target: "net::protocol_version::run()", "END");
@@ -102,7 +113,13 @@ impl ProtocolVersion {
     async fn send_version(self: Arc<Self>) -> Result<()> {
         debug!(target: "net::protocol_version::send_version()", "START");
 
-        let version = message::VersionMessage { node_id: self.settings.node_id.clone() };
+        let version = message::VersionMessage {
+            node_id: self.settings.node_id.clone(),
+            version: PROTOCOL_VERSION,
+            services: self.settings.services,
+            timestamp: Timestamp::current_time().0 as u64,
+            features: self.features.clone(),
+        };
 
         self.channel.clone().send(version).await?;
 
@@ -166,7 +183,28 @@ impl ProtocolVersion {
         debug!(target: "net::protocol_version::recv_version()", "START");
         // Receive version message
         let version = self.version_sub.receive().await?;
-        self.channel.set_remote_node_id(version.node_id.clone()).await;
+        self.channel.set_remote_version(&version).await;
+
+        if let Err(reason) = check_version(&version, self.settings.required_services) {
+            error!(
+                target: "net::protocol_version::recv_version()",
+                "Incompatible peer ({}): {}. Disconnecting from channel.",
+                self.channel.address(),
+                reason
+            );
+            self.channel.stop().await;
+            return Err(Error::IncompatiblePeer(reason))
+        }
+
+        let now = Timestamp::current_time().0 as u64;
+        if now.abs_diff(version.timestamp) > MAX_CLOCK_SKEW_SECONDS {
+            warn!(
+                target: "net::protocol_version::recv_version()",
+                "Clock of peer ({}) is off by {} seconds",
+                self.channel.address(),
+                now as i64 - version.timestamp as i64
+            );
+        }
 
         // Send version acknowledgement
         let verack =
@@ -177,3 +215,50 @@ impl ProtocolVersion {
         Ok(())
     }
 }
+
+/// Check that a peer's version message is something we can work with,
+/// returning the reason if it is not.
+fn check_version(
+    version: &message::VersionMessage,
+    required_services: u64,
+) -> std::result::Result<(), String> {
+    if version.version < MIN_PROTOCOL_VERSION {
+        return Err(format!(
+            "protocol version {} is older than the minimum supported {}",
+            version.version, MIN_PROTOCOL_VERSION
+        ))
+    }
+
+    let missing = required_services & !version.services;
+    if missing != 0 {
+        return Err(format!("missing required services {:#b}", missing))
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::super::constants::LEGACY_PROTOCOL_VERSION, *};
+
+    fn version_message(version: u32, services: u64) -> message::VersionMessage {
+        message::VersionMessage {
+            node_id: String::new(),
+            version,
+            services,
+            timestamp: Timestamp::current_time().0 as u64,
+            features: vec![],
+        }
+    }
+
+    #[test]
+    fn test_check_version() {
+        assert!(check_version(&version_message(PROTOCOL_VERSION, 0), 0).is_ok());
+        let err = check_version(&version_message(LEGACY_PROTOCOL_VERSION, 0), 0).unwrap_err();
+        assert_eq!(err, "protocol version 0 is older than the minimum supported 1");
+
+        assert!(check_version(&version_message(PROTOCOL_VERSION, 0b11), 0b01).is_ok());
+        let err = check_version(&version_message(PROTOCOL_VERSION, 0b01), 0b11).unwrap_err();
+        assert_eq!(err, "missing required services 0b10");
+    }
+}
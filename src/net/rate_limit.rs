@@ -0,0 +1,149 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use async_std::sync::Mutex;
+use serde::Deserialize;
+use smol::Timer;
+
+/// Token bucket rate limit of a message command: on average `rate`
+/// messages per second, with bursts of up to `burst` messages.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub struct RateLimit {
+    pub rate: f64,
+    pub burst: u32,
+}
+
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: &RateLimit) -> Self {
+        Self { tokens: limit.burst as f64, last_refill: Instant::now() }
+    }
+
+    /// Take a token, or return how long to wait until one is available.
+    fn take(&mut self, limit: &RateLimit, now: Instant) -> Option<Duration> {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.rate).min(limit.burst as f64);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return None
+        }
+
+        // A bucket that never refills, check back every now and then
+        if limit.rate <= 0.0 {
+            return Some(Duration::from_secs(60 * 60))
+        }
+        Some(Duration::from_secs_f64((1.0 - self.tokens) / limit.rate))
+    }
+}
+
+/// Applies per-command rate limits to the messages of a channel. Commands
+/// without a configured limit are never limited.
+pub struct RateLimiter {
+    limits: HashMap<String, RateLimit>,
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: HashMap<String, RateLimit>) -> Self {
+        Self { limits, buckets: Mutex::new(HashMap::new()) }
+    }
+
+    /// Take a token for `command`, returning false if its limit is exceeded.
+    pub async fn try_acquire(&self, command: &str) -> bool {
+        self.take(command).await.is_none()
+    }
+
+    /// Take a token for `command`, waiting for one to become available.
+    pub async fn acquire(&self, command: &str) {
+        while let Some(wait) = self.take(command).await {
+            Timer::after(wait).await;
+        }
+    }
+
+    async fn take(&self, command: &str) -> Option<Duration> {
+        let limit = self.limits.get(command)?;
+        let mut buckets = self.buckets.lock().await;
+        let bucket = buckets.entry(command.to_string()).or_insert_with(|| TokenBucket::new(limit));
+        bucket.take(limit, Instant::now())
+    }
+}
+
+/// Default limits on the messages we accept from peers. Peers exceeding
+/// them get disconnected.
+pub fn default_recv_rate_limits() -> HashMap<String, RateLimit> {
+    HashMap::from([
+        // Sent once per connection, and by seed queries
+        ("getaddr".to_string(), RateLimit { rate: 0.1, burst: 5 }),
+        ("tx".to_string(), RateLimit { rate: 100.0, burst: 500 }),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let limit = RateLimit { rate: 2.0, burst: 3 };
+        let start = Instant::now();
+        let mut bucket = TokenBucket { tokens: 3.0, last_refill: start };
+
+        // The burst goes through right away
+        for _ in 0..3 {
+            assert_eq!(bucket.take(&limit, start), None);
+        }
+        // Then we have to wait for the bucket to refill
+        assert_eq!(bucket.take(&limit, start), Some(Duration::from_millis(500)));
+        assert_eq!(bucket.take(&limit, start + Duration::from_millis(500)), None);
+
+        // Refilling never goes beyond the burst
+        let later = start + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert_eq!(bucket.take(&limit, later), None);
+        }
+        assert!(bucket.take(&limit, later).is_some());
+    }
+
+    #[async_std::test]
+    async fn test_rate_limiter() {
+        let limiter = RateLimiter::new(HashMap::from([(
+            "getaddr".to_string(),
+            RateLimit { rate: 0.0, burst: 2 },
+        )]));
+
+        assert!(limiter.try_acquire("getaddr").await);
+        assert!(limiter.try_acquire("getaddr").await);
+        assert!(!limiter.try_acquire("getaddr").await);
+
+        // Unlimited commands
+        for _ in 0..100 {
+            assert!(limiter.try_acquire("ping").await);
+        }
+    }
+}
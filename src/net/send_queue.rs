@@ -0,0 +1,201 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::VecDeque;
+
+use async_std::sync::Mutex;
+
+use super::message::{MessagePriority, Packet};
+use crate::{Error, Result};
+
+/// Number of times a non-empty queue may be passed over in favour of higher
+/// priority ones before it gets served anyway.
+const MAX_SKIPS: usize = 8;
+
+/// Packet waiting to be written, along with where to report the outcome.
+struct QueuedPacket {
+    packet: Packet,
+    reply: smol::channel::Sender<Result<()>>,
+}
+
+struct Queues {
+    queues: [VecDeque<QueuedPacket>; MessagePriority::COUNT],
+    skips: [usize; MessagePriority::COUNT],
+}
+
+/// Outbound queue of a channel. Packets are written out in priority order,
+/// so large low priority messages don't hold back urgent ones, while lower
+/// priorities still get their turn under constant higher priority traffic.
+pub struct SendQueue {
+    queues: Mutex<Queues>,
+    notify_send: smol::channel::Sender<()>,
+    notify_recv: smol::channel::Receiver<()>,
+}
+
+impl Default for SendQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SendQueue {
+    pub fn new() -> Self {
+        let (notify_send, notify_recv) = smol::channel::unbounded();
+        Self {
+            queues: Mutex::new(Queues { queues: Default::default(), skips: Default::default() }),
+            notify_send,
+            notify_recv,
+        }
+    }
+
+    /// Queue a packet and wait for it to be written out.
+    pub async fn send(&self, priority: MessagePriority, packet: Packet) -> Result<()> {
+        if self.notify_send.is_closed() {
+            return Err(Error::ChannelStopped)
+        }
+
+        let (reply, result) = smol::channel::bounded(1);
+        self.queues.lock().await.queues[priority as usize]
+            .push_back(QueuedPacket { packet, reply });
+        self.notify_send.send(()).await?;
+
+        // The writer going away drops the reply sender
+        result.recv().await.unwrap_or(Err(Error::ChannelStopped))
+    }
+
+    /// Close the queue, failing the packets still waiting in it.
+    pub async fn close(&self) {
+        self.notify_send.close();
+        for queue in self.queues.lock().await.queues.iter_mut() {
+            queue.clear();
+        }
+    }
+
+    /// Wait for the next packet to write out. The outcome of writing it has
+    /// to be reported through the returned sender.
+    pub async fn next(&self) -> Result<(Packet, smol::channel::Sender<Result<()>>)> {
+        self.notify_recv.recv().await?;
+
+        let queues = &mut *self.queues.lock().await;
+        // Every notification comes with a queued packet
+        let mut pick = queues.queues.iter().position(|q| !q.is_empty()).unwrap();
+
+        // Account for the lower priorities we pass over, and serve the
+        // first one that waited for too long instead.
+        for priority in pick + 1..MessagePriority::COUNT {
+            if !queues.queues[priority].is_empty() {
+                queues.skips[priority] += 1;
+            }
+        }
+        if let Some(starved) = (pick + 1..MessagePriority::COUNT)
+            .find(|p| !queues.queues[*p].is_empty() && queues.skips[*p] > MAX_SKIPS)
+        {
+            pick = starved;
+        }
+        queues.skips[pick] = 0;
+
+        let queued = queues.queues[pick].pop_front().unwrap();
+        Ok((queued.packet, queued.reply))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_std::sync::Arc;
+
+    use super::*;
+
+    fn packet(command: &str) -> Packet {
+        Packet { command: command.to_string(), payload: vec![] }
+    }
+
+    #[async_std::test]
+    async fn test_send_queue_order() {
+        let queue = Arc::new(SendQueue::new());
+
+        // Queue the packets from tasks, since send() waits for the writer
+        let mut tasks = vec![];
+        for (priority, command) in [
+            (MessagePriority::Low, "low"),
+            (MessagePriority::Normal, "normal"),
+            (MessagePriority::High, "high"),
+        ] {
+            let queue = queue.clone();
+            tasks.push(async_std::task::spawn(async move {
+                queue.send(priority, packet(command)).await
+            }));
+            // Make sure they are queued in this order
+            while queue.notify_recv.len() < tasks.len() {
+                async_std::task::yield_now().await;
+            }
+        }
+
+        for expected in ["high", "normal", "low"] {
+            let (packet, reply) = queue.next().await.unwrap();
+            assert_eq!(packet.command, expected);
+            reply.send(Ok(())).await.unwrap();
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+    }
+
+    #[async_std::test]
+    async fn test_send_queue_fairness() {
+        let queue = Arc::new(SendQueue::new());
+
+        let low = {
+            let queue = queue.clone();
+            async_std::task::spawn(
+                async move { queue.send(MessagePriority::Low, packet("low")).await },
+            )
+        };
+        while queue.notify_recv.is_empty() {
+            async_std::task::yield_now().await;
+        }
+
+        // Keep the high priority queue busy, the low priority packet still
+        // makes it through once it got skipped too many times.
+        let mut served = vec![];
+        while !served.contains(&"low".to_string()) {
+            let high = {
+                let queue = queue.clone();
+                async_std::task::spawn(async move {
+                    queue.send(MessagePriority::High, packet("high")).await
+                })
+            };
+            while queue.notify_recv.len() < 2 {
+                async_std::task::yield_now().await;
+            }
+
+            let (packet, reply) = queue.next().await.unwrap();
+            served.push(packet.command);
+            reply.send(Ok(())).await.unwrap();
+
+            if served.last().unwrap() == "low" {
+                // Serve the high priority packet still in the queue
+                let (_, reply) = queue.next().await.unwrap();
+                reply.send(Ok(())).await.unwrap();
+            }
+            high.await.unwrap();
+        }
+
+        assert_eq!(served.len(), MAX_SKIPS + 1);
+        low.await.unwrap();
+    }
+}
@@ -0,0 +1,241 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{collections::HashMap, fs, path::Path};
+
+use async_std::sync::{Arc, Mutex, Weak};
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use serde_json::json;
+use smol::Executor;
+use url::Url;
+
+use crate::{
+    system::{StoppableTask, StoppableTaskPtr},
+    util::{
+        file::{load_file, save_file},
+        path::expand_path,
+    },
+    Error, Result,
+};
+
+use super::{
+    super::{ChannelPtr, Connector, P2p},
+    Session, SessionBitflag, SESSION_OUTBOUND,
+};
+
+/// Defines anchor connections session.
+pub struct AnchorSession {
+    p2p: Weak<P2p>,
+    connect_tasks: Mutex<Vec<StoppableTaskPtr>>,
+    channels: Mutex<HashMap<Url, ChannelPtr>>,
+}
+
+impl AnchorSession {
+    /// Create a new anchor session.
+    pub fn new(p2p: Weak<P2p>) -> Arc<Self> {
+        Arc::new(Self {
+            p2p,
+            connect_tasks: Mutex::new(Vec::new()),
+            channels: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Start the anchor session. Connects to the anchors saved on our
+    /// previous run, and returns once every attempt either connected or
+    /// failed, so we are back with the peers we trusted before we ask the
+    /// seeds for new ones.
+    pub async fn start(self: Arc<Self>, executor: Arc<Executor<'_>>) -> Result<()> {
+        let settings = self.p2p().settings();
+        let Some(anchorlist) = &settings.anchorlist else { return Ok(()) };
+
+        let anchors = match expand_path(anchorlist).and_then(|path| load_anchors(&path)) {
+            Ok(anchors) => anchors,
+            Err(e) => {
+                warn!(target: "net::anchor_session", "Failed loading anchors {}: {}", anchorlist, e);
+                return Ok(())
+            }
+        };
+        let anchors: Vec<Url> =
+            anchors.into_iter().take(settings.anchor_connections as usize).collect();
+        if anchors.is_empty() {
+            return Ok(())
+        }
+
+        info!(target: "net::anchor_session", "Connecting to {} anchors", anchors.len());
+        let (ready_send, ready_recv) = smol::channel::bounded(anchors.len());
+        let mut connect_tasks = self.connect_tasks.lock().await;
+
+        for (index, anchor) in anchors.into_iter().enumerate() {
+            let task = StoppableTask::new();
+
+            task.clone().start(
+                self.clone().connect_anchor(index, anchor, ready_send.clone(), executor.clone()),
+                // Ignore stop handler
+                |_| async {},
+                Error::NetworkServiceStopped,
+                executor.clone(),
+            );
+
+            connect_tasks.push(task);
+        }
+        drop(ready_send);
+
+        // Every task reports once it connected or failed, and drops its sender
+        while ready_recv.recv().await.is_ok() {}
+
+        Ok(())
+    }
+
+    /// Stop the anchor session.
+    pub async fn stop(&self) {
+        for task in &*self.connect_tasks.lock().await {
+            task.stop().await;
+        }
+    }
+
+    /// Connect to an anchor and keep the channel around until it closes.
+    async fn connect_anchor(
+        self: Arc<Self>,
+        index: usize,
+        anchor: Url,
+        ready: smol::channel::Sender<()>,
+        executor: Arc<Executor<'_>>,
+    ) -> Result<()> {
+        let p2p = self.p2p();
+        if p2p.bans().is_banned(&anchor).await || !p2p.add_pending(anchor.clone()).await {
+            return Ok(())
+        }
+
+        info!(target: "net::anchor_session", "#{} connecting to anchor [{}]", index, anchor);
+        let parent = Arc::downgrade(&self);
+        let connector = Connector::new(p2p.settings(), Arc::new(parent));
+        let result = match connector.connect(anchor.clone()).await {
+            Ok(channel) => {
+                self.clone().register_channel(channel.clone(), executor).await.map(|_| channel)
+            }
+            Err(e) => Err(e),
+        };
+        p2p.remove_pending(&anchor).await;
+        let _ = ready.send(()).await;
+        drop(ready);
+
+        let channel = match result {
+            Ok(channel) => channel,
+            Err(e) => {
+                warn!(target: "net::anchor_session", "#{} failed connecting to anchor [{}]: {}", index, anchor, e);
+                return Err(e)
+            }
+        };
+
+        info!(target: "net::anchor_session", "#{} connected to anchor [{}]", index, anchor);
+        p2p.hosts().mark_connected(&anchor).await;
+
+        let stop_sub = channel.subscribe_stop().await?;
+        self.channels.lock().await.insert(anchor.clone(), channel);
+        stop_sub.receive().await;
+        self.channels.lock().await.remove(&anchor);
+
+        info!(target: "net::anchor_session", "#{} anchor disconnected [{}]", index, anchor);
+        Ok(())
+    }
+}
+
+/// Read the anchors saved by [`save_anchors`]. A missing file means we
+/// have no anchors.
+pub fn load_anchors(path: &Path) -> Result<Vec<Url>> {
+    if !path.exists() {
+        return Ok(vec![])
+    }
+
+    let mut anchors = vec![];
+    for line in load_file(path)?.lines() {
+        match Url::parse(line) {
+            Ok(anchor) => anchors.push(anchor),
+            Err(_) => {
+                warn!(target: "net::anchor_session", "Skipping malformed anchor: {}", line)
+            }
+        }
+    }
+
+    debug!(target: "net::anchor_session", "Loaded {} anchors", anchors.len());
+    Ok(anchors)
+}
+
+/// Write the anchors to a file, one url per line, best anchor first. The
+/// file is written next to the target and then renamed over it.
+pub fn save_anchors(path: &Path, anchors: &[Url]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let contents: String = anchors.iter().map(|anchor| format!("{}\n", anchor)).collect();
+    let tmp_path = path.with_extension("tmp");
+    save_file(&tmp_path, &contents)?;
+    fs::rename(tmp_path, path)?;
+
+    debug!(target: "net::anchor_session", "Saved {} anchors", anchors.len());
+    Ok(())
+}
+
+#[async_trait]
+impl Session for AnchorSession {
+    async fn get_info(&self) -> serde_json::Value {
+        let mut infos = HashMap::new();
+        for (addr, channel) in &*self.channels.lock().await {
+            infos.insert(addr.to_string(), channel.get_info().await);
+        }
+        json!({
+            "connected": infos,
+        })
+    }
+
+    fn p2p(&self) -> Arc<P2p> {
+        self.p2p.upgrade().unwrap()
+    }
+
+    // Anchors are regular outbound connections, running the same protocols
+    fn type_id(&self) -> SessionBitflag {
+        SESSION_OUTBOUND
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anchors_persistence() {
+        let dir = std::env::temp_dir().join(format!(
+            "darkfi_test_anchors_{}_{}",
+            std::process::id(),
+            rand::random::<u64>()
+        ));
+        let path = dir.join("anchors.txt");
+        assert!(load_anchors(&path).unwrap().is_empty());
+
+        let anchors = vec![
+            Url::parse("tcp://10.0.0.1:8342").unwrap(),
+            Url::parse("tls://[::1]:8342").unwrap(),
+        ];
+        save_anchors(&path, &anchors).unwrap();
+        assert_eq!(load_anchors(&path).unwrap(), anchors);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
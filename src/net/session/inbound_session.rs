@@ -15,14 +15,19 @@
  * You should have received a copy of the GNU Affero General Public License
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
-use std::collections::HashMap;
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, VecDeque},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    time::{Duration, Instant},
+};
 
 use async_std::sync::{Arc, Mutex, Weak};
 use async_trait::async_trait;
-use log::{error, info};
+use log::{error, info, warn};
 use serde_json::json;
 use smol::Executor;
-use url::Url;
+use url::{Host, Url};
 
 use crate::{
     system::{StoppableTask, StoppableTaskPtr},
@@ -30,7 +35,7 @@ use crate::{
 };
 
 use super::{
-    super::{Acceptor, AcceptorPtr, ChannelPtr, P2p},
+    super::{Acceptor, AcceptorPtr, BanList, ChannelPtr, P2p},
     Session, SessionBitflag, SESSION_INBOUND,
 };
 
@@ -44,12 +49,24 @@ impl InboundInfo {
     }
 }
 
+/// An inbound channel that is connected, or still performing the handshake.
+struct InboundPeer {
+    channel: ChannelPtr,
+    /// Address the inbound limits apply to, unset for exempt peers
+    ip: Option<IpAddr>,
+    connected_at: Instant,
+}
+
 /// Defines inbound connections session.
 pub struct InboundSession {
     p2p: Weak<P2p>,
     acceptors: Mutex<Vec<AcceptorPtr>>,
     accept_tasks: Mutex<Vec<StoppableTaskPtr>>,
     connect_infos: Mutex<Vec<HashMap<Url, InboundInfo>>>,
+    /// Inbound channels counted against the inbound limits
+    peers: Mutex<Vec<InboundPeer>>,
+    /// Start times of the handshakes of the last second
+    handshakes: Mutex<VecDeque<Instant>>,
 }
 
 impl InboundSession {
@@ -60,6 +77,8 @@ impl InboundSession {
             acceptors: Mutex::new(Vec::new()),
             accept_tasks: Mutex::new(Vec::new()),
             connect_infos: Mutex::new(Vec::new()),
+            peers: Mutex::new(Vec::new()),
+            handshakes: Mutex::new(VecDeque::new()),
         })
     }
 
@@ -159,11 +178,107 @@ impl InboundSession {
     ) -> Result<()> {
         info!(target: "net::inbound_session", "#{} connected inbound [{}]", index, channel.address());
 
-        self.clone().register_channel(channel.clone(), executor.clone()).await?;
+        if !self.admit(&channel).await {
+            channel.stop().await;
+            return Ok(())
+        }
+
+        let result = self.clone().register_channel(channel.clone(), executor.clone()).await;
+        match result {
+            Ok(()) => self.manage_channel_for_get_info(index, channel.clone()).await,
+            Err(_) => channel.stop().await,
+        }
 
-        self.manage_channel_for_get_info(index, channel).await;
+        self.peers.lock().await.retain(|peer| !Arc::ptr_eq(&peer.channel, &channel));
 
-        Ok(())
+        result
+    }
+
+    /// Decide whether to take in a new inbound channel. If one of the inbound
+    /// connection limits is hit, the worst scored and then oldest peers it
+    /// applies to get evicted to make room. Returns false if the channel
+    /// should be dropped instead, which happens when too many handshakes are
+    /// being started.
+    async fn admit(&self, channel: &ChannelPtr) -> bool {
+        let p2p = self.p2p();
+        let settings = p2p.settings();
+
+        if !self.handshake_allowed(settings.inbound_handshakes_per_second).await {
+            warn!(
+                target: "net::inbound_session",
+                "Too many inbound handshakes, dropping [{}]",
+                channel.address()
+            );
+            return false
+        }
+
+        let bans = p2p.bans();
+        let ip = limited_ip(&channel.address());
+        let mut peers = self.peers.lock().await;
+        let mut evicted = vec![];
+
+        if let Some(ip) = ip {
+            let limit = settings.inbound_connections_per_ip;
+            while limit > 0 && peers.iter().filter(|p| p.ip == Some(ip)).count() >= limit {
+                match evict(&mut peers, &bans, |p| p.ip == Some(ip)).await {
+                    Some(channel) => evicted.push(channel),
+                    None => break,
+                }
+            }
+
+            let limit = settings.inbound_connections_per_subnet;
+            let same_subnet = |p: &InboundPeer| p.ip.map(|i| subnet(&i)) == Some(subnet(&ip));
+            while limit > 0 && peers.iter().filter(|p| same_subnet(p)).count() >= limit {
+                match evict(&mut peers, &bans, same_subnet).await {
+                    Some(channel) => evicted.push(channel),
+                    None => break,
+                }
+            }
+        }
+
+        let limit = settings.inbound_connections;
+        while limit > 0 && peers.len() >= limit {
+            match evict(&mut peers, &bans, |_| true).await {
+                Some(channel) => evicted.push(channel),
+                None => break,
+            }
+        }
+
+        peers.push(InboundPeer { channel: channel.clone(), ip, connected_at: Instant::now() });
+        drop(peers);
+
+        for channel in evicted {
+            info!(
+                target: "net::inbound_session",
+                "Inbound limit hit, evicting [{}]",
+                channel.address()
+            );
+            channel.stop().await;
+        }
+
+        true
+    }
+
+    /// Account for a new handshake, unless `per_second` handshakes were
+    /// already started within the last second.
+    async fn handshake_allowed(&self, per_second: u32) -> bool {
+        if per_second == 0 {
+            return true
+        }
+
+        let now = Instant::now();
+        let mut handshakes = self.handshakes.lock().await;
+        while handshakes.front().map_or(false, |t| now.duration_since(*t) >= Duration::from_secs(1))
+        {
+            handshakes.pop_front();
+        }
+
+        if handshakes.len() >= per_second as usize {
+            return false
+        }
+
+        handshakes.push_back(now);
+        true
     }
 
     async fn manage_channel_for_get_info(&self, index: usize, channel: ChannelPtr) {
@@ -181,6 +296,62 @@ impl InboundSession {
     }
 }
 
+/// Remove the peer to evict out of the ones matching `filter`, returning
+/// its channel.
+async fn evict<F>(peers: &mut Vec<InboundPeer>, bans: &BanList, filter: F) -> Option<ChannelPtr>
+where
+    F: Fn(&InboundPeer) -> bool,
+{
+    let mut candidates = vec![];
+    for (index, peer) in peers.iter().enumerate() {
+        if filter(peer) {
+            let score = bans.score(&peer.channel.address()).await;
+            candidates.push((index, score, peer.connected_at));
+        }
+    }
+
+    let index = pick_eviction(&candidates)?;
+    Some(peers.remove(index).channel)
+}
+
+/// Pick the peer with the highest misbehavior score out of the
+/// `(index, score, connected_at)` candidates, the oldest one on ties.
+fn pick_eviction(candidates: &[(usize, u32, Instant)]) -> Option<usize> {
+    candidates
+        .iter()
+        .max_by_key(|(_, score, connected_at)| (*score, Reverse(*connected_at)))
+        .map(|(index, _, _)| *index)
+}
+
+/// IP address of an inbound peer that the per-IP and per-subnet limits
+/// apply to. Peers on loopback and non-IP transports are exempt.
+fn limited_ip(addr: &Url) -> Option<IpAddr> {
+    let ip = match addr.host()? {
+        Host::Ipv4(ip) => IpAddr::V4(ip),
+        Host::Ipv6(ip) => IpAddr::V6(ip),
+        Host::Domain(_) => return None,
+    };
+
+    if ip.is_loopback() {
+        return None
+    }
+    Some(ip)
+}
+
+/// Subnet an address belongs to: its /24 for IPv4 and its /48 for IPv6.
+fn subnet(ip: &IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            IpAddr::V4(Ipv4Addr::new(a, b, c, 0))
+        }
+        IpAddr::V6(ip) => {
+            let s = ip.segments();
+            IpAddr::V6(Ipv6Addr::new(s[0], s[1], s[2], 0, 0, 0, 0, 0))
+        }
+    }
+}
+
 #[async_trait]
 impl Session for InboundSession {
     async fn get_info(&self) -> serde_json::Value {
@@ -206,3 +377,31 @@ impl Session for InboundSession {
         SESSION_INBOUND
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inbound_limit_helpers() {
+        let url = |s: &str| Url::parse(s).unwrap();
+        let ip = limited_ip(&url("tcp://10.1.2.3:41234")).unwrap();
+        assert_eq!(subnet(&ip), limited_ip(&url("tcp://10.1.2.200:1")).unwrap());
+        assert_ne!(subnet(&ip), subnet(&limited_ip(&url("tcp://10.1.3.3:1")).unwrap()));
+
+        let ip = limited_ip(&url("tcp://[2001:db8:1:2::1]:41234")).unwrap();
+        let other = limited_ip(&url("tcp://[2001:db8:1:ffff::1]:1")).unwrap();
+        assert_eq!(subnet(&ip), subnet(&other));
+
+        assert!(limited_ip(&url("tcp://127.0.0.1:41234")).is_none());
+        assert!(limited_ip(&url("tor://abcdef.onion:41234")).is_none());
+
+        let now = Instant::now();
+        let later = now + Duration::from_secs(1);
+        // Oldest peer goes first
+        assert_eq!(pick_eviction(&[(0, 0, later), (1, 0, now)]), Some(1));
+        // Misbehaving peers go before older ones
+        assert_eq!(pick_eviction(&[(0, 0, now), (1, 10, later)]), Some(1));
+        assert_eq!(pick_eviction(&[]), None);
+    }
+}
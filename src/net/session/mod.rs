@@ -16,7 +16,10 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::sync::{Arc, Weak};
+use std::{
+    sync::{Arc, Weak},
+    time::Instant,
+};
 
 use async_trait::async_trait;
 use log::debug;
@@ -48,6 +51,12 @@ use super::{p2p::P2pPtr, protocol::ProtocolVersion, ChannelPtr};
 /// signal.
 pub mod seedsync_session;
 
+/// Anchor connections session. On startup, before seeding, reconnects to the
+/// outbound peers that stayed connected the longest on our previous run, as
+/// saved by [`P2p::stop()`](super::P2p::stop). Reconnecting to peers we
+/// already trust makes it harder to eclipse a restarting node.
+pub mod anchor_session;
+
 pub mod manual_session;
 
 /// Inbound connections session. Manages the creation of inbound sessions. Used
@@ -76,6 +85,7 @@ pub const SESSION_MANUAL: SessionBitflag = 0b0100;
 pub const SESSION_SEED: SessionBitflag = 0b1000;
 pub const SESSION_ALL: SessionBitflag = 0b1111;
 
+pub use anchor_session::AnchorSession;
 pub use inbound_session::InboundSession;
 pub use manual_session::ManualSession;
 pub use outbound_session::OutboundSession;
@@ -130,26 +140,34 @@ pub trait Session: Sync {
         let protocols =
             p2p.protocol_registry().attach(self.type_id(), channel.clone(), p2p.clone()).await;
 
-        // Perform the handshake protocol
-        let protocol_version =
-            ProtocolVersion::new(channel.clone(), p2p.settings().clone(), p2p.hosts().clone())
-                .await;
+        // Perform the handshake protocol, advertising our optional protocols
+        let protocol_version = ProtocolVersion::new(
+            channel.clone(),
+            p2p.settings().clone(),
+            p2p.hosts().clone(),
+            protocols.features(),
+        )
+        .await;
         let handshake_task =
             self.perform_handshake_protocols(protocol_version, channel.clone(), executor.clone());
 
         // Switch on the channel
+        let handshake_start = Instant::now();
         channel.start(executor.clone());
 
         // Wait for handshake to finish.
         handshake_task.await?;
+        channel.set_handshake_latency(handshake_start.elapsed()).await;
 
         // Now the channel is ready
         debug!(target: "net", "Session handshake complete. Activating remaining protocols");
 
-        // Now start all the protocols
+        // Now start the core protocols, and the optional ones the peer
+        // advertised as well. The rest get dropped here.
         // They are responsible for managing their own lifetimes and
         // correctly self destructing when the channel ends.
-        for protocol in protocols {
+        let remote_features = channel.remote_features().await;
+        for protocol in protocols.negotiate(&remote_features) {
             // Activate protocol
             protocol.start(executor.clone()).await?;
         }
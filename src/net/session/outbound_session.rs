@@ -21,7 +21,6 @@ use std::fmt;
 use async_std::sync::{Arc, Mutex, Weak};
 use async_trait::async_trait;
 use log::{debug, error, info, warn};
-use rand::seq::SliceRandom;
 use serde_json::{json, Value};
 use smol::Executor;
 use url::Url;
@@ -229,6 +228,7 @@ impl OutboundSession {
 
                     // Remove pending lock since register_channel will add the channel to p2p
                     self.p2p().remove_pending(&addr).await;
+                    self.p2p().hosts().mark_connected(&addr).await;
                     {
                         let info = &mut self.slot_info.lock().await[slot_number as usize];
                         info.channel = Some(channel.clone());
@@ -251,8 +251,11 @@ impl OutboundSession {
             }
         }
 
-        // Remove url from hosts
-        self.p2p().hosts().remove(&addr).await;
+        // Record the failure, evicting the url from hosts if it keeps failing
+        if self.p2p().hosts().mark_failed(&addr).await {
+            info!(target: "net::outbound_session", "#{} evicted failing host [{}]", slot_number, addr);
+        }
+        self.p2p().remove_pending(&addr).await;
 
         {
             let info = &mut self.slot_info.lock().await[slot_number as usize];
@@ -279,14 +282,8 @@ impl OutboundSession {
             let p2p = self.p2p();
             let self_inbound_addr = p2p.settings().external_addr.clone();
 
-            let mut addrs;
-
-            {
-                let hosts = p2p.hosts().load_all().await;
-                addrs = hosts;
-            }
-
-            addrs.shuffle(&mut rand::thread_rng());
+            // Hosts that worked before are tried first
+            let addrs = p2p.hosts().load_ordered().await;
 
             for addr in addrs {
                 if p2p.exists(&addr).await? {
@@ -298,6 +295,11 @@ impl OutboundSession {
                     continue
                 }
 
+                // Skip banned peers
+                if p2p.bans().is_banned(&addr).await {
+                    continue
+                }
+
                 // Obtain a lock on this address to prevent duplicate connections
                 if !p2p.add_pending(addr.clone()).await {
                     continue
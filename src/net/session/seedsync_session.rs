@@ -57,7 +57,7 @@ impl SeedSyncSession {
             warn!(target: "net::seedsync_session", "Skipping seed sync process since no seeds are configured.");
             // Store external addresses in hosts explicitly
             if !settings.external_addr.is_empty() {
-                self.p2p().hosts().store(settings.external_addr.clone()).await
+                self.p2p().hosts().store(None, settings.external_addr.clone()).await
             }
 
             return Ok(())
@@ -16,14 +16,22 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use serde::Deserialize;
 use structopt::StructOpt;
 use structopt_toml::StructOptToml;
 use url::Url;
 
-use crate::net::transport::TransportName;
+use crate::net::{
+    constants::{
+        ANCHOR_CONNECTIONS, BAN_DURATION_SECONDS, BAN_THRESHOLD, MAX_INBOUND_CONNECTIONS,
+        MAX_INBOUND_CONNECTIONS_PER_IP, MAX_INBOUND_CONNECTIONS_PER_SUBNET,
+        MAX_INBOUND_HANDSHAKES_PER_SECOND, MAX_PAYLOAD_SIZE,
+    },
+    rate_limit::{default_recv_rate_limits, RateLimit},
+    transport::{MemoryNetwork, TransportName},
+};
 
 /// Atomic pointer to network settings.
 pub type SettingsPtr = Arc<Settings>;
@@ -35,6 +43,17 @@ pub struct Settings {
     pub inbound: Vec<Url>,
     /// Outbound connection slots number
     pub outbound_connections: u32,
+    /// Maximum number of inbound connections, 0 for no limit
+    pub inbound_connections: usize,
+    /// Maximum number of inbound connections from a single IP, 0 for no
+    /// limit. Loopback addresses are exempt.
+    pub inbound_connections_per_ip: usize,
+    /// Maximum number of inbound connections from a single /24 (IPv4) or
+    /// /48 (IPv6) subnet, 0 for no limit. Loopback addresses are exempt.
+    pub inbound_connections_per_subnet: usize,
+    /// Maximum number of inbound handshakes started per second, 0 for no
+    /// limit
+    pub inbound_handshakes_per_second: u32,
     /// Manual connections retry limit, 0 for forever looping
     pub manual_attempt_limit: u32,
     /// Seed connection establishment timeout
@@ -61,12 +80,44 @@ pub struct Settings {
     pub app_version: Option<String>,
     /// Prefered transports for outbound connections
     pub outbound_transports: Vec<TransportName>,
+    /// SOCKS5 proxy that `socks5://` and `socks5+tls://` outbound connections
+    /// are routed through, with optional `user:pass@` credentials
+    pub socks5_proxy: Option<Url>,
     /// Allow localnet hosts
     pub localnet: bool,
     /// Enable peer discovery
     pub peer_discovery: bool,
     /// Enable channel logging
     pub channel_log: bool,
+    /// Maximum payload size of the packets we accept from peers, on top of
+    /// the maximum size of each message type
+    pub max_payload_size: usize,
+    /// Path to the file the host list is persisted in across restarts
+    pub hostlist: Option<String>,
+    /// Path to the file the anchor peers are persisted in across restarts
+    pub anchorlist: Option<String>,
+    /// Number of longest lived outbound peers kept as anchors
+    pub anchor_connections: u32,
+    /// Misbehavior score at which a peer gets banned
+    pub ban_threshold: u32,
+    /// Duration of a ban, in seconds
+    pub ban_duration_seconds: u64,
+    /// Services bitfield we advertise to peers in the version handshake
+    pub services: u64,
+    /// Services bits a peer must advertise for us to keep the connection
+    pub required_services: u64,
+    /// Address to serve Prometheus metrics on over HTTP, e.g.
+    /// `tcp://127.0.0.1:9101`
+    pub metrics_addr: Option<Url>,
+    /// Rate limits of the messages we send, per command. Sending waits
+    /// until the limit allows it.
+    pub send_rate_limits: HashMap<String, RateLimit>,
+    /// Rate limits of the messages we accept from peers, per command.
+    /// Peers exceeding them get disconnected.
+    pub recv_rate_limits: HashMap<String, RateLimit>,
+    /// Network `memory://` addresses are reached on. Tests running nodes
+    /// in parallel should give each of their networks its own.
+    pub memory_network: MemoryNetwork,
 }
 
 impl Default for Settings {
@@ -74,6 +125,10 @@ impl Default for Settings {
         Self {
             inbound: Vec::new(),
             outbound_connections: 0,
+            inbound_connections: MAX_INBOUND_CONNECTIONS,
+            inbound_connections_per_ip: MAX_INBOUND_CONNECTIONS_PER_IP,
+            inbound_connections_per_subnet: MAX_INBOUND_CONNECTIONS_PER_SUBNET,
+            inbound_handshakes_per_second: MAX_INBOUND_HANDSHAKES_PER_SECOND,
             manual_attempt_limit: 0,
             seed_query_timeout_seconds: 8,
             connect_timeout_seconds: 10,
@@ -86,9 +141,22 @@ impl Default for Settings {
             node_id: String::new(),
             app_version: Some(option_env!("CARGO_PKG_VERSION").unwrap_or("").to_string()),
             outbound_transports: get_outbound_transports(vec![]),
+            socks5_proxy: None,
             localnet: false,
             peer_discovery: true,
             channel_log: false,
+            max_payload_size: MAX_PAYLOAD_SIZE,
+            hostlist: None,
+            anchorlist: None,
+            anchor_connections: ANCHOR_CONNECTIONS,
+            ban_threshold: BAN_THRESHOLD,
+            ban_duration_seconds: BAN_DURATION_SECONDS,
+            services: 0,
+            required_services: 0,
+            metrics_addr: None,
+            send_rate_limits: HashMap::new(),
+            recv_rate_limits: default_recv_rate_limits(),
+            memory_network: MemoryNetwork::global(),
         }
     }
 }
@@ -106,6 +174,23 @@ pub struct SettingsOpt {
     #[structopt(long = "slots")]
     pub outbound_connections: Option<u32>,
 
+    /// Maximum number of inbound connections, 0 for no limit
+    #[structopt(long)]
+    pub inbound_connections: Option<usize>,
+
+    /// Maximum number of inbound connections from a single IP, 0 for no limit
+    #[structopt(skip)]
+    pub inbound_connections_per_ip: Option<usize>,
+
+    /// Maximum number of inbound connections from a single /24 (IPv4) or
+    /// /48 (IPv6) subnet, 0 for no limit
+    #[structopt(skip)]
+    pub inbound_connections_per_subnet: Option<usize>,
+
+    /// Maximum number of inbound handshakes started per second, 0 for no limit
+    #[structopt(skip)]
+    pub inbound_handshakes_per_second: Option<u32>,
+
     /// P2P external addresses node advertises so other peers can reach us
     /// and connect to us, as long us inbound addresses are also configured
     #[serde(default)]
@@ -162,6 +247,12 @@ pub struct SettingsOpt {
     #[structopt(long = "transports")]
     pub outbound_transports: Vec<String>,
 
+    /// SOCKS5 proxy that `socks5://` and `socks5+tls://` outbound connections
+    /// are routed through, with optional `user:pass@` credentials
+    #[serde(default)]
+    #[structopt(long)]
+    pub socks5_proxy: Option<Url>,
+
     /// Allow localnet hosts
     #[serde(default)]
     #[structopt(long)]
@@ -176,6 +267,54 @@ pub struct SettingsOpt {
     #[serde(default)]
     #[structopt(long)]
     pub channel_log: bool,
+
+    /// Maximum payload size of the packets we accept from peers
+    #[structopt(skip)]
+    pub max_payload_size: Option<usize>,
+
+    /// Path to the file the host list is persisted in across restarts
+    #[serde(default)]
+    #[structopt(long)]
+    pub hostlist: Option<String>,
+
+    /// Path to the file the anchor peers are persisted in across restarts
+    #[serde(default)]
+    #[structopt(long)]
+    pub anchorlist: Option<String>,
+
+    /// Number of longest lived outbound peers kept as anchors
+    #[structopt(skip)]
+    pub anchor_connections: Option<u32>,
+
+    /// Misbehavior score at which a peer gets banned
+    #[structopt(skip)]
+    pub ban_threshold: Option<u32>,
+
+    /// Duration of a ban, in seconds
+    #[structopt(skip)]
+    pub ban_duration_seconds: Option<u64>,
+
+    /// Services bitfield we advertise to peers in the version handshake
+    #[structopt(skip)]
+    pub services: Option<u64>,
+
+    /// Services bits a peer must advertise for us to keep the connection
+    #[structopt(skip)]
+    pub required_services: Option<u64>,
+
+    /// Address to serve Prometheus metrics on over HTTP, e.g.
+    /// `tcp://127.0.0.1:9101`
+    #[serde(default)]
+    #[structopt(long)]
+    pub metrics_addr: Option<Url>,
+
+    /// Rate limits of the messages we send, per command
+    #[structopt(skip)]
+    pub send_rate_limits: Option<HashMap<String, RateLimit>>,
+
+    /// Rate limits of the messages we accept from peers, per command
+    #[structopt(skip)]
+    pub recv_rate_limits: Option<HashMap<String, RateLimit>>,
 }
 
 impl From<SettingsOpt> for Settings {
@@ -183,6 +322,18 @@ impl From<SettingsOpt> for Settings {
         Self {
             inbound: settings_opt.inbound,
             outbound_connections: settings_opt.outbound_connections.unwrap_or(0),
+            inbound_connections: settings_opt
+                .inbound_connections
+                .unwrap_or(MAX_INBOUND_CONNECTIONS),
+            inbound_connections_per_ip: settings_opt
+                .inbound_connections_per_ip
+                .unwrap_or(MAX_INBOUND_CONNECTIONS_PER_IP),
+            inbound_connections_per_subnet: settings_opt
+                .inbound_connections_per_subnet
+                .unwrap_or(MAX_INBOUND_CONNECTIONS_PER_SUBNET),
+            inbound_handshakes_per_second: settings_opt
+                .inbound_handshakes_per_second
+                .unwrap_or(MAX_INBOUND_HANDSHAKES_PER_SECOND),
             manual_attempt_limit: settings_opt.manual_attempt_limit.unwrap_or(0),
             seed_query_timeout_seconds: settings_opt.seed_query_timeout_seconds.unwrap_or(8),
             connect_timeout_seconds: settings_opt.connect_timeout_seconds.unwrap_or(10),
@@ -195,9 +346,24 @@ impl From<SettingsOpt> for Settings {
             node_id: settings_opt.node_id,
             app_version: settings_opt.app_version,
             outbound_transports: get_outbound_transports(settings_opt.outbound_transports),
+            socks5_proxy: settings_opt.socks5_proxy,
             localnet: settings_opt.localnet,
             peer_discovery: settings_opt.peer_discovery,
             channel_log: settings_opt.channel_log,
+            max_payload_size: settings_opt.max_payload_size.unwrap_or(MAX_PAYLOAD_SIZE),
+            hostlist: settings_opt.hostlist,
+            anchorlist: settings_opt.anchorlist,
+            anchor_connections: settings_opt.anchor_connections.unwrap_or(ANCHOR_CONNECTIONS),
+            ban_threshold: settings_opt.ban_threshold.unwrap_or(BAN_THRESHOLD),
+            ban_duration_seconds: settings_opt.ban_duration_seconds.unwrap_or(BAN_DURATION_SECONDS),
+            services: settings_opt.services.unwrap_or(0),
+            required_services: settings_opt.required_services.unwrap_or(0),
+            metrics_addr: settings_opt.metrics_addr,
+            send_rate_limits: settings_opt.send_rate_limits.unwrap_or_default(),
+            recv_rate_limits: settings_opt
+                .recv_rate_limits
+                .unwrap_or_else(default_recv_rate_limits),
+            memory_network: MemoryNetwork::global(),
         }
     }
 }
@@ -34,9 +34,15 @@ pub use tcp::TcpTransport;
 mod tor;
 pub use tor::TorTransport;
 
+mod socks5;
+pub use socks5::Socks5Transport;
+
 mod unix;
 pub use unix::UnixTransport;
 
+mod memory;
+pub use memory::{MemoryNetwork, MemoryTransport};
+
 /// A helper function to convert SocketAddr to Url and add scheme
 pub(crate) fn socket_addr_to_url(addr: SocketAddr, scheme: &str) -> Result<Url> {
     let url = Url::parse(&format!("{}://{}", scheme, addr))?;
@@ -57,7 +63,9 @@ pub enum TransportName {
     Tcp(Option<String>),
     Tor(Option<String>),
     Nym(Option<String>),
+    Socks5(Option<String>),
     Unix,
+    Memory,
 }
 
 impl TransportName {
@@ -69,7 +77,10 @@ impl TransportName {
             Self::Tor(Some(opt)) => format!("tor+{}", opt),
             Self::Nym(None) => "nym".into(),
             Self::Nym(Some(opt)) => format!("nym+{}", opt),
+            Self::Socks5(None) => "socks5".into(),
+            Self::Socks5(Some(opt)) => format!("socks5+{}", opt),
             Self::Unix => "unix".into(),
+            Self::Memory => "memory".into(),
         }
     }
 }
@@ -85,7 +96,10 @@ impl TryFrom<&str> for TransportName {
             "tor+tls" => Self::Tor(Some("tls".into())),
             "nym" => Self::Nym(None),
             "nym+tls" => Self::Nym(Some("tls".into())),
+            "socks5" => Self::Socks5(None),
+            "socks5+tls" => Self::Socks5(Some("tls".into())),
             "unix" => Self::Unix,
+            "memory" => Self::Memory,
             n => return Err(crate::Error::UnsupportedTransport(n.into())),
         };
         Ok(transport_name)
@@ -0,0 +1,503 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! In-memory transport, used to run many nodes inside a single process.
+//!
+//! Nodes listen on and dial `memory://` addresses, and are identified by
+//! the host part of their address, e.g. `memory://node0:1000`. Nodes only
+//! reach the ones on the same [`MemoryNetwork`], given to them through
+//! their settings, so tests running in parallel can each use their own.
+//! The conditions of a simulated network can be changed at any time:
+//!
+//! * latency, either for all links or for specific pairs of nodes,
+//! * packet loss, where a lost write resets the connection, the same way
+//!   a reliable stream transport would end up behaving,
+//! * partitions, which refuse connections between the two sides and cut
+//!   the existing ones.
+//!
+//! Packet loss is drawn from a seeded RNG so test runs are reproducible.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use futures::{prelude::*, ready};
+use futures_rustls::{TlsAcceptor, TlsStream};
+use log::debug;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use smol::{
+    channel::{unbounded, Receiver, Sender},
+    Timer,
+};
+use url::Url;
+
+use super::{Transport, TransportListener, TransportStream};
+use crate::{Error, Result};
+
+/// Network shared by the nodes that don't get one of their own
+static GLOBAL_NETWORK: Mutex<Option<MemoryNetwork>> = Mutex::new(None);
+
+/// First port handed out to dialing streams, kept apart from the ports
+/// nodes usually listen on
+const EPHEMERAL_PORT_START: u16 = 49152;
+
+/// Data written on one end of a link, along with when it becomes
+/// readable on the other end.
+struct Chunk {
+    deliver_at: Instant,
+    data: Vec<u8>,
+}
+
+/// Both directions of a live link, kept so partitions can cut it.
+struct LinkHandle {
+    hosts: (String, String),
+    a: Sender<Chunk>,
+    b: Sender<Chunk>,
+}
+
+impl LinkHandle {
+    fn close(&self) {
+        self.a.close();
+        self.b.close();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.a.is_closed() && self.b.is_closed()
+    }
+}
+
+struct NetworkState {
+    /// Listening addresses and the queues their accepted streams are sent to
+    listeners: HashMap<String, Sender<(MemoryStream, Url)>>,
+    /// Live links
+    links: Vec<LinkHandle>,
+    /// Latency applied to links without a specific one
+    latency: Duration,
+    /// Latency of specific pairs of hosts
+    link_latency: HashMap<(String, String), Duration>,
+    /// Probability of a write getting lost
+    loss: f64,
+    /// RNG drawing the lost writes
+    rng: StdRng,
+    /// Pairs of hosts that can't reach each other
+    partitions: HashSet<(String, String)>,
+    /// Counter used for the ports of dialing streams
+    next_port: u16,
+}
+
+impl Default for NetworkState {
+    fn default() -> Self {
+        Self {
+            listeners: HashMap::new(),
+            links: vec![],
+            latency: Duration::ZERO,
+            link_latency: HashMap::new(),
+            loss: 0.0,
+            rng: StdRng::seed_from_u64(0),
+            partitions: HashSet::new(),
+            next_port: EPHEMERAL_PORT_START,
+        }
+    }
+}
+
+impl NetworkState {
+    fn latency(&self, a: &str, b: &str) -> Duration {
+        *self.link_latency.get(&pair(a, b)).unwrap_or(&self.latency)
+    }
+
+    fn is_partitioned(&self, a: &str, b: &str) -> bool {
+        self.partitions.contains(&pair(a, b))
+    }
+}
+
+/// Unordered pair of hosts.
+fn pair(a: &str, b: &str) -> (String, String) {
+    if a <= b {
+        (a.to_string(), b.to_string())
+    } else {
+        (b.to_string(), a.to_string())
+    }
+}
+
+/// Host part of a memory address.
+fn host(url: &Url) -> String {
+    url.host_str().unwrap_or_default().to_string()
+}
+
+/// Listener key of a memory address.
+fn listener_key(url: &Url) -> String {
+    format!("{}:{}", host(url), url.port().unwrap_or(0))
+}
+
+/// An in-memory network, along with its conditions. Clones refer to the
+/// same network.
+#[derive(Clone, Default)]
+pub struct MemoryNetwork(Arc<Mutex<NetworkState>>);
+
+impl fmt::Debug for MemoryNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("MemoryNetwork").finish()
+    }
+}
+
+impl MemoryNetwork {
+    /// Create a new network, isolated from all the others.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Network shared by all the nodes of the process that weren't given
+    /// one of their own.
+    pub fn global() -> Self {
+        GLOBAL_NETWORK.lock().unwrap().get_or_insert_with(Self::new).clone()
+    }
+
+    /// Run a closure over the network state.
+    fn with<T>(&self, f: impl FnOnce(&mut NetworkState) -> T) -> T {
+        f(&mut self.0.lock().unwrap())
+    }
+
+    /// Set the latency of all links without a specific latency.
+    pub fn set_latency(&self, latency: Duration) {
+        self.with(|n| n.latency = latency);
+    }
+
+    /// Set the latency of the links between two nodes.
+    pub fn set_link_latency(&self, a: &Url, b: &Url, latency: Duration) {
+        self.with(|n| n.link_latency.insert(pair(&host(a), &host(b)), latency));
+    }
+
+    /// Set the probability of a write getting lost, which resets its
+    /// connection. Losses are drawn from an RNG seeded with `seed`.
+    pub fn set_loss(&self, loss: f64, seed: u64) {
+        self.with(|n| {
+            n.loss = loss;
+            n.rng = StdRng::seed_from_u64(seed);
+        });
+    }
+
+    /// Partition the network between two sets of nodes. Connections
+    /// between the two sides are refused, and existing ones are cut.
+    pub fn partition(&self, side_a: &[Url], side_b: &[Url]) {
+        self.with(|n| {
+            for a in side_a {
+                for b in side_b {
+                    n.partitions.insert(pair(&host(a), &host(b)));
+                }
+            }
+
+            for link in &n.links {
+                if n.is_partitioned(&link.hosts.0, &link.hosts.1) {
+                    link.close();
+                }
+            }
+            n.links.retain(|l| !l.is_closed());
+        });
+    }
+
+    /// Remove all partitions.
+    pub fn heal(&self) {
+        self.with(|n| n.partitions.clear());
+    }
+
+    /// Cut all links and reset the network conditions, keeping listeners.
+    pub fn reset(&self) {
+        self.with(|n| {
+            for link in &n.links {
+                link.close();
+            }
+
+            let listeners = std::mem::take(&mut n.listeners);
+            *n = NetworkState { listeners, ..Default::default() };
+        });
+    }
+}
+
+/// One end of an in-memory link.
+pub struct MemoryStream {
+    network: MemoryNetwork,
+    local: String,
+    remote: String,
+    tx: Sender<Chunk>,
+    rx: Receiver<Chunk>,
+    /// Data received but not yet read
+    buf: Vec<u8>,
+    pos: usize,
+    /// Waits for the buffered data to arrive, when the link has latency
+    timer: Option<Timer>,
+}
+
+impl MemoryStream {
+    /// Create both ends of a new link and register it.
+    fn pair(network: &MemoryNetwork, local: &str, remote: &str) -> (Self, Self) {
+        let (a_tx, a_rx) = unbounded();
+        let (b_tx, b_rx) = unbounded();
+
+        let link = LinkHandle {
+            hosts: (local.to_string(), remote.to_string()),
+            a: a_tx.clone(),
+            b: b_tx.clone(),
+        };
+        network.with(|n| {
+            n.links.retain(|l| !l.is_closed());
+            n.links.push(link);
+        });
+
+        let ours = Self::new(network, local, remote, a_tx, b_rx);
+        let theirs = Self::new(network, remote, local, b_tx, a_rx);
+        (ours, theirs)
+    }
+
+    fn new(
+        network: &MemoryNetwork,
+        local: &str,
+        remote: &str,
+        tx: Sender<Chunk>,
+        rx: Receiver<Chunk>,
+    ) -> Self {
+        Self {
+            network: network.clone(),
+            local: local.to_string(),
+            remote: remote.to_string(),
+            tx,
+            rx,
+            buf: vec![],
+            pos: 0,
+            timer: None,
+        }
+    }
+
+    fn shutdown(&self) {
+        self.tx.close();
+        self.rx.close();
+    }
+}
+
+impl Drop for MemoryStream {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+impl AsyncRead for MemoryStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        out: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            // Wait for the buffered data to go through the link
+            if let Some(timer) = self.timer.as_mut() {
+                ready!(Pin::new(timer).poll(cx));
+                self.timer = None;
+            }
+
+            if self.pos < self.buf.len() {
+                let n = out.len().min(self.buf.len() - self.pos);
+                out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+                self.pos += n;
+                return Poll::Ready(Ok(n))
+            }
+
+            match ready!(Pin::new(&mut self.rx).poll_next(cx)) {
+                Some(chunk) => {
+                    if chunk.deliver_at > Instant::now() {
+                        self.timer = Some(Timer::at(chunk.deliver_at));
+                    }
+                    self.buf = chunk.data;
+                    self.pos = 0;
+                }
+                // Link closed
+                None => return Poll::Ready(Ok(0)),
+            }
+        }
+    }
+}
+
+impl AsyncWrite for MemoryStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let (latency, lost) = self.network.with(|n| {
+            let lost = n.loss > 0.0 && n.rng.gen_bool(n.loss.min(1.0));
+            (n.latency(&self.local, &self.remote), lost)
+        });
+
+        if lost {
+            debug!(target: "net::memory", "Lost write from {} to {}, resetting link", self.local, self.remote);
+            self.shutdown();
+            return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()))
+        }
+
+        let chunk = Chunk { deliver_at: Instant::now() + latency, data: buf.to_vec() };
+        match self.tx.try_send(chunk) {
+            Ok(()) => Poll::Ready(Ok(buf.len())),
+            Err(_) => Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.tx.close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl TransportStream for MemoryStream {}
+
+/// Accepts the streams dialed to a memory address.
+pub struct MemoryListener {
+    network: MemoryNetwork,
+    key: String,
+    rx: Receiver<(MemoryStream, Url)>,
+}
+
+impl Drop for MemoryListener {
+    fn drop(&mut self) {
+        self.network.with(|n| n.listeners.remove(&self.key));
+    }
+}
+
+#[async_trait]
+impl TransportListener for MemoryListener {
+    async fn next(&self) -> Result<(Box<dyn TransportStream>, Url)> {
+        match self.rx.recv().await {
+            Ok((stream, url)) => Ok((Box::new(stream), url)),
+            Err(_) => Err(Error::AcceptConnectionFailed(self.key.clone())),
+        }
+    }
+}
+
+#[async_trait]
+impl TransportListener for (TlsAcceptor, MemoryListener) {
+    async fn next(&self) -> Result<(Box<dyn TransportStream>, Url)> {
+        unimplemented!("TLS not supported for memory transport");
+    }
+}
+
+#[derive(Clone)]
+pub struct MemoryTransport {
+    /// Network the node is on
+    network: MemoryNetwork,
+    /// Address of the node using the transport. Dialed streams originate
+    /// from its host, so partitions and bans apply to them.
+    local: Option<Url>,
+}
+
+impl Transport for MemoryTransport {
+    type Acceptor = MemoryListener;
+    type Connector = MemoryStream;
+
+    type Listener = Pin<Box<dyn Future<Output = Result<Self::Acceptor>> + Send>>;
+    type Dial = Pin<Box<dyn Future<Output = Result<Self::Connector>> + Send>>;
+
+    type TlsListener = Pin<Box<dyn Future<Output = Result<(TlsAcceptor, Self::Acceptor)>> + Send>>;
+    type TlsDialer = Pin<Box<dyn Future<Output = Result<TlsStream<Self::Connector>>> + Send>>;
+
+    fn listen_on(self, url: Url) -> Result<Self::Listener> {
+        match url.scheme() {
+            "memory" => {}
+            x => return Err(Error::UnsupportedTransport(x.to_string())),
+        }
+
+        debug!(target: "net::memory", "{} transport: listening on {}", url.scheme(), url);
+        Ok(Box::pin(self.do_listen(url)))
+    }
+
+    fn upgrade_listener(self, _acceptor: Self::Acceptor) -> Result<Self::TlsListener> {
+        unimplemented!("TLS not supported for memory transport");
+    }
+
+    fn dial(self, url: Url, _timeout: Option<Duration>) -> Result<Self::Dial> {
+        match url.scheme() {
+            "memory" => {}
+            x => return Err(Error::UnsupportedTransport(x.to_string())),
+        }
+
+        debug!(target: "net::memory", "{} transport: dialing {}", url.scheme(), url);
+        Ok(Box::pin(self.do_dial(url)))
+    }
+
+    fn upgrade_dialer(self, _connector: Self::Connector) -> Result<Self::TlsDialer> {
+        unimplemented!("TLS not supported for memory transport");
+    }
+}
+
+impl MemoryTransport {
+    /// Create a new memory transport for the node at `local` on the given
+    /// network. Nodes without a memory address dial from an anonymous host.
+    pub fn new(network: MemoryNetwork, local: Option<Url>) -> Self {
+        Self { network, local }
+    }
+
+    async fn do_listen(self, url: Url) -> Result<MemoryListener> {
+        let key = listener_key(&url);
+        let (tx, rx) = unbounded();
+
+        self.network.with(|n| {
+            if n.listeners.contains_key(&key) {
+                return Err(io::Error::from(io::ErrorKind::AddrInUse))
+            }
+            n.listeners.insert(key.clone(), tx);
+            Ok(())
+        })?;
+
+        Ok(MemoryListener { network: self.network, key, rx })
+    }
+
+    async fn do_dial(self, url: Url) -> Result<MemoryStream> {
+        let remote = host(&url);
+
+        let (listener, local, port) = self.network.with(|n| {
+            let port = n.next_port;
+            n.next_port = n.next_port.checked_add(1).unwrap_or(EPHEMERAL_PORT_START);
+            let local = match &self.local {
+                Some(l) => host(l),
+                None => format!("anonymous{}", port),
+            };
+
+            if n.is_partitioned(&local, &remote) {
+                return Err(io::Error::from(io::ErrorKind::ConnectionRefused))
+            }
+
+            match n.listeners.get(&listener_key(&url)) {
+                Some(listener) => Ok((listener.clone(), local, port)),
+                None => Err(io::Error::from(io::ErrorKind::ConnectionRefused)),
+            }
+        })?;
+
+        let (ours, theirs) = MemoryStream::pair(&self.network, &local, &remote);
+        let dialer_url = Url::parse(&format!("memory://{}:{}", local, port))?;
+        if listener.send((theirs, dialer_url)).await.is_err() {
+            return Err(io::Error::from(io::ErrorKind::ConnectionRefused).into())
+        }
+
+        Ok(ours)
+    }
+}
@@ -28,7 +28,7 @@ use async_std::{
     net::{TcpListener, TcpStream},
     sync::Arc,
 };
-use fast_socks5::client::{Config, Socks5Stream};
+use fast_socks5::client::Socks5Stream;
 use futures::prelude::*;
 use futures_rustls::{TlsAcceptor, TlsStream};
 use socket2::{Domain, Socket, TcpKeepalive, Type};
@@ -36,7 +36,7 @@ use url::Url;
 
 use crate::{Error, Result};
 
-use super::{TlsUpgrade, Transport, TransportStream};
+use super::{socks5::socks5_dial, TlsUpgrade, Transport, TransportStream};
 
 /// Implements communication through the tor proxy service.
 ///
@@ -232,25 +232,7 @@ impl TorTransport {
     }
 
     pub async fn do_dial(self, url: Url) -> Result<Socks5Stream<TcpStream>> {
-        let socks_url_str = self.socks_url.socket_addrs(|| None)?[0].to_string();
-        let host = url.host().unwrap().to_string();
-        let port = url.port().unwrap_or(80);
-        let config = Config::default();
-        let stream = if !self.socks_url.username().is_empty() && self.socks_url.password().is_some()
-        {
-            Socks5Stream::connect_with_password(
-                socks_url_str,
-                host,
-                port,
-                self.socks_url.username().to_string(),
-                self.socks_url.password().unwrap().to_string(),
-                config,
-            )
-            .await?
-        } else {
-            Socks5Stream::connect(socks_url_str, host, port, config).await?
-        };
-        Ok(stream)
+        socks5_dial(&self.socks_url, &url).await
     }
 
     fn create_socket(&self, socket_addr: SocketAddr) -> io::Result<Socket> {
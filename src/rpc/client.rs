@@ -28,7 +28,8 @@ use url::Url;
 use super::jsonrpc::{ErrorCode, JsonError, JsonRequest, JsonResult};
 use crate::{
     net::transport::{
-        TcpTransport, TorTransport, Transport, TransportName, TransportStream, UnixTransport,
+        MemoryNetwork, MemoryTransport, TcpTransport, TorTransport, Transport, TransportName,
+        TransportStream, UnixTransport,
     },
     system::SubscriberPtr,
     Error, Result,
@@ -219,6 +220,11 @@ impl RpcClient {
                 let stream = transport.dial(uri.clone(), None);
                 reqrep!(stream, transport, None);
             }
+            TransportName::Memory => {
+                let transport = MemoryTransport::new(MemoryNetwork::global(), None);
+                let stream = transport.clone().dial(uri.clone(), None);
+                reqrep!(stream, transport, None);
+            }
             _ => unimplemented!(),
         }
 
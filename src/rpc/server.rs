@@ -26,8 +26,8 @@ use url::Url;
 use super::jsonrpc::{JsonRequest, JsonResult};
 use crate::{
     net::transport::{
-        TcpTransport, TorTransport, Transport, TransportListener, TransportName, TransportStream,
-        UnixTransport,
+        MemoryNetwork, MemoryTransport, TcpTransport, TorTransport, Transport, TransportListener,
+        TransportName, TransportStream, UnixTransport,
     },
     Error, Result,
 };
@@ -195,6 +195,11 @@ pub async fn listen_and_serve(
             let listener = transport.listen_on(accept_url.clone());
             accept!(listener, transport, None);
         }
+        TransportName::Memory => {
+            let transport = MemoryTransport::new(MemoryNetwork::global(), Some(accept_url.clone()));
+            let listener = transport.clone().listen_on(accept_url.clone());
+            accept!(listener, transport, None);
+        }
         _ => unimplemented!(),
     }
 
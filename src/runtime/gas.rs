@@ -0,0 +1,239 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use log::error;
+use wasmer::{wasmparser::Operator, FunctionEnvMut};
+use wasmer_middlewares::metering::{get_remaining_points, set_remaining_points, MeteringPoints};
+
+use super::vm_runtime::Env;
+
+/// Gas limit of a runtime that isn't given one explicitly
+pub const DEFAULT_GAS_LIMIT: u64 = 200_000_000;
+
+/// Base cost of calling any host function
+pub const GAS_HOST_CALL: u64 = 100;
+/// Cost per byte copied between the host and the wasm memory
+pub const GAS_PER_BYTE: u64 = 1;
+/// Cost per page (64KiB) a contract grows its memory by, charged once the
+/// contract function returns
+pub const GAS_MEMORY_PER_PAGE: u64 = 65_536;
+/// Cost of initializing or looking up a database
+pub const GAS_DB_OPEN: u64 = 2_000;
+/// Cost of reading a key from a database
+pub const GAS_DB_READ: u64 = 1_000;
+/// Cost per byte read from a database
+pub const GAS_DB_READ_PER_BYTE: u64 = 2;
+/// Cost of writing or removing a key in a database
+pub const GAS_DB_WRITE: u64 = 2_000;
+/// Cost per byte written to a database
+pub const GAS_DB_WRITE_PER_BYTE: u64 = 10;
+/// Cost of adding leaves to a Merkle tree
+pub const GAS_MERKLE_ADD: u64 = 10_000;
+/// Cost per byte of leaves added to a Merkle tree, covering the hashing
+pub const GAS_MERKLE_PER_BYTE: u64 = 100;
+/// Cost per byte of wasm and zkas bincode stored by a contract deployment
+pub const GAS_DEPLOY_PER_BYTE: u64 = 500;
+/// Cost per row of the circuits brought by a contract deployment,
+/// covering the creation of their verifying keys
+pub const GAS_DEPLOY_PER_CIRCUIT_ROW: u64 = 10_000;
+/// Cost of calling another contract, on top of what the callee spends
+pub const GAS_CALL_CONTRACT: u64 = 20_000;
+/// Cost per byte of wasm compiled when calling another contract
+pub const GAS_CALL_PER_WASM_BYTE: u64 = 5;
+
+/// Returns the cost of executing the given wasm operator.
+/// https://docs.rs/wasmparser/latest/wasmparser/enum.Operator.html
+pub fn operator_cost(operator: &Operator) -> u64 {
+    match operator {
+        // Structural instructions only mark where blocks begin and end
+        Operator::Nop { .. } |
+        Operator::Unreachable { .. } |
+        Operator::Block { .. } |
+        Operator::Loop { .. } |
+        Operator::Else { .. } |
+        Operator::End { .. } => 0,
+
+        // Branches
+        Operator::If { .. } |
+        Operator::Br { .. } |
+        Operator::BrIf { .. } |
+        Operator::Return { .. } |
+        Operator::Select { .. } |
+        Operator::TypedSelect { .. } |
+        Operator::Drop { .. } => 1,
+        Operator::BrTable { .. } => 5,
+
+        // Calls
+        Operator::Call { .. } => 10,
+        Operator::CallIndirect { .. } => 20,
+
+        // Locals, globals and constants
+        Operator::LocalGet { .. } |
+        Operator::LocalSet { .. } |
+        Operator::LocalTee { .. } |
+        Operator::I32Const { .. } |
+        Operator::I64Const { .. } => 1,
+        Operator::GlobalGet { .. } | Operator::GlobalSet { .. } => 2,
+
+        // Memory access
+        Operator::I32Load { .. } |
+        Operator::I64Load { .. } |
+        Operator::I32Load8S { .. } |
+        Operator::I32Load8U { .. } |
+        Operator::I32Load16S { .. } |
+        Operator::I32Load16U { .. } |
+        Operator::I64Load8S { .. } |
+        Operator::I64Load8U { .. } |
+        Operator::I64Load16S { .. } |
+        Operator::I64Load16U { .. } |
+        Operator::I64Load32S { .. } |
+        Operator::I64Load32U { .. } |
+        Operator::I32Store { .. } |
+        Operator::I64Store { .. } |
+        Operator::I32Store8 { .. } |
+        Operator::I32Store16 { .. } |
+        Operator::I64Store8 { .. } |
+        Operator::I64Store16 { .. } |
+        Operator::I64Store32 { .. } => 3,
+        Operator::MemorySize { .. } => 3,
+        // The pages it adds are charged separately, see `GAS_MEMORY_PER_PAGE`.
+        // Bulk memory operations, whose size isn't known upfront, are rejected
+        // by the runtime, so they don't show up here.
+        Operator::MemoryGrow { .. } => 10,
+
+        // Integer comparisons and cheap arithmetic
+        Operator::I32Eqz { .. } |
+        Operator::I32Eq { .. } |
+        Operator::I32Ne { .. } |
+        Operator::I32LtS { .. } |
+        Operator::I32LtU { .. } |
+        Operator::I32GtS { .. } |
+        Operator::I32GtU { .. } |
+        Operator::I32LeS { .. } |
+        Operator::I32LeU { .. } |
+        Operator::I32GeS { .. } |
+        Operator::I32GeU { .. } |
+        Operator::I64Eqz { .. } |
+        Operator::I64Eq { .. } |
+        Operator::I64Ne { .. } |
+        Operator::I64LtS { .. } |
+        Operator::I64LtU { .. } |
+        Operator::I64GtS { .. } |
+        Operator::I64GtU { .. } |
+        Operator::I64LeS { .. } |
+        Operator::I64LeU { .. } |
+        Operator::I64GeS { .. } |
+        Operator::I64GeU { .. } |
+        Operator::I32Add { .. } |
+        Operator::I32Sub { .. } |
+        Operator::I32And { .. } |
+        Operator::I32Or { .. } |
+        Operator::I32Xor { .. } |
+        Operator::I32Shl { .. } |
+        Operator::I32ShrS { .. } |
+        Operator::I32ShrU { .. } |
+        Operator::I32Rotl { .. } |
+        Operator::I32Rotr { .. } |
+        Operator::I64Add { .. } |
+        Operator::I64Sub { .. } |
+        Operator::I64And { .. } |
+        Operator::I64Or { .. } |
+        Operator::I64Xor { .. } |
+        Operator::I64Shl { .. } |
+        Operator::I64ShrS { .. } |
+        Operator::I64ShrU { .. } |
+        Operator::I64Rotl { .. } |
+        Operator::I64Rotr { .. } |
+        Operator::I32WrapI64 { .. } |
+        Operator::I64ExtendI32S { .. } |
+        Operator::I64ExtendI32U { .. } |
+        Operator::I32Extend8S { .. } |
+        Operator::I32Extend16S { .. } |
+        Operator::I64Extend8S { .. } |
+        Operator::I64Extend16S { .. } |
+        Operator::I64Extend32S { .. } => 1,
+        Operator::I32Clz { .. } |
+        Operator::I32Ctz { .. } |
+        Operator::I32Popcnt { .. } |
+        Operator::I64Clz { .. } |
+        Operator::I64Ctz { .. } |
+        Operator::I64Popcnt { .. } => 2,
+
+        // Multiplication and division
+        Operator::I32Mul { .. } | Operator::I64Mul { .. } => 3,
+        Operator::I32DivS { .. } |
+        Operator::I32DivU { .. } |
+        Operator::I32RemS { .. } |
+        Operator::I32RemU { .. } |
+        Operator::I64DivS { .. } |
+        Operator::I64DivU { .. } |
+        Operator::I64RemS { .. } |
+        Operator::I64RemU { .. } => 10,
+
+        // Everything else, like floating point, SIMD or table operations,
+        // shouldn't be used by contracts, so it's priced accordingly.
+        _ => 50,
+    }
+}
+
+/// Charge `cost` gas to the contract running in the given context.
+/// Returns `false` if there isn't enough gas left, in which case all of the
+/// remaining gas is burned and the runtime is marked as out of gas, so the
+/// host function should bail out.
+pub(crate) fn charge(ctx: &mut FunctionEnvMut<Env>, cost: u64) -> bool {
+    let (env, mut store) = ctx.data_and_store_mut();
+    let instance = env.instance.as_ref().expect("instance should be set");
+
+    match get_remaining_points(&mut store, instance) {
+        MeteringPoints::Remaining(rem) if rem >= cost => {
+            set_remaining_points(&mut store, instance, rem - cost);
+            true
+        }
+        _ => {
+            error!(target: "runtime::gas", "Contract ran out of gas");
+            set_remaining_points(&mut store, instance, 0);
+            env.out_of_gas = true;
+            false
+        }
+    }
+}
+
+/// Returns the gas left to the instance that called the host function.
+pub(crate) fn remaining(ctx: &mut FunctionEnvMut<Env>) -> u64 {
+    let (env, mut store) = ctx.data_and_store_mut();
+    let instance = env.instance.as_ref().expect("instance should be set");
+
+    match get_remaining_points(&mut store, instance) {
+        MeteringPoints::Remaining(rem) => rem,
+        MeteringPoints::Exhausted => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_operator_cost() {
+        // Branching back in a loop isn't free, so neither are loops
+        assert!(operator_cost(&Operator::Br { relative_depth: 0 }) > 0);
+        assert!(operator_cost(&Operator::I32Add) > 0);
+        assert!(operator_cost(&Operator::I64DivU) > operator_cost(&Operator::I64Add));
+        assert!(operator_cost(&Operator::F64Sqrt) > operator_cost(&Operator::I64Mul));
+    }
+}
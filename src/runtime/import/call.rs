@@ -0,0 +1,179 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::io::Cursor;
+
+use darkfi_sdk::{
+    crypto::ContractId,
+    error::{CALLER_ACCESS_DENIED, CALL_CONTRACT_FAILED, CALL_DEPTH_EXCEEDED, INTERNAL_ERROR},
+    util::NO_OBJECT,
+};
+use darkfi_serial::{serialize, Decodable};
+use log::{debug, error};
+use wasmer::{FunctionEnvMut, WasmPtr};
+
+use crate::{
+    runtime::{
+        gas::{self, GAS_CALL_CONTRACT, GAS_CALL_PER_WASM_BYTE, GAS_HOST_CALL, GAS_PER_BYTE},
+        vm_runtime::{ContractSection, Env, Runtime, MAX_CALL_DEPTH, NESTED_ENTRYPOINT},
+    },
+    Error,
+};
+
+/// Only exec() can call this. Executes the `__nested_entrypoint` function of
+/// another contract with the given instruction data, and passes the state update
+/// it returned back to the caller as an object. The update is also recorded, so
+/// it gets applied right after the caller's one, and a contract can't make up
+/// updates for other contracts.
+///
+/// The callee's metadata isn't run, so none of its ZK proofs and signatures are
+/// verified. Contracts have to opt in to being called by exporting the nested
+/// entrypoint, and calls to the ones that don't are rejected. A contract's
+/// `__entrypoint` can never be reached this way.
+pub(crate) fn call_contract(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i64 {
+    if !gas::charge(&mut ctx, GAS_HOST_CALL + GAS_CALL_CONTRACT + len as u64 * GAS_PER_BYTE) {
+        return CALL_CONTRACT_FAILED
+    }
+
+    let env = ctx.data();
+    if !matches!(env.contract_section, ContractSection::Exec) {
+        error!(target: "runtime::call::call_contract()", "call_contract called outside of exec");
+        return CALLER_ACCESS_DENIED
+    }
+
+    if env.call_depth >= MAX_CALL_DEPTH {
+        error!(target: "runtime::call::call_contract()", "Maximum call depth exceeded");
+        return CALL_DEPTH_EXCEEDED
+    }
+
+    let memory_view = env.memory_view(&ctx);
+
+    let Ok(mem_slice) = ptr.slice(&memory_view, len) else {
+        error!(target: "runtime::call::call_contract()", "Failed to make slice from ptr");
+        return CALL_CONTRACT_FAILED
+    };
+
+    let mut buf = vec![0_u8; len as usize];
+    if let Err(e) = mem_slice.read_slice(&mut buf) {
+        error!(target: "runtime::call::call_contract()", "Failed to read from memory slice: {}", e);
+        return CALL_CONTRACT_FAILED
+    };
+
+    let mut buf_reader = Cursor::new(buf);
+
+    let contract_id: ContractId = match Decodable::decode(&mut buf_reader) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(target: "runtime::call::call_contract()", "Failed to decode ContractId: {}", e);
+            return CALL_CONTRACT_FAILED
+        }
+    };
+
+    let data: Vec<u8> = match Decodable::decode(&mut buf_reader) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(target: "runtime::call::call_contract()", "Failed to decode call data: {}", e);
+            return CALL_CONTRACT_FAILED
+        }
+    };
+
+    let wasm = match env.blockchain.wasm_bincode.get_staged(&env.db_overlay, contract_id) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(target: "runtime::call::call_contract()", "Could not find wasm bincode for contract {}: {}", contract_id, e);
+            return CALL_CONTRACT_FAILED
+        }
+    };
+
+    let blockchain = env.blockchain.clone();
+    let db_overlay = env.db_overlay.clone();
+    let caller = env.contract_id;
+    let call_depth = env.call_depth + 1;
+    let tx_context = env.tx_context;
+
+    // Compiling the callee isn't free either
+    if !gas::charge(&mut ctx, wasm.len() as u64 * GAS_CALL_PER_WASM_BYTE) {
+        return CALL_CONTRACT_FAILED
+    }
+
+    debug!(target: "runtime::call::call_contract()", "Calling contract {} from {}", contract_id, caller);
+    let mut runtime = match Runtime::new(&wasm, blockchain, contract_id) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(target: "runtime::call::call_contract()", "Failed to instantiate contract {}: {}", contract_id, e);
+            return CALL_CONTRACT_FAILED
+        }
+    };
+
+    if !runtime.accepts_nested_calls() {
+        error!(target: "runtime::call::call_contract()", "Contract {} doesn't export {}", contract_id, NESTED_ENTRYPOINT);
+        return CALLER_ACCESS_DENIED
+    }
+
+    // The callee shares the gas left to the caller, and sees the same
+    // staged state updates and transaction context.
+    runtime.set_gas_limit(gas::remaining(&mut ctx));
+    runtime.set_db_overlay(db_overlay);
+    runtime.set_caller(caller, call_depth);
+    runtime.set_tx_context(tx_context);
+
+    let result = runtime.exec_nested(&data);
+    if !gas::charge(&mut ctx, runtime.gas_used()) {
+        return CALL_CONTRACT_FAILED
+    }
+
+    let update = match result {
+        Ok(v) => v,
+        Err(Error::OutOfGas(_)) => {
+            // Burn whatever is left, so the caller runs out of gas as well.
+            gas::charge(&mut ctx, u64::MAX);
+            return CALL_CONTRACT_FAILED
+        }
+        Err(e) => {
+            error!(target: "runtime::call::call_contract()", "Call to contract {} failed: {}", contract_id, e);
+            return CALL_CONTRACT_FAILED
+        }
+    };
+
+    // The callee's update goes first, followed by the ones of the
+    // contracts it called itself.
+    let env = ctx.data();
+    let mut nested_updates = env.nested_updates.borrow_mut();
+    nested_updates.push((contract_id, update.clone()));
+    nested_updates.extend(runtime.nested_updates());
+
+    let mut objects = env.objects.borrow_mut();
+    objects.push(update);
+    (objects.len() - 1) as i64
+}
+
+/// Everyone can call this. Passes the serialized ID of the contract that called
+/// the executing one with `call_contract` to the VM as an object. Returns
+/// [`NO_OBJECT`] if the contract was called directly by the transaction.
+pub(crate) fn get_caller(mut ctx: FunctionEnvMut<Env>) -> i64 {
+    if !gas::charge(&mut ctx, GAS_HOST_CALL) {
+        return INTERNAL_ERROR
+    }
+
+    let env = ctx.data();
+    let Some(caller) = env.caller else { return NO_OBJECT };
+
+    let mut objects = env.objects.borrow_mut();
+    objects.push(serialize(&caller));
+    (objects.len() - 1) as i64
+}
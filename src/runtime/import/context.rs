@@ -0,0 +1,77 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use darkfi_sdk::error::INTERNAL_ERROR;
+use wasmer::FunctionEnvMut;
+
+use crate::runtime::{
+    gas::{self, GAS_HOST_CALL, GAS_PER_BYTE},
+    vm_runtime::Env,
+};
+
+/// Everyone can call this. Returns the slot the transaction is executed in.
+pub(crate) fn get_current_slot(mut ctx: FunctionEnvMut<Env>) -> i64 {
+    if !gas::charge(&mut ctx, GAS_HOST_CALL) {
+        return INTERNAL_ERROR
+    }
+
+    ctx.data().tx_context.slot as i64
+}
+
+/// Everyone can call this. Returns the epoch the transaction is executed in.
+pub(crate) fn get_current_epoch(mut ctx: FunctionEnvMut<Env>) -> i64 {
+    if !gas::charge(&mut ctx, GAS_HOST_CALL) {
+        return INTERNAL_ERROR
+    }
+
+    ctx.data().tx_context.epoch as i64
+}
+
+/// Everyone can call this. Returns the starting timestamp of the slot the
+/// transaction is executed in, in seconds since the Unix epoch.
+pub(crate) fn get_timestamp(mut ctx: FunctionEnvMut<Env>) -> i64 {
+    if !gas::charge(&mut ctx, GAS_HOST_CALL) {
+        return INTERNAL_ERROR
+    }
+
+    ctx.data().tx_context.timestamp
+}
+
+/// Everyone can call this. Passes the blake3 hash of the executing transaction
+/// to the VM as an object. The hash leaves out the transaction's signatures,
+/// which could otherwise be swapped for other valid ones to change it.
+pub(crate) fn get_tx_hash(mut ctx: FunctionEnvMut<Env>) -> i64 {
+    if !gas::charge(&mut ctx, GAS_HOST_CALL + 32 * GAS_PER_BYTE) {
+        return INTERNAL_ERROR
+    }
+
+    let env = ctx.data();
+    let mut objects = env.objects.borrow_mut();
+    objects.push(env.tx_context.tx_hash.to_vec());
+    (objects.len() - 1) as i64
+}
+
+/// Everyone can call this. Returns the index of the executing call in the
+/// transaction.
+pub(crate) fn get_call_index(mut ctx: FunctionEnvMut<Env>) -> i64 {
+    if !gas::charge(&mut ctx, GAS_HOST_CALL) {
+        return INTERNAL_ERROR
+    }
+
+    ctx.data().tx_context.call_idx as i64
+}
@@ -16,21 +16,34 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::io::Cursor;
+use std::{
+    cmp::Ordering,
+    collections::VecDeque,
+    io::Cursor,
+    ops::Bound::{self, Excluded, Included, Unbounded},
+};
 
 use darkfi_sdk::{
     crypto::ContractId,
     db::{
         CALLER_ACCESS_DENIED, DB_CONTAINS_KEY_FAILED, DB_DEL_FAILED, DB_GET_FAILED, DB_INIT_FAILED,
-        DB_LOOKUP_FAILED, DB_SET_FAILED, DB_SUCCESS,
+        DB_ITER_NEXT_FAILED, DB_ITER_START_FAILED, DB_LOOKUP_FAILED, DB_SET_FAILED, DB_SUCCESS,
     },
+    util::NO_OBJECT,
 };
-use darkfi_serial::Decodable;
+use darkfi_serial::{serialize, Decodable};
 use log::{debug, error};
 use wasmer::{FunctionEnvMut, WasmPtr};
 
 use crate::{
-    runtime::vm_runtime::{ContractSection, Env},
+    blockchain::{DbBatch, DbOverlay},
+    runtime::{
+        gas::{
+            self, GAS_DB_OPEN, GAS_DB_READ, GAS_DB_READ_PER_BYTE, GAS_DB_WRITE,
+            GAS_DB_WRITE_PER_BYTE, GAS_HOST_CALL, GAS_PER_BYTE,
+        },
+        vm_runtime::{ContractSection, Env},
+    },
     Result,
 };
 
@@ -38,14 +51,19 @@ use crate::{
 pub struct DbHandle {
     pub contract_id: ContractId,
     tree: sled::Tree,
+    overlay: DbOverlay,
 }
 
 impl DbHandle {
-    pub fn new(contract_id: ContractId, tree: sled::Tree) -> Self {
-        Self { contract_id, tree }
+    pub fn new(contract_id: ContractId, tree: sled::Tree, overlay: DbOverlay) -> Self {
+        Self { contract_id, tree, overlay }
     }
 
     pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        if let Some(v) = self.overlay.get(&self.tree, key) {
+            return Ok(v)
+        }
+
         if let Some(v) = self.tree.get(key)? {
             return Ok(Some(v.to_vec()))
         };
@@ -54,21 +72,96 @@ impl DbHandle {
     }
 
     pub fn contains_key(&self, key: &[u8]) -> Result<bool> {
+        if let Some(v) = self.overlay.get(&self.tree, key) {
+            return Ok(v.is_some())
+        }
+
         Ok(self.tree.contains_key(key)?)
     }
 
-    pub fn apply_batch(&self, batch: sled::Batch) -> Result<()> {
-        Ok(self.tree.apply_batch(batch)?)
+    /// Stage the given writes in the overlay this handle reads through.
+    pub fn stage(&self, batch: DbBatch) {
+        self.overlay.stage(&self.tree, batch)
     }
 
-    pub fn flush(&self) -> Result<()> {
-        let _ = self.tree.flush()?;
-        Ok(())
+    /// Create an iterator over the keys within the given bounds, in ascending
+    /// or descending order. Along with the ones in the overlay, it sees the
+    /// given pending writes, as they are when it gets created.
+    pub fn iter(
+        &self,
+        pending: &DbBatch,
+        bounds: (Bound<Vec<u8>>, Bound<Vec<u8>>),
+        reverse: bool,
+    ) -> DbIter {
+        let mut staged = self.overlay.staged_range(&self.tree, bounds.clone());
+        staged.extend(pending.range(bounds.clone()));
+
+        let staged: VecDeque<_> =
+            if reverse { staged.into_iter().rev().collect() } else { staged.into_iter().collect() };
+
+        DbIter { tree: self.tree.range(bounds), tree_next: None, staged, reverse }
+    }
+}
+
+/// Ordered iterator over a sled tree, with the writes that weren't written
+/// to the database yet merged in.
+pub struct DbIter {
+    /// Iterator over the tree itself
+    tree: sled::Iter,
+    /// Next item of `tree`, once it was read
+    tree_next: Option<(Vec<u8>, Vec<u8>)>,
+    /// Writes made on top of the tree, in iteration order
+    staged: VecDeque<(Vec<u8>, Option<Vec<u8>>)>,
+    /// Iterate in descending key order
+    reverse: bool,
+}
+
+impl Iterator for DbIter {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.tree_next.is_none() {
+                let item = if self.reverse { self.tree.next_back() } else { self.tree.next() };
+                match item {
+                    Some(Ok((key, value))) => self.tree_next = Some((key.to_vec(), value.to_vec())),
+                    Some(Err(e)) => return Some(Err(e.into())),
+                    None => {}
+                }
+            }
+
+            // Which of the tree item and the staged write comes first
+            let order = match (&self.tree_next, self.staged.front()) {
+                (None, None) => return None,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some((tree_key, _)), Some((staged_key, _))) if self.reverse => {
+                    staged_key.cmp(tree_key)
+                }
+                (Some((tree_key, _)), Some((staged_key, _))) => tree_key.cmp(staged_key),
+            };
+
+            match order {
+                Ordering::Less => return self.tree_next.take().map(Ok),
+                // The staged write replaces the value in the tree
+                Ordering::Equal => self.tree_next = None,
+                Ordering::Greater => {}
+            }
+
+            // Removed keys are skipped
+            if let (key, Some(value)) = self.staged.pop_front().unwrap() {
+                return Some(Ok((key, value)))
+            }
+        }
     }
 }
 
 /// Only deploy() can call this. Creates a new database instance for this contract.
-pub(crate) fn db_init(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
+pub(crate) fn db_init(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
+    if !gas::charge(&mut ctx, GAS_HOST_CALL + GAS_DB_OPEN + len as u64 * GAS_PER_BYTE) {
+        return DB_INIT_FAILED
+    }
+
     let env = ctx.data();
     match env.contract_section {
         ContractSection::Deploy => {
@@ -118,7 +211,9 @@ pub(crate) fn db_init(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i
                 return CALLER_ACCESS_DENIED
             }
 
-            let tree_handle = match contracts.init(db, &cid, &db_name) {
+            // The state only gets marked as initialized once the overlay
+            // holding the deployment is committed.
+            let tree_handle = match contracts.stage(db, &env.db_overlay, &cid, &db_name) {
                 Ok(v) => v,
                 Err(e) => {
                     error!(target: "runtime::db::db_init()", "Failed to init db: {}", e);
@@ -128,14 +223,14 @@ pub(crate) fn db_init(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i
 
             // TODO: Make sure we don't duplicate the DbHandle in the vec.
             //       It should behave like an ordered set.
-            // In `lookup()` we also create a `sled::Batch`. This is done for
+            // In `lookup()` we also create a `DbBatch`. This is done for
             // some simplicity reasons, and also for possible future changes.
             // However, we make sure that unauthorized writes are not available
             // from other functions that interface with the databases.
             let mut db_handles = env.db_handles.borrow_mut();
             let mut db_batches = env.db_batches.borrow_mut();
-            db_handles.push(DbHandle::new(cid, tree_handle));
-            db_batches.push(sled::Batch::default());
+            db_handles.push(DbHandle::new(cid, tree_handle, env.db_overlay.clone()));
+            db_batches.push(DbBatch::default());
             (db_handles.len() - 1) as i32
         }
         _ => {
@@ -146,7 +241,11 @@ pub(crate) fn db_init(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i
 }
 
 /// Everyone can call this. Lookups up a database handle from its name.
-pub(crate) fn db_lookup(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
+pub(crate) fn db_lookup(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
+    if !gas::charge(&mut ctx, GAS_HOST_CALL + GAS_DB_OPEN + len as u64 * GAS_PER_BYTE) {
+        return DB_LOOKUP_FAILED
+    }
+
     let env = ctx.data();
     match env.contract_section {
         ContractSection::Deploy |
@@ -193,7 +292,7 @@ pub(crate) fn db_lookup(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) ->
                 return DB_LOOKUP_FAILED
             }*/
 
-            let tree_handle = match contracts.lookup(db, &cid, &db_name) {
+            let tree_handle = match contracts.lookup_staged(db, &env.db_overlay, &cid, &db_name) {
                 Ok(v) => v,
                 Err(e) => {
                     error!(target: "runtime::db::db_lookup()", "Failed to lookup db: {}", e);
@@ -203,14 +302,14 @@ pub(crate) fn db_lookup(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) ->
 
             // TODO: Make sure we don't duplicate the DbHandle in the vec.
             //       It should behave like an ordered set.
-            // In `lookup()` we also create a `sled::Batch`. This is done for
+            // In `lookup()` we also create a `DbBatch`. This is done for
             // some simplicity reasons, and also for possible future changes.
             // However, we make sure that unauthorized writes are not available
             // from other functions that interface with the databases.
             let mut db_handles = env.db_handles.borrow_mut();
             let mut db_batches = env.db_batches.borrow_mut();
-            db_handles.push(DbHandle::new(cid, tree_handle));
-            db_batches.push(sled::Batch::default());
+            db_handles.push(DbHandle::new(cid, tree_handle, env.db_overlay.clone()));
+            db_batches.push(DbBatch::default());
             (db_handles.len() - 1) as i32
         }
         _ => {
@@ -221,7 +320,11 @@ pub(crate) fn db_lookup(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) ->
 }
 
 /// Only update() can call this. Set a value within the transaction.
-pub(crate) fn db_set(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
+pub(crate) fn db_set(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
+    if !gas::charge(&mut ctx, GAS_HOST_CALL + GAS_DB_WRITE + len as u64 * GAS_DB_WRITE_PER_BYTE) {
+        return DB_SET_FAILED
+    }
+
     let env = ctx.data();
     match env.contract_section {
         ContractSection::Deploy | ContractSection::Update => {
@@ -290,7 +393,7 @@ pub(crate) fn db_set(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i3
                 return CALLER_ACCESS_DENIED
             }
 
-            db_batch.insert(key, value);
+            db_batch.insert(&key, &value);
 
             DB_SUCCESS
         }
@@ -299,7 +402,11 @@ pub(crate) fn db_set(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i3
 }
 
 /// Only update() can call this. Remove a key from the database.
-pub(crate) fn db_del(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
+pub(crate) fn db_del(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
+    if !gas::charge(&mut ctx, GAS_HOST_CALL + GAS_DB_WRITE + len as u64 * GAS_DB_WRITE_PER_BYTE) {
+        return DB_DEL_FAILED
+    }
+
     let env = ctx.data();
     match env.contract_section {
         ContractSection::Deploy | ContractSection::Update => {
@@ -360,7 +467,7 @@ pub(crate) fn db_del(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i3
                 return CALLER_ACCESS_DENIED
             }
 
-            db_batch.remove(key);
+            db_batch.remove(&key);
 
             DB_SUCCESS
         }
@@ -369,7 +476,11 @@ pub(crate) fn db_del(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i3
 }
 
 /// Everyone can call this. Will read a key from the key-value store.
-pub(crate) fn db_get(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i64 {
+pub(crate) fn db_get(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i64 {
+    if !gas::charge(&mut ctx, GAS_HOST_CALL + GAS_DB_READ + len as u64 * GAS_PER_BYTE) {
+        return DB_GET_FAILED.into()
+    }
+
     let env = ctx.data();
     match env.contract_section {
         ContractSection::Deploy | ContractSection::Exec | ContractSection::Metadata => {
@@ -433,10 +544,17 @@ pub(crate) fn db_get(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i6
 
             let Some(return_data) = ret else {
                 debug!(target: "runtime::db::db_get()", "returned empty vec");
-                return -127
+                return NO_OBJECT
             };
 
+            // Charge for the bytes read from the database
+            drop(db_handles);
+            if !gas::charge(&mut ctx, return_data.len() as u64 * GAS_DB_READ_PER_BYTE) {
+                return DB_GET_FAILED.into()
+            }
+
             // Copy Vec<u8> to the VM
+            let env = ctx.data();
             let mut objects = env.objects.borrow_mut();
             objects.push(return_data);
             (objects.len() - 1) as i64
@@ -446,7 +564,11 @@ pub(crate) fn db_get(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i6
 }
 
 /// Everyone can call this. Will check if a given db contains given key.
-pub(crate) fn db_contains_key(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
+pub(crate) fn db_contains_key(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
+    if !gas::charge(&mut ctx, GAS_HOST_CALL + GAS_DB_READ + len as u64 * GAS_PER_BYTE) {
+        return DB_CONTAINS_KEY_FAILED
+    }
+
     let env = ctx.data();
     match env.contract_section {
         ContractSection::Deploy |
@@ -514,3 +636,194 @@ pub(crate) fn db_contains_key(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u
         _ => CALLER_ACCESS_DENIED,
     }
 }
+
+/// Everyone can call this. Starts an ordered iteration over the keys of a
+/// database within the given bounds, and returns a handle to the iterator.
+/// The iteration sees the writes the contract made to the database so far.
+pub(crate) fn db_iter_start(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
+    if !gas::charge(&mut ctx, GAS_HOST_CALL + GAS_DB_OPEN + len as u64 * GAS_PER_BYTE) {
+        return DB_ITER_START_FAILED
+    }
+
+    let env = ctx.data();
+    match env.contract_section {
+        ContractSection::Deploy |
+        ContractSection::Exec |
+        ContractSection::Update |
+        ContractSection::Metadata => {
+            let memory_view = env.memory_view(&ctx);
+
+            let Ok(mem_slice) = ptr.slice(&memory_view, len) else {
+                error!(target: "runtime::db::db_iter_start()", "Failed to make slice from ptr");
+                return DB_ITER_START_FAILED
+            };
+
+            let mut buf = vec![0_u8; len as usize];
+            if let Err(e) = mem_slice.read_slice(&mut buf) {
+                error!(target: "runtime::db::db_iter_start()", "Failed to read from memory slice: {}", e);
+                return DB_ITER_START_FAILED
+            };
+
+            let mut buf_reader = Cursor::new(buf);
+
+            // FIXME: There's a type DbHandle=u32, but this should maybe be renamed
+            let db_handle: u32 = match Decodable::decode(&mut buf_reader) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!(target: "runtime::db::db_iter_start()", "Failed to decode DbHandle: {}", e);
+                    return DB_ITER_START_FAILED
+                }
+            };
+            let db_handle = db_handle as usize;
+
+            let start: Option<Vec<u8>> = match Decodable::decode(&mut buf_reader) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!(target: "runtime::db::db_iter_start()", "Failed to decode start key: {}", e);
+                    return DB_ITER_START_FAILED
+                }
+            };
+
+            let end: Option<Vec<u8>> = match Decodable::decode(&mut buf_reader) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!(target: "runtime::db::db_iter_start()", "Failed to decode end key: {}", e);
+                    return DB_ITER_START_FAILED
+                }
+            };
+
+            let reverse: bool = match Decodable::decode(&mut buf_reader) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!(target: "runtime::db::db_iter_start()", "Failed to decode order: {}", e);
+                    return DB_ITER_START_FAILED
+                }
+            };
+
+            // Disabled until cursor_remaining feature is available on master.
+            // Then enable #![feature(cursor_remaining)] in src/lib.rs
+            /*if !buf_reader.is_empty() {
+                error!(target: "runtime::db::db_iter_start()", "Trailing bytes in argument stream");
+                return DB_ITER_START_FAILED
+            }*/
+
+            if let (Some(start), Some(end)) = (&start, &end) {
+                if start > end {
+                    error!(target: "runtime::db::db_iter_start()", "Start key is past the end key");
+                    return DB_ITER_START_FAILED
+                }
+            }
+
+            let db_handles = env.db_handles.borrow();
+            let db_batches = env.db_batches.borrow();
+
+            if db_handles.len() <= db_handle || db_batches.len() <= db_handle {
+                error!(target: "runtime::db::db_iter_start()", "Requested DbHandle that is out of bounds");
+                return DB_ITER_START_FAILED
+            }
+
+            let bounds = (start.map_or(Unbounded, Included), end.map_or(Unbounded, Excluded));
+            let db_iter = db_handles[db_handle].iter(&db_batches[db_handle], bounds, reverse);
+
+            let mut db_iters = env.db_iters.borrow_mut();
+            db_iters.push(db_iter);
+            (db_iters.len() - 1) as i32
+        }
+        _ => CALLER_ACCESS_DENIED,
+    }
+}
+
+/// Everyone can call this. Passes the next key and value of an iteration
+/// to the VM as a serialized object. Returns [`NO_OBJECT`] when the iteration
+/// is over.
+pub(crate) fn db_iter_next(mut ctx: FunctionEnvMut<Env>, db_iter: u32) -> i64 {
+    if !gas::charge(&mut ctx, GAS_HOST_CALL + GAS_DB_READ) {
+        return DB_ITER_NEXT_FAILED.into()
+    }
+
+    let env = ctx.data();
+    let mut db_iters = env.db_iters.borrow_mut();
+
+    let Some(db_iter) = db_iters.get_mut(db_iter as usize) else {
+        error!(target: "runtime::db::db_iter_next()", "Requested DbIter that is out of bounds");
+        return DB_ITER_NEXT_FAILED.into()
+    };
+
+    let item = match db_iter.next() {
+        Some(Ok(v)) => v,
+        Some(Err(e)) => {
+            error!(target: "runtime::db::db_iter_next()", "Internal error iterating over tree: {}", e);
+            return DB_ITER_NEXT_FAILED.into()
+        }
+        None => {
+            debug!(target: "runtime::db::db_iter_next()", "iteration is over");
+            return NO_OBJECT
+        }
+    };
+
+    // Charge for the bytes read from the database
+    drop(db_iters);
+    let item = serialize(&item);
+    if !gas::charge(&mut ctx, item.len() as u64 * GAS_DB_READ_PER_BYTE) {
+        return DB_ITER_NEXT_FAILED.into()
+    }
+
+    // Copy the serialized item to the VM
+    let env = ctx.data();
+    let mut objects = env.objects.borrow_mut();
+    objects.push(item);
+    (objects.len() - 1) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use darkfi_sdk::pasta::pallas;
+
+    use super::*;
+
+    #[test]
+    fn test_db_iter() -> Result<()> {
+        let db = sled::Config::new().temporary(true).open()?;
+        let tree = db.open_tree("test")?;
+        tree.insert(b"a", b"1")?;
+        tree.insert(b"b", b"2")?;
+        tree.insert(b"c", b"3")?;
+        tree.insert(b"d", b"4")?;
+
+        // Staged in the overlay: "b" is updated and "e" added
+        let overlay = DbOverlay::default();
+        let mut batch = DbBatch::default();
+        batch.insert(b"b", b"20");
+        batch.insert(b"e", b"50");
+        overlay.stage(&tree, batch);
+
+        // Pending in the runtime: "c" is removed and "bb" added
+        let mut pending = DbBatch::default();
+        pending.remove(b"c");
+        pending.insert(b"bb", b"22");
+
+        let handle = DbHandle::new(ContractId::from(pallas::Base::from(1)), tree, overlay);
+        let collect = |bounds, reverse| -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+            handle.iter(&pending, bounds, reverse).collect()
+        };
+
+        let all: Vec<(Vec<u8>, Vec<u8>)> = vec![
+            (b"a".to_vec(), b"1".to_vec()),
+            (b"b".to_vec(), b"20".to_vec()),
+            (b"bb".to_vec(), b"22".to_vec()),
+            (b"d".to_vec(), b"4".to_vec()),
+            (b"e".to_vec(), b"50".to_vec()),
+        ];
+        assert_eq!(collect((Unbounded, Unbounded), false)?, all);
+
+        let mut all_rev = all.clone();
+        all_rev.reverse();
+        assert_eq!(collect((Unbounded, Unbounded), true)?, all_rev);
+
+        let range = (Included(b"b".to_vec()), Excluded(b"d".to_vec()));
+        assert_eq!(collect(range.clone(), false)?, all[1..3]);
+        assert_eq!(collect(range, true)?, all_rev[2..4]);
+
+        Ok(())
+    }
+}
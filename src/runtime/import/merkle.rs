@@ -26,11 +26,21 @@ use darkfi_serial::{serialize, Decodable, Encodable, WriteExt};
 use log::{debug, error};
 use wasmer::{FunctionEnvMut, WasmPtr};
 
-use crate::runtime::vm_runtime::{ContractSection, Env};
+use crate::runtime::{
+    gas::{
+        self, GAS_DB_READ, GAS_DB_READ_PER_BYTE, GAS_DB_WRITE, GAS_DB_WRITE_PER_BYTE,
+        GAS_HOST_CALL, GAS_MERKLE_ADD, GAS_MERKLE_PER_BYTE,
+    },
+    vm_runtime::{ContractSection, Env},
+};
 
 type MerkleTree = BridgeTree<MerkleNode, { MERKLE_DEPTH }>;
 
-pub(crate) fn merkle_add(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
+pub(crate) fn merkle_add(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i32 {
+    if !gas::charge(&mut ctx, GAS_HOST_CALL + GAS_MERKLE_ADD + len as u64 * GAS_MERKLE_PER_BYTE) {
+        return -2
+    }
+
     let env = ctx.data();
     match env.contract_section {
         ContractSection::Update => {
@@ -176,8 +186,22 @@ pub(crate) fn merkle_add(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -
                 error!(target: "runtime::merkle", "Couldn't reserialize modified tree");
                 return -2
             }
+
+            // The whole tree gets read and written back, so charge for both
+            drop(db_batches);
+            drop(db_handles);
+            let tree_cost = GAS_DB_READ +
+                return_data.len() as u64 * GAS_DB_READ_PER_BYTE +
+                GAS_DB_WRITE +
+                tree_data.len() as u64 * GAS_DB_WRITE_PER_BYTE;
+            if !gas::charge(&mut ctx, tree_cost) {
+                return -2
+            }
+
+            let env = ctx.data();
+            let mut db_batches = env.db_batches.borrow_mut();
             let db_info_batch = &mut db_batches[info_handle_idx];
-            db_info_batch.insert(key, tree_data);
+            db_info_batch.insert(&key, &tree_data);
 
             // Here we add the Merkle root to our set of roots
             // TODO: We should probably make sure that this root isn't in the set
@@ -191,7 +215,7 @@ pub(crate) fn merkle_add(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -
                 // FIXME: This assert can be used to DoS nodes from contracts
                 assert_eq!(root_value.len(), 32);
                 //db_roots_batch.insert(root_index, root_value);
-                db_roots_batch.insert(root_value, &[]);
+                db_roots_batch.insert(&root_value, &[]);
             }
 
             0
@@ -16,6 +16,12 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+/// Host functions for calling other contracts
+pub(crate) mod call;
+
+/// Host functions exposing the transaction's chain context
+pub(crate) mod context;
+
 /// Host functions for interacting with db backend
 pub(crate) mod db;
 
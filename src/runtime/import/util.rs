@@ -19,11 +19,18 @@
 use log::error;
 use wasmer::{FunctionEnvMut, WasmPtr};
 
-use crate::runtime::vm_runtime::{ContractSection, Env};
+use crate::runtime::{
+    gas::{self, GAS_HOST_CALL, GAS_PER_BYTE},
+    vm_runtime::{ContractSection, Env},
+};
 
 /// Host function for logging strings.
 /// This is injected into the runtime with wasmer's `imports!` macro.
-pub(crate) fn drk_log(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) {
+pub(crate) fn drk_log(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) {
+    if !gas::charge(&mut ctx, GAS_HOST_CALL + len as u64 * GAS_PER_BYTE) {
+        return
+    }
+
     let env = ctx.data();
     let memory_view = env.memory_view(&ctx);
 
@@ -39,7 +46,11 @@ pub(crate) fn drk_log(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) {
     }
 }
 
-pub(crate) fn set_return_data(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i64 {
+pub(crate) fn set_return_data(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i64 {
+    if !gas::charge(&mut ctx, GAS_HOST_CALL + len as u64 * GAS_PER_BYTE) {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
+
     let env = ctx.data();
     match env.contract_section {
         ContractSection::Exec | ContractSection::Metadata => {
@@ -64,7 +75,11 @@ pub(crate) fn set_return_data(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u
     }
 }
 
-pub(crate) fn put_object_bytes(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i64 {
+pub(crate) fn put_object_bytes(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i64 {
+    if !gas::charge(&mut ctx, GAS_HOST_CALL + len as u64 * GAS_PER_BYTE) {
+        return -2
+    }
+
     let env = ctx.data();
     let memory_view = env.memory_view(&ctx);
 
@@ -99,7 +114,13 @@ pub(crate) fn put_object_bytes(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len:
     obj_idx as i64
 }
 
-pub(crate) fn get_object_bytes(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, idx: u32) -> i64 {
+pub(crate) fn get_object_bytes(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, idx: u32) -> i64 {
+    // Charge for the bytes we're about to copy into the VM
+    let obj_len = ctx.data().objects.borrow().get(idx as usize).map_or(0, |obj| obj.len());
+    if !gas::charge(&mut ctx, GAS_HOST_CALL + obj_len as u64 * GAS_PER_BYTE) {
+        return -2
+    }
+
     // Get the slice, where we will read the size of the buffer
 
     let env = ctx.data();
@@ -131,7 +152,11 @@ pub(crate) fn get_object_bytes(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, idx:
     0
 }
 
-pub(crate) fn get_object_size(ctx: FunctionEnvMut<Env>, idx: u32) -> i64 {
+pub(crate) fn get_object_size(mut ctx: FunctionEnvMut<Env>, idx: u32) -> i64 {
+    if !gas::charge(&mut ctx, GAS_HOST_CALL) {
+        return -2
+    }
+
     // Get the slice, where we will read the size of the buffer
 
     let env = ctx.data();
@@ -19,8 +19,14 @@
 /// Main wasm vm runtime implementation
 pub mod vm_runtime;
 
+/// Gas schedule of the runtime
+pub mod gas;
+
 /// VM memory access (read/write)
 pub(crate) mod memory;
 
+/// Limits on the memory of wasm instances
+pub(crate) mod tunables;
+
 /// Imported host functions
 pub(crate) mod import;
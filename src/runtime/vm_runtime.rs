@@ -25,23 +25,41 @@ use darkfi_sdk::{crypto::ContractId, entrypoint};
 use darkfi_serial::serialize;
 use log::{debug, error, info};
 use wasmer::{
-    imports, wasmparser::Operator, AsStoreRef, CompilerConfig, Function, FunctionEnv, Instance,
-    Memory, MemoryView, Module, Pages, Store, Value, WASM_PAGE_SIZE,
+    imports, AsStoreRef, BaseTunables, CompilerConfig, EngineBuilder, Features, Function,
+    FunctionEnv, Instance, Memory, MemoryView, Module, Pages, Store, Target, Value, WASM_PAGE_SIZE,
 };
 use wasmer_compiler_singlepass::Singlepass;
 use wasmer_middlewares::{
-    metering::{get_remaining_points, MeteringPoints},
+    metering::{get_remaining_points, set_remaining_points, MeteringPoints},
     Metering,
 };
 
-use super::{import, import::db::DbHandle, memory::MemoryManipulation};
-use crate::{blockchain::Blockchain, Error, Result};
+use super::{
+    gas::{operator_cost, DEFAULT_GAS_LIMIT, GAS_MEMORY_PER_PAGE},
+    import,
+    import::db::{DbHandle, DbIter},
+    memory::MemoryManipulation,
+    tunables::LimitingTunables,
+};
+use crate::{
+    blockchain::{Blockchain, DbBatch, DbOverlay},
+    Error, Result,
+};
 
 /// Name of the wasm linear memory in our guest module
 const MEMORY: &str = "memory";
 
-/// Gas limit for a contract
-const GAS_LIMIT: u64 = 200000000;
+/// Maximum depth of nested contract calls made with `call_contract`
+pub const MAX_CALL_DEPTH: u32 = 4;
+
+/// Maximum number of pages (64KiB) the memory of a contract can grow to
+pub const MAX_MEMORY_PAGES: u32 = 256;
+
+/// Function a contract has to export to accept calls from other contracts
+/// made with `call_contract`. It runs like `__entrypoint`, but without any
+/// of the contract's ZK proofs and signatures being verified, so contracts
+/// that rely on those can't be called this way.
+pub const NESTED_ENTRYPOINT: &str = "__nested_entrypoint";
 
 #[derive(Clone, Copy)]
 pub enum ContractSection {
@@ -69,6 +87,23 @@ impl ContractSection {
     }
 }
 
+/// Context of the transaction a contract function is executed in, which
+/// contracts can read through host functions.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TxContext {
+    /// Slot the transaction is executed in
+    pub slot: u64,
+    /// Epoch of that slot
+    pub epoch: u64,
+    /// Starting timestamp of that slot, in seconds since the Unix epoch
+    pub timestamp: i64,
+    /// blake3 hash of the transaction without its signatures,
+    /// see [`Transaction::data_hash`](crate::tx::Transaction::data_hash)
+    pub tx_hash: [u8; 32],
+    /// Index of the executing call in the transaction
+    pub call_idx: u32,
+}
+
 /// The wasm vm runtime instantiated for every smart contract that runs.
 pub struct Env {
     /// Blockchain access
@@ -76,7 +111,11 @@ pub struct Env {
     /// sled tree handles used with `db_*`
     pub db_handles: RefCell<Vec<DbHandle>>,
     /// sled tree batches, indexed the same as `db_handles`.
-    pub db_batches: RefCell<Vec<sled::Batch>>,
+    pub db_batches: RefCell<Vec<DbBatch>>,
+    /// Iterators over sled trees, used with `db_iter_*`
+    pub db_iters: RefCell<Vec<DbIter>>,
+    /// State updates staged by this and other runtimes, not yet written to the database
+    pub db_overlay: DbOverlay,
     /// The contract ID being executed
     pub contract_id: ContractId,
     /// The compiled wasm bincode being executed,
@@ -91,6 +130,19 @@ pub struct Env {
     pub memory: Option<Memory>,
     /// Object store for transferring memory from the host to VM
     pub objects: RefCell<Vec<Vec<u8>>>,
+    /// The instance being executed, used by host functions to charge gas
+    pub instance: Option<Instance>,
+    /// Set when a host function couldn't charge for its work
+    pub out_of_gas: bool,
+    /// The contract that called this one with `call_contract`, if any
+    pub caller: Option<ContractId>,
+    /// Depth of nested contract calls this runtime is executing at
+    pub call_depth: u32,
+    /// State updates produced by the contracts called with `call_contract`,
+    /// in the order they have to be applied
+    pub nested_updates: RefCell<Vec<(ContractId, Vec<u8>)>>,
+    /// Context of the transaction being executed
+    pub tx_context: TxContext,
 }
 
 impl Env {
@@ -115,34 +167,37 @@ pub struct Runtime {
     pub instance: Instance,
     pub store: Store,
     pub ctx: FunctionEnv<Env>,
+    /// Gas the runtime is allowed to spend
+    gas_limit: u64,
 }
 
 impl Runtime {
     /// Create a new wasm runtime instance that contains the given wasm module.
     pub fn new(wasm_bytes: &[u8], blockchain: Blockchain, contract_id: ContractId) -> Result<Self> {
         info!(target: "runtime::vm_runtime", "Instantiating a new runtime");
-        // This function will be called for each `Operator` encountered during
-        // the wasm module execution. It should return the cost of the operator
-        // that it received as its first argument.
-        // https://docs.rs/wasmparser/latest/wasmparser/enum.Operator.html
-        let cost_function = |operator: &Operator| -> u64 {
-            match operator {
-                Operator::LocalGet { .. } => 1,
-                Operator::I32Const { .. } => 1,
-                Operator::I32Add { .. } => 2,
-                _ => 0,
-            }
-        };
-
         // `Metering` needs to be conigured with a limit and a cost function.
         // For each `Operator`, the metering middleware will call the cost
         // function and subtract the cost from the remaining points.
-        let metering = Arc::new(Metering::new(GAS_LIMIT, cost_function));
+        // The limit can be changed afterwards with `set_gas_limit()`.
+        let metering = Arc::new(Metering::new(DEFAULT_GAS_LIMIT, operator_cost));
 
         // Define the compiler and middleware, engine, and store
         let mut compiler_config = Singlepass::new();
         compiler_config.push_middleware(metering);
-        let mut store = Store::new(compiler_config);
+
+        // Bulk memory operations touch any amount of memory for a flat cost,
+        // so modules using them are rejected. This also rules out reference
+        // types, which depend on them.
+        let mut features = Features::new();
+        features.bulk_memory(false);
+        let engine = EngineBuilder::new(compiler_config).set_features(Some(features)).engine();
+
+        // Memories can't grow past `MAX_MEMORY_PAGES`
+        let tunables = LimitingTunables::new(
+            BaseTunables::for_target(&Target::default()),
+            Pages(MAX_MEMORY_PAGES),
+        );
+        let mut store = Store::new_with_tunables(&engine, tunables);
 
         debug!(target: "runtime::vm_runtime", "Compiling module");
         let module = Module::new(&store, wasm_bytes)?;
@@ -160,6 +215,8 @@ impl Runtime {
                 blockchain,
                 db_handles,
                 db_batches,
+                db_iters: RefCell::new(vec![]),
+                db_overlay: DbOverlay::default(),
                 contract_id,
                 contract_bincode: wasm_bytes.to_vec(),
                 contract_section: ContractSection::Null,
@@ -167,6 +224,12 @@ impl Runtime {
                 logs,
                 memory: None,
                 objects: RefCell::new(vec![]),
+                instance: None,
+                out_of_gas: false,
+                caller: None,
+                call_depth: 0,
+                nested_updates: RefCell::new(vec![]),
+                tx_context: TxContext::default(),
             },
         );
 
@@ -220,6 +283,18 @@ impl Runtime {
                     import::db::db_del,
                 ),
 
+                "db_iter_start_" => Function::new_typed_with_env(
+                    &mut store,
+                    &ctx,
+                    import::db::db_iter_start,
+                ),
+
+                "db_iter_next_" => Function::new_typed_with_env(
+                    &mut store,
+                    &ctx,
+                    import::db::db_iter_next,
+                ),
+
                 "put_object_bytes_" => Function::new_typed_with_env(
                     &mut store,
                     &ctx,
@@ -243,6 +318,48 @@ impl Runtime {
                     &ctx,
                     import::merkle::merkle_add,
                 ),
+
+                "call_contract_" => Function::new_typed_with_env(
+                    &mut store,
+                    &ctx,
+                    import::call::call_contract,
+                ),
+
+                "get_caller_" => Function::new_typed_with_env(
+                    &mut store,
+                    &ctx,
+                    import::call::get_caller,
+                ),
+
+                "get_current_slot_" => Function::new_typed_with_env(
+                    &mut store,
+                    &ctx,
+                    import::context::get_current_slot,
+                ),
+
+                "get_current_epoch_" => Function::new_typed_with_env(
+                    &mut store,
+                    &ctx,
+                    import::context::get_current_epoch,
+                ),
+
+                "get_timestamp_" => Function::new_typed_with_env(
+                    &mut store,
+                    &ctx,
+                    import::context::get_timestamp,
+                ),
+
+                "get_tx_hash_" => Function::new_typed_with_env(
+                    &mut store,
+                    &ctx,
+                    import::context::get_tx_hash,
+                ),
+
+                "get_call_index_" => Function::new_typed_with_env(
+                    &mut store,
+                    &ctx,
+                    import::context::get_call_index,
+                ),
             }
         };
 
@@ -251,19 +368,28 @@ impl Runtime {
 
         let mut env_mut = ctx.as_mut(&mut store);
         env_mut.memory = Some(instance.exports.get_with_generics(MEMORY)?);
+        env_mut.instance = Some(instance.clone());
 
-        Ok(Self { instance, store, ctx })
+        Ok(Self { instance, store, ctx, gas_limit: DEFAULT_GAS_LIMIT })
     }
 
-    fn call(&mut self, section: ContractSection, payload: &[u8]) -> Result<Vec<u8>> {
-        debug!(target: "runtime::vm_runtime", "Calling {} method", section.name());
+    fn call(
+        &mut self,
+        section: ContractSection,
+        function: &str,
+        payload: &[u8],
+    ) -> Result<Vec<u8>> {
+        debug!(target: "runtime::vm_runtime", "Calling {} method", function);
 
         let mut env_mut = self.ctx.as_mut(&mut self.store);
         env_mut.contract_section = section;
         assert!(env_mut.contract_return_data.take().is_none());
         env_mut.contract_return_data.set(None);
-        // Clear the logs
+        // Clear the logs, the updates of previously called contracts and the
+        // iterators left open by previous calls
         let _ = env_mut.logs.take();
+        let _ = env_mut.nested_updates.take();
+        let _ = env_mut.db_iters.take();
 
         // Serialize the payload for the format the wasm runtime is expecting.
         let payload = Self::serialize_payload(&env_mut.contract_id, payload);
@@ -272,9 +398,10 @@ impl Runtime {
         let pages_required = payload.len() / WASM_PAGE_SIZE + 1;
         self.set_memory_page_size(pages_required as u32)?;
         self.copy_to_memory(&payload)?;
+        let pages_before = self.memory_pages();
 
-        debug!(target: "runtime::vm_runtime", "Getting {} function", section.name());
-        let entrypoint = self.instance.exports.get_function(section.name())?;
+        debug!(target: "runtime::vm_runtime", "Getting {} function", function);
+        let entrypoint = self.instance.exports.get_function(function)?;
 
         debug!(target: "runtime::vm_runtime", "Executing wasm");
         let ret = match entrypoint.call(&mut self.store, &[Value::I32(0_i32)]) {
@@ -286,12 +413,27 @@ impl Runtime {
             Err(e) => {
                 self.print_logs();
                 debug!(target: "runtime::vm_runtime", "{}", self.gas_info());
+                if self.out_of_gas() {
+                    error!(target: "runtime::vm_runtime", "Contract ran out of gas");
+                    return Err(Error::OutOfGas(self.gas_limit))
+                }
                 // WasmerRuntimeError panics are handled here. Return from run() immediately.
                 error!(target: "runtime::vm_runtime", "Wasmer Runtime Error: {:#?}", e);
                 return Err(e.into())
             }
         };
 
+        // The memory the contract grew is paid for now. How much it could
+        // allocate is bounded by `MAX_MEMORY_PAGES`.
+        let pages_grown = self.memory_pages().saturating_sub(pages_before);
+        self.charge(pages_grown as u64 * GAS_MEMORY_PER_PAGE);
+
+        // A host function may have run out of gas without the contract noticing.
+        if self.out_of_gas() {
+            error!(target: "runtime::vm_runtime", "Contract ran out of gas");
+            return Err(Error::OutOfGas(self.gas_limit))
+        }
+
         debug!(target: "runtime::vm_runtime", "wasm executed successfully");
         debug!(target: "runtime::vm_runtime", "Contract returned: {:?}", ret[0]);
 
@@ -326,22 +468,19 @@ impl Runtime {
     /// state, and it can create, delete, modify, read, and write to databases it's allowed to.
     /// The permissions for this are handled by the `ContractId` in the sled db API so we
     /// assume that the contract is only able to do write operations on its own sled trees.
+    /// The resulting writes and the contract's wasm bincode are staged in the runtime's
+    /// [`DbOverlay`], and they only reach the database once the overlay is committed.
     pub fn deploy(&mut self, payload: &[u8]) -> Result<()> {
         info!(target: "runtime::vm_runtime", "[wasm-runtime] Running deploy");
         debug!(target: "runtime::vm_runtime", "[wasm-runtime] payload: {:?}", payload);
-        let _ = self.call(ContractSection::Deploy, payload)?;
+        let _ = self.call(ContractSection::Deploy, ContractSection::Deploy.name(), payload)?;
 
-        // If the above didn't fail, we write the batches.
-        // TODO: Make all the writes atomic in a transaction over all trees.
-        let env_mut = self.ctx.as_mut(&mut self.store);
-        for (idx, db) in env_mut.db_handles.get_mut().iter().enumerate() {
-            let batch = env_mut.db_batches.borrow()[idx].clone();
-            db.apply_batch(batch)?;
-            db.flush()?;
-        }
+        // If the above didn't fail, we stage the batches.
+        self.stage_batches();
 
-        // Update the wasm bincode in the WasmStore
-        env_mut.blockchain.wasm_bincode.insert(env_mut.contract_id, &env_mut.contract_bincode)?;
+        // Stage the wasm bincode for the WasmStore
+        let env = self.ctx.as_ref(&self.store);
+        env.blockchain.wasm_bincode.stage(&env.db_overlay, env.contract_id, &env.contract_bincode);
 
         Ok(())
     }
@@ -352,7 +491,20 @@ impl Runtime {
     /// be used inside the vm by the runtime.
     pub fn exec(&mut self, payload: &[u8]) -> Result<Vec<u8>> {
         debug!(target: "runtime::vm_runtime", "exec: {:?}", payload);
-        self.call(ContractSection::Exec, payload)
+        self.call(ContractSection::Exec, ContractSection::Exec.name(), payload)
+    }
+
+    /// Execute the contract's [`NESTED_ENTRYPOINT`], for a call made by another
+    /// contract. It runs with the same permissions as `exec`.
+    pub(crate) fn exec_nested(&mut self, payload: &[u8]) -> Result<Vec<u8>> {
+        debug!(target: "runtime::vm_runtime", "exec_nested: {:?}", payload);
+        self.call(ContractSection::Exec, NESTED_ENTRYPOINT, payload)
+    }
+
+    /// Returns `true` if the contract accepts calls from other contracts,
+    /// by exporting a [`NESTED_ENTRYPOINT`].
+    pub(crate) fn accepts_nested_calls(&self) -> bool {
+        self.instance.exports.get_function(NESTED_ENTRYPOINT).is_ok()
     }
 
     /// This function runs after successful execution of `exec` and tries to
@@ -360,24 +512,63 @@ impl Runtime {
     /// The runtime will lok for an `UPDATE` symbol in the wasm code, and execute
     /// it if found. The function does not take an arbitrary payload, but just takes
     /// a state update from `env` and passes it into the wasm runtime.
+    /// The resulting writes are staged in the runtime's [`DbOverlay`], and they
+    /// only reach the database once the overlay is committed.
     pub fn apply(&mut self, update: &[u8]) -> Result<()> {
         debug!(target: "runtime::vm_runtime", "apply: {:?}", update);
-        let _ = self.call(ContractSection::Update, update)?;
+        let _ = self.call(ContractSection::Update, ContractSection::Update.name(), update)?;
 
-        // If the above didn't fail, we write the batches.
-        // TODO: Make all the writes atomic in a transaction over all trees.
-        let env_mut = self.ctx.as_mut(&mut self.store);
-        for (idx, db) in env_mut.db_handles.get_mut().iter().enumerate() {
-            let batch = env_mut.db_batches.borrow()[idx].clone();
-            db.apply_batch(batch)?;
-            db.flush()?;
-        }
+        // If the above didn't fail, we stage the batches.
+        self.stage_batches();
 
         Ok(())
     }
 
+    /// Share the given [`DbOverlay`] with this runtime, so it sees the state
+    /// updates staged in it, and stages its own ones there. This has to be
+    /// done before running any of the contract's functions.
+    pub fn set_db_overlay(&mut self, overlay: DbOverlay) {
+        self.ctx.as_mut(&mut self.store).db_overlay = overlay;
+    }
+
+    /// Get the [`DbOverlay`] used by this runtime.
+    pub fn db_overlay(&self) -> DbOverlay {
+        self.ctx.as_ref(&self.store).db_overlay.clone()
+    }
+
+    /// Set the context of the transaction the contract's functions are executed
+    /// in. This has to be done before running any of them, otherwise contracts
+    /// see an empty context.
+    pub fn set_tx_context(&mut self, tx_context: TxContext) {
+        self.ctx.as_mut(&mut self.store).tx_context = tx_context;
+    }
+
+    /// Mark this runtime as called by the given contract with `call_contract`,
+    /// at the given depth of nested calls.
+    pub(crate) fn set_caller(&mut self, caller: ContractId, call_depth: u32) {
+        let env_mut = self.ctx.as_mut(&mut self.store);
+        env_mut.caller = Some(caller);
+        env_mut.call_depth = call_depth;
+    }
+
+    /// Take the state updates produced by the contracts the last `exec` call
+    /// called with `call_contract`, along with their contract IDs. They have to
+    /// be applied right after the update returned by `exec`, in this order.
+    pub fn nested_updates(&mut self) -> Vec<(ContractId, Vec<u8>)> {
+        self.ctx.as_mut(&mut self.store).nested_updates.take()
+    }
+
+    /// Move the writes made by the last call into the overlay.
+    fn stage_batches(&mut self) {
+        let env_mut = self.ctx.as_mut(&mut self.store);
+        let batches = env_mut.db_batches.get_mut().iter_mut().map(std::mem::take);
+        for (db, batch) in env_mut.db_handles.get_mut().iter().zip(batches) {
+            db.stage(batch);
+        }
+    }
+
     pub fn metadata(&mut self, payload: &[u8]) -> Result<Vec<u8>> {
-        self.call(ContractSection::Metadata, payload)
+        self.call(ContractSection::Metadata, ContractSection::Metadata.name(), payload)
     }
 
     fn print_logs(&self) {
@@ -387,28 +578,76 @@ impl Runtime {
         }
     }
 
-    fn gas_info(&mut self) -> String {
-        let remaining_points = get_remaining_points(&mut self.store, &self.instance);
+    /// Set the amount of gas the runtime is allowed to spend from now on,
+    /// and reset the gas used so far.
+    pub fn set_gas_limit(&mut self, gas_limit: u64) {
+        set_remaining_points(&mut self.store, &self.instance, gas_limit);
+        self.ctx.as_mut(&mut self.store).out_of_gas = false;
+        self.gas_limit = gas_limit;
+    }
+
+    /// Get the amount of gas spent since the gas limit was set.
+    pub fn gas_used(&mut self) -> u64 {
+        match get_remaining_points(&mut self.store, &self.instance) {
+            MeteringPoints::Remaining(rem) => self.gas_limit - rem,
+            MeteringPoints::Exhausted => self.gas_limit,
+        }
+    }
+
+    /// Returns `true` if the runtime ran out of gas, either in the wasm code
+    /// or in a host function.
+    fn out_of_gas(&mut self) -> bool {
+        self.ctx.as_ref(&self.store).out_of_gas ||
+            matches!(
+                get_remaining_points(&mut self.store, &self.instance),
+                MeteringPoints::Exhausted
+            )
+    }
 
-        match remaining_points {
-            MeteringPoints::Remaining(rem) => {
-                format!("Gas used: {}/{}", GAS_LIMIT - rem, GAS_LIMIT)
+    /// Charge `cost` gas for work done outside of the wasm code. If there
+    /// isn't enough gas left, all of it is burned and the runtime is marked
+    /// as out of gas.
+    fn charge(&mut self, cost: u64) {
+        match get_remaining_points(&mut self.store, &self.instance) {
+            MeteringPoints::Remaining(rem) if rem >= cost => {
+                set_remaining_points(&mut self.store, &self.instance, rem - cost)
             }
-            MeteringPoints::Exhausted => {
-                format!("Gas fully exhausted: {}/{}", GAS_LIMIT + 1, GAS_LIMIT)
+            _ => {
+                error!(target: "runtime::vm_runtime", "Contract ran out of gas");
+                set_remaining_points(&mut self.store, &self.instance, 0);
+                self.ctx.as_mut(&mut self.store).out_of_gas = true;
             }
         }
     }
 
-    /// Set the memory page size
+    fn gas_info(&mut self) -> String {
+        if self.out_of_gas() {
+            return format!("Gas fully exhausted: {}/{}", self.gas_limit, self.gas_limit)
+        }
+
+        format!("Gas used: {}/{}", self.gas_used(), self.gas_limit)
+    }
+
+    /// Set the memory page size, growing the memory if it's smaller
     fn set_memory_page_size(&mut self, pages: u32) -> Result<Pages> {
+        let current = self.memory_pages();
+        if current >= pages {
+            return Ok(Pages(current))
+        }
+
         // Grab memory by value
         let memory = self.take_memory();
         // Modify the memory
-        let ret = memory.grow(&mut self.store, Pages(pages))?;
+        let ret = memory.grow(&mut self.store, Pages(pages - current));
         // Replace the memory back again
         self.ctx.as_mut(&mut self.store).memory = Some(memory);
-        Ok(ret)
+        Ok(ret?)
+    }
+
+    /// Number of pages the memory currently holds
+    fn memory_pages(&self) -> u32 {
+        let env = self.ctx.as_ref(&self.store);
+        env.memory_view(&self.store).size().0
     }
 
     /// Take Memory by value. Needed to modify the Memory object
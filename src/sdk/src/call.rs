@@ -0,0 +1,85 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use darkfi_serial::{deserialize, Encodable};
+
+use super::{
+    crypto::ContractId,
+    error::{ContractError, GenericResult},
+    util::{get_object_bytes, get_object_size, NO_OBJECT},
+};
+
+/// Only exec() can call this. Executes the `nested` function another contract
+/// gave in [`define_contract!`](crate::define_contract) with the given data as
+/// its instruction, and returns the state update it produced. Contracts without
+/// one can't be called. The called contract runs with what's left of the
+/// transaction's gas, and its state update is applied right after the caller's
+/// own one, so there is no need to pass it along in the caller's update.
+///
+/// ```
+///     update = call_contract(contract_id, data);
+/// ```
+pub fn call_contract(contract_id: ContractId, data: &[u8]) -> GenericResult<Vec<u8>> {
+    let mut len = 0;
+    let mut buf = vec![];
+    len += contract_id.encode(&mut buf)?;
+    len += data.to_vec().encode(&mut buf)?;
+
+    let ret = unsafe { call_contract_(buf.as_ptr(), len as u32) };
+
+    if ret < 0 {
+        return Err(ContractError::from(ret))
+    }
+
+    let obj = ret as u32;
+    let obj_size = get_object_size(obj);
+    let mut buf = vec![0u8; obj_size as usize];
+    get_object_bytes(&mut buf, obj);
+
+    Ok(buf)
+}
+
+/// Everyone can call this. Returns the ID of the contract that called the
+/// executing one with [`call_contract`], or `None` if it was called directly
+/// by the transaction.
+///
+/// ```
+///     caller = get_caller();
+/// ```
+pub fn get_caller() -> GenericResult<Option<ContractId>> {
+    let ret = unsafe { get_caller_() };
+
+    if ret < 0 {
+        match ret {
+            NO_OBJECT => return Ok(None),
+            _ => return Err(ContractError::from(ret)),
+        }
+    }
+
+    let obj = ret as u32;
+    let obj_size = get_object_size(obj);
+    let mut buf = vec![0u8; obj_size as usize];
+    get_object_bytes(&mut buf, obj);
+
+    Ok(Some(deserialize(&buf)?))
+}
+
+extern "C" {
+    fn call_contract_(ptr: *const u8, len: u32) -> i64;
+    fn get_caller_() -> i64;
+}
@@ -0,0 +1,96 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use super::{
+    error::{ContractError, GenericResult},
+    util::{get_object_bytes, get_object_size},
+};
+
+/// Everyone can call this. Returns the slot the transaction is executed in.
+pub fn get_current_slot() -> GenericResult<u64> {
+    let ret = unsafe { get_current_slot_() };
+
+    if ret < 0 {
+        return Err(ContractError::from(ret))
+    }
+
+    Ok(ret as u64)
+}
+
+/// Everyone can call this. Returns the epoch the transaction is executed in.
+pub fn get_current_epoch() -> GenericResult<u64> {
+    let ret = unsafe { get_current_epoch_() };
+
+    if ret < 0 {
+        return Err(ContractError::from(ret))
+    }
+
+    Ok(ret as u64)
+}
+
+/// Everyone can call this. Returns the starting timestamp of the slot the
+/// transaction is executed in, in seconds since the Unix epoch.
+pub fn get_timestamp() -> GenericResult<u64> {
+    let ret = unsafe { get_timestamp_() };
+
+    if ret < 0 {
+        return Err(ContractError::from(ret))
+    }
+
+    Ok(ret as u64)
+}
+
+/// Everyone can call this. Returns the blake3 hash of the executing transaction,
+/// leaving out its signatures.
+pub fn get_tx_hash() -> GenericResult<[u8; 32]> {
+    let ret = unsafe { get_tx_hash_() };
+
+    if ret < 0 {
+        return Err(ContractError::from(ret))
+    }
+
+    let obj = ret as u32;
+    if get_object_size(obj) != 32 {
+        return Err(ContractError::Internal)
+    }
+
+    let mut buf = [0u8; 32];
+    get_object_bytes(&mut buf, obj);
+
+    Ok(buf)
+}
+
+/// Everyone can call this. Returns the index of the executing call in the
+/// transaction.
+pub fn get_call_index() -> GenericResult<u32> {
+    let ret = unsafe { get_call_index_() };
+
+    if ret < 0 {
+        return Err(ContractError::from(ret))
+    }
+
+    Ok(ret as u32)
+}
+
+extern "C" {
+    fn get_current_slot_() -> i64;
+    fn get_current_epoch_() -> i64;
+    fn get_timestamp_() -> i64;
+    fn get_tx_hash_() -> i64;
+    fn get_call_index_() -> i64;
+}
@@ -36,8 +36,19 @@ lazy_static! {
     /// Contract ID for the native DAO contract
     pub static ref DAO_CONTRACT_ID: ContractId =
         ContractId::from(poseidon_hash([pallas::Base::zero(), pallas::Base::from(1)]));
+
+    /// Contract ID called to deploy or upgrade contracts. There's no wasm
+    /// code behind it, the calls are handled by the validator itself.
+    pub static ref DEPLOY_CONTRACT_ID: ContractId =
+        ContractId::from(poseidon_hash([pallas::Base::zero(), pallas::Base::from(2)]));
 }
 
+/// Money contract state tree holding the nullifiers of spent coins.
+/// The validator indexes its entries by the transaction revealing them.
+pub const MONEY_CONTRACT_NULLIFIERS_TREE: &str = "nullifiers";
+/// Money contract state tree holding the nullifiers of spent lead coins.
+pub const MONEY_CONTRACT_LEAD_NULLIFIERS_TREE: &str = "lead_nullifiers";
+
 /// ContractId represents an on-chain identifier for a certain smart contract.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, SerialEncodable, SerialDecodable)]
 pub struct ContractId(pallas::Base);
@@ -45,7 +56,11 @@ pub struct ContractId(pallas::Base);
 impl ContractId {
     /// Derive a contract ID from a `SecretKey` (deploy key)
     pub fn derive(deploy_key: SecretKey) -> Self {
-        let public_key = PublicKey::from_secret(deploy_key);
+        Self::derive_public(PublicKey::from_secret(deploy_key))
+    }
+
+    /// Derive a contract ID from the public counterpart of a deploy key
+    pub fn derive_public(public_key: PublicKey) -> Self {
         let (x, y) = public_key.xy();
         let hash = poseidon_hash::<2>([x, y]);
         Self(hash)
@@ -16,18 +16,19 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use darkfi_serial::Encodable;
+use darkfi_serial::{deserialize, Encodable};
 
 use super::{
     crypto::ContractId,
     error::{ContractError, GenericResult},
-    util::{get_object_bytes, get_object_size},
+    util::{get_object_bytes, get_object_size, NO_OBJECT},
 };
 
 // This might not be the right place for this constant...
 pub const SMART_CONTRACT_ZKAS_DB_NAME: &str = "_zkas";
 
 pub type DbHandle = u32;
+pub type DbIterHandle = u32;
 
 pub const DB_SUCCESS: i32 = 0;
 pub const CALLER_ACCESS_DENIED: i32 = -1;
@@ -37,6 +38,8 @@ pub const DB_GET_FAILED: i32 = -4;
 pub const DB_CONTAINS_KEY_FAILED: i32 = -5;
 pub const DB_SET_FAILED: i32 = -6;
 pub const DB_DEL_FAILED: i32 = -7;
+pub const DB_ITER_START_FAILED: i32 = -8;
+pub const DB_ITER_NEXT_FAILED: i32 = -9;
 
 /// Only deploy() can call this. Creates a new database instance for this contract.
 ///
@@ -57,7 +60,7 @@ pub fn db_init(contract_id: ContractId, db_name: &str) -> GenericResult<DbHandle
             match ret {
                 CALLER_ACCESS_DENIED => return Err(ContractError::CallerAccessDenied),
                 DB_INIT_FAILED => return Err(ContractError::DbInitFailed),
-                _ => unimplemented!(),
+                _ => return Err(ContractError::Internal),
             }
         }
 
@@ -78,7 +81,7 @@ pub fn db_lookup(contract_id: ContractId, db_name: &str) -> GenericResult<DbHand
             match ret {
                 CALLER_ACCESS_DENIED => return Err(ContractError::CallerAccessDenied),
                 DB_LOOKUP_FAILED => return Err(ContractError::DbLookupFailed),
-                _ => unimplemented!(),
+                _ => return Err(ContractError::Internal),
             }
         }
 
@@ -99,12 +102,15 @@ pub fn db_get(db_handle: DbHandle, key: &[u8]) -> GenericResult<Option<Vec<u8>>>
 
     let ret = unsafe { db_get_(buf.as_ptr(), len as u32) };
 
+    if ret == NO_OBJECT {
+        return Ok(None)
+    }
+
     if ret < 0 {
         match ret as i32 {
             CALLER_ACCESS_DENIED => return Err(ContractError::CallerAccessDenied),
             DB_GET_FAILED => return Err(ContractError::DbGetFailed),
-            -127 => return Ok(None),
-            _ => unimplemented!(),
+            _ => return Err(ContractError::Internal),
         }
     }
 
@@ -136,7 +142,7 @@ pub fn db_contains_key(db_handle: DbHandle, key: &[u8]) -> GenericResult<bool> {
         DB_CONTAINS_KEY_FAILED => Err(ContractError::DbContainsKeyFailed),
         0 => Ok(false),
         1 => Ok(true),
-        _ => unimplemented!(),
+        _ => Err(ContractError::Internal),
     }
 }
 
@@ -185,6 +191,100 @@ pub fn db_del(db_handle: DbHandle, key: &[u8]) -> GenericResult<()> {
     }
 }
 
+/// Everyone can call this. Starts an ordered iteration over the keys of a db,
+/// from `start` (inclusive) to `end` (exclusive). Leaving a bound out makes the
+/// iteration start at the first key, or go on until the last one. Keys come in
+/// ascending order, or descending if `reverse` is set. The iteration sees the
+/// writes made by the contract before it was started.
+///
+/// ```
+///     let iter = db_iter_start(db_handle, Some(start), None, false)?;
+///     while let Some((key, value)) = db_iter_next(iter)? {
+///         // ...
+///     }
+/// ```
+pub fn db_iter_start(
+    db_handle: DbHandle,
+    start: Option<&[u8]>,
+    end: Option<&[u8]>,
+    reverse: bool,
+) -> GenericResult<DbIterHandle> {
+    let mut len = 0;
+    let mut buf = vec![];
+    len += db_handle.encode(&mut buf)?;
+    len += start.map(|k| k.to_vec()).encode(&mut buf)?;
+    len += end.map(|k| k.to_vec()).encode(&mut buf)?;
+    len += reverse.encode(&mut buf)?;
+
+    let ret = unsafe { db_iter_start_(buf.as_ptr(), len as u32) };
+
+    if ret < 0 {
+        match ret {
+            CALLER_ACCESS_DENIED => return Err(ContractError::CallerAccessDenied),
+            DB_ITER_START_FAILED => return Err(ContractError::DbIterStartFailed),
+            _ => return Err(ContractError::Internal),
+        }
+    }
+
+    Ok(ret as u32)
+}
+
+/// Everyone can call this. Starts an ordered iteration over the keys of a db
+/// that start with the given prefix. See [`db_iter_start`].
+///
+/// ```
+///     let iter = db_iter_prefix(db_handle, prefix, true)?;
+/// ```
+pub fn db_iter_prefix(
+    db_handle: DbHandle,
+    prefix: &[u8],
+    reverse: bool,
+) -> GenericResult<DbIterHandle> {
+    db_iter_start(db_handle, Some(prefix), prefix_end(prefix).as_deref(), reverse)
+}
+
+/// Everyone can call this. Retrieves the next key and value of an iteration
+/// started with [`db_iter_start`], or `None` once it is over.
+///
+/// ```
+///     (key, value) = db_iter_next(iter);
+/// ```
+pub fn db_iter_next(db_iter: DbIterHandle) -> GenericResult<Option<(Vec<u8>, Vec<u8>)>> {
+    let ret = unsafe { db_iter_next_(db_iter) };
+
+    if ret == NO_OBJECT {
+        return Ok(None)
+    }
+
+    if ret < 0 {
+        match ret as i32 {
+            DB_ITER_NEXT_FAILED => return Err(ContractError::DbIterNextFailed),
+            _ => return Err(ContractError::Internal),
+        }
+    }
+
+    let obj = ret as u32;
+    let obj_size = get_object_size(obj);
+    let mut buf = vec![0u8; obj_size as usize];
+    get_object_bytes(&mut buf, obj);
+
+    Ok(Some(deserialize(&buf)?))
+}
+
+/// Returns the smallest key that comes after all the keys starting with the
+/// given prefix, or `None` if there is no such key.
+fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Some(end)
+        }
+    }
+
+    None
+}
+
 extern "C" {
     fn db_init_(ptr: *const u8, len: u32) -> i32;
     fn db_lookup_(ptr: *const u8, len: u32) -> i32;
@@ -192,4 +292,19 @@ extern "C" {
     fn db_contains_key_(ptr: *const u8, len: u32) -> i32;
     fn db_set_(ptr: *const u8, len: u32) -> i32;
     fn db_del_(ptr: *const u8, len: u32) -> i32;
+    fn db_iter_start_(ptr: *const u8, len: u32) -> i32;
+    fn db_iter_next_(db_iter: u32) -> i64;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix_end() {
+        assert_eq!(prefix_end(b"abc"), Some(b"abd".to_vec()));
+        assert_eq!(prefix_end(&[0x01, 0xff]), Some(vec![0x02]));
+        assert_eq!(prefix_end(&[0xff, 0xff]), None);
+        assert_eq!(prefix_end(&[]), None);
+    }
 }
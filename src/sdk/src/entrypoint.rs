@@ -23,8 +23,37 @@ use crate::crypto::ContractId;
 /// Success exit code for a contract
 pub const SUCCESS: i64 = 0;
 
+/// Define the entrypoints of a contract. Contracts that other contracts can
+/// call with [`call_contract`](crate::call::call_contract) also give a `nested`
+/// function, which gets the caller's instruction data. None of the contract's
+/// ZK proofs and signatures are verified for those calls, so it has to check
+/// the caller with [`get_caller`](crate::call::get_caller) instead.
 #[macro_export]
 macro_rules! define_contract {
+    (
+        init: $init_func:ident,
+        exec: $exec_func:ident,
+        apply: $apply_func:ident,
+        metadata: $metadata_func:ident,
+        nested: $nested_func:ident
+    ) => {
+        $crate::define_contract!(
+            init: $init_func,
+            exec: $exec_func,
+            apply: $apply_func,
+            metadata: $metadata_func
+        );
+
+        #[no_mangle]
+        pub unsafe extern "C" fn __nested_entrypoint(input: *mut u8) -> i64 {
+            let (contract_id, instruction_data) = $crate::entrypoint::deserialize(input);
+
+            match $nested_func(contract_id, &instruction_data) {
+                Ok(()) => $crate::entrypoint::SUCCESS,
+                Err(e) => e.into(),
+            }
+        }
+    };
     (
         init: $init_func:ident,
         exec: $exec_func:ident,
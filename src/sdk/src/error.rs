@@ -74,6 +74,18 @@ pub enum ContractError {
 
     #[error("Invalid function call")]
     InvalidFunction,
+
+    #[error("Call to another contract failed")]
+    CallContractFailed,
+
+    #[error("Maximum depth of contract calls exceeded")]
+    CallDepthExceeded,
+
+    #[error("Db iter_start failed")]
+    DbIterStartFailed,
+
+    #[error("Db iter_next failed")]
+    DbIterNextFailed,
 }
 
 /// Builtin return values occupy the upper 32 bits
@@ -99,6 +111,10 @@ pub const DB_GET_FAILED: i64 = to_builtin!(13);
 pub const DB_CONTAINS_KEY_FAILED: i64 = to_builtin!(14);
 pub const INVALID_FUNCTION: i64 = to_builtin!(15);
 pub const DB_DEL_FAILED: i64 = to_builtin!(16);
+pub const CALL_CONTRACT_FAILED: i64 = to_builtin!(17);
+pub const CALL_DEPTH_EXCEEDED: i64 = to_builtin!(18);
+pub const DB_ITER_START_FAILED: i64 = to_builtin!(19);
+pub const DB_ITER_NEXT_FAILED: i64 = to_builtin!(20);
 
 impl From<ContractError> for i64 {
     fn from(err: ContractError) -> Self {
@@ -118,6 +134,10 @@ impl From<ContractError> for i64 {
             ContractError::DbContainsKeyFailed => DB_CONTAINS_KEY_FAILED,
             ContractError::InvalidFunction => INVALID_FUNCTION,
             ContractError::DbDelFailed => DB_DEL_FAILED,
+            ContractError::CallContractFailed => CALL_CONTRACT_FAILED,
+            ContractError::CallDepthExceeded => CALL_DEPTH_EXCEEDED,
+            ContractError::DbIterStartFailed => DB_ITER_START_FAILED,
+            ContractError::DbIterNextFailed => DB_ITER_NEXT_FAILED,
             ContractError::Custom(error) => {
                 if error == 0 {
                     CUSTOM_ZERO
@@ -148,6 +168,10 @@ impl From<i64> for ContractError {
             DB_CONTAINS_KEY_FAILED => Self::DbContainsKeyFailed,
             INVALID_FUNCTION => Self::InvalidFunction,
             DB_DEL_FAILED => Self::DbDelFailed,
+            CALL_CONTRACT_FAILED => Self::CallContractFailed,
+            CALL_DEPTH_EXCEEDED => Self::CallDepthExceeded,
+            DB_ITER_START_FAILED => Self::DbIterStartFailed,
+            DB_ITER_NEXT_FAILED => Self::DbIterNextFailed,
             _ => Self::Custom(error as u32),
         }
     }
@@ -19,6 +19,13 @@
 pub use incrementalmerkletree;
 pub use pasta_curves as pasta;
 
+/// Calls to other contracts
+pub mod call;
+pub use call::call_contract;
+
+/// Chain context of the executing transaction
+pub mod context;
+
 /// Database functions
 pub mod db;
 
@@ -18,6 +18,10 @@
 
 use super::error::ContractError;
 
+/// Returned by the host functions passing an object to the VM when there is
+/// nothing to pass, e.g. a missing key or an iteration that is over.
+pub const NO_OBJECT: i64 = -127;
+
 pub fn set_return_data(data: &[u8]) -> Result<(), ContractError> {
     unsafe {
         match set_return_data_(data.as_ptr(), data.len() as u32) {
@@ -22,7 +22,7 @@ use async_std::sync::{Arc, RwLock};
 use darkfi_sdk::{
     crypto::{
         schnorr::{SchnorrPublic, SchnorrSecret, Signature},
-        PublicKey, SecretKey,
+        ContractId, PublicKey, SecretKey,
     },
     pasta::pallas,
     tx::ContractCall,
@@ -43,6 +43,12 @@ macro_rules! zip {
     )
 }
 
+/// Gas limit wallets declare for the transactions they build
+pub const DEFAULT_GAS_LIMIT: u64 = 1_000_000_000;
+
+/// Maximum gas limit a transaction can declare
+pub const MAX_GAS_LIMIT: u64 = 10_000_000_000;
+
 // ANCHOR: transaction
 /// A Transaction contains an arbitrary number of `ContractCall` objects,
 /// along with corresponding ZK proofs and Schnorr signatures.
@@ -50,6 +56,8 @@ macro_rules! zip {
 pub struct Transaction {
     /// Calls executed in this transaction
     pub calls: Vec<ContractCall>,
+    /// Maximum gas the calls can spend, summed over all of their phases
+    pub gas_limit: u64,
     /// Attached ZK proofs
     pub proofs: Vec<Vec<Proof>>,
     /// Attached Schnorr signatures
@@ -57,44 +65,104 @@ pub struct Transaction {
 }
 // ANCHOR_END: transaction
 
+/// Data of a call to `DEPLOY_CONTRACT_ID`, which deploys the contract with
+/// the ID derived from `deploy_key`, or upgrades it if it already exists.
+/// The call has to be signed with the secret deploy key.
+#[derive(Debug, Clone, Eq, PartialEq, SerialEncodable, SerialDecodable)]
+pub struct ContractDeploy {
+    /// Public counterpart of the deploy key
+    pub deploy_key: PublicKey,
+    /// Wasm bincode of the contract
+    pub wasm_bincode: Vec<u8>,
+    /// zkas bincodes of the contract's circuits
+    pub circuits: Vec<Vec<u8>>,
+    /// Payload passed to the contract's `__initialize` function
+    pub payload: Vec<u8>,
+}
+
+impl ContractDeploy {
+    /// ID of the contract being deployed
+    pub fn contract_id(&self) -> ContractId {
+        ContractId::derive_public(self.deploy_key)
+    }
+}
+
 type VerifyingKeyMap = Arc<RwLock<HashMap<[u8; 32], Vec<(String, VerifyingKey)>>>>;
 
+/// Proofs checked with the same verifying key, along with their public
+/// inputs and the index of the transaction they belong to.
+type ProofBatch<'a> = (ContractId, &'a VerifyingKey, Vec<(usize, &'a Proof, &'a [pallas::Base])>);
+
 impl Transaction {
     /// Verify ZK proofs for the entire transaction.
     pub async fn verify_zkps(
         &self,
         verifying_keys: VerifyingKeyMap,
         zkp_table: Vec<Vec<(String, Vec<pallas::Base>)>>,
+    ) -> Result<()> {
+        Self::verify_zkps_batch(std::slice::from_ref(self), verifying_keys, vec![zkp_table]).await
+    }
+
+    /// Verify ZK proofs for a set of transactions, e.g. the ones in a block.
+    /// The proofs are grouped by the verifying key they're checked with, and
+    /// each group is verified as a single batch. If a batch fails, the
+    /// proofs in it are checked separately to find the offending transaction.
+    pub async fn verify_zkps_batch(
+        txs: &[Transaction],
+        verifying_keys: VerifyingKeyMap,
+        zkp_tables: Vec<Vec<Vec<(String, Vec<pallas::Base>)>>>,
     ) -> Result<()> {
         // TODO: Are we sure we should assert here?
-        assert_eq!(self.calls.len(), self.proofs.len());
-        assert_eq!(self.calls.len(), zkp_table.len());
-
-        for (call, (proofs, pubvals)) in zip!(self.calls, self.proofs, zkp_table) {
-            assert_eq!(proofs.len(), pubvals.len());
-
-            for (proof, (zk_ns, public_vals)) in proofs.iter().zip(pubvals.iter()) {
-                if let Some(vks) = verifying_keys.read().await.get(&call.contract_id.to_bytes()) {
-                    if let Some(vk) = vks.iter().find(|x| &x.0 == zk_ns) {
-                        // We have a verifying key for this
-                        debug!("public inputs: {:#?}", public_vals);
-                        if let Err(e) = proof.verify(&vk.1, public_vals) {
-                            error!(
-                                target: "",
-                                "Failed verifying {}::{} ZK proof: {:#?}",
-                                call.contract_id, zk_ns, e
-                            );
-                            return Err(VerifyFailed::ProofVerifyFailed(e.to_string()).into())
-                        }
-                        debug!("Successfully verified {}::{} ZK proof", call.contract_id, zk_ns);
-                        continue
-                    }
+        assert_eq!(txs.len(), zkp_tables.len());
+
+        let verifying_keys = verifying_keys.read().await;
+
+        // Proofs to verify, grouped by contract and circuit namespace
+        let mut batches: HashMap<_, ProofBatch> = HashMap::new();
+
+        for (tx_idx, (tx, zkp_table)) in txs.iter().zip(zkp_tables.iter()).enumerate() {
+            assert_eq!(tx.calls.len(), tx.proofs.len());
+            assert_eq!(tx.calls.len(), zkp_table.len());
+
+            for (call, (proofs, pubvals)) in zip!(tx.calls, tx.proofs, zkp_table) {
+                assert_eq!(proofs.len(), pubvals.len());
+
+                let vks = verifying_keys.get(&call.contract_id.to_bytes());
+                for (proof, (zk_ns, public_vals)) in proofs.iter().zip(pubvals.iter()) {
+                    let Some(vk) = vks.and_then(|vks| vks.iter().find(|x| &x.0 == zk_ns)) else {
+                        let e = format!("{}:{} circuit VK nonexistent", call.contract_id, zk_ns);
+                        error!("{}", e);
+                        return Err(VerifyFailed::ProofVerifyFailed(e).into())
+                    };
+
+                    debug!("public inputs: {:#?}", public_vals);
+                    batches
+                        .entry((call.contract_id.to_bytes(), zk_ns.as_str()))
+                        .or_insert_with(|| (call.contract_id, &vk.1, vec![]))
+                        .2
+                        .push((tx_idx, proof, &public_vals[..]));
                 }
+            }
+        }
 
-                let e = format!("{}:{} circuit VK nonexistent", call.contract_id, zk_ns);
-                error!("{}", e);
-                return Err(VerifyFailed::ProofVerifyFailed(e).into())
+        for ((_, zk_ns), (contract_id, vk, items)) in batches {
+            let proofs: Vec<_> = items.iter().map(|(_, proof, vals)| (*proof, *vals)).collect();
+
+            if let Err(invalid) = Proof::verify_batch(vk, &proofs) {
+                let tx_idx = items[invalid[0]].0;
+                error!(
+                    target: "",
+                    "Failed verifying {}::{} ZK proof of transaction {}",
+                    contract_id, zk_ns, tx_idx
+                );
+                return Err(VerifyFailed::ProofVerifyFailed(format!(
+                    "Invalid {}::{} ZK proof in transaction {}",
+                    contract_id, zk_ns, tx_idx
+                ))
+                .into())
             }
+
+            debug!("Successfully verified {} {}::{} ZK proof(s)", proofs.len(), contract_id, zk_ns);
         }
 
         Ok(())
@@ -102,13 +170,25 @@ impl Transaction {
 
     /// Verify Schnorr signatures for the entire transaction.
     pub fn verify_sigs(&self, pub_table: Vec<Vec<PublicKey>>) -> Result<()> {
-        let tx_data = self.encode_without_sigs()?;
-        let data_hash = blake3::hash(&tx_data);
+        let data_hash = self.data_hash()?;
         debug!("tx.verify_sigs: data_hash: {:?}", data_hash.as_bytes());
 
-        assert!(pub_table.len() == self.signatures.len());
+        if pub_table.len() != self.signatures.len() {
+            error!("tx::verify_sigs: Incorrect number of signature sets");
+            return Err(Error::InvalidSignature)
+        }
 
         for (i, (sigs, pubkeys)) in self.signatures.iter().zip(pub_table.iter()).enumerate() {
+            if sigs.len() != pubkeys.len() {
+                error!(
+                    "tx::verify_sigs[{}] has {} signatures for {} public keys",
+                    i,
+                    sigs.len(),
+                    pubkeys.len()
+                );
+                return Err(Error::InvalidSignature)
+            }
+
             for (pubkey, signature) in pubkeys.iter().zip(sigs) {
                 debug!("Verifying signature with public key: {}", pubkey);
                 if !pubkey.verify(&data_hash.as_bytes()[..], signature) {
@@ -128,8 +208,7 @@ impl Transaction {
         rng: &mut (impl CryptoRng + RngCore),
         secret_keys: &[SecretKey],
     ) -> Result<Vec<Signature>> {
-        let tx_data = self.encode_without_sigs()?;
-        let data_hash = blake3::hash(&tx_data);
+        let data_hash = self.data_hash()?;
         debug!("tx.create_sigs: data_hash: {:?}", data_hash.as_bytes());
 
         let mut sigs = vec![];
@@ -146,7 +225,16 @@ impl Transaction {
     pub fn encode_without_sigs(&self) -> Result<Vec<u8>> {
         let mut buf = vec![];
         self.calls.encode(&mut buf)?;
+        self.gas_limit.encode(&mut buf)?;
         self.proofs.encode(&mut buf)?;
         Ok(buf)
     }
+
+    /// Hash of the transaction without its signatures, which is what they sign.
+    /// Unlike the hash of the whole transaction, this can't be changed by
+    /// anyone without invalidating the signatures, so it's the one contracts
+    /// get to see.
+    pub fn data_hash(&self) -> Result<blake3::Hash> {
+        Ok(blake3::hash(&self.encode_without_sigs()?))
+    }
 }
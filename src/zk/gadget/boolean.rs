@@ -0,0 +1,309 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use halo2_proofs::{
+    arithmetic::FieldExt,
+    circuit::{AssignedCell, Chip, Layouter},
+    pasta::pallas,
+    plonk,
+    plonk::{Advice, Column, ConstraintSystem, Constraints, Expression, Selector},
+    poly::Rotation,
+};
+
+pub trait BooleanInstruction<F: FieldExt>: Chip<F> {
+    fn and(
+        &self,
+        layouter: impl Layouter<F>,
+        a: &AssignedCell<F, F>,
+        b: &AssignedCell<F, F>,
+    ) -> Result<AssignedCell<F, F>, plonk::Error>;
+
+    fn or(
+        &self,
+        layouter: impl Layouter<F>,
+        a: &AssignedCell<F, F>,
+        b: &AssignedCell<F, F>,
+    ) -> Result<AssignedCell<F, F>, plonk::Error>;
+
+    fn not(
+        &self,
+        layouter: impl Layouter<F>,
+        a: &AssignedCell<F, F>,
+    ) -> Result<AssignedCell<F, F>, plonk::Error>;
+}
+
+/// Boolean logic over Base field elements. All the inputs are enforced
+/// to be booleans, i.e. either 0 or 1.
+#[derive(Clone, Debug)]
+pub struct BooleanConfig {
+    a: Column<Advice>,
+    b: Column<Advice>,
+    c: Column<Advice>,
+    q_and: Selector,
+    q_or: Selector,
+    q_not: Selector,
+}
+
+pub struct BooleanChip {
+    config: BooleanConfig,
+}
+
+impl Chip<pallas::Base> for BooleanChip {
+    type Config = BooleanConfig;
+    type Loaded = ();
+
+    fn config(&self) -> &Self::Config {
+        &self.config
+    }
+
+    fn loaded(&self) -> &Self::Loaded {
+        &()
+    }
+}
+
+impl BooleanChip {
+    pub fn configure(
+        meta: &mut ConstraintSystem<pallas::Base>,
+        a: Column<Advice>,
+        b: Column<Advice>,
+        c: Column<Advice>,
+    ) -> BooleanConfig {
+        meta.enable_equality(a);
+        meta.enable_equality(b);
+        meta.enable_equality(c);
+
+        let q_and = meta.selector();
+        let q_or = meta.selector();
+        let q_not = meta.selector();
+
+        let bool_check = |v: Expression<pallas::Base>| {
+            v.clone() * (Expression::Constant(pallas::Base::one()) - v)
+        };
+
+        meta.create_gate("Boolean and: c = a * b", |meta| {
+            let q_and = meta.query_selector(q_and);
+            let a = meta.query_advice(a, Rotation::cur());
+            let b = meta.query_advice(b, Rotation::cur());
+            let c = meta.query_advice(c, Rotation::cur());
+
+            Constraints::with_selector(
+                q_and,
+                [
+                    ("bool_check(a)", bool_check(a.clone())),
+                    ("bool_check(b)", bool_check(b.clone())),
+                    ("a and b", a * b - c),
+                ],
+            )
+        });
+
+        meta.create_gate("Boolean or: c = a + b - a * b", |meta| {
+            let q_or = meta.query_selector(q_or);
+            let a = meta.query_advice(a, Rotation::cur());
+            let b = meta.query_advice(b, Rotation::cur());
+            let c = meta.query_advice(c, Rotation::cur());
+
+            Constraints::with_selector(
+                q_or,
+                [
+                    ("bool_check(a)", bool_check(a.clone())),
+                    ("bool_check(b)", bool_check(b.clone())),
+                    ("a or b", a.clone() + b.clone() - a * b - c),
+                ],
+            )
+        });
+
+        meta.create_gate("Boolean not: c = 1 - a", |meta| {
+            let q_not = meta.query_selector(q_not);
+            let a = meta.query_advice(a, Rotation::cur());
+            let c = meta.query_advice(c, Rotation::cur());
+
+            let one = Expression::Constant(pallas::Base::one());
+
+            Constraints::with_selector(
+                q_not,
+                [("bool_check(a)", bool_check(a.clone())), ("not a", one - a - c)],
+            )
+        });
+
+        BooleanConfig { a, b, c, q_and, q_or, q_not }
+    }
+
+    pub fn construct(config: BooleanConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl BooleanInstruction<pallas::Base> for BooleanChip {
+    fn and(
+        &self,
+        mut layouter: impl Layouter<pallas::Base>,
+        a: &AssignedCell<pallas::Base, pallas::Base>,
+        b: &AssignedCell<pallas::Base, pallas::Base>,
+    ) -> Result<AssignedCell<pallas::Base, pallas::Base>, plonk::Error> {
+        layouter.assign_region(
+            || "c = a and b",
+            |mut region| {
+                self.config.q_and.enable(&mut region, 0)?;
+
+                a.copy_advice(|| "copy a", &mut region, self.config.a, 0)?;
+                b.copy_advice(|| "copy b", &mut region, self.config.b, 0)?;
+
+                let scalar_val = a.value().zip(b.value()).map(|(a, b)| a * b);
+                region.assign_advice(|| "c", self.config.c, 0, || scalar_val)
+            },
+        )
+    }
+
+    fn or(
+        &self,
+        mut layouter: impl Layouter<pallas::Base>,
+        a: &AssignedCell<pallas::Base, pallas::Base>,
+        b: &AssignedCell<pallas::Base, pallas::Base>,
+    ) -> Result<AssignedCell<pallas::Base, pallas::Base>, plonk::Error> {
+        layouter.assign_region(
+            || "c = a or b",
+            |mut region| {
+                self.config.q_or.enable(&mut region, 0)?;
+
+                a.copy_advice(|| "copy a", &mut region, self.config.a, 0)?;
+                b.copy_advice(|| "copy b", &mut region, self.config.b, 0)?;
+
+                let scalar_val = a.value().zip(b.value()).map(|(a, b)| a + b - a * b);
+                region.assign_advice(|| "c", self.config.c, 0, || scalar_val)
+            },
+        )
+    }
+
+    fn not(
+        &self,
+        mut layouter: impl Layouter<pallas::Base>,
+        a: &AssignedCell<pallas::Base, pallas::Base>,
+    ) -> Result<AssignedCell<pallas::Base, pallas::Base>, plonk::Error> {
+        layouter.assign_region(
+            || "c = not a",
+            |mut region| {
+                self.config.q_not.enable(&mut region, 0)?;
+
+                a.copy_advice(|| "copy a", &mut region, self.config.a, 0)?;
+
+                let scalar_val = a.value().map(|a| pallas::Base::one() - a);
+                region.assign_advice(|| "c", self.config.c, 0, || scalar_val)
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use halo2_proofs::{
+        circuit::{floor_planner, Value},
+        dev::MockProver,
+        plonk::{Circuit, Instance},
+    };
+
+    #[derive(Default)]
+    struct BooleanCircuit {
+        a: Value<pallas::Base>,
+        b: Value<pallas::Base>,
+    }
+
+    impl Circuit<pallas::Base> for BooleanCircuit {
+        type Config = (BooleanConfig, Column<Advice>, Column<Instance>);
+        type FloorPlanner = floor_planner::V1;
+
+        fn without_witnesses(&self) -> Self {
+            Self { a: Value::unknown(), b: Value::unknown() }
+        }
+
+        fn configure(meta: &mut ConstraintSystem<pallas::Base>) -> Self::Config {
+            let w = meta.advice_column();
+            meta.enable_equality(w);
+
+            let instance = meta.instance_column();
+            meta.enable_equality(instance);
+
+            let a = meta.advice_column();
+            let b = meta.advice_column();
+            let c = meta.advice_column();
+
+            (BooleanChip::configure(meta, a, b, c), w, instance)
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<pallas::Base>,
+        ) -> Result<(), plonk::Error> {
+            let boolean_chip = BooleanChip::construct(config.0.clone());
+
+            let a = layouter.assign_region(
+                || "witness a",
+                |mut region| region.assign_advice(|| "a", config.1, 0, || self.a),
+            )?;
+            let b = layouter.assign_region(
+                || "witness b",
+                |mut region| region.assign_advice(|| "b", config.1, 0, || self.b),
+            )?;
+
+            let and = boolean_chip.and(layouter.namespace(|| "a and b"), &a, &b)?;
+            let or = boolean_chip.or(layouter.namespace(|| "a or b"), &a, &b)?;
+            let not = boolean_chip.not(layouter.namespace(|| "not a"), &a)?;
+
+            layouter.constrain_instance(and.cell(), config.2, 0)?;
+            layouter.constrain_instance(or.cell(), config.2, 1)?;
+            layouter.constrain_instance(not.cell(), config.2, 2)?;
+
+            Ok(())
+        }
+    }
+
+    /// Public inputs for `a and b`, `a or b` and `not a`, following the
+    /// arithmetic of the gates.
+    fn public_inputs(a: pallas::Base, b: pallas::Base) -> Vec<pallas::Base> {
+        vec![a * b, a + b - a * b, pallas::Base::one() - a]
+    }
+
+    #[test]
+    fn boolean_ops() {
+        let k = 6;
+
+        for (a, b) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+            let (a, b) = (pallas::Base::from(a), pallas::Base::from(b));
+            let circuit = BooleanCircuit { a: Value::known(a), b: Value::known(b) };
+            let prover = MockProver::run(k, &circuit, vec![public_inputs(a, b)]).unwrap();
+            prover.assert_satisfied();
+        }
+
+        // Non-boolean inputs are rejected, even though the outputs match
+        // the arithmetic of the gates.
+        for (a, b) in [(2, 1), (1, 2), (2, 3)] {
+            let (a, b) = (pallas::Base::from(a), pallas::Base::from(b));
+            let circuit = BooleanCircuit { a: Value::known(a), b: Value::known(b) };
+            let prover = MockProver::run(k, &circuit, vec![public_inputs(a, b)]).unwrap();
+            assert!(prover.verify().is_err());
+        }
+
+        // Wrong outputs are rejected
+        let one = pallas::Base::one();
+        let zero = pallas::Base::zero();
+        let circuit = BooleanCircuit { a: Value::known(one), b: Value::known(one) };
+        let prover = MockProver::run(k, &circuit, vec![vec![zero, one, zero]]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+}
@@ -0,0 +1,226 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use halo2_proofs::{
+    circuit::{AssignedCell, Chip, Layouter},
+    pasta::pallas,
+    plonk,
+    plonk::{Advice, Column, ConstraintSystem, Constraints, Expression, Selector},
+    poly::Rotation,
+};
+
+/// Conditional selection: `out = if cond { a } else { b }`.
+/// `cond` is enforced to be a boolean, i.e. either 0 or 1.
+#[derive(Clone, Debug)]
+pub struct CondSelectConfig {
+    cond: Column<Advice>,
+    a: Column<Advice>,
+    b: Column<Advice>,
+    out: Column<Advice>,
+    selector: Selector,
+}
+
+pub struct CondSelectChip {
+    config: CondSelectConfig,
+}
+
+impl Chip<pallas::Base> for CondSelectChip {
+    type Config = CondSelectConfig;
+    type Loaded = ();
+
+    fn config(&self) -> &Self::Config {
+        &self.config
+    }
+
+    fn loaded(&self) -> &Self::Loaded {
+        &()
+    }
+}
+
+impl CondSelectChip {
+    pub fn configure(
+        meta: &mut ConstraintSystem<pallas::Base>,
+        cond: Column<Advice>,
+        a: Column<Advice>,
+        b: Column<Advice>,
+        out: Column<Advice>,
+    ) -> CondSelectConfig {
+        meta.enable_equality(cond);
+        meta.enable_equality(a);
+        meta.enable_equality(b);
+        meta.enable_equality(out);
+
+        let selector = meta.selector();
+
+        meta.create_gate("Conditional selection: out = cond * a + (1 - cond) * b", |meta| {
+            let selector = meta.query_selector(selector);
+            let cond = meta.query_advice(cond, Rotation::cur());
+            let a = meta.query_advice(a, Rotation::cur());
+            let b = meta.query_advice(b, Rotation::cur());
+            let out = meta.query_advice(out, Rotation::cur());
+
+            let one = Expression::Constant(pallas::Base::one());
+
+            Constraints::with_selector(
+                selector,
+                [
+                    ("bool_check(cond)", cond.clone() * (one.clone() - cond.clone())),
+                    ("cond_select", cond.clone() * a + (one - cond) * b - out),
+                ],
+            )
+        });
+
+        CondSelectConfig { cond, a, b, out, selector }
+    }
+
+    pub fn construct(config: CondSelectConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn conditional_select(
+        &self,
+        mut layouter: impl Layouter<pallas::Base>,
+        cond: &AssignedCell<pallas::Base, pallas::Base>,
+        a: &AssignedCell<pallas::Base, pallas::Base>,
+        b: &AssignedCell<pallas::Base, pallas::Base>,
+    ) -> Result<AssignedCell<pallas::Base, pallas::Base>, plonk::Error> {
+        layouter.assign_region(
+            || "out = if cond { a } else { b }",
+            |mut region| {
+                self.config.selector.enable(&mut region, 0)?;
+
+                cond.copy_advice(|| "copy cond", &mut region, self.config.cond, 0)?;
+                a.copy_advice(|| "copy a", &mut region, self.config.a, 0)?;
+                b.copy_advice(|| "copy b", &mut region, self.config.b, 0)?;
+
+                let out = cond
+                    .value()
+                    .zip(a.value())
+                    .zip(b.value())
+                    .map(|((cond, a), b)| cond * a + (pallas::Base::one() - cond) * b);
+
+                region.assign_advice(|| "out", self.config.out, 0, || out)
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use halo2_proofs::{
+        circuit::{floor_planner, Value},
+        dev::MockProver,
+        plonk::{Circuit, Instance},
+    };
+
+    #[derive(Default)]
+    struct CondSelectCircuit {
+        cond: Value<pallas::Base>,
+        a: Value<pallas::Base>,
+        b: Value<pallas::Base>,
+    }
+
+    impl Circuit<pallas::Base> for CondSelectCircuit {
+        type Config = (CondSelectConfig, Column<Advice>, Column<Instance>);
+        type FloorPlanner = floor_planner::V1;
+
+        fn without_witnesses(&self) -> Self {
+            Self { cond: Value::unknown(), a: Value::unknown(), b: Value::unknown() }
+        }
+
+        fn configure(meta: &mut ConstraintSystem<pallas::Base>) -> Self::Config {
+            let w = meta.advice_column();
+            meta.enable_equality(w);
+
+            let instance = meta.instance_column();
+            meta.enable_equality(instance);
+
+            let cond = meta.advice_column();
+            let a = meta.advice_column();
+            let b = meta.advice_column();
+            let out = meta.advice_column();
+
+            (CondSelectChip::configure(meta, cond, a, b, out), w, instance)
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<pallas::Base>,
+        ) -> Result<(), plonk::Error> {
+            let cond_select_chip = CondSelectChip::construct(config.0.clone());
+
+            let mut witness = |name: &'static str, value: Value<pallas::Base>| {
+                layouter.assign_region(
+                    || name,
+                    |mut region| region.assign_advice(|| name, config.1, 0, || value),
+                )
+            };
+
+            let cond = witness("cond", self.cond)?;
+            let a = witness("a", self.a)?;
+            let b = witness("b", self.b)?;
+
+            let out = cond_select_chip.conditional_select(
+                layouter.namespace(|| "cond_select"),
+                &cond,
+                &a,
+                &b,
+            )?;
+            layouter.constrain_instance(out.cell(), config.2, 0)?;
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn cond_select() {
+        let k = 6;
+        let a = pallas::Base::from(42);
+        let b = pallas::Base::from(69);
+
+        for (cond, out) in [(pallas::Base::one(), a), (pallas::Base::zero(), b)] {
+            let circuit = CondSelectCircuit {
+                cond: Value::known(cond),
+                a: Value::known(a),
+                b: Value::known(b),
+            };
+            let prover = MockProver::run(k, &circuit, vec![vec![out]]).unwrap();
+            prover.assert_satisfied();
+        }
+
+        // Selecting the wrong value is rejected
+        let circuit = CondSelectCircuit {
+            cond: Value::known(pallas::Base::one()),
+            a: Value::known(a),
+            b: Value::known(b),
+        };
+        let prover = MockProver::run(k, &circuit, vec![vec![b]]).unwrap();
+        assert!(prover.verify().is_err());
+
+        // A non-boolean condition is rejected, even though the output matches
+        // the arithmetic of the gate.
+        let cond = pallas::Base::from(2);
+        let out = cond * a + (pallas::Base::one() - cond) * b;
+        let circuit =
+            CondSelectCircuit { cond: Value::known(cond), a: Value::known(a), b: Value::known(b) };
+        let prover = MockProver::run(k, &circuit, vec![vec![out]]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+}
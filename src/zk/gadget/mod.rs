@@ -30,3 +30,12 @@ pub mod less_than;
 
 /// is_zero comparison gadget
 pub mod is_zero;
+
+/// Conditional selection gadget
+pub mod cond_select;
+
+/// Zero-conditional selection gadget
+pub mod zero_cond;
+
+/// Boolean logic gadget
+pub mod boolean;
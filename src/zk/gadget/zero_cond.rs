@@ -0,0 +1,126 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use halo2_proofs::{
+    circuit::{AssignedCell, Chip, Layouter},
+    pasta::pallas,
+    plonk,
+    plonk::{Advice, Column, ConstraintSystem, Constraints, Expression, Selector},
+    poly::Rotation,
+};
+
+use super::is_zero::{IsZeroChip, IsZeroConfig};
+
+/// Zero-conditional selection: `out = if a == 0 { a } else { b }`.
+/// This uses the `is_zero` gadget to witness whether `a` is zero.
+#[derive(Clone, Debug)]
+pub struct ZeroCondConfig {
+    a: Column<Advice>,
+    b: Column<Advice>,
+    out: Column<Advice>,
+    is_zero: IsZeroConfig<pallas::Base>,
+    selector: Selector,
+}
+
+pub struct ZeroCondChip {
+    config: ZeroCondConfig,
+}
+
+impl Chip<pallas::Base> for ZeroCondChip {
+    type Config = ZeroCondConfig;
+    type Loaded = ();
+
+    fn config(&self) -> &Self::Config {
+        &self.config
+    }
+
+    fn loaded(&self) -> &Self::Loaded {
+        &()
+    }
+}
+
+impl ZeroCondChip {
+    pub fn configure(
+        meta: &mut ConstraintSystem<pallas::Base>,
+        a: Column<Advice>,
+        b: Column<Advice>,
+        a_inv: Column<Advice>,
+        out: Column<Advice>,
+    ) -> ZeroCondConfig {
+        meta.enable_equality(a);
+        meta.enable_equality(b);
+        meta.enable_equality(out);
+
+        let selector = meta.selector();
+
+        let is_zero = IsZeroChip::configure(
+            meta,
+            |meta| meta.query_selector(selector),
+            |meta| meta.query_advice(a, Rotation::cur()),
+            a_inv,
+        );
+
+        meta.create_gate("Zero-conditional selection: out = if a == 0 { a } else { b }", |meta| {
+            let selector = meta.query_selector(selector);
+            let a = meta.query_advice(a, Rotation::cur());
+            let b = meta.query_advice(b, Rotation::cur());
+            let out = meta.query_advice(out, Rotation::cur());
+
+            let one = Expression::Constant(pallas::Base::one());
+
+            Constraints::with_selector(
+                selector,
+                Some(is_zero.expr() * a + (one - is_zero.expr()) * b - out),
+            )
+        });
+
+        ZeroCondConfig { a, b, out, is_zero, selector }
+    }
+
+    pub fn construct(config: ZeroCondConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn zero_cond_select(
+        &self,
+        mut layouter: impl Layouter<pallas::Base>,
+        a: &AssignedCell<pallas::Base, pallas::Base>,
+        b: &AssignedCell<pallas::Base, pallas::Base>,
+    ) -> Result<AssignedCell<pallas::Base, pallas::Base>, plonk::Error> {
+        let is_zero_chip = IsZeroChip::construct(self.config.is_zero.clone());
+
+        layouter.assign_region(
+            || "out = if a == 0 { a } else { b }",
+            |mut region| {
+                self.config.selector.enable(&mut region, 0)?;
+
+                a.copy_advice(|| "copy a", &mut region, self.config.a, 0)?;
+                b.copy_advice(|| "copy b", &mut region, self.config.b, 0)?;
+
+                is_zero_chip.assign(&mut region, 0, a.value().copied())?;
+
+                let out =
+                    a.value()
+                        .zip(b.value())
+                        .map(|(a, b)| if *a == pallas::Base::zero() { *a } else { *b });
+
+                region.assign_advice(|| "out", self.config.out, 0, || out)
+            },
+        )
+    }
+}
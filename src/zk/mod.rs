@@ -23,6 +23,13 @@ pub use vm::ZkCircuit;
 pub mod vm_stack;
 pub use vm_stack::{empty_witnesses, Witness};
 
+/// VM debugging helpers
+mod vm_debug;
+
+/// Computation of the smallest `k` a circuit fits in
+pub mod vm_rows;
+pub use vm_rows::{circuit_k, minimum_k};
+
 /// ZK gadget implementations
 pub mod gadget;
 
@@ -30,6 +37,10 @@ pub mod gadget;
 pub mod proof;
 pub use proof::{Proof, ProvingKey};
 
+/// Cache for proving and verifying keys
+pub mod params_cache;
+pub use params_cache::{ParamsCache, ParamsCachePtr};
+
 pub mod halo2 {
     pub use halo2_proofs::{
         arithmetic::Field,
@@ -0,0 +1,245 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use halo2_proofs::{pasta::vesta, poly::commitment::Params};
+use log::{debug, warn};
+
+use super::{circuit_k, empty_witnesses, proof::VerifyingKey, ProvingKey, ZkCircuit};
+use crate::{zkas::ZkBinary, Result};
+
+pub type ParamsCachePtr = Arc<ParamsCache>;
+
+/// Cache key: blake3 hash of the zkas bincode
+type CacheKey = [u8; 32];
+
+/// A cache for the ZK params of each `k`, which don't depend on the
+/// circuit. They're written to disk along with their blake3 hash, and
+/// read back from there on the next start.
+///
+/// The halo2 version we use doesn't expose serialization of the plonk
+/// keys, so they can't be persisted. The proving and verifying keys
+/// built from the params are only kept in memory, indexed by the blake3
+/// hash of the zkas bincode they're built from, and have to be built
+/// again on every start.
+pub struct ParamsCache {
+    /// Directory holding the serialized params, if any
+    path: Option<PathBuf>,
+    params: Mutex<HashMap<u32, Params<vesta::Affine>>>,
+    verifying_keys: Mutex<HashMap<CacheKey, VerifyingKey>>,
+    proving_keys: Mutex<HashMap<CacheKey, ProvingKey>>,
+}
+
+impl ParamsCache {
+    /// Open a params cache in the given directory, creating it if needed.
+    pub fn new(path: &Path) -> Result<ParamsCachePtr> {
+        fs::create_dir_all(path)?;
+        Ok(Arc::new(Self::with_path(Some(path.to_path_buf()))))
+    }
+
+    /// Create a params cache that is only kept in memory.
+    pub fn in_memory() -> ParamsCachePtr {
+        Arc::new(Self::with_path(None))
+    }
+
+    fn with_path(path: Option<PathBuf>) -> Self {
+        Self {
+            path,
+            params: Mutex::new(HashMap::new()),
+            verifying_keys: Mutex::new(HashMap::new()),
+            proving_keys: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Fetch the params for `k`. They are read from disk if they were
+    /// written there before, otherwise they're generated and written.
+    pub fn params(&self, k: u32) -> Result<Params<vesta::Affine>> {
+        let mut params = self.params.lock().unwrap();
+        if let Some(p) = params.get(&k) {
+            return Ok(p.clone())
+        }
+
+        let p = match self.params_path(k) {
+            Some(path) => match Self::read_params(&path, k) {
+                Some(p) => p,
+                None => {
+                    debug!(target: "zk::params_cache", "Generating params for k={}", k);
+                    let p = Params::new(k);
+                    Self::write_params(&path, &p)?;
+                    p
+                }
+            },
+            None => Params::new(k),
+        };
+
+        params.insert(k, p.clone());
+        Ok(p)
+    }
+
+    /// Fetch the verifying key for the given zkas bincode.
+    pub fn verifying_key(&self, bincode: &[u8]) -> Result<VerifyingKey> {
+        let key = *blake3::hash(bincode).as_bytes();
+        if let Some(vk) = self.verifying_keys.lock().unwrap().get(&key) {
+            return Ok(vk.clone())
+        }
+
+        let zkbin = ZkBinary::decode(bincode)?;
+        let k = circuit_k(&zkbin)?;
+        let circuit = ZkCircuit::new(empty_witnesses(&zkbin), zkbin);
+        let vk = VerifyingKey::build_with_params(self.params(k)?, &circuit)?;
+
+        self.verifying_keys.lock().unwrap().insert(key, vk.clone());
+        Ok(vk)
+    }
+
+    /// Fetch the proving key for the given zkas bincode.
+    pub fn proving_key(&self, bincode: &[u8]) -> Result<ProvingKey> {
+        let key = *blake3::hash(bincode).as_bytes();
+        if let Some(pk) = self.proving_keys.lock().unwrap().get(&key) {
+            return Ok(pk.clone())
+        }
+
+        let zkbin = ZkBinary::decode(bincode)?;
+        let k = circuit_k(&zkbin)?;
+        let circuit = ZkCircuit::new(empty_witnesses(&zkbin), zkbin);
+        let pk = ProvingKey::build_with_params(self.params(k)?, &circuit)?;
+
+        self.proving_keys.lock().unwrap().insert(key, pk.clone());
+        Ok(pk)
+    }
+
+    fn params_path(&self, k: u32) -> Option<PathBuf> {
+        self.path.as_ref().map(|p| p.join(format!("params_k{}.bin", k)))
+    }
+
+    /// Read params from disk. Returns `None` if they're missing or unusable,
+    /// in which case they should be generated again.
+    fn read_params(path: &Path, k: u32) -> Option<Params<vesta::Affine>> {
+        let data = fs::read(path).ok()?;
+
+        // The serialized params are followed by their blake3 hash
+        if data.len() < 4 + blake3::OUT_LEN {
+            warn!(target: "zk::params_cache", "Params in {:?} are truncated", path);
+            return None
+        }
+        let (params, hash) = data.split_at(data.len() - blake3::OUT_LEN);
+        if blake3::hash(params).as_bytes() != hash {
+            warn!(target: "zk::params_cache", "Params in {:?} are corrupted", path);
+            return None
+        }
+
+        // The serialized params start with their `k`
+        if u32::from_le_bytes(params[..4].try_into().unwrap()) != k {
+            warn!(target: "zk::params_cache", "Params in {:?} were not built for k={}", path, k);
+            return None
+        }
+
+        match Params::<vesta::Affine>::read(&mut &params[..]) {
+            Ok(p) => {
+                debug!(target: "zk::params_cache", "Loaded params for k={} from {:?}", k, path);
+                Some(p)
+            }
+            Err(e) => {
+                warn!(target: "zk::params_cache", "Failed reading params from {:?}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    /// Write params to disk, followed by their blake3 hash. They're written
+    /// to a temporary file first, so an interrupted write doesn't leave a
+    /// corrupted cache behind.
+    fn write_params(path: &Path, params: &Params<vesta::Affine>) -> Result<()> {
+        let tmp_path = path.with_extension("tmp");
+
+        let mut data = vec![];
+        params.write(&mut data)?;
+        let hash = blake3::hash(&data);
+
+        {
+            let mut file = File::create(&tmp_path)?;
+            file.write_all(&data)?;
+            file.write_all(hash.as_bytes())?;
+            file.sync_all()?;
+        }
+
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn params_cache_reuses_params() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("darkfi_params_cache_{}", std::process::id()));
+        let bincode = include_bytes!("../../proof/arithmetic.zk.bin");
+        let k = ZkBinary::decode(bincode)?.k;
+
+        let cache = ParamsCache::new(&path)?;
+        let vk = cache.verifying_key(bincode)?;
+        assert!(path.join(format!("params_k{}.bin", k)).exists());
+
+        // A fresh cache should load the params from disk and
+        // derive the same verifying key from them.
+        let cache = ParamsCache::new(&path)?;
+        let params = cache.params(k)?;
+        assert_eq!(params.get_g(), vk.params.get_g());
+
+        let cached_vk = cache.verifying_key(bincode)?;
+        assert_eq!(format!("{:?}", cached_vk.vk.pinned()), format!("{:?}", vk.vk.pinned()));
+
+        fs::remove_dir_all(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn params_cache_rejects_corrupted_params() -> Result<()> {
+        let path = std::env::temp_dir()
+            .join(format!("darkfi_params_cache_corrupted_{}", std::process::id()));
+        let k = 4;
+
+        let cache = ParamsCache::new(&path)?;
+        let params = cache.params(k)?;
+        let params_path = path.join(format!("params_k{}.bin", k));
+
+        // Flip a bit in the middle of the params
+        let mut data = fs::read(&params_path)?;
+        let idx = data.len() / 2;
+        data[idx] ^= 1;
+        fs::write(&params_path, &data)?;
+        assert!(ParamsCache::read_params(&params_path, k).is_none());
+
+        // They're generated and written again
+        let cache = ParamsCache::new(&path)?;
+        assert_eq!(cache.params(k)?.get_g(), params.get_g());
+        assert!(ParamsCache::read_params(&params_path, k).is_some());
+
+        fs::remove_dir_all(&path)?;
+        Ok(())
+    }
+}
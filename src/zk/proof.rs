@@ -20,12 +20,14 @@ use darkfi_serial::{SerialDecodable, SerialEncodable};
 use halo2_proofs::{
     pasta::{pallas, vesta},
     plonk,
-    plonk::{Circuit, SingleVerifier},
+    plonk::{BatchVerifier, Circuit, SingleVerifier},
     poly::commitment::Params,
     transcript::{Blake2bRead, Blake2bWrite},
 };
 use rand::RngCore;
 
+use crate::Result;
+
 #[derive(Clone, Debug)]
 pub struct VerifyingKey {
     pub params: Params<vesta::Affine>,
@@ -34,9 +36,18 @@ pub struct VerifyingKey {
 
 impl VerifyingKey {
     pub fn build(k: u32, c: &impl Circuit<pallas::Base>) -> Self {
-        let params = Params::new(k);
-        let vk = plonk::keygen_vk(&params, c).unwrap();
-        VerifyingKey { params, vk }
+        Self::build_with_params(Params::new(k), c).unwrap()
+    }
+
+    /// Build the verifying key using already generated params,
+    /// e.g. the ones loaded from a [`ParamsCache`](super::ParamsCache).
+    /// Fails if the circuit doesn't fit in the rows given by the params.
+    pub fn build_with_params(
+        params: Params<vesta::Affine>,
+        c: &impl Circuit<pallas::Base>,
+    ) -> Result<Self> {
+        let vk = plonk::keygen_vk(&params, c)?;
+        Ok(VerifyingKey { params, vk })
     }
 }
 
@@ -48,10 +59,19 @@ pub struct ProvingKey {
 
 impl ProvingKey {
     pub fn build(k: u32, c: &impl Circuit<pallas::Base>) -> Self {
-        let params = Params::new(k);
-        let vk = plonk::keygen_vk(&params, c).unwrap();
-        let pk = plonk::keygen_pk(&params, vk, c).unwrap();
-        ProvingKey { params, pk }
+        Self::build_with_params(Params::new(k), c).unwrap()
+    }
+
+    /// Build the proving key using already generated params,
+    /// e.g. the ones loaded from a [`ParamsCache`](super::ParamsCache).
+    /// Fails if the circuit doesn't fit in the rows given by the params.
+    pub fn build_with_params(
+        params: Params<vesta::Affine>,
+        c: &impl Circuit<pallas::Base>,
+    ) -> Result<Self> {
+        let vk = plonk::keygen_vk(&params, c)?;
+        let pk = plonk::keygen_pk(&params, vk, c)?;
+        Ok(ProvingKey { params, pk })
     }
 }
 
@@ -101,6 +121,39 @@ impl Proof {
         plonk::verify_proof(&vk.params, &vk.vk, strategy, &[&[instances]], &mut transcript)
     }
 
+    /// Verify a batch of proofs made with the same verifying key, along
+    /// with their public inputs, in a single multi-scalar multiplication.
+    /// If the batch doesn't verify, the proofs are verified one by one,
+    /// and the indexes of the invalid ones are returned.
+    pub fn verify_batch(
+        vk: &VerifyingKey,
+        proofs: &[(&Proof, &[pallas::Base])],
+    ) -> std::result::Result<(), Vec<usize>> {
+        let mut batch = BatchVerifier::new();
+        for (proof, instances) in proofs {
+            batch.add_proof(vec![vec![instances.to_vec()]], proof.0.clone());
+        }
+
+        if batch.finalize(&vk.params, &vk.vk) {
+            return Ok(())
+        }
+
+        // The batch only tells us that some proof is invalid, so we have to
+        // go through them separately to find out which.
+        let invalid: Vec<usize> = proofs
+            .iter()
+            .enumerate()
+            .filter(|(_, (proof, instances))| proof.verify(vk, instances).is_err())
+            .map(|(i, _)| i)
+            .collect();
+
+        if invalid.is_empty() {
+            return Ok(())
+        }
+
+        Err(invalid)
+    }
+
     pub fn new(bytes: Vec<u8>) -> Self {
         Proof(bytes)
     }
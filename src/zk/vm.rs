@@ -44,7 +44,7 @@ use halo2_proofs::{
     circuit::{floor_planner, AssignedCell, Layouter, Value},
     pasta::{group::Curve, pallas, Fp},
     plonk,
-    plonk::{Advice, Circuit, Column, ConstraintSystem, Instance as InstanceColumn},
+    plonk::{Advice, Circuit, Column, ConstraintSystem, Fixed, Instance as InstanceColumn},
 };
 use log::{error, trace};
 
@@ -53,12 +53,17 @@ use super::{
     assign_free_advice,
     gadget::{
         arithmetic::{ArithChip, ArithConfig, ArithInstruction},
+        boolean::{BooleanChip, BooleanConfig, BooleanInstruction},
+        cond_select::{CondSelectChip, CondSelectConfig},
         less_than::{LessThanChip, LessThanConfig},
         native_range_check::{NativeRangeCheckChip, NativeRangeCheckConfig},
         small_range_check::{SmallRangeCheckChip, SmallRangeCheckConfig},
+        zero_cond::{ZeroCondChip, ZeroCondConfig},
     },
+    vm_debug::DebugLayouter,
 };
 use crate::zkas::{
+    decoder::DebugInfo,
     types::{LitType, StackType},
     Opcode, ZkBinary,
 };
@@ -67,6 +72,8 @@ use crate::zkas::{
 pub struct VmConfig {
     primary: Column<InstanceColumn>,
     advices: [Column<Advice>; 10],
+    /// Fixed column used for loading global constants
+    pub(super) constants: Column<Fixed>,
     ecc_config: EccConfig<OrchardFixedBases>,
     merkle_cfg1: MerkleConfig<OrchardHashDomains, OrchardCommitDomains, OrchardFixedBases>,
     merkle_cfg2: MerkleConfig<OrchardHashDomains, OrchardCommitDomains, OrchardFixedBases>,
@@ -79,6 +86,9 @@ pub struct VmConfig {
     native_253_range_check_config: NativeRangeCheckConfig<3, 253, 85>,
     lessthan_config: LessThanConfig<3, 253, 85>,
     boolcheck_config: SmallRangeCheckConfig,
+    condselect_config: CondSelectConfig,
+    zerocond_config: ZeroCondConfig,
+    boolean_config: BooleanConfig,
 }
 
 impl VmConfig {
@@ -105,6 +115,18 @@ impl VmConfig {
     fn arithmetic_chip(&self) -> ArithChip {
         ArithChip::construct(self.arith_config.clone())
     }
+
+    fn condselect_chip(&self) -> CondSelectChip {
+        CondSelectChip::construct(self.condselect_config.clone())
+    }
+
+    fn zerocond_chip(&self) -> ZeroCondChip {
+        ZeroCondChip::construct(self.zerocond_config.clone())
+    }
+
+    fn boolean_chip(&self) -> BooleanChip {
+        BooleanChip::construct(self.boolean_config.clone())
+    }
 }
 
 pub struct ZkCircuit {
@@ -112,6 +134,7 @@ pub struct ZkCircuit {
     witnesses: Vec<Witness>,
     literals: Vec<(LitType, String)>,
     opcodes: Vec<(Opcode, Vec<(StackType, usize)>)>,
+    debug_info: Option<DebugInfo>,
 }
 
 impl ZkCircuit {
@@ -119,7 +142,19 @@ impl ZkCircuit {
         let constants = circuit_code.constants.iter().map(|x| x.1.clone()).collect();
         #[allow(clippy::map_clone)]
         let literals = circuit_code.literals.iter().map(|x| x.clone()).collect();
-        Self { constants, witnesses, literals, opcodes: circuit_code.opcodes }
+        Self {
+            constants,
+            witnesses,
+            literals,
+            opcodes: circuit_code.opcodes,
+            debug_info: circuit_code.debug_info,
+        }
+    }
+
+    /// Return the source location of the opcode at the given index, if the
+    /// zkas binary was compiled with debug info.
+    fn opcode_location(&self, idx: usize) -> Option<String> {
+        self.debug_info.as_ref().and_then(|d| d.opcode_location(idx))
     }
 }
 
@@ -133,6 +168,7 @@ impl Circuit<pallas::Base> for ZkCircuit {
             witnesses: self.witnesses.clone(),
             literals: self.literals.clone(),
             opcodes: self.opcodes.clone(),
+            debug_info: self.debug_info.clone(),
         }
     }
 
@@ -183,7 +219,8 @@ impl Circuit<pallas::Base> for ZkCircuit {
         let rc_b = lagrange_coeffs[5..8].try_into().unwrap();
 
         // Also use the first Lagrange coefficient column for loading global constants.
-        meta.enable_constant(lagrange_coeffs[0]);
+        let constants = lagrange_coeffs[0];
+        meta.enable_constant(constants);
 
         // Use one of the right-most advice columns for all of our range checks.
         let range_check = LookupRangeCheckConfig::configure(meta, advices[9], table_idx);
@@ -263,9 +300,22 @@ impl Circuit<pallas::Base> for ZkCircuit {
         // chip with a range of 2, which enforces one bit, i.e. 0 or 1.
         let boolcheck_config = SmallRangeCheckChip::configure(meta, advices[9], 2);
 
+        // Configuration for conditional selection
+        let condselect_config =
+            CondSelectChip::configure(meta, advices[1], advices[2], advices[3], advices[4]);
+
+        // Configuration for zero-conditional selection, which uses the
+        // is_zero gadget and therefore needs an extra column for the inverse.
+        let zerocond_config =
+            ZeroCondChip::configure(meta, advices[1], advices[2], advices[3], advices[4]);
+
+        // Configuration for boolean logic
+        let boolean_config = BooleanChip::configure(meta, advices[1], advices[2], advices[3]);
+
         VmConfig {
             primary,
             advices,
+            constants,
             ecc_config,
             merkle_cfg1,
             merkle_cfg2,
@@ -277,6 +327,9 @@ impl Circuit<pallas::Base> for ZkCircuit {
             native_253_range_check_config,
             lessthan_config,
             boolcheck_config,
+            condselect_config,
+            zerocond_config,
+            boolean_config,
         }
     }
 
@@ -287,6 +340,9 @@ impl Circuit<pallas::Base> for ZkCircuit {
     ) -> std::result::Result<(), plonk::Error> {
         trace!(target: "zk::vm", "Entering synthesize()");
 
+        // Annotate regions with source locations if we have debug info.
+        let mut layouter = DebugLayouter::new(layouter);
+
         // ===================
         // VM Setup
         //====================
@@ -335,6 +391,13 @@ impl Circuit<pallas::Base> for ZkCircuit {
         // Construct the boolean check chip.
         let boolcheck_chip = SmallRangeCheckChip::construct(config.boolcheck_config.clone());
 
+        // Construct the conditional selection chips.
+        let condselect_chip = config.condselect_chip();
+        let zerocond_chip = config.zerocond_chip();
+
+        // Construct the boolean logic chip.
+        let boolean_chip = config.boolean_chip();
+
         // ==========================
         // Constants setup
         // ==========================
@@ -475,7 +538,13 @@ impl Circuit<pallas::Base> for ZkCircuit {
         // And now, work through opcodes
         // =============================
         // TODO: Copy constraints
-        for opcode in &self.opcodes {
+        for (idx, opcode) in self.opcodes.iter().enumerate() {
+            let location = self.opcode_location(idx);
+            if let Some(location) = &location {
+                trace!(target: "zk::vm", "{}", location);
+            }
+            layouter.set_location(location);
+
             match opcode.0 {
                 Opcode::EcAdd => {
                     trace!(target: "zk::vm", "Executing `EcAdd{:?}` opcode", opcode.1);
@@ -798,6 +867,80 @@ impl Circuit<pallas::Base> for ZkCircuit {
                         .small_range_check(layouter.namespace(|| "copy boolean check"), w)?;
                 }
 
+                Opcode::CondSelect => {
+                    trace!(target: "zk::vm", "Executing `CondSelect{:?}` opcode", opcode.1);
+                    let args = &opcode.1;
+
+                    let cond = &stack[args[0].1].clone().into();
+                    let a = &stack[args[1].1].clone().into();
+                    let b = &stack[args[2].1].clone().into();
+
+                    let out = condselect_chip.conditional_select(
+                        layouter.namespace(|| "CondSelect()"),
+                        cond,
+                        a,
+                        b,
+                    )?;
+
+                    trace!(target: "zk::vm", "Pushing assignment to stack index {}", stack.len());
+                    stack.push(StackVar::Base(out));
+                }
+
+                Opcode::ZeroCondSelect => {
+                    trace!(target: "zk::vm", "Executing `ZeroCondSelect{:?}` opcode", opcode.1);
+                    let args = &opcode.1;
+
+                    let a = &stack[args[0].1].clone().into();
+                    let b = &stack[args[1].1].clone().into();
+
+                    let out = zerocond_chip.zero_cond_select(
+                        layouter.namespace(|| "ZeroCond()"),
+                        a,
+                        b,
+                    )?;
+
+                    trace!(target: "zk::vm", "Pushing assignment to stack index {}", stack.len());
+                    stack.push(StackVar::Base(out));
+                }
+
+                Opcode::And => {
+                    trace!(target: "zk::vm", "Executing `And{:?}` opcode", opcode.1);
+                    let args = &opcode.1;
+
+                    let a = &stack[args[0].1].clone().into();
+                    let b = &stack[args[1].1].clone().into();
+
+                    let out = boolean_chip.and(layouter.namespace(|| "And()"), a, b)?;
+
+                    trace!(target: "zk::vm", "Pushing assignment to stack index {}", stack.len());
+                    stack.push(StackVar::Base(out));
+                }
+
+                Opcode::Or => {
+                    trace!(target: "zk::vm", "Executing `Or{:?}` opcode", opcode.1);
+                    let args = &opcode.1;
+
+                    let a = &stack[args[0].1].clone().into();
+                    let b = &stack[args[1].1].clone().into();
+
+                    let out = boolean_chip.or(layouter.namespace(|| "Or()"), a, b)?;
+
+                    trace!(target: "zk::vm", "Pushing assignment to stack index {}", stack.len());
+                    stack.push(StackVar::Base(out));
+                }
+
+                Opcode::Not => {
+                    trace!(target: "zk::vm", "Executing `Not{:?}` opcode", opcode.1);
+                    let args = &opcode.1;
+
+                    let a = &stack[args[0].1].clone().into();
+
+                    let out = boolean_chip.not(layouter.namespace(|| "Not()"), a)?;
+
+                    trace!(target: "zk::vm", "Pushing assignment to stack index {}", stack.len());
+                    stack.push(StackVar::Base(out));
+                }
+
                 Opcode::ConstrainEqualBase => {
                     trace!(target: "zk::vm", "Executing `ConstrainEqualBase{:?}` opcode", opcode.1);
                     let args = &opcode.1;
@@ -849,6 +992,8 @@ impl Circuit<pallas::Base> for ZkCircuit {
             }
         }
 
+        layouter.set_location(None);
+
         trace!(target: "zk::vm", "Exiting synthesize() successfully");
         Ok(())
     }
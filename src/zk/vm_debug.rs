@@ -0,0 +1,116 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! VM debugging helpers
+use halo2_proofs::{
+    arithmetic::Field,
+    circuit::{Cell, Layouter, Region, Table},
+    plonk::{Column, Error, Instance},
+};
+use log::error;
+
+/// A [`Layouter`] wrapper that appends the zkas source location of the
+/// opcode currently being executed to the name of every region assigned
+/// through it. `MockProver` failures then point to e.g.
+/// `line 42: constrain_equal_base(x, y)` rather than a bare region index,
+/// and synthesis errors get logged with the same location.
+pub struct DebugLayouter<L> {
+    inner: L,
+    location: Option<String>,
+}
+
+impl<L> DebugLayouter<L> {
+    pub fn new(inner: L) -> Self {
+        Self { inner, location: None }
+    }
+
+    /// Set the source location used to annotate the following regions.
+    /// `None` leaves region names untouched.
+    pub fn set_location(&mut self, location: Option<String>) {
+        self.location = location;
+    }
+}
+
+impl<F: Field, L: Layouter<F>> Layouter<F> for DebugLayouter<L> {
+    type Root = Self;
+
+    fn assign_region<A, AR, N, NR>(&mut self, name: N, assignment: A) -> Result<AR, Error>
+    where
+        A: FnMut(Region<'_, F>) -> Result<AR, Error>,
+        N: Fn() -> NR,
+        NR: Into<String>,
+    {
+        let location = match &self.location {
+            Some(v) => v.clone(),
+            None => return self.inner.assign_region(name, assignment),
+        };
+
+        let annotated_name = || {
+            let name: String = name().into();
+            format!("{} ({})", name, location)
+        };
+
+        let ret = self.inner.assign_region(annotated_name, assignment);
+
+        if let Err(e) = &ret {
+            error!(target: "zk::vm", "Failed synthesizing {}: {:?}", location, e);
+        }
+
+        ret
+    }
+
+    fn assign_table<A, N, NR>(&mut self, name: N, assignment: A) -> Result<(), Error>
+    where
+        A: FnMut(Table<'_, F>) -> Result<(), Error>,
+        N: Fn() -> NR,
+        NR: Into<String>,
+    {
+        self.inner.assign_table(name, assignment)
+    }
+
+    fn constrain_instance(
+        &mut self,
+        cell: Cell,
+        column: Column<Instance>,
+        row: usize,
+    ) -> Result<(), Error> {
+        let ret = self.inner.constrain_instance(cell, column, row);
+
+        if let (Err(e), Some(location)) = (&ret, &self.location) {
+            error!(target: "zk::vm", "Failed synthesizing {}: {:?}", location, e);
+        }
+
+        ret
+    }
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+
+    fn push_namespace<NR, N>(&mut self, name_fn: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+        self.inner.get_root().push_namespace(name_fn)
+    }
+
+    fn pop_namespace(&mut self, gadget_name: Option<String>) {
+        self.inner.get_root().pop_namespace(gadget_name)
+    }
+}
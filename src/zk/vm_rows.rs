@@ -0,0 +1,173 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::cmp::max;
+
+use halo2_proofs::{
+    arithmetic::Field,
+    circuit::{floor_planner, Value},
+    plonk,
+    plonk::{
+        Advice, Any, Assigned, Assignment, Circuit, Column, ConstraintSystem, Fixed, FloorPlanner,
+        Instance, Selector,
+    },
+};
+
+use super::{empty_witnesses, ZkCircuit};
+use crate::{zkas::ZkBinary, Result};
+
+/// Return the smallest `k` the given circuit fits in, accounting for
+/// the rows used by its regions, lookup tables and constants, and the
+/// rows halo2 reserves for blinding factors.
+///
+/// The circuit is laid out the same way as in keygen, but without
+/// doing any of the actual work, so this is cheap to run.
+pub fn minimum_k(zkbin: &ZkBinary) -> Result<u32> {
+    let circuit = ZkCircuit::new(empty_witnesses(zkbin), zkbin.clone());
+
+    let mut cs = ConstraintSystem::default();
+    let config = ZkCircuit::configure(&mut cs);
+    let constants = vec![config.constants];
+
+    let mut counter = RowCounter::default();
+    floor_planner::V1::synthesize(&mut counter, &circuit, config, constants)?;
+
+    // The last `blinding_factors() + 1` rows are not usable by the circuit.
+    let rows = max(counter.rows + cs.blinding_factors() + 1, cs.minimum_rows());
+    Ok(rows.next_power_of_two().trailing_zeros())
+}
+
+/// Return the `k` recorded in the zkas binary, or compute it if the
+/// binary was compiled without one.
+pub fn circuit_k(zkbin: &ZkBinary) -> Result<u32> {
+    match zkbin.k {
+        0 => minimum_k(zkbin),
+        k => Ok(k),
+    }
+}
+
+/// An [`Assignment`] that only keeps track of the rows being used
+#[derive(Default)]
+struct RowCounter {
+    rows: usize,
+}
+
+impl RowCounter {
+    fn use_row(&mut self, row: usize) {
+        self.rows = max(self.rows, row + 1);
+    }
+}
+
+impl<F: Field> Assignment<F> for RowCounter {
+    fn enter_region<NR, N>(&mut self, _: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn exit_region(&mut self) {}
+
+    fn enable_selector<A, AR>(
+        &mut self,
+        _: A,
+        _: &Selector,
+        row: usize,
+    ) -> std::result::Result<(), plonk::Error>
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.use_row(row);
+        Ok(())
+    }
+
+    fn query_instance(
+        &self,
+        _: Column<Instance>,
+        _: usize,
+    ) -> std::result::Result<Value<F>, plonk::Error> {
+        // Instance rows are counted when they get copied into the circuit
+        Ok(Value::unknown())
+    }
+
+    fn assign_advice<V, VR, A, AR>(
+        &mut self,
+        _: A,
+        _: Column<Advice>,
+        row: usize,
+        _: V,
+    ) -> std::result::Result<(), plonk::Error>
+    where
+        V: FnOnce() -> Value<VR>,
+        VR: Into<Assigned<F>>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.use_row(row);
+        Ok(())
+    }
+
+    fn assign_fixed<V, VR, A, AR>(
+        &mut self,
+        _: A,
+        _: Column<Fixed>,
+        row: usize,
+        _: V,
+    ) -> std::result::Result<(), plonk::Error>
+    where
+        V: FnOnce() -> Value<VR>,
+        VR: Into<Assigned<F>>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.use_row(row);
+        Ok(())
+    }
+
+    fn copy(
+        &mut self,
+        _: Column<Any>,
+        left_row: usize,
+        _: Column<Any>,
+        right_row: usize,
+    ) -> std::result::Result<(), plonk::Error> {
+        self.use_row(left_row);
+        self.use_row(right_row);
+        Ok(())
+    }
+
+    fn fill_from_row(
+        &mut self,
+        _: Column<Fixed>,
+        row: usize,
+        _: Value<Assigned<F>>,
+    ) -> std::result::Result<(), plonk::Error> {
+        self.use_row(row);
+        Ok(())
+    }
+
+    fn push_namespace<NR, N>(&mut self, _: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn pop_namespace(&mut self, _: Option<String>) {}
+}
@@ -63,6 +63,27 @@ impl Analyzer {
             //println!("{:?}", statement);
             let mut stmt = statement.clone();
 
+            // Only circuit variables can be reassigned, witnesses and
+            // constants always refer to themselves.
+            if statement.typ == StatementType::Assign {
+                let lhs = statement.lhs.as_ref().unwrap();
+                if self.lookup_constant(&lhs.name).is_some() {
+                    self.error.abort(
+                        &format!("Cannot assign to `{}`, which is a constant.", lhs.name),
+                        lhs.line,
+                        lhs.column,
+                    );
+                }
+
+                if self.lookup_witness(&lhs.name).is_some() {
+                    self.error.abort(
+                        &format!("Cannot assign to `{}`, which is a witness.", lhs.name),
+                        lhs.line,
+                        lhs.column,
+                    );
+                }
+            }
+
             let (return_types, arg_types) = statement.opcode.arg_types();
             let mut rhs = vec![];
 
@@ -195,11 +216,18 @@ impl Analyzer {
                                     Var::Variable(c) => (c.typ, c.line, c.column),
                                 };
 
-                                if var_type != f_arg_types[inner_idx] {
+                                // Array arguments take any number of elements.
+                                let expected = match f_arg_types[0] {
+                                    VarType::BaseArray => VarType::Base,
+                                    VarType::ScalarArray => VarType::Scalar,
+                                    _ => f_arg_types[inner_idx],
+                                };
+
+                                if var_type != expected {
                                     self.error.abort(
                                         &format!(
                                             "Incorrect argument type. Expected `{:?}`, got `{:?}`.",
-                                            f_arg_types[inner_idx], var_type
+                                            expected, var_type
                                         ),
                                         ln,
                                         col,
@@ -230,6 +258,7 @@ impl Analyzer {
                         lhs: Some(v.clone()),
                         rhs: rhs_inner,
                         line: func.line,
+                        column: func.column,
                     };
 
                     // The lhs of the inner function call becomes rhs of the outer one.
@@ -369,6 +398,8 @@ impl Analyzer {
             return Some(Var::Witness(r))
         }
 
+        // Circuit variables can be reassigned (e.g. in unrolled loops),
+        // so the last assignment shadows the previous ones.
         if let Some(r) = self.lookup_stack(name) {
             return Some(Var::Variable(r))
         }
@@ -397,7 +428,7 @@ impl Analyzer {
     }
 
     fn lookup_stack(&self, name: &str) -> Option<Variable> {
-        for i in &self.stack {
+        for i in self.stack.iter().rev() {
             if i.name == name {
                 return Some(i.clone())
             }
@@ -442,7 +473,7 @@ impl Analyzer {
             for arg in &i.rhs {
                 if let Arg::Var(arg) = arg {
                     print!("Looking up `{}` on the stack... ", arg.name);
-                    if let Some(index) = stack.iter().position(|&r| r == &arg.name) {
+                    if let Some(index) = stack.iter().rposition(|&r| r == &arg.name) {
                         println!("Found at stack index {}", index);
                     } else {
                         self.error.abort(
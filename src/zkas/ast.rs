@@ -79,10 +79,18 @@ pub struct Statement {
     pub lhs: Option<Variable>,
     pub rhs: Vec<Arg>,
     pub line: usize,
+    pub column: usize,
 }
 
 impl Default for Statement {
     fn default() -> Self {
-        Self { typ: StatementType::Noop, opcode: Opcode::Noop, lhs: None, rhs: vec![], line: 0 }
+        Self {
+            typ: StatementType::Noop,
+            opcode: Opcode::Noop,
+            lhs: None,
+            rhs: vec![],
+            line: 0,
+            column: 0,
+        }
     }
 }
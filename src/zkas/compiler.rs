@@ -27,7 +27,7 @@ use super::{
 };
 
 /// Version of the binary
-pub const BINARY_VERSION: u8 = 2;
+pub const BINARY_VERSION: u8 = 3;
 /// Magic bytes prepended to the binary
 pub const MAGIC_BYTES: [u8; 4] = [0x0b, 0x01, 0xb1, 0x35];
 
@@ -68,6 +68,11 @@ impl Compiler {
         bincode.extend_from_slice(&MAGIC_BYTES);
         bincode.push(BINARY_VERSION);
 
+        // Reserve space for the circuit's `k`. The compiler doesn't know
+        // anything about the proof system, so this is left as 0 and is
+        // filled in afterwards with `ZkBinary::set_k()`.
+        bincode.extend_from_slice(&0u32.to_le_bytes());
+
         // Write the circuit's namespace
         bincode.extend_from_slice(&serialize(&self.namespace));
 
@@ -100,23 +105,18 @@ impl Compiler {
             bincode.push(i.typ as u8);
         }
 
+        // Constants and witnesses can't be reassigned, only what comes after them
+        let fixed = tmp_stack.len();
+
         bincode.extend_from_slice(b".circuit");
         for i in &self.statements {
-            match i.typ {
-                StatementType::Assign => tmp_stack.push(&i.lhs.as_ref().unwrap().name),
-                // In case of a simple call, we don't append anything to the stack
-                StatementType::Call => {}
-                // TODO: FIXME: unreachable is reached with missing semicolons in the code
-                _ => unreachable!(),
-            }
-
             bincode.push(i.opcode as u8);
             bincode.extend_from_slice(&serialize(&VarInt(i.rhs.len() as u64)));
 
             for arg in &i.rhs {
                 match arg {
                     Arg::Var(arg) => {
-                        if let Some(found) = Compiler::lookup_stack(&tmp_stack, &arg.name) {
+                        if let Some(found) = Compiler::lookup_stack(&tmp_stack, fixed, &arg.name) {
                             bincode.push(StackType::Var as u8);
                             bincode.extend_from_slice(&serialize(&VarInt(found as u64)));
                             continue
@@ -144,6 +144,16 @@ impl Compiler {
                     _ => unreachable!(),
                 };
             }
+
+            // The result is pushed after the arguments are looked up, so
+            // a reassigned variable can refer to its previous value.
+            match i.typ {
+                StatementType::Assign => tmp_stack.push(&i.lhs.as_ref().unwrap().name),
+                // In case of a simple call, we don't append anything to the stack
+                StatementType::Call => {}
+                // TODO: FIXME: unreachable is reached with missing semicolons in the code
+                _ => unreachable!(),
+            }
         }
 
         // If we're not doing debug info, we're done here and can return.
@@ -151,13 +161,74 @@ impl Compiler {
             return bincode
         }
 
-        // TODO: Otherwise, we proceed appending debug info.
+        // Otherwise, we proceed appending debug info. In the .debug section
+        // we first write the source location of every opcode along with a
+        // reconstruction of its statement, so the VM can report failures
+        // in terms of the source code. Then we write the name and source
+        // location of every stack slot, in the same order as the stack is
+        // built in the VM.
+        bincode.extend_from_slice(b".debug");
+        bincode.extend_from_slice(&serialize(&VarInt(self.statements.len() as u64)));
+        for i in &self.statements {
+            bincode.extend_from_slice(&serialize(&VarInt(i.line as u64)));
+            bincode.extend_from_slice(&serialize(&VarInt(i.column as u64)));
+            bincode.extend_from_slice(&serialize(&Compiler::statement_source(i)));
+        }
+
+        let mut stack_info = vec![];
+        for i in &self.constants {
+            stack_info.push((&i.name, i.line, i.column));
+        }
+        for i in &self.witnesses {
+            stack_info.push((&i.name, i.line, i.column));
+        }
+        for i in &self.statements {
+            if let Some(lhs) = &i.lhs {
+                stack_info.push((&lhs.name, lhs.line, lhs.column));
+            }
+        }
+
+        bincode.extend_from_slice(&serialize(&VarInt(stack_info.len() as u64)));
+        for (name, line, column) in stack_info {
+            bincode.extend_from_slice(&serialize(name));
+            bincode.extend_from_slice(&serialize(&VarInt(line as u64)));
+            bincode.extend_from_slice(&serialize(&VarInt(column as u64)));
+        }
 
         bincode
     }
 
-    fn lookup_stack(stack: &[&str], name: &str) -> Option<usize> {
-        for (idx, n) in stack.iter().enumerate() {
+    /// Reconstruct the source of a statement, e.g. `c = base_add(a, b)`.
+    /// Nested function calls are shown through their intermediate
+    /// stack variable.
+    fn statement_source(statement: &Statement) -> String {
+        let args: Vec<&str> = statement
+            .rhs
+            .iter()
+            .map(|arg| match arg {
+                Arg::Var(v) => v.name.as_str(),
+                Arg::Lit(l) => l.name.as_str(),
+                _ => unreachable!(),
+            })
+            .collect();
+
+        let call = format!("{}({})", statement.opcode.name(), args.join(", "));
+
+        match &statement.lhs {
+            Some(lhs) => format!("{} = {}", lhs.name, call),
+            None => call,
+        }
+    }
+
+    /// Find `name` on the stack. The first `fixed` entries are the constants
+    /// and witnesses, which take precedence. The circuit variables after them
+    /// are searched backwards, so the last assignment shadows previous ones.
+    fn lookup_stack(stack: &[&str], fixed: usize, name: &str) -> Option<usize> {
+        if let Some(idx) = stack[..fixed].iter().position(|n| n == &name) {
+            return Some(idx)
+        }
+
+        for (idx, n) in stack.iter().enumerate().skip(fixed).rev() {
             if n == &name {
                 return Some(idx)
             }
@@ -18,7 +18,11 @@
 
 use darkfi_serial::{deserialize_partial, VarInt};
 
-use super::{compiler::MAGIC_BYTES, types::StackType, LitType, Opcode, VarType};
+use super::{
+    compiler::{BINARY_VERSION, MAGIC_BYTES},
+    types::StackType,
+    LitType, Opcode, VarType,
+};
 use crate::{Error::ZkasDecoderError as ZkasErr, Result};
 
 /// A ZkBinary decoded from compiled zkas code.
@@ -26,10 +30,52 @@ use crate::{Error::ZkasDecoderError as ZkasErr, Result};
 #[derive(Clone, Debug)]
 pub struct ZkBinary {
     pub namespace: String,
+    /// The smallest `k` the circuit fits in, or 0 if it wasn't computed
+    pub k: u32,
     pub constants: Vec<(VarType, String)>,
     pub literals: Vec<(LitType, String)>,
     pub witnesses: Vec<VarType>,
     pub opcodes: Vec<(Opcode, Vec<(StackType, usize)>)>,
+    pub debug_info: Option<DebugInfo>,
+}
+
+/// Source location of a single opcode in a zkas binary
+#[derive(Clone, Debug)]
+pub struct DebugOpcode {
+    pub line: usize,
+    pub column: usize,
+    /// Reconstructed source of the statement, e.g. `c = base_add(a, b)`
+    pub source: String,
+}
+
+/// Source location of a single stack slot in a zkas binary
+#[derive(Clone, Debug)]
+pub struct DebugVariable {
+    pub name: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Debug info decoded from the optional `.debug` section of a zkas binary
+#[derive(Clone, Debug, Default)]
+pub struct DebugInfo {
+    /// Source locations of the opcodes, in order of execution
+    pub opcodes: Vec<DebugOpcode>,
+    /// Source locations of the stack slots, in order of appearance
+    pub stack: Vec<DebugVariable>,
+}
+
+impl DebugInfo {
+    /// Return a human-readable source location of the opcode at the given
+    /// index, e.g. `line 42: constrain_equal_base(x, y)`
+    pub fn opcode_location(&self, idx: usize) -> Option<String> {
+        self.opcodes.get(idx).map(|op| format!("line {}: {}", op.line, op.source))
+    }
+
+    /// Return the variable name of the stack slot at the given index
+    pub fn stack_name(&self, idx: usize) -> Option<&str> {
+        self.stack.get(idx).map(|var| var.name.as_str())
+    }
 }
 
 // https://stackoverflow.com/questions/35901547/how-can-i-find-a-subsequence-in-a-u8-slice
@@ -37,17 +83,30 @@ fn find_subslice(haystack: &[u8], needle: &[u8]) -> Option<usize> {
     haystack.windows(needle.len()).position(|window| window == needle)
 }
 
+/// Offset of the circuit's `k` in the binary header
+const K_OFFSET: usize = MAGIC_BYTES.len() + 1;
+
 impl ZkBinary {
     pub fn decode(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < K_OFFSET + 4 {
+            return Err(ZkasErr("Binary is too short.".to_string()))
+        }
+
         let magic_bytes = &bytes[0..4];
         if magic_bytes != MAGIC_BYTES {
             return Err(ZkasErr("Magic bytes are incorrect.".to_string()))
         }
 
-        let _binary_version = &bytes[4];
+        let binary_version = bytes[4];
+        if binary_version != BINARY_VERSION {
+            return Err(ZkasErr(format!("Unsupported binary version {}.", binary_version)))
+        }
+
+        // After the binary version, we have the circuit's `k`
+        let k = u32::from_le_bytes(bytes[K_OFFSET..K_OFFSET + 4].try_into().unwrap());
 
-        // After the binary version, we're supposed to have the contract namespace
-        let (namespace, _) = deserialize_partial(&bytes[5..])?;
+        // And after that, we're supposed to have the contract namespace
+        let (namespace, _) = deserialize_partial(&bytes[K_OFFSET + 4..])?;
 
         let constants_offset = match find_subslice(bytes, b".constant") {
             Some(v) => v,
@@ -100,9 +159,31 @@ impl ZkBinary {
         let witnesses = ZkBinary::parse_contract(contract_section)?;
         let opcodes = ZkBinary::parse_circuit(circuit_section)?;
 
-        // TODO: Debug info
+        let debug_info = if debug_offset < bytes.len() {
+            let debug_section = &bytes[debug_offset + b".debug".len()..];
+            let debug_info = ZkBinary::parse_debug(debug_section)?;
 
-        Ok(Self { namespace, constants, literals, witnesses, opcodes })
+            if debug_info.opcodes.len() != opcodes.len() {
+                return Err(ZkasErr(".debug section does not match .circuit opcodes".to_string()))
+            }
+
+            Some(debug_info)
+        } else {
+            None
+        };
+
+        Ok(Self { namespace, k, constants, literals, witnesses, opcodes, debug_info })
+    }
+
+    /// Write the circuit's `k` into the header of the given zkas bincode.
+    /// The compiler leaves it empty, since computing it requires the zkvm.
+    pub fn set_k(bytes: &mut [u8], k: u32) -> Result<()> {
+        if bytes.len() < K_OFFSET + 4 || bytes[0..4] != MAGIC_BYTES {
+            return Err(ZkasErr("Not a zkas binary.".to_string()))
+        }
+
+        bytes[K_OFFSET..K_OFFSET + 4].copy_from_slice(&k.to_le_bytes());
+        Ok(())
     }
 
     fn parse_constants(bytes: &[u8]) -> Result<Vec<(VarType, String)>> {
@@ -219,4 +300,51 @@ impl ZkBinary {
 
         Ok(opcodes)
     }
+
+    fn parse_debug(bytes: &[u8]) -> Result<DebugInfo> {
+        let mut debug_info = DebugInfo::default();
+
+        let mut iter_offset = 0;
+        let (opcodes_num, offset) = deserialize_partial::<VarInt>(&bytes[iter_offset..])?;
+        iter_offset += offset;
+
+        for _ in 0..opcodes_num.0 {
+            let (line, offset) = deserialize_partial::<VarInt>(&bytes[iter_offset..])?;
+            iter_offset += offset;
+            let (column, offset) = deserialize_partial::<VarInt>(&bytes[iter_offset..])?;
+            iter_offset += offset;
+            let (source, offset) = deserialize_partial::<String>(&bytes[iter_offset..])?;
+            iter_offset += offset;
+
+            debug_info.opcodes.push(DebugOpcode {
+                line: line.0 as usize,
+                column: column.0 as usize,
+                source,
+            });
+        }
+
+        let (stack_num, offset) = deserialize_partial::<VarInt>(&bytes[iter_offset..])?;
+        iter_offset += offset;
+
+        for _ in 0..stack_num.0 {
+            let (name, offset) = deserialize_partial::<String>(&bytes[iter_offset..])?;
+            iter_offset += offset;
+            let (line, offset) = deserialize_partial::<VarInt>(&bytes[iter_offset..])?;
+            iter_offset += offset;
+            let (column, offset) = deserialize_partial::<VarInt>(&bytes[iter_offset..])?;
+            iter_offset += offset;
+
+            debug_info.stack.push(DebugVariable {
+                name,
+                line: line.0 as usize,
+                column: column.0 as usize,
+            });
+        }
+
+        if iter_offset != bytes.len() {
+            return Err(ZkasErr("Trailing bytes found in .debug section".to_string()))
+        }
+
+        Ok(debug_info)
+    }
 }
@@ -20,7 +20,7 @@ use std::str::Chars;
 
 use super::error::ErrorEmitter;
 
-const SPECIAL_CHARS: [char; 7] = ['{', '}', '(', ')', ',', ';', '='];
+const SPECIAL_CHARS: [char; 10] = ['{', '}', '(', ')', '[', ']', ',', ';', '=', '.'];
 
 fn is_letter(ch: char) -> bool {
     ('a'..='z').contains(&ch) || ('A'..='Z').contains(&ch) || ch == '_'
@@ -39,9 +39,12 @@ pub enum TokenType {
     RightBrace,
     LeftParen,
     RightParen,
+    LeftBracket,
+    RightBracket,
     Comma,
     Semicolon,
     Assign,
+    Dot,
 }
 
 #[derive(Clone, Debug)]
@@ -240,6 +243,14 @@ impl<'a> Lexer<'a> {
                         tokens.push(Token::new(")", TokenType::RightParen, lineno, column));
                         continue
                     }
+                    '[' => {
+                        tokens.push(Token::new("[", TokenType::LeftBracket, lineno, column));
+                        continue
+                    }
+                    ']' => {
+                        tokens.push(Token::new("]", TokenType::RightBracket, lineno, column));
+                        continue
+                    }
                     ',' => {
                         tokens.push(Token::new(",", TokenType::Comma, lineno, column));
                         continue
@@ -252,6 +263,10 @@ impl<'a> Lexer<'a> {
                         tokens.push(Token::new("=", TokenType::Assign, lineno, column));
                         continue
                     }
+                    '.' => {
+                        tokens.push(Token::new(".", TokenType::Dot, lineno, column));
+                        continue
+                    }
                     _ => self.error.abort(&format!("Invalid token `{}`", c), lineno, column - 1),
                 }
                 continue
@@ -78,6 +78,21 @@ pub enum Opcode {
     /// Check if a field element fits in a boolean (Either 0 or 1)
     BoolCheck = 0x53,
 
+    /// Conditionally select between two Base field elements given a boolean
+    CondSelect = 0x58,
+
+    /// Output the first Base field element if it is zero, otherwise the second
+    ZeroCondSelect = 0x59,
+
+    /// Boolean AND of two Base field elements (Either 0 or 1)
+    And = 0x5a,
+
+    /// Boolean OR of two Base field elements (Either 0 or 1)
+    Or = 0x5b,
+
+    /// Boolean NOT of a Base field element (Either 0 or 1)
+    Not = 0x5c,
+
     /// Constrain equality of two Base field elements inside the circuit
     ConstrainEqualBase = 0xe0,
 
@@ -111,6 +126,11 @@ impl Opcode {
             "less_than_strict" => Some(Self::LessThanStrict),
             "less_than_loose" => Some(Self::LessThanLoose),
             "bool_check" => Some(Self::BoolCheck),
+            "cond_select" => Some(Self::CondSelect),
+            "zero_cond" => Some(Self::ZeroCondSelect),
+            "and" => Some(Self::And),
+            "or" => Some(Self::Or),
+            "not" => Some(Self::Not),
             "constrain_equal_base" => Some(Self::ConstrainEqualBase),
             "constrain_equal_point" => Some(Self::ConstrainEqualPoint),
             "constrain_instance" => Some(Self::ConstrainInstance),
@@ -119,6 +139,38 @@ impl Opcode {
         }
     }
 
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Noop => "noop",
+            Self::EcAdd => "ec_add",
+            Self::EcMul => "ec_mul",
+            Self::EcMulBase => "ec_mul_base",
+            Self::EcMulShort => "ec_mul_short",
+            Self::EcMulVarBase => "ec_mul_var_base",
+            Self::EcGetX => "ec_get_x",
+            Self::EcGetY => "ec_get_y",
+            Self::PoseidonHash => "poseidon_hash",
+            Self::MerkleRoot => "merkle_root",
+            Self::BaseAdd => "base_add",
+            Self::BaseMul => "base_mul",
+            Self::BaseSub => "base_sub",
+            Self::WitnessBase => "witness_base",
+            Self::RangeCheck => "range_check",
+            Self::LessThanStrict => "less_than_strict",
+            Self::LessThanLoose => "less_than_loose",
+            Self::BoolCheck => "bool_check",
+            Self::CondSelect => "cond_select",
+            Self::ZeroCondSelect => "zero_cond",
+            Self::And => "and",
+            Self::Or => "or",
+            Self::Not => "not",
+            Self::ConstrainEqualBase => "constrain_equal_base",
+            Self::ConstrainEqualPoint => "constrain_equal_point",
+            Self::ConstrainInstance => "constrain_instance",
+            Self::DebugPrint => "debug",
+        }
+    }
+
     pub fn from_repr(b: u8) -> Option<Self> {
         match b {
             0x01 => Some(Self::EcAdd),
@@ -138,6 +190,11 @@ impl Opcode {
             0x51 => Some(Self::LessThanStrict),
             0x52 => Some(Self::LessThanLoose),
             0x53 => Some(Self::BoolCheck),
+            0x58 => Some(Self::CondSelect),
+            0x59 => Some(Self::ZeroCondSelect),
+            0x5a => Some(Self::And),
+            0x5b => Some(Self::Or),
+            0x5c => Some(Self::Not),
             0xe0 => Some(Self::ConstrainEqualBase),
             0xe1 => Some(Self::ConstrainEqualPoint),
             0xf0 => Some(Self::ConstrainInstance),
@@ -194,6 +251,18 @@ impl Opcode {
 
             Opcode::BoolCheck => (vec![], vec![VarType::Base]),
 
+            Opcode::CondSelect => {
+                (vec![VarType::Base], vec![VarType::Base, VarType::Base, VarType::Base])
+            }
+
+            Opcode::ZeroCondSelect => (vec![VarType::Base], vec![VarType::Base, VarType::Base]),
+
+            Opcode::And => (vec![VarType::Base], vec![VarType::Base, VarType::Base]),
+
+            Opcode::Or => (vec![VarType::Base], vec![VarType::Base, VarType::Base]),
+
+            Opcode::Not => (vec![VarType::Base], vec![VarType::Base]),
+
             Opcode::ConstrainEqualBase => (vec![], vec![VarType::Base, VarType::Base]),
 
             Opcode::ConstrainEqualPoint => (vec![], vec![VarType::EcPoint, VarType::EcPoint]),
@@ -78,6 +78,8 @@ impl Parser {
         // Contains constant and contract sections
         let mut ast_inner = IndexMap::new();
         let mut ast = IndexMap::new();
+        // Witness arrays and the names of their elements
+        let mut witness_arrays = IndexMap::new();
 
         if self.tokens[0].token_type != TokenType::Symbol {
             self.error.abort(
@@ -101,8 +103,11 @@ impl Parser {
                 //
                 // We use this macro to avoid code repetition in the following
                 // match statement for soaking up the section tokens.
+                // Braces can be nested inside a section (e.g. `for` loops),
+                // so we keep track of the depth to find the section's end.
                 macro_rules! absorb_inner_tokens {
                     ($v:ident) => {
+                        let mut depth = 0;
                         for inner in iter.by_ref() {
                             if KEYWORDS.contains(&inner.token.as_str()) &&
                                 inner.token_type == TokenType::Symbol
@@ -115,8 +120,15 @@ impl Parser {
                             }
 
                             $v.push(inner.clone());
-                            if inner.token_type == TokenType::RightBrace {
-                                break
+                            match inner.token_type {
+                                TokenType::LeftBrace => depth += 1,
+                                TokenType::RightBrace => {
+                                    depth -= 1;
+                                    if depth == 0 {
+                                        break
+                                    }
+                                }
+                                _ => {}
                             }
                         }
                     };
@@ -221,14 +233,42 @@ impl Parser {
 
                 let mut witnesses_map = IndexMap::new();
                 // This is everything between the braces: { ... }
-                let mut contract_inner = contract_tokens[2..contract_tokens.len() - 1].iter();
-                while let Some((typ, name, comma)) = contract_inner.next_tuple() {
+                let mut contract_inner =
+                    contract_tokens[2..contract_tokens.len() - 1].iter().peekable();
+                while let Some(typ) = contract_inner.next() {
+                    // Witness arrays are declared as `Type[N] name`, and they
+                    // are expanded into N witnesses `name[0]`, ..., `name[N-1]`.
+                    let mut array_len = None;
+                    if let Some(bracket) =
+                        contract_inner.next_if(|t| t.token_type == TokenType::LeftBracket)
+                    {
+                        array_len = Some(self.parse_array_len(
+                            contract_inner.next(),
+                            contract_inner.next(),
+                            bracket,
+                        ));
+                    }
+
+                    let (name, comma) = match (contract_inner.next(), contract_inner.next()) {
+                        (Some(name), Some(comma)) => (name, comma),
+                        _ => {
+                            self.error.abort(
+                                "Invalid witness declaration. Must be '<Type> <name>' followed by a comma ','.",
+                                typ.line,
+                                typ.column,
+                            );
+                            unreachable!();
+                        }
+                    };
+
                     if comma.token_type != TokenType::Comma {
                         self.error.abort("Separator is not a comma.", comma.line, comma.column);
                     }
 
                     // No variable shadowing
-                    if witnesses_map.contains_key(name.token.as_str()) {
+                    if witnesses_map.contains_key(name.token.as_str()) ||
+                        witness_arrays.contains_key(name.token.as_str())
+                    {
                         self.error.abort(
                             &format!(
                                 "Section `contract` already contains the token `{}`.",
@@ -239,7 +279,26 @@ impl Parser {
                         );
                     }
 
-                    witnesses_map.insert(name.token.clone(), (name.clone(), typ.clone()));
+                    let names = match array_len {
+                        Some(len) => {
+                            let elements: Vec<Token> = (0..len)
+                                .map(|i| Token {
+                                    token: format!("{}[{}]", name.token, i),
+                                    token_type: TokenType::Symbol,
+                                    line: name.line,
+                                    column: name.column,
+                                })
+                                .collect();
+
+                            witness_arrays.insert(name.token.clone(), elements.clone());
+                            elements
+                        }
+                        None => vec![name.clone()],
+                    };
+
+                    for name in names {
+                        witnesses_map.insert(name.token.clone(), (name, typ.clone()));
+                    }
                 }
 
                 if contract_inner.next().is_some() {
@@ -260,22 +319,31 @@ impl Parser {
                 self.check_section_structure("circuit", circuit_tokens.clone());
                 check_namespace!(circuit_tokens);
 
-                // Grab tokens for each statement
-                for i in circuit_tokens[2..circuit_tokens.len() - 1].iter() {
-                    if i.token_type == TokenType::Semicolon {
-                        // Push completed statement to the stack
-                        circuit_stmts.push(circuit_stmt.clone());
-                        circuit_stmt = vec![];
-                        continue
-                    }
-                    circuit_stmt.push(i.clone());
-                }
-
                 declaring_circuit = false;
                 declared_circuit = true;
             }
         }
 
+        if !declared_circuit {
+            self.error.abort("Missing `circuit` section in .zk source.", 0, 0);
+        }
+
+        // Unroll the loops and resolve the arrays used in the circuit,
+        // so we're left only with plain statements.
+        let circuit_inner = self.unroll_loops(&circuit_tokens[2..circuit_tokens.len() - 1]);
+        let circuit_inner = self.resolve_arrays(&circuit_inner, witness_arrays);
+
+        // Grab tokens for each statement
+        for i in circuit_inner {
+            if i.token_type == TokenType::Semicolon {
+                // Push completed statement to the stack
+                circuit_stmts.push(circuit_stmt.clone());
+                circuit_stmt = vec![];
+                continue
+            }
+            circuit_stmt.push(i);
+        }
+
         // Tokens have been processed and ast is complete
 
         let ns = namespace.unwrap();
@@ -311,6 +379,369 @@ impl Parser {
         (ns, constants, witnesses, statements)
     }
 
+    /// Parse the length of a witness array declaration, i.e. the `N]`
+    /// part of `Type[N] name`.
+    fn parse_array_len(&self, len: Option<&Token>, close: Option<&Token>, open: &Token) -> usize {
+        let (len, close) = match (len, close) {
+            (Some(len), Some(close)) => (len, close),
+            _ => {
+                self.error.abort("Premature ending of array declaration.", open.line, open.column);
+                unreachable!();
+            }
+        };
+
+        if close.token_type != TokenType::RightBracket {
+            self.error.abort(
+                "Array declaration must be closed with a right bracket ']'.",
+                close.line,
+                close.column,
+            );
+        }
+
+        if len.token_type != TokenType::Number {
+            self.error.abort("Array length must be a number.", len.line, len.column);
+        }
+
+        match len.token.parse::<usize>() {
+            Ok(0) => {
+                self.error.abort("Array length can't be zero.", len.line, len.column);
+                unreachable!();
+            }
+            Ok(v) => v,
+            Err(e) => {
+                self.error.abort(
+                    &format!("Failed to convert array length into usize: {}", e),
+                    len.line,
+                    len.column,
+                );
+                unreachable!();
+            }
+        }
+    }
+
+    /// Unroll the `for` loops found in the circuit section.
+    /// The syntax is `for i in 0..N { ... }`, where the body gets
+    /// repeated N times, with `i` replaced by the iteration number.
+    fn unroll_loops(&self, tokens: &[Token]) -> Vec<Token> {
+        let mut ret = vec![];
+
+        // Returns the token at the given index if it's of the expected type.
+        let expect = |idx: usize, typ: TokenType, start: &Token| -> Token {
+            match tokens.get(idx) {
+                Some(t) if t.token_type == typ => t.clone(),
+                Some(t) => {
+                    self.error.abort(
+                        "Invalid loop syntax. Must be `for <var> in <start>..<end> { ... }`.",
+                        t.line,
+                        t.column,
+                    );
+                    unreachable!();
+                }
+                None => {
+                    self.error.abort("Premature ending of loop.", start.line, start.column);
+                    unreachable!();
+                }
+            }
+        };
+
+        let mut i = 0;
+        while i < tokens.len() {
+            let token = &tokens[i];
+
+            match token.token_type {
+                TokenType::Symbol if token.token == "for" => {}
+                TokenType::LeftBrace | TokenType::RightBrace | TokenType::Dot => {
+                    self.error.abort(
+                        &format!("Illegal token `{}`.", token.token),
+                        token.line,
+                        token.column,
+                    );
+                }
+                _ => {
+                    ret.push(token.clone());
+                    i += 1;
+                    continue
+                }
+            }
+
+            let var = expect(i + 1, TokenType::Symbol, token);
+            let in_kw = expect(i + 2, TokenType::Symbol, token);
+            if in_kw.token != "in" {
+                self.error.abort("Expected `in` after loop variable.", in_kw.line, in_kw.column);
+            }
+
+            if Opcode::from_name(&var.token).is_some() {
+                self.error.abort(
+                    &format!("Loop variable `{}` is an opcode name.", var.token),
+                    var.line,
+                    var.column,
+                );
+            }
+
+            let start = expect(i + 3, TokenType::Number, token);
+            expect(i + 4, TokenType::Dot, token);
+            expect(i + 5, TokenType::Dot, token);
+            let end = expect(i + 6, TokenType::Number, token);
+            expect(i + 7, TokenType::LeftBrace, token);
+
+            let (start, end) = match (start.token.parse::<u64>(), end.token.parse::<u64>()) {
+                (Ok(s), Ok(e)) if s < e => (s, e),
+                _ => {
+                    self.error.abort("Invalid loop range.", start.line, start.column);
+                    unreachable!();
+                }
+            };
+
+            // Find the closing brace of the loop body
+            let mut depth = 0;
+            let mut close = None;
+            for (idx, t) in tokens.iter().enumerate().skip(i + 7) {
+                match t.token_type {
+                    TokenType::LeftBrace => depth += 1,
+                    TokenType::RightBrace => {
+                        depth -= 1;
+                        if depth == 0 {
+                            close = Some(idx);
+                            break
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            let close = match close {
+                Some(v) => v,
+                None => {
+                    self.error.abort(
+                        "Loop is not closed with a right brace '}'.",
+                        token.line,
+                        token.column,
+                    );
+                    unreachable!();
+                }
+            };
+
+            let body = self.unroll_loops(&tokens[i + 8..close]);
+            if body.is_empty() {
+                self.error.warn("Loop body is empty.", token.line, token.column);
+            } else if body.last().unwrap().token_type != TokenType::Semicolon {
+                let last = body.last().unwrap();
+                self.error.abort(
+                    "Loop body does not end with a semicolon.",
+                    last.line,
+                    last.column,
+                );
+            }
+
+            for n in start..end {
+                for t in &body {
+                    if t.token_type == TokenType::Symbol && t.token == var.token {
+                        ret.push(Token {
+                            token: n.to_string(),
+                            token_type: TokenType::Number,
+                            line: t.line,
+                            column: t.column,
+                        });
+                        continue
+                    }
+
+                    ret.push(t.clone());
+                }
+            }
+
+            i = close + 1;
+        }
+
+        ret
+    }
+
+    /// Resolve the arrays used in the circuit section. Indexing such as
+    /// `values[2]` becomes a single symbol, array literals assigned with
+    /// `arr = [a, b, c];` are aliases that get substituted where they're
+    /// used, and a bare array name used as an argument expands into all
+    /// of its elements.
+    fn resolve_arrays(&self, tokens: &[Token], arrays: IndexMap<String, Vec<Token>>) -> Vec<Token> {
+        let mut arrays = arrays;
+
+        // First merge constant indexing into single symbols.
+        let mut merged: Vec<Token> = vec![];
+        let mut i = 0;
+        while i < tokens.len() {
+            let token = &tokens[i];
+
+            let is_index = token.token_type == TokenType::LeftBracket &&
+                merged.last().map_or(false, |t| t.token_type == TokenType::Symbol);
+
+            if !is_index {
+                merged.push(token.clone());
+                i += 1;
+                continue
+            }
+
+            let (index, close) = match (tokens.get(i + 1), tokens.get(i + 2)) {
+                (Some(index), Some(close)) => (index, close),
+                _ => {
+                    self.error.abort("Premature ending of array index.", token.line, token.column);
+                    unreachable!();
+                }
+            };
+
+            if index.token_type != TokenType::Number || index.token.parse::<usize>().is_err() {
+                self.error.abort(
+                    "Array index must be a constant number.",
+                    index.line,
+                    index.column,
+                );
+            }
+
+            if close.token_type != TokenType::RightBracket {
+                self.error.abort(
+                    "Array index must be closed with a right bracket ']'.",
+                    close.line,
+                    close.column,
+                );
+            }
+
+            let symbol = merged.last_mut().unwrap();
+            symbol.token = format!("{}[{}]", symbol.token, index.token);
+            i += 3;
+        }
+
+        // Then resolve the array literals and their usage.
+        let mut ret: Vec<Token> = vec![];
+        let mut stmt_start = true;
+        let mut iter = merged.iter().peekable();
+        while let Some(token) = iter.next() {
+            let at_start = stmt_start;
+            stmt_start = token.token_type == TokenType::Semicolon;
+
+            if token.token_type != TokenType::Symbol {
+                // Brackets of inline array literals are dropped, which
+                // flattens them into the surrounding arguments.
+                if token.token_type != TokenType::LeftBracket &&
+                    token.token_type != TokenType::RightBracket
+                {
+                    ret.push(token.clone());
+                }
+                continue
+            }
+
+            let is_lhs = iter.peek().map_or(false, |t| t.token_type == TokenType::Assign);
+
+            // An array literal definition: `arr = [a, b, c];`
+            if at_start && is_lhs {
+                let mut lookahead = iter.clone();
+                lookahead.next();
+                if lookahead.peek().map_or(false, |t| t.token_type == TokenType::LeftBracket) {
+                    lookahead.next();
+                    let mut elements = vec![];
+                    let mut closed = false;
+                    for t in lookahead.by_ref() {
+                        match t.token_type {
+                            TokenType::RightBracket => {
+                                closed = true;
+                                break
+                            }
+                            TokenType::Comma => {}
+                            TokenType::Symbol => match arrays.get(&t.token) {
+                                Some(v) => elements.extend(v.iter().cloned()),
+                                None => elements.push(self.resolve_element(t, &arrays)),
+                            },
+                            _ => {
+                                self.error.abort(
+                                    "Array elements must be variables.",
+                                    t.line,
+                                    t.column,
+                                );
+                            }
+                        }
+                    }
+
+                    let semicolon = lookahead.next();
+                    if !closed || semicolon.map_or(true, |t| t.token_type != TokenType::Semicolon) {
+                        self.error.abort(
+                            "Invalid array definition. Must be `<name> = [<var>, ...];`.",
+                            token.line,
+                            token.column,
+                        );
+                    }
+
+                    if elements.is_empty() {
+                        self.error.abort("Array can't be empty.", token.line, token.column);
+                    }
+
+                    arrays.insert(token.token.clone(), elements);
+                    iter = lookahead;
+                    stmt_start = true;
+                    continue
+                }
+            }
+
+            // A bare array name used as an argument expands into its elements.
+            if !is_lhs {
+                if let Some(elements) = arrays.get(&token.token) {
+                    for (idx, element) in elements.iter().enumerate() {
+                        if idx > 0 {
+                            ret.push(Token {
+                                token: ",".to_string(),
+                                token_type: TokenType::Comma,
+                                line: token.line,
+                                column: token.column,
+                            });
+                        }
+                        ret.push(Token {
+                            token: element.token.clone(),
+                            token_type: TokenType::Symbol,
+                            line: token.line,
+                            column: token.column,
+                        });
+                    }
+                    continue
+                }
+            }
+
+            ret.push(self.resolve_element(token, &arrays));
+        }
+
+        ret
+    }
+
+    /// Resolve an indexed symbol such as `arr[1]` into the array's element,
+    /// if `arr` is a known array. Other symbols are returned unchanged.
+    fn resolve_element(&self, token: &Token, arrays: &IndexMap<String, Vec<Token>>) -> Token {
+        let (name, index) = match token.token.strip_suffix(']').and_then(|v| v.rsplit_once('[')) {
+            Some(v) => v,
+            None => return token.clone(),
+        };
+
+        let elements = match arrays.get(name) {
+            Some(v) => v,
+            None => return token.clone(),
+        };
+
+        // The index was checked to be a number when it was merged.
+        let index: usize = index.parse().unwrap();
+        if index >= elements.len() {
+            self.error.abort(
+                &format!(
+                    "Index {} is out of bounds for array `{}` of length {}.",
+                    index,
+                    name,
+                    elements.len()
+                ),
+                token.line,
+                token.column,
+            );
+        }
+
+        Token {
+            token: elements[index].token.clone(),
+            token_type: TokenType::Symbol,
+            line: token.line,
+            column: token.column,
+        }
+    }
+
     /// Routine checks on section structure
     fn check_section_structure(&self, section: &str, tokens: Vec<Token>) {
         if tokens[0].token_type != TokenType::String {
@@ -343,7 +774,9 @@ impl Parser {
                     self.error.warn(&format!("{} section is empty.", section), 0, 0);
                 }
 
-                if tokens[2..tokens.len() - 1].len() % 3 != 0 {
+                // Witness arrays are declared with extra tokens, so the
+                // `contract` section is checked while it's being parsed.
+                if section == "constant" && tokens[2..tokens.len() - 1].len() % 3 != 0 {
                     self.error.abort(
                         &format!("Invalid number of elements in '{}' section. Must be pairs of '<Type> <name>' separated with a comma ','.", section),
                         tokens[0].line,
@@ -356,7 +789,10 @@ impl Parser {
                     self.error.abort("circuit section is empty.", 0, 0);
                 }
 
-                if tokens[tokens.len() - 2].token_type != TokenType::Semicolon {
+                // A section can also end with the closing brace of a loop.
+                if tokens[tokens.len() - 2].token_type != TokenType::Semicolon &&
+                    tokens[tokens.len() - 2].token_type != TokenType::RightBrace
+                {
                     self.error.abort(
                         "Circuit section does not end with a semicolon. Would never finish parsing.",
                         tokens[tokens.len()-2].line,
@@ -678,6 +1114,7 @@ impl Parser {
                     if let Some(next_token) = iter.peek() {
                         if next_token.token_type == TokenType::Assign {
                             stmt.line = token.line;
+                            stmt.column = token.column;
                             stmt.typ = StatementType::Assign;
                             stmt.rhs = vec![];
                             stmt.lhs = Some(Variable {
@@ -695,6 +1132,7 @@ impl Parser {
 
                         if next_token.token_type == TokenType::LeftParen {
                             stmt.line = token.line;
+                            stmt.column = token.column;
                             stmt.typ = StatementType::Call;
                             stmt.rhs = vec![];
                             stmt.lhs = None;
@@ -803,6 +1241,7 @@ impl Parser {
                         lhs: Some(var),
                         rhs: args,
                         line: arg.line,
+                        column: arg.column,
                     });
 
                     ret.push(arg);
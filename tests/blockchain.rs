@@ -0,0 +1,263 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use darkfi::{
+    blockchain::{Blockchain, DbBatch, DbOverlay, TxLocation},
+    consensus::{Block, BlockInfo, Header, LeadInfo},
+    tx::{Transaction, DEFAULT_GAS_LIMIT},
+    util::time::Timestamp,
+    Error, Result,
+};
+use darkfi_sdk::{
+    crypto::{contract_id::MONEY_CONTRACT_ID, Nullifier},
+    pasta::pallas,
+    tx::ContractCall,
+};
+use darkfi_serial::serialize;
+
+/// Create a block on top of the given one, at the given slot.
+fn next_block(previous: blake3::Hash, slot: u64) -> BlockInfo {
+    let mut header = Header::genesis_header(Timestamp::current_time(), blake3::hash(b"test"));
+    header.previous = previous;
+    header.slot = slot;
+    BlockInfo::new(header, vec![], LeadInfo::default())
+}
+
+#[test]
+fn blockchain_consistency() -> Result<()> {
+    let sled_db = sled::Config::new().temporary(true).open()?;
+    let genesis_ts = Timestamp::current_time();
+    let genesis_data = blake3::hash(b"genesis");
+
+    let blockchain = Blockchain::new(&sled_db, genesis_ts, genesis_data)?;
+    let (_, genesis_hash) = blockchain.last()?;
+
+    // A fully written block survives a restart
+    let block1 = next_block(genesis_hash, 1);
+    let hashes = blockchain.add(&[block1.clone()])?;
+    assert!(blockchain.has_block(&block1)?);
+
+    // A slot pointing to a block that was never written
+    let block2 = next_block(hashes[0], 2);
+    blockchain.order.insert(&[2], &[block2.blockhash()])?;
+
+    // A block and header that never made it to the block order
+    let block3 = next_block(hashes[0], 3);
+    blockchain.headers.insert(&[block3.header.clone()])?;
+    let orphan = blockchain.blocks.insert(&[Block::from(block3.clone())])?;
+
+    // Restarting only repairs the tip of the chain
+    let blockchain = Blockchain::new(&sled_db, genesis_ts, genesis_data)?;
+    assert!(blockchain.has_block(&block1)?);
+    assert!(!blockchain.has_slot(2)?);
+    assert!(blockchain.merkle_roots.contains(&block1.header.root)?);
+    assert_eq!(blockchain.last()?, (1, hashes[0]));
+    assert!(blockchain.blocks.contains(&orphan[0])?);
+
+    // The full check also removes the orphans
+    assert!(blockchain.check_consistency()?.is_empty());
+    assert!(!blockchain.blocks.contains(&orphan[0])?);
+    assert!(!blockchain.headers.contains(&block3.header.headerhash())?);
+    assert!(blockchain.has_block(&block1)?);
+
+    // Nothing left to repair
+    assert!(blockchain.check_consistency()?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn blockchain_revert() -> Result<()> {
+    let sled_db = sled::Config::new().temporary(true).open()?;
+    let genesis_ts = Timestamp::current_time();
+    let genesis_data = blake3::hash(b"genesis");
+
+    let blockchain = Blockchain::new(&sled_db, genesis_ts, genesis_data)?;
+    let (_, genesis_hash) = blockchain.last()?;
+
+    // Contract state written along with the blocks
+    let state = sled_db.open_tree(b"test_state")?;
+    state.insert(b"key", b"genesis")?;
+
+    let mut previous = genesis_hash;
+    let mut blocks = vec![];
+    for slot in 1..4 {
+        let block = next_block(previous, slot);
+        let overlay = DbOverlay::default();
+        let mut batch = DbBatch::default();
+        batch.insert(b"key", format!("slot {}", slot).as_bytes());
+        batch.insert(format!("new {}", slot).as_bytes(), b"");
+        overlay.stage(&state, batch);
+        previous = blockchain.stage(&overlay, &[block.clone()])[0];
+        blockchain.commit(&overlay, slot)?;
+        blocks.push(block);
+    }
+
+    assert_eq!(blockchain.canonical_slot(&blocks[2].blockhash())?, Some(3));
+    assert_eq!(state.get(b"key")?.unwrap().as_ref(), b"slot 3");
+
+    // Reverting to slot 1 removes the last two blocks and their state updates
+    let reverted = blockchain.revert_to(1)?;
+    let reverted: Vec<_> = reverted.iter().map(|x| x.blockhash()).collect();
+    assert_eq!(reverted, vec![blocks[1].blockhash(), blocks[2].blockhash()]);
+    assert_eq!(blockchain.last()?, (1, blocks[0].blockhash()));
+    assert_eq!(blockchain.canonical_slot(&blocks[2].blockhash())?, None);
+    assert!(!blockchain.blocks.contains(&blocks[1].blockhash())?);
+    assert_eq!(state.get(b"key")?.unwrap().as_ref(), b"slot 1");
+    assert!(state.contains_key(b"new 1")?);
+    assert!(!state.contains_key(b"new 2")?);
+    assert!(!state.contains_key(b"new 3")?);
+
+    // Reverting to the genesis block restores the initial state
+    blockchain.revert_to(0)?;
+    assert_eq!(blockchain.last()?, (0, genesis_hash));
+    assert_eq!(state.get(b"key")?.unwrap().as_ref(), b"genesis");
+    assert!(!state.contains_key(b"new 1")?);
+
+    // Blocks written without an undo log can't be reverted
+    blockchain.headers.insert(&[blocks[0].header.clone()])?;
+    blockchain.blocks.insert(&[Block::from(blocks[0].clone())])?;
+    blockchain.order.insert(&[1], &[blocks[0].blockhash()])?;
+    assert!(matches!(blockchain.revert_to(0), Err(Error::UndoLogNotFound(1))));
+
+    Ok(())
+}
+
+#[test]
+fn blockchain_reorg() -> Result<()> {
+    let sled_db = sled::Config::new().temporary(true).open()?;
+    let genesis_ts = Timestamp::current_time();
+    let genesis_data = blake3::hash(b"genesis");
+
+    let blockchain = Blockchain::new(&sled_db, genesis_ts, genesis_data)?;
+    let (_, genesis_hash) = blockchain.last()?;
+    let state = sled_db.open_tree(b"test_state")?;
+
+    let mut previous = genesis_hash;
+    let mut blocks = vec![];
+    for slot in 1..4 {
+        let block = next_block(previous, slot);
+        let overlay = DbOverlay::default();
+        let mut batch = DbBatch::default();
+        batch.insert(b"key", format!("slot {}", slot).as_bytes());
+        overlay.stage(&state, batch);
+        previous = blockchain.stage(&overlay, &[block.clone()])[0];
+        blockchain.commit(&overlay, slot)?;
+        blocks.push(block);
+    }
+
+    // A fork of two blocks extending the block in slot 1 is staged
+    // on top of the revert of the blocks after it.
+    let revert = DbOverlay::default();
+    let reverted = blockchain.stage_revert_to(&revert, 1)?;
+    assert_eq!(reverted.len(), 2);
+
+    let mut previous = blocks[0].blockhash();
+    let mut fork = vec![];
+    let mut staged: Vec<(DbOverlay, u64)> = vec![];
+    for slot in 4..6 {
+        let overlay = match staged.last() {
+            Some((v, _)) => v.layer(),
+            None => revert.layer(),
+        };
+
+        // Each layer sees the state the ones under it leave behind
+        let expected = match slot {
+            4 => "slot 1".to_string(),
+            _ => format!("fork {}", slot - 1),
+        };
+        assert_eq!(overlay.get(&state, b"key"), Some(Some(expected.into_bytes())));
+
+        let block = next_block(previous, slot);
+        let mut batch = DbBatch::default();
+        batch.insert(b"key", format!("fork {}", slot).as_bytes());
+        overlay.stage(&state, batch);
+        previous = blockchain.stage(&overlay, &[block.clone()])[0];
+        staged.push((overlay, slot));
+        fork.push(block);
+    }
+
+    // Nothing is written until the layers are committed
+    assert_eq!(blockchain.last()?, (3, blocks[2].blockhash()));
+    assert_eq!(state.get(b"key")?.unwrap().as_ref(), b"slot 3");
+
+    blockchain.commit_layers(&revert, &staged)?;
+    assert_eq!(blockchain.last()?, (5, fork[1].blockhash()));
+    assert_eq!(blockchain.canonical_slot(&blocks[2].blockhash())?, None);
+    assert!(blockchain.has_block(&fork[0])?);
+    assert_eq!(state.get(b"key")?.unwrap().as_ref(), b"fork 5");
+
+    // Each fork block got its own undo log
+    blockchain.revert_to(4)?;
+    assert_eq!(state.get(b"key")?.unwrap().as_ref(), b"fork 4");
+    blockchain.revert_to(1)?;
+    assert_eq!(state.get(b"key")?.unwrap().as_ref(), b"slot 1");
+
+    // Pruned undo logs can't be reverted anymore
+    blockchain.undo.prune(0)?;
+    assert!(matches!(blockchain.revert_to(0), Err(Error::UndoLogNotFound(1))));
+
+    Ok(())
+}
+
+#[test]
+fn blockchain_tx_index() -> Result<()> {
+    let sled_db = sled::Config::new().temporary(true).open()?;
+    let genesis_ts = Timestamp::current_time();
+    let genesis_data = blake3::hash(b"genesis");
+
+    let blockchain = Blockchain::new(&sled_db, genesis_ts, genesis_data)?;
+    let (_, genesis_hash) = blockchain.last()?;
+
+    let txs: Vec<Transaction> = (0..3)
+        .map(|i| Transaction {
+            calls: vec![ContractCall { contract_id: *MONEY_CONTRACT_ID, data: vec![i] }],
+            gas_limit: DEFAULT_GAS_LIMIT,
+            proofs: vec![],
+            signatures: vec![],
+        })
+        .collect();
+
+    let mut block = next_block(genesis_hash, 1);
+    block.txs = txs.clone();
+    let blockhash = blockchain.add(&[block])?[0];
+
+    for (position, tx) in txs.iter().enumerate() {
+        let tx_hash = blake3::hash(&serialize(tx));
+        let location = blockchain.tx_locations.get(&[tx_hash], true)?[0].unwrap();
+        assert_eq!(location, TxLocation { slot: 1, blockhash, position: position as u32 });
+    }
+
+    let unknown = blake3::hash(b"unknown");
+    assert!(blockchain.tx_locations.get(&[unknown], false)?[0].is_none());
+
+    // Nullifiers are indexed by the transaction that revealed them
+    let tx_hash = blake3::hash(&serialize(&txs[0]));
+    let nullifier = Nullifier::from(pallas::Base::from(42));
+    blockchain.nullifiers.insert(&[nullifier], &tx_hash)?;
+    assert_eq!(blockchain.nullifiers.spent_by(&nullifier)?, Some(tx_hash));
+    assert_eq!(blockchain.nullifiers.spent_by(&Nullifier::from(pallas::Base::from(43)))?, None);
+
+    // Nullifiers stored before their transactions got recorded
+    let old = Nullifier::from(pallas::Base::from(44));
+    sled_db.open_tree(b"_nullifiers")?.insert(serialize(&old), vec![])?;
+    assert!(blockchain.nullifiers.contains(&old)?);
+    assert_eq!(blockchain.nullifiers.spent_by(&old)?, None);
+
+    Ok(())
+}
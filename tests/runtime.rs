@@ -0,0 +1,785 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Tests running contracts through the wasm runtime and the validator.
+//! The contracts are written in the WebAssembly text format, which the
+//! runtime compiles the same way as wasm bincode.
+
+use darkfi::{
+    blockchain::Blockchain,
+    consensus::{
+        constants::{EPOCH_LENGTH, MAX_DEPLOY_CIRCUIT_K},
+        BlockInfo, Header, LeadInfo, ValidatorState, ValidatorStatePtr,
+        TESTNET_BOOTSTRAP_TIMESTAMP, TESTNET_GENESIS_HASH_BYTES, TESTNET_GENESIS_TIMESTAMP,
+        TESTNET_INITIAL_DISTRIBUTION,
+    },
+    runtime::{
+        gas::GAS_MEMORY_PER_PAGE,
+        vm_runtime::{Runtime, MAX_MEMORY_PAGES},
+    },
+    tx::{ContractDeploy, Transaction, DEFAULT_GAS_LIMIT},
+    util::time::Timestamp,
+    wallet::WalletDb,
+    zk::{halo2::Value, ParamsCache, Proof, Witness, ZkCircuit},
+    zkas::ZkBinary,
+    Error, Result, VerifyFailed,
+};
+use darkfi_sdk::{
+    crypto::{ContractId, PublicKey, SecretKey, DEPLOY_CONTRACT_ID},
+    db::SMART_CONTRACT_ZKAS_DB_NAME,
+    error::ContractError,
+    pasta::pallas,
+    tx::ContractCall,
+};
+use darkfi_serial::{serialize, Encodable};
+use rand::rngs::OsRng;
+
+/// Contract that returns empty metadata, spends some gas in `exec`, and
+/// doesn't touch any state.
+const GAS_CONTRACT: &str = r#"
+(module
+  (import "env" "set_return_data_" (func $set_return_data (param i32 i32) (result i64)))
+  (memory (export "memory") 1)
+  ;; Empty ZK public inputs and signature public keys
+  (data (i32.const 32768) "\00\00")
+
+  (func (export "__initialize") (param i32) (result i64)
+    (i64.const 0))
+
+  (func (export "__metadata") (param i32) (result i64)
+    (drop (call $set_return_data (i32.const 32768) (i32.const 2)))
+    (i64.const 0))
+
+  (func (export "__entrypoint") (param i32) (result i64)
+    (local $i i32)
+    (loop $spend
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br_if $spend (i32.lt_u (local.get $i) (i32.const 1000))))
+    (i64.const 0))
+
+  (func (export "__update") (param i32) (result i64)
+    (i64.const 0))
+)
+"#;
+
+/// Contract whose `exec` never returns.
+const LOOP_CONTRACT: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (func (export "__initialize") (param i32) (result i64)
+    (i64.const 0))
+  (func (export "__metadata") (param i32) (result i64)
+    (i64.const 0))
+  (func (export "__entrypoint") (param i32) (result i64)
+    (loop $forever
+      (br $forever))
+    (i64.const 0))
+  (func (export "__update") (param i32) (result i64)
+    (i64.const 0))
+)
+"#;
+
+/// Contract whose `exec` grows its memory by `pages` pages, and fails if
+/// that isn't possible.
+fn grow_contract(pages: u32) -> String {
+    format!(
+        r#"
+(module
+  (memory (export "memory") 1)
+  (func (export "__initialize") (param i32) (result i64)
+    (i64.const 0))
+  (func (export "__metadata") (param i32) (result i64)
+    (i64.const 0))
+  (func (export "__entrypoint") (param i32) (result i64)
+    (if (i32.eq (memory.grow (i32.const {pages})) (i32.const -1))
+      (then (return (i64.const 1))))
+    (i64.const 0))
+  (func (export "__update") (param i32) (result i64)
+    (i64.const 0))
+)
+"#
+    )
+}
+
+/// Contract whose `exec` fills its whole memory with a single bulk memory
+/// operation.
+const FILL_CONTRACT: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (func (export "__initialize") (param i32) (result i64)
+    (i64.const 0))
+  (func (export "__metadata") (param i32) (result i64)
+    (i64.const 0))
+  (func (export "__entrypoint") (param i32) (result i64)
+    (drop (memory.grow (i32.const 255)))
+    (memory.fill (i32.const 0) (i32.const 255) (i32.mul (memory.size) (i32.const 65536)))
+    (i64.const 0))
+  (func (export "__update") (param i32) (result i64)
+    (i64.const 0))
+)
+"#;
+
+/// Escape the given bytes for a data segment in the WebAssembly text format.
+fn wat_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("\\{:02x}", b)).collect()
+}
+
+/// Contract whose calls have to be signed by `signer`, and which accepts
+/// calls from other contracts if `nested` is set.
+fn signed_contract(signer: PublicKey, nested: bool) -> String {
+    let nested_entrypoint = match nested {
+        true => r#"(func (export "__nested_entrypoint") (param i32) (result i64) (i64.const 0))"#,
+        false => "",
+    };
+
+    format!(
+        r#"
+(module
+  (import "env" "set_return_data_" (func $set_return_data (param i32 i32) (result i64)))
+  (memory (export "memory") 1)
+  ;; No ZK public inputs, and the signer's public key
+  (data (i32.const 32768) "\00\01{signer}")
+
+  (func (export "__initialize") (param i32) (result i64)
+    (i64.const 0))
+  (func (export "__metadata") (param i32) (result i64)
+    (drop (call $set_return_data (i32.const 32768) (i32.const 34)))
+    (i64.const 0))
+  (func (export "__entrypoint") (param i32) (result i64)
+    (i64.const 0))
+  (func (export "__update") (param i32) (result i64)
+    (i64.const 0))
+  {nested_entrypoint}
+)
+"#,
+        signer = wat_bytes(&serialize(&signer)),
+    )
+}
+
+/// Contract that calls `callee` with empty instruction data in `exec`, and
+/// fails if that call fails.
+fn caller_contract(callee: ContractId) -> String {
+    format!(
+        r#"
+(module
+  (import "env" "set_return_data_" (func $set_return_data (param i32 i32) (result i64)))
+  (import "env" "call_contract_" (func $call_contract (param i32 i32) (result i64)))
+  (memory (export "memory") 1)
+  (data (i32.const 32768) "\00\00")
+  ;; The callee's ID followed by empty instruction data
+  (data (i32.const 32800) "{callee}\00")
+
+  (func (export "__initialize") (param i32) (result i64)
+    (i64.const 0))
+  (func (export "__metadata") (param i32) (result i64)
+    (drop (call $set_return_data (i32.const 32768) (i32.const 2)))
+    (i64.const 0))
+  (func (export "__entrypoint") (param i32) (result i64)
+    (local $ret i64)
+    (local.set $ret (call $call_contract (i32.const 32800) (i32.const 33)))
+    (select (local.get $ret) (i64.const 0) (i64.lt_s (local.get $ret) (i64.const 0))))
+  (func (export "__update") (param i32) (result i64)
+    (i64.const 0))
+)
+"#,
+        callee = wat_bytes(&serialize(&callee)),
+    )
+}
+
+/// Contract whose `exec` fails with a custom error unless it sees the given
+/// transaction context.
+fn context_contract(
+    slot: u64,
+    epoch: u64,
+    timestamp: i64,
+    call_idx: u32,
+    tx_hash: blake3::Hash,
+) -> String {
+    format!(
+        r#"
+(module
+  (import "env" "set_return_data_" (func $set_return_data (param i32 i32) (result i64)))
+  (import "env" "get_object_bytes_" (func $get_object_bytes (param i32 i32) (result i64)))
+  (import "env" "get_current_slot_" (func $get_current_slot (result i64)))
+  (import "env" "get_current_epoch_" (func $get_current_epoch (result i64)))
+  (import "env" "get_timestamp_" (func $get_timestamp (result i64)))
+  (import "env" "get_call_index_" (func $get_call_index (result i64)))
+  (import "env" "get_tx_hash_" (func $get_tx_hash (result i64)))
+  (memory (export "memory") 1)
+  (data (i32.const 32768) "\00\00")
+  ;; The expected transaction hash, with room for the actual one after it
+  (data (i32.const 32800) "{tx_hash}")
+
+  (func (export "__initialize") (param i32) (result i64)
+    (i64.const 0))
+  (func (export "__metadata") (param i32) (result i64)
+    (drop (call $set_return_data (i32.const 32768) (i32.const 2)))
+    (i64.const 0))
+  (func (export "__entrypoint") (param i32) (result i64)
+    (local $i i32)
+    (if (i64.ne (call $get_current_slot) (i64.const {slot}))
+      (then (return (i64.const 1))))
+    (if (i64.ne (call $get_current_epoch) (i64.const {epoch}))
+      (then (return (i64.const 2))))
+    (if (i64.ne (call $get_timestamp) (i64.const {timestamp}))
+      (then (return (i64.const 3))))
+    (if (i64.ne (call $get_call_index) (i64.const {call_idx}))
+      (then (return (i64.const 4))))
+    (drop (call $get_object_bytes (i32.const 32832) (i32.wrap_i64 (call $get_tx_hash))))
+    (loop $compare
+      (if (i64.ne (i64.load (i32.add (i32.const 32800) (local.get $i)))
+                  (i64.load (i32.add (i32.const 32832) (local.get $i))))
+        (then (return (i64.const 5))))
+      (local.set $i (i32.add (local.get $i) (i32.const 8)))
+      (br_if $compare (i32.lt_u (local.get $i) (i32.const 32))))
+    (i64.const 0))
+  (func (export "__update") (param i32) (result i64)
+    (i64.const 0))
+)
+"#,
+        tx_hash = wat_bytes(tx_hash.as_bytes()),
+    )
+}
+
+/// Contract that creates a `state` database when deployed, and then fails
+/// its deployment with a custom error if `fail` is set.
+fn db_contract(contract_id: ContractId, fail: bool) -> String {
+    format!(
+        r#"
+(module
+  (import "env" "db_init_" (func $db_init (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  ;; The contract's ID followed by the database name
+  (data (i32.const 32800) "{contract_id}\05state")
+
+  (func (export "__initialize") (param i32) (result i64)
+    (if (i32.lt_s (call $db_init (i32.const 32800) (i32.const 38)) (i32.const 0))
+      (then (return (i64.const 2))))
+    (i64.const {ret}))
+  (func (export "__metadata") (param i32) (result i64)
+    (i64.const 0))
+  (func (export "__entrypoint") (param i32) (result i64)
+    (i64.const 0))
+  (func (export "__update") (param i32) (result i64)
+    (i64.const 0))
+)
+"#,
+        contract_id = wat_bytes(&serialize(&contract_id)),
+        ret = fail as u8,
+    )
+}
+
+/// Contract whose calls with empty data set a flag in its `state` database,
+/// and whose other calls fail in `metadata` with a custom error unless the
+/// flag is set.
+fn flag_contract(contract_id: ContractId) -> String {
+    format!(
+        r#"
+(module
+  (import "env" "set_return_data_" (func $set_return_data (param i32 i32) (result i64)))
+  (import "env" "db_init_" (func $db_init (param i32 i32) (result i32)))
+  (import "env" "db_lookup_" (func $db_lookup (param i32 i32) (result i32)))
+  (import "env" "db_contains_key_" (func $db_contains_key (param i32 i32) (result i32)))
+  (import "env" "db_set_" (func $db_set (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 32768) "\00\00")
+  ;; The contract's ID followed by the database name
+  (data (i32.const 32800) "{contract_id}\05state")
+  ;; Room for a db handle, followed by the flag's key and value
+  (data (i32.const 32896) "\00\00\00\00\01k\01\01")
+
+  (func $open_db
+    (i32.store (i32.const 32896) (call $db_lookup (i32.const 32800) (i32.const 38))))
+
+  (func (export "__initialize") (param i32) (result i64)
+    (drop (call $db_init (i32.const 32800) (i32.const 38)))
+    (i64.const 0))
+  (func (export "__metadata") (param i32) (result i64)
+    ;; Length of the first call's data, after the payload header
+    (if (i32.load8_u (i32.const 77))
+      (then
+        (call $open_db)
+        (if (i32.ne (call $db_contains_key (i32.const 32896) (i32.const 6)) (i32.const 1))
+          (then (return (i64.const 1))))))
+    (drop (call $set_return_data (i32.const 32768) (i32.const 2)))
+    (i64.const 0))
+  (func (export "__entrypoint") (param i32) (result i64)
+    (i64.const 0))
+  (func (export "__update") (param i32) (result i64)
+    (call $open_db)
+    (drop (call $db_set (i32.const 32896) (i32.const 8)))
+    (i64.const 0))
+)
+"#,
+        contract_id = wat_bytes(&serialize(&contract_id)),
+    )
+}
+
+/// Contract whose calls carry a proof of its `Arith` circuit, with the given
+/// public inputs.
+fn arith_contract(public_inputs: &[pallas::Base]) -> String {
+    let mut metadata = serialize(&vec![("Arith".to_string(), public_inputs.to_vec())]);
+    // No signature public keys
+    metadata.push(0);
+
+    format!(
+        r#"
+(module
+  (import "env" "set_return_data_" (func $set_return_data (param i32 i32) (result i64)))
+  (memory (export "memory") 1)
+  (data (i32.const 32768) "{metadata}")
+
+  (func (export "__initialize") (param i32) (result i64)
+    (i64.const 0))
+  (func (export "__metadata") (param i32) (result i64)
+    (drop (call $set_return_data (i32.const 32768) (i32.const {len})))
+    (i64.const 0))
+  (func (export "__entrypoint") (param i32) (result i64)
+    (i64.const 0))
+  (func (export "__update") (param i32) (result i64)
+    (i64.const 0))
+)
+"#,
+        metadata = wat_bytes(&metadata),
+        len = metadata.len(),
+    )
+}
+
+/// Create a validator with the native contracts deployed.
+async fn validator() -> Result<ValidatorStatePtr> {
+    let sled_db = sled::Config::new().temporary(true).open()?;
+    let wallet = WalletDb::new("sqlite::memory:", "foo").await?;
+
+    ValidatorState::new(
+        &sled_db,
+        *TESTNET_BOOTSTRAP_TIMESTAMP,
+        *TESTNET_GENESIS_TIMESTAMP,
+        *TESTNET_GENESIS_HASH_BYTES,
+        *TESTNET_INITIAL_DISTRIBUTION,
+        wallet,
+        vec![],
+        false,
+        ParamsCache::in_memory(),
+    )
+    .await
+}
+
+/// Create an unsigned transaction making `n` calls to the given contract.
+fn calls_tx(contract_id: ContractId, n: usize, gas_limit: u64) -> Transaction {
+    let calls = vec![ContractCall { contract_id, data: vec![] }; n];
+    Transaction { calls, gas_limit, proofs: vec![vec![]; n], signatures: vec![vec![]; n] }
+}
+
+/// Create a transaction deploying the given contract under `deploy_key`,
+/// signed with `signer`.
+fn deploy_tx(
+    deploy_key: SecretKey,
+    signer: SecretKey,
+    wasm: &str,
+    circuits: Vec<Vec<u8>>,
+) -> Result<Transaction> {
+    let deploy = ContractDeploy {
+        deploy_key: PublicKey::from_secret(deploy_key),
+        wasm_bincode: wasm.as_bytes().to_vec(),
+        circuits,
+        payload: vec![],
+    };
+    let calls = vec![ContractCall { contract_id: *DEPLOY_CONTRACT_ID, data: serialize(&deploy) }];
+    let mut tx = Transaction {
+        calls,
+        gas_limit: DEFAULT_GAS_LIMIT,
+        proofs: vec![vec![]],
+        signatures: vec![],
+    };
+    tx.signatures = vec![tx.create_sigs(&mut OsRng, &[signer])?];
+    Ok(tx)
+}
+
+/// Create a block with the given transactions on top of the last one.
+fn next_block(blockchain: &Blockchain, txs: Vec<Transaction>) -> Result<BlockInfo> {
+    let (slot, previous) = blockchain.last()?;
+    let mut header = Header::genesis_header(Timestamp::current_time(), blake3::hash(b"test"));
+    header.previous = previous;
+    header.slot = slot + 1;
+    Ok(BlockInfo::new(header, txs, LeadInfo::default()))
+}
+
+/// Payload the validator passes to the given call of a transaction.
+fn call_payload(tx: &Transaction, idx: u32) -> Result<Vec<u8>> {
+    let mut payload = vec![];
+    idx.encode(&mut payload)?;
+    tx.calls.encode(&mut payload)?;
+    Ok(payload)
+}
+
+#[test]
+fn runtime_infinite_loop_runs_out_of_gas() -> Result<()> {
+    let sled_db = sled::Config::new().temporary(true).open()?;
+    let blockchain =
+        Blockchain::new(&sled_db, *TESTNET_GENESIS_TIMESTAMP, *TESTNET_GENESIS_HASH_BYTES)?;
+    let contract_id = ContractId::derive(SecretKey::random(&mut OsRng));
+
+    let mut runtime = Runtime::new(LOOP_CONTRACT.as_bytes(), blockchain, contract_id)?;
+    runtime.set_gas_limit(1_000_000);
+    assert!(matches!(runtime.exec(&[]), Err(Error::OutOfGas(1_000_000))));
+    assert_eq!(runtime.gas_used(), 1_000_000);
+
+    Ok(())
+}
+
+#[test]
+fn runtime_memory_is_capped_and_charged_per_page() -> Result<()> {
+    let sled_db = sled::Config::new().temporary(true).open()?;
+    let blockchain =
+        Blockchain::new(&sled_db, *TESTNET_GENESIS_TIMESTAMP, *TESTNET_GENESIS_HASH_BYTES)?;
+    let contract_id = ContractId::derive(SecretKey::random(&mut OsRng));
+
+    // The memory can't grow past the cap
+    let wat = grow_contract(MAX_MEMORY_PAGES);
+    let mut runtime = Runtime::new(wat.as_bytes(), blockchain.clone(), contract_id)?;
+    runtime.set_gas_limit(DEFAULT_GAS_LIMIT);
+    let err = runtime.exec(&[]).unwrap_err();
+    assert!(matches!(err, Error::ContractError(ContractError::Custom(1))));
+
+    // Growing within the cap is paid for by the page
+    let wat = grow_contract(MAX_MEMORY_PAGES / 2);
+    let mut runtime = Runtime::new(wat.as_bytes(), blockchain.clone(), contract_id)?;
+    runtime.set_gas_limit(DEFAULT_GAS_LIMIT);
+    runtime.exec(&[])?;
+    let grow_gas = (MAX_MEMORY_PAGES / 2) as u64 * GAS_MEMORY_PER_PAGE;
+    assert!(runtime.gas_used() >= grow_gas);
+
+    // And runs out of gas if the limit doesn't cover it
+    let mut runtime = Runtime::new(wat.as_bytes(), blockchain.clone(), contract_id)?;
+    runtime.set_gas_limit(grow_gas - 1);
+    assert!(matches!(runtime.exec(&[]), Err(Error::OutOfGas(_))));
+    assert_eq!(runtime.gas_used(), grow_gas - 1);
+
+    // Bulk memory operations aren't accepted at all
+    assert!(Runtime::new(FILL_CONTRACT.as_bytes(), blockchain, contract_id).is_err());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn runtime_gas_is_summed_over_phases_and_calls() -> Result<()> {
+    let state = validator().await?;
+    let blockchain = state.read().await.blockchain.clone();
+    let contract_id = ContractId::derive(SecretKey::random(&mut OsRng));
+    blockchain.wasm_bincode.insert(contract_id, GAS_CONTRACT.as_bytes())?;
+
+    // Gas spent by each phase of a single call, run directly
+    let tx = calls_tx(contract_id, 1, DEFAULT_GAS_LIMIT);
+    let payload = call_payload(&tx, 0)?;
+    let mut runtime = Runtime::new(GAS_CONTRACT.as_bytes(), blockchain.clone(), contract_id)?;
+    runtime.set_gas_limit(DEFAULT_GAS_LIMIT);
+    runtime.metadata(&payload)?;
+    let metadata_gas = runtime.gas_used();
+    runtime.set_gas_limit(DEFAULT_GAS_LIMIT);
+    let update = runtime.exec(&payload)?;
+    let exec_gas = runtime.gas_used();
+    runtime.set_gas_limit(DEFAULT_GAS_LIMIT);
+    runtime.apply(&update)?;
+    let apply_gas = runtime.gas_used();
+    assert!(metadata_gas > 0 && exec_gas > metadata_gas && apply_gas > 0);
+
+    // The validator charges the transaction for all of them
+    let gas = state.read().await.verify_transactions(&[tx.clone()], false).await?;
+    assert_eq!(gas, vec![metadata_gas + exec_gas]);
+    let gas = state.read().await.verify_transactions(&[tx], true).await?;
+    let call_gas = metadata_gas + exec_gas + apply_gas;
+    assert_eq!(gas, vec![call_gas]);
+
+    // And for all of its calls
+    let tx = calls_tx(contract_id, 2, DEFAULT_GAS_LIMIT);
+    let gas = state.read().await.verify_transactions(&[tx], true).await?;
+    assert_eq!(gas, vec![2 * call_gas]);
+
+    // So the limit has to cover the whole transaction
+    let tx = calls_tx(contract_id, 2, 2 * call_gas);
+    assert!(state.read().await.verify_transactions(&[tx], true).await.is_ok());
+    let tx = calls_tx(contract_id, 2, 2 * call_gas - 1);
+    let err = state.read().await.verify_transactions(&[tx], true).await.unwrap_err();
+    assert!(matches!(err, Error::OutOfGas(_)));
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn runtime_deploy_and_upgrade() -> Result<()> {
+    let state = validator().await?;
+    let blockchain = state.read().await.blockchain.clone();
+    let deploy_key = SecretKey::random(&mut OsRng);
+    let contract_id = ContractId::derive(deploy_key);
+    let circuit = include_bytes!("../proof/arithmetic.zk.bin").to_vec();
+
+    // Deploying stores the contract, which can then be called
+    let tx = deploy_tx(deploy_key, deploy_key, GAS_CONTRACT, vec![circuit])?;
+    state.read().await.verify_transactions(&[tx], true).await?;
+    assert_eq!(blockchain.wasm_bincode.get(contract_id)?, GAS_CONTRACT.as_bytes());
+    let tx = calls_tx(contract_id, 1, DEFAULT_GAS_LIMIT);
+    state.read().await.verify_transactions(&[tx], true).await?;
+
+    // Deploying again with the same key upgrades it
+    let tx = deploy_tx(deploy_key, deploy_key, LOOP_CONTRACT, vec![])?;
+    state.read().await.verify_transactions(&[tx], true).await?;
+    assert_eq!(blockchain.wasm_bincode.get(contract_id)?, LOOP_CONTRACT.as_bytes());
+
+    // But not when signed with another key
+    let tx = deploy_tx(deploy_key, SecretKey::random(&mut OsRng), GAS_CONTRACT, vec![])?;
+    let err = state.read().await.verify_transactions(&[tx], true).await.unwrap_err();
+    assert!(matches!(err, Error::InvalidSignature));
+
+    // Or not signed at all
+    let mut tx = deploy_tx(deploy_key, deploy_key, GAS_CONTRACT, vec![])?;
+    tx.signatures = vec![vec![]];
+    let err = state.read().await.verify_transactions(&[tx], true).await.unwrap_err();
+    assert!(matches!(err, Error::InvalidSignature));
+    assert_eq!(blockchain.wasm_bincode.get(contract_id)?, LOOP_CONTRACT.as_bytes());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn runtime_deploy_and_call_in_one_block() -> Result<()> {
+    let state = validator().await?;
+    let blockchain = state.read().await.blockchain.clone();
+    let params_cache = state.read().await.params_cache.clone();
+    let deploy_key = SecretKey::random(&mut OsRng);
+    let contract_id = ContractId::derive(deploy_key);
+    let circuit = include_bytes!("../proof/arithmetic.zk.bin").to_vec();
+
+    // A call carrying a proof of the contract's circuit
+    let (a, b) = (pallas::Base::from(4), pallas::Base::from(2));
+    let public_inputs = vec![a + b, a * b, a - b];
+    let witnesses = vec![Witness::Base(Value::known(a)), Witness::Base(Value::known(b))];
+    let zk_circuit = ZkCircuit::new(witnesses, ZkBinary::decode(&circuit)?);
+    let proving_key = params_cache.proving_key(&circuit)?;
+    let proof = Proof::create(&proving_key, &[zk_circuit], &public_inputs, &mut OsRng)?;
+    let mut call = calls_tx(contract_id, 1, DEFAULT_GAS_LIMIT);
+    call.proofs = vec![vec![proof]];
+
+    // The call is verified with the circuit deployed before it in the same block
+    let wasm = arith_contract(&public_inputs);
+    let deploy = deploy_tx(deploy_key, deploy_key, &wasm, vec![circuit])?;
+    let block = next_block(&blockchain, vec![deploy, call.clone()])?;
+    state.write().await.receive_blocks(&[block]).await?;
+
+    // Once the contract is upgraded to one without circuits, the calls before
+    // the upgrade still verify, but not the ones after it
+    let upgrade = deploy_tx(deploy_key, deploy_key, &wasm, vec![])?;
+    let txs = [call.clone(), upgrade.clone(), call.clone()];
+    let err = state.read().await.verify_transactions(&txs, true).await.unwrap_err();
+    assert!(matches!(err, Error::VerifyFailed(VerifyFailed::ProofVerifyFailed(_))));
+
+    let txs = [call.clone(), upgrade];
+    state.read().await.verify_transactions(&txs, true).await?;
+    let err = state.read().await.verify_transactions(&[call], false).await.unwrap_err();
+    assert!(matches!(err, Error::VerifyFailed(VerifyFailed::ProofVerifyFailed(_))));
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn runtime_deploy_circuit_k() -> Result<()> {
+    let state = validator().await?;
+    let deploy_key = SecretKey::random(&mut OsRng);
+    let mut circuit = include_bytes!("../proof/arithmetic.zk.bin").to_vec();
+
+    // A circuit too large to build the verifying key for
+    ZkBinary::set_k(&mut circuit, MAX_DEPLOY_CIRCUIT_K + 1)?;
+    let tx = deploy_tx(deploy_key, deploy_key, GAS_CONTRACT, vec![circuit.clone()])?;
+    let err = state.read().await.verify_transactions(&[tx], false).await.unwrap_err();
+    assert!(matches!(err, Error::ContractDeployFailed(_)));
+
+    // A circuit that doesn't fit in its k
+    ZkBinary::set_k(&mut circuit, 1)?;
+    let tx = deploy_tx(deploy_key, deploy_key, GAS_CONTRACT, vec![circuit.clone()])?;
+    assert!(state.read().await.verify_transactions(&[tx], false).await.is_err());
+
+    // Creating the verifying key is paid for
+    ZkBinary::set_k(&mut circuit, MAX_DEPLOY_CIRCUIT_K)?;
+    let mut tx = deploy_tx(deploy_key, deploy_key, GAS_CONTRACT, vec![circuit])?;
+    tx.gas_limit = 100_000_000;
+    tx.signatures = vec![tx.create_sigs(&mut OsRng, &[deploy_key])?];
+    let err = state.read().await.verify_transactions(&[tx], false).await.unwrap_err();
+    assert!(matches!(err, Error::OutOfGas(100_000_000)));
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn runtime_deploy_revert() -> Result<()> {
+    let state = validator().await?;
+    let blockchain = state.read().await.blockchain.clone();
+    let (genesis_slot, _) = blockchain.last()?;
+    let deploy_key = SecretKey::random(&mut OsRng);
+    let contract_id = ContractId::derive(deploy_key);
+
+    // Deploy and upgrade the contract in consecutive blocks
+    let tx = deploy_tx(deploy_key, deploy_key, GAS_CONTRACT, vec![])?;
+    let block = next_block(&blockchain, vec![tx])?;
+    let deploy_slot = block.header.slot;
+    state.write().await.receive_blocks(&[block]).await?;
+
+    let tx = deploy_tx(deploy_key, deploy_key, LOOP_CONTRACT, vec![])?;
+    let block = next_block(&blockchain, vec![tx])?;
+    state.write().await.receive_blocks(&[block]).await?;
+    assert_eq!(blockchain.wasm_bincode.get(contract_id)?, LOOP_CONTRACT.as_bytes());
+
+    // Reverting the upgrade brings the previous wasm back
+    blockchain.revert_to(deploy_slot)?;
+    assert_eq!(blockchain.wasm_bincode.get(contract_id)?, GAS_CONTRACT.as_bytes());
+
+    // And reverting the deployment removes the contract
+    blockchain.revert_to(genesis_slot)?;
+    assert!(matches!(blockchain.wasm_bincode.get(contract_id), Err(Error::WasmBincodeNotFound)));
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn runtime_deploy_stages_contract_databases() -> Result<()> {
+    let state = validator().await?;
+    let blockchain = state.read().await.blockchain.clone();
+    let db = &blockchain.sled_db;
+    let (genesis_slot, _) = blockchain.last()?;
+    let deploy_key = SecretKey::random(&mut OsRng);
+    let contract_id = ContractId::derive(deploy_key);
+
+    // A failed deployment leaves none of the databases it created behind
+    let tx = deploy_tx(deploy_key, deploy_key, &db_contract(contract_id, true), vec![])?;
+    let err = state.read().await.verify_transactions(&[tx], true).await.unwrap_err();
+    assert!(matches!(err, Error::ContractError(ContractError::Custom(1))));
+    assert!(blockchain.contracts.lookup(db, &contract_id, "state").is_err());
+    assert!(blockchain.contracts.lookup(db, &contract_id, SMART_CONTRACT_ZKAS_DB_NAME).is_err());
+
+    // A successful one creates them along with the block holding it
+    let tx = deploy_tx(deploy_key, deploy_key, &db_contract(contract_id, false), vec![])?;
+    let block = next_block(&blockchain, vec![tx])?;
+    state.write().await.receive_blocks(&[block]).await?;
+    blockchain.contracts.lookup(db, &contract_id, "state")?;
+    blockchain.contracts.lookup(db, &contract_id, SMART_CONTRACT_ZKAS_DB_NAME)?;
+
+    // And reverting the block removes them again
+    blockchain.revert_to(genesis_slot)?;
+    assert!(blockchain.contracts.lookup(db, &contract_id, "state").is_err());
+    assert!(blockchain.contracts.lookup(db, &contract_id, SMART_CONTRACT_ZKAS_DB_NAME).is_err());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn runtime_metadata_sees_staged_state() -> Result<()> {
+    let state = validator().await?;
+    let blockchain = state.read().await.blockchain.clone();
+    let deploy_key = SecretKey::random(&mut OsRng);
+    let contract_id = ContractId::derive(deploy_key);
+    let tx = deploy_tx(deploy_key, deploy_key, &flag_contract(contract_id), vec![])?;
+    state.read().await.verify_transactions(&[tx], true).await?;
+
+    let set_flag = calls_tx(contract_id, 1, DEFAULT_GAS_LIMIT);
+    let mut check_flag = set_flag.clone();
+    check_flag.calls[0].data = vec![1];
+
+    // Without the flag set, the checking call's metadata fails
+    let err = state.read().await.verify_transactions(&[check_flag.clone()], false).await;
+    assert!(matches!(err.unwrap_err(), Error::ContractError(ContractError::Custom(1))));
+
+    // But it sees the flag staged by a transaction before it in the same block
+    let block = next_block(&blockchain, vec![set_flag, check_flag.clone()])?;
+    state.write().await.receive_blocks(&[block]).await?;
+    state.read().await.verify_transactions(&[check_flag], false).await?;
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn runtime_nested_call_needs_opt_in() -> Result<()> {
+    let state = validator().await?;
+    let blockchain = state.read().await.blockchain.clone();
+    let signer = PublicKey::from_secret(SecretKey::random(&mut OsRng));
+
+    // Called directly, the contract's calls have to be signed
+    let guarded_id = ContractId::derive(SecretKey::random(&mut OsRng));
+    blockchain.wasm_bincode.insert(guarded_id, signed_contract(signer, false).as_bytes())?;
+    let tx = calls_tx(guarded_id, 1, DEFAULT_GAS_LIMIT);
+    let err = state.read().await.verify_transactions(&[tx], false).await.unwrap_err();
+    assert!(matches!(err, Error::InvalidSignature));
+
+    // Calling it from another contract doesn't get around that
+    let caller_id = ContractId::derive(SecretKey::random(&mut OsRng));
+    blockchain.wasm_bincode.insert(caller_id, caller_contract(guarded_id).as_bytes())?;
+    let tx = calls_tx(caller_id, 1, DEFAULT_GAS_LIMIT);
+    let err = state.read().await.verify_transactions(&[tx], false).await.unwrap_err();
+    assert!(matches!(err, Error::ContractError(ContractError::CallerAccessDenied)));
+
+    // Unless the callee accepts calls from other contracts
+    let open_id = ContractId::derive(SecretKey::random(&mut OsRng));
+    blockchain.wasm_bincode.insert(open_id, signed_contract(signer, true).as_bytes())?;
+    let caller_id = ContractId::derive(SecretKey::random(&mut OsRng));
+    blockchain.wasm_bincode.insert(caller_id, caller_contract(open_id).as_bytes())?;
+    let tx = calls_tx(caller_id, 1, DEFAULT_GAS_LIMIT);
+    state.read().await.verify_transactions(&[tx], true).await?;
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn runtime_contract_sees_block_context() -> Result<()> {
+    let state = validator().await?;
+    let blockchain = state.read().await.blockchain.clone();
+
+    // The contract checking its context is the transaction's second call
+    let gas_id = ContractId::derive(SecretKey::random(&mut OsRng));
+    blockchain.wasm_bincode.insert(gas_id, GAS_CONTRACT.as_bytes())?;
+    let context_id = ContractId::derive(SecretKey::random(&mut OsRng));
+    let calls = vec![
+        ContractCall { contract_id: gas_id, data: vec![] },
+        ContractCall { contract_id: context_id, data: vec![] },
+    ];
+    let tx = Transaction {
+        calls,
+        gas_limit: DEFAULT_GAS_LIMIT,
+        proofs: vec![vec![]; 2],
+        signatures: vec![vec![]; 2],
+    };
+
+    // A block a few epochs in
+    let mut block = next_block(&blockchain, vec![tx.clone()])?;
+    block.header.slot = 2 * EPOCH_LENGTH as u64 + 3;
+    let slot = block.header.slot;
+    let (epoch, timestamp) = {
+        let state = state.read().await;
+        (state.consensus.slot_epoch(slot), state.consensus.slot_timestamp(slot).0)
+    };
+    assert_eq!(epoch, 2);
+
+    let wasm = context_contract(slot, epoch, timestamp, 1, tx.data_hash()?);
+    blockchain.wasm_bincode.insert(context_id, wasm.as_bytes())?;
+
+    // In any other slot, the contract sees a different context
+    let mut other_block = block.clone();
+    other_block.header.slot = slot + 1;
+    let err = state.write().await.receive_blocks(&[other_block]).await.unwrap_err();
+    assert!(matches!(err, Error::ContractError(ContractError::Custom(1))));
+
+    state.write().await.receive_blocks(&[block]).await?;
+
+    Ok(())
+}
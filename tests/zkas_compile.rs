@@ -0,0 +1,124 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2023 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Checks that the circuits in the tree keep compiling to the same binary.
+//! Changes to the zkas frontend must not silently change what an existing
+//! circuit constrains.
+
+use darkfi::zkas::{Analyzer, Compiler, Lexer, Parser};
+
+/// blake3 hash of each circuit's compiled sections, from `.constant` to the end,
+/// without debug info. Except for `opcodes.zk` and `dao-vote-main.zk`, which were
+/// changed to use `cond_select`, these are the hashes from before arrays, loops and
+/// variable reassignment were added to zkas.
+const CIRCUITS: &[(&str, &str)] = &[
+    ("proof/arithmetic.zk", "5ef488528fa6d3503599112320e3d31dc50d405e453c684a04a1b49dca29dcdb"),
+    ("proof/burn.zk", "43d0d2299dee928e17149b2adc61fbd14bc4b6377f3978fb276a6b5511d4b724"),
+    (
+        "proof/deploy_contract.zk",
+        "30f766b679652e22b95e10ee1f6038c6d92280dff17feb600b1c8a5eafa6bad4",
+    ),
+    ("proof/encrypt.zk", "b20295d87c74107cdd9963407654d5abe5d21a30c87eb00af8c54d4fb565775f"),
+    ("proof/lead.zk", "ba4b572a0c97267d602363fe0a17757714ac369edec353c8960cb2d95e874cbe"),
+    ("proof/mint.zk", "c6b75b4df7fadc04580a9a69d21e5fdfcddf61fa918f9908f115b72cf55816fe"),
+    ("proof/opcodes.zk", "66594da2be4b155b25d5d25af4f599240568ecdef1900763749ea7e31dc414a8"),
+    ("proof/tx.zk", "8706884e63cd6a00d800696c11b56a7fd5624b6fd13ad90b1ca16a10815f402f"),
+    ("proof/voting.zk", "174774a141708eadf23bd78acfabb737940e6cdd5d4e4cff7b6c124aef9c8795"),
+    (
+        "src/contract/dao/proof/dao-exec.zk",
+        "5c669924dce1fe27bbf7974fd525f2357527b78db8169578b1c1bda52def388f",
+    ),
+    (
+        "src/contract/dao/proof/dao-mint.zk",
+        "5a11e8a432ceb7542506b2b35a34b4b0f6848d1edef8cc8b727e4ec925fea3bd",
+    ),
+    (
+        "src/contract/dao/proof/dao-propose-burn.zk",
+        "00474910634158f322578b0104da2d50159f04e4af74dea77f77678a180e55da",
+    ),
+    (
+        "src/contract/dao/proof/dao-propose-main.zk",
+        "ce49bcfeb9f588972d8accc0a78287a38a3baf23c90ad213872ce3a7405f6f22",
+    ),
+    (
+        "src/contract/dao/proof/dao-vote-burn.zk",
+        "2e06f8ca71dad2c9d0c2bd29acb2a09d44e2beb264533195f6b036ea61453a8b",
+    ),
+    (
+        "src/contract/dao/proof/dao-vote-main.zk",
+        "3c1ea41b92a5db1ee0114ec6a1f11c90d3afe5cf65edec8988c9ee6fdda1c38d",
+    ),
+    (
+        "src/contract/money/proof/burn_v1.zk",
+        "6c5ec67249d427f4644e43b3a8b7ecd8568d0a50c081eea664ae7908191c3ec9",
+    ),
+    (
+        "src/contract/money/proof/lead_burn_v1.zk",
+        "1210273c930e02f7838807448f71af68bba6b97c606ded709622d6b5fba84619",
+    ),
+    (
+        "src/contract/money/proof/lead_mint_v1.zk",
+        "1a8d744b23389ea09a098691f61fd715b060eac74e24ad0a8c07a814384d2ab0",
+    ),
+    (
+        "src/contract/money/proof/mint_v1.zk",
+        "bb713dda35a3181922d5361491c9d4aa953cb35653d1acbf976604bc6c77ce5b",
+    ),
+    (
+        "src/contract/money/proof/token_mint_v1.zk",
+        "dd61ec07f1b3bc3357d8af9d6b926cc7f34cb6f842783bdd295242f56fc77c06",
+    ),
+];
+
+/// Compile the given zkas source the same way `zkas` does, without debug info.
+fn compile(filename: &str, source: &str) -> Vec<u8> {
+    let lexer = Lexer::new(filename, source.chars());
+    let tokens = lexer.lex();
+
+    let parser = Parser::new(filename, source.chars(), tokens);
+    let (namespace, constants, witnesses, statements) = parser.parse();
+
+    let mut analyzer = Analyzer::new(filename, source.chars(), constants, witnesses, statements);
+    analyzer.analyze_types();
+
+    let compiler = Compiler::new(
+        filename,
+        source.chars(),
+        namespace,
+        analyzer.constants,
+        analyzer.witnesses,
+        analyzer.statements,
+        analyzer.literals,
+        false,
+    );
+
+    compiler.compile()
+}
+
+#[test]
+fn zkas_circuits_unchanged() {
+    for (path, expected) in CIRCUITS {
+        let source =
+            std::fs::read_to_string(format!("{}/{}", env!("CARGO_MANIFEST_DIR"), path)).unwrap();
+        let bincode = compile(path, &source);
+
+        // The header gained the circuit's `k` since, so it's left out
+        let start = bincode.windows(9).position(|w| w == b".constant").unwrap();
+        assert_eq!(blake3::hash(&bincode[start..]).to_hex().as_str(), *expected, "{}", path);
+    }
+}
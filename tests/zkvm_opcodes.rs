@@ -27,13 +27,17 @@ use halo2_gadgets::poseidon::{
 use halo2_proofs::{
     arithmetic::{CurveAffine, Field},
     circuit::Value,
+    dev::MockProver,
     pasta::{group::Curve, pallas},
+    plonk,
+    poly::commitment::Params,
 };
 use rand::rngs::OsRng;
 use simplelog::{ColorChoice, Config, LevelFilter, TermLogger, TerminalMode};
 
 use darkfi::{
     zk::{
+        minimum_k,
         proof::{ProvingKey, VerifyingKey},
         vm::ZkCircuit,
         vm_stack::{empty_witnesses, Witness},
@@ -119,13 +123,173 @@ fn zkvm_opcodes() -> Result<()> {
     ];
 
     let circuit = ZkCircuit::new(prover_witnesses, zkbin.clone());
-    let proving_key = ProvingKey::build(13, &circuit);
+    let proving_key = ProvingKey::build(zkbin.k, &circuit);
     let proof = Proof::create(&proving_key, &[circuit], &public_inputs, &mut OsRng)?;
 
     let verifier_witnesses = empty_witnesses(&zkbin);
-    let circuit = ZkCircuit::new(verifier_witnesses, zkbin);
-    let verifying_key = VerifyingKey::build(13, &circuit);
+    let circuit = ZkCircuit::new(verifier_witnesses, zkbin.clone());
+    let verifying_key = VerifyingKey::build(zkbin.k, &circuit);
     proof.verify(&verifying_key, &public_inputs)?;
 
     Ok(())
 }
+
+#[test]
+fn zkvm_debug_info() -> Result<()> {
+    let bincode = include_bytes!("../proof/opcodes.zk.bin");
+    let zkbin = ZkBinary::decode(bincode)?;
+
+    let debug_info = zkbin.debug_info.clone().unwrap();
+    assert_eq!(debug_info.opcodes.len(), zkbin.opcodes.len());
+    assert_eq!(debug_info.stack[0].name, "VALUE_COMMIT_VALUE");
+    assert_eq!(
+        debug_info.opcode_location(0).unwrap(),
+        "line 26: vcv = ec_mul_short(value, VALUE_COMMIT_VALUE)"
+    );
+
+    // Run the circuit with a witness breaking `less_than_strict(a, b)`
+    // and make sure the failure points back to the source.
+    let pubkey = PublicKey::from_secret(SecretKey::random(&mut OsRng)).inner();
+    let witnesses = vec![
+        Witness::Base(Value::known(pallas::Base::from(666))),
+        Witness::Scalar(Value::known(pallas::Scalar::random(&mut OsRng))),
+        Witness::Base(Value::known(pallas::Base::random(&mut OsRng))),
+        Witness::Base(Value::known(pallas::Base::from(69))),
+        Witness::Base(Value::known(pallas::Base::from(42))),
+        Witness::Base(Value::known(pallas::Base::random(&mut OsRng))),
+        Witness::EcNiPoint(Value::known(pubkey)),
+        Witness::Base(Value::known(pallas::Base::random(&mut OsRng))),
+        Witness::Uint32(Value::known(0)),
+        Witness::MerklePath(Value::known([MerkleNode::from(pallas::Base::zero()); 32])),
+    ];
+
+    let circuit = ZkCircuit::new(witnesses, zkbin);
+    let prover = MockProver::run(13, &circuit, vec![vec![pallas::Base::zero(); 9]])?;
+    let failures = prover.verify().unwrap_err();
+
+    assert!(failures.iter().any(|f| format!("{}", f).contains("line 51: less_than_strict(a, b)")));
+
+    Ok(())
+}
+
+#[test]
+fn zkvm_arrays() -> Result<()> {
+    let bincode = include_bytes!("../proof/arrays.zk.bin");
+    let zkbin = ZkBinary::decode(bincode)?;
+
+    // Values for the proof
+    let values = [42_u64, 69, 420, 1337];
+    let blinds: Vec<pallas::Scalar> = (0..4).map(|_| pallas::Scalar::random(&mut OsRng)).collect();
+    let points = [
+        PublicKey::from_secret(SecretKey::random(&mut OsRng)).inner(),
+        PublicKey::from_secret(SecretKey::random(&mut OsRng)).inner(),
+    ];
+
+    let mut prover_witnesses = vec![];
+    for value in values {
+        prover_witnesses.push(Witness::Base(Value::known(pallas::Base::from(value))));
+    }
+    for blind in &blinds {
+        prover_witnesses.push(Witness::Scalar(Value::known(*blind)));
+    }
+    for point in points {
+        prover_witnesses.push(Witness::EcPoint(Value::known(point)));
+    }
+
+    let sum: u64 = values.iter().sum();
+    let blind_sum = blinds.iter().fold(pallas::Scalar::zero(), |acc, b| acc + b);
+    let value_commit = pedersen_commitment_u64(sum, blind_sum);
+    let value_coords = value_commit.to_affine().coordinates().unwrap();
+
+    let pair_hash = {
+        let messages = [pallas::Base::from(values[0]), pallas::Base::from(values[1])];
+        poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init().hash(messages)
+    };
+
+    let all_hash = {
+        let messages = values.map(pallas::Base::from);
+        poseidon::Hash::<_, P128Pow5T3, ConstantLength<4>, 3, 2>::init().hash(messages)
+    };
+
+    let p = (points[0] + points[1]).to_affine().coordinates().unwrap();
+
+    let public_inputs = vec![
+        pallas::Base::from(sum),
+        *value_coords.x(),
+        *value_coords.y(),
+        pair_hash,
+        all_hash,
+        *p.x(),
+        *p.y(),
+    ];
+
+    let circuit = ZkCircuit::new(prover_witnesses, zkbin.clone());
+    let proving_key = ProvingKey::build(zkbin.k, &circuit);
+    let proof = Proof::create(&proving_key, &[circuit], &public_inputs, &mut OsRng)?;
+
+    let verifier_witnesses = empty_witnesses(&zkbin);
+    let circuit = ZkCircuit::new(verifier_witnesses, zkbin.clone());
+    let verifying_key = VerifyingKey::build(zkbin.k, &circuit);
+    proof.verify(&verifying_key, &public_inputs)?;
+
+    Ok(())
+}
+
+#[test]
+fn zkvm_minimum_k() -> Result<()> {
+    let bincodes: [&[u8]; 3] = [
+        include_bytes!("../proof/arithmetic.zk.bin"),
+        include_bytes!("../proof/opcodes.zk.bin"),
+        include_bytes!("../proof/arrays.zk.bin"),
+    ];
+
+    for bincode in bincodes {
+        let zkbin = ZkBinary::decode(bincode)?;
+
+        // zkas records the computed k in the binary
+        let k = minimum_k(&zkbin)?;
+        assert_eq!(zkbin.k, k);
+
+        // The circuit fits in k rows, and doesn't fit in k - 1
+        let circuit = ZkCircuit::new(empty_witnesses(&zkbin), zkbin);
+        assert!(plonk::keygen_vk(&Params::new(k), &circuit).is_ok());
+        assert!(matches!(
+            plonk::keygen_vk(&Params::new(k - 1), &circuit),
+            Err(plonk::Error::NotEnoughRowsAvailable { .. })
+        ));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn zkvm_batch_verify() -> Result<()> {
+    let bincode = include_bytes!("../proof/arithmetic.zk.bin");
+    let zkbin = ZkBinary::decode(bincode)?;
+
+    let circuit = ZkCircuit::new(empty_witnesses(&zkbin), zkbin.clone());
+    let proving_key = ProvingKey::build(zkbin.k, &circuit);
+    let verifying_key = VerifyingKey::build(zkbin.k, &circuit);
+
+    let mut proofs = vec![];
+    for _ in 0..3 {
+        let a = pallas::Base::random(&mut OsRng);
+        let b = pallas::Base::random(&mut OsRng);
+        let witnesses = vec![Witness::Base(Value::known(a)), Witness::Base(Value::known(b))];
+        let public_inputs = vec![a + b, a * b, a - b];
+
+        let circuit = ZkCircuit::new(witnesses, zkbin.clone());
+        let proof = Proof::create(&proving_key, &[circuit], &public_inputs, &mut OsRng)?;
+        proofs.push((proof, public_inputs));
+    }
+
+    let batch: Vec<_> = proofs.iter().map(|(p, i)| (p, &i[..])).collect();
+    assert!(Proof::verify_batch(&verifying_key, &batch).is_ok());
+
+    // A proof with wrong public inputs should be singled out
+    proofs[1].1[0] += pallas::Base::one();
+    let batch: Vec<_> = proofs.iter().map(|(p, i)| (p, &i[..])).collect();
+    assert_eq!(Proof::verify_batch(&verifying_key, &batch), Err(vec![1]));
+
+    Ok(())
+}